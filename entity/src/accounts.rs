@@ -14,6 +14,12 @@ pub struct Model {
     pub last_login_at: Option<DateTimeWithTimeZone>,
     pub created_at: DateTimeWithTimeZone,
     pub updated_at: Option<DateTimeWithTimeZone>,
+    pub totp_secret: Option<String>,
+    pub totp_enabled: bool,
+    #[sea_orm(column_type = "Custom(\"jsonb\".to_owned())")]
+    pub recovery_codes: Json,
+    pub must_change_password: bool,
+    pub password_changed_at: Option<DateTimeWithTimeZone>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -24,6 +30,8 @@ pub enum Relation {
     Devices,
     #[sea_orm(has_many = "super::labels::Entity")]
     Labels,
+    #[sea_orm(has_many = "super::login_attempts::Entity")]
+    LoginAttempts,
 }
 
 impl Related<super::schemas::Entity> for Entity {
@@ -44,4 +52,10 @@ impl Related<super::labels::Entity> for Entity {
     }
 }
 
+impl Related<super::login_attempts::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::LoginAttempts.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -12,7 +12,9 @@ pub struct Model {
     pub mode: String,
     pub body: String,
     pub created_at: DateTimeWithTimeZone,
-    pub device_id: String,
+    pub device_id: Option<String>,
+    pub label_id: Option<String>,
+    pub error: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -25,6 +27,14 @@ pub enum Relation {
         on_delete = "Cascade"
     )]
     Devices,
+    #[sea_orm(
+        belongs_to = "super::labels::Entity",
+        from = "Column::LabelId",
+        to = "super::labels::Column::Id",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    Labels,
     #[sea_orm(has_many = "super::command_response_logs::Entity")]
     CommandResponseLogs,
 }
@@ -35,6 +45,12 @@ impl Related<super::devices::Entity> for Entity {
     }
 }
 
+impl Related<super::labels::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Labels.def()
+    }
+}
+
 impl Related<super::command_response_logs::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::CommandResponseLogs.def()
@@ -1,5 +1,6 @@
 //! SeaORM Entity. Generated by sea-orm-codegen 0.6.0
 
+pub use super::sea_orm_active_enums::LifecycleState;
 use sea_orm::entity::prelude::*;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
@@ -14,12 +15,19 @@ pub struct Model {
     pub is_active: bool,
     pub is_online: bool,
     pub mqtt_username: String,
-    pub mqtt_password: String,
     pub is_super_device: bool,
+    pub parent_id: Option<String>,
+    pub lifecycle_state: LifecycleState,
+    pub decommissioned_at: Option<DateTimeWithTimeZone>,
     #[sea_orm(column_type = "Custom(\"jsonb\".to_owned())")]
     pub acl_pubs: Json,
     #[sea_orm(column_type = "Custom(\"jsonb\".to_owned())")]
     pub acl_subs: Json,
+    pub hardware_serial: Option<String>,
+    pub provisioning_profile_id: Option<String>,
+    pub firmware_version: Option<String>,
+    pub firmware_reported_at: Option<DateTimeWithTimeZone>,
+    pub config_stale: bool,
     pub created_at: DateTimeWithTimeZone,
     pub updated_at: Option<DateTimeWithTimeZone>,
 }
@@ -48,6 +56,10 @@ pub enum Relation {
     CommandRequestLogs,
     #[sea_orm(has_many = "super::device_connections::Entity")]
     DeviceConnections,
+    #[sea_orm(has_many = "super::device_credentials::Entity")]
+    DeviceCredentials,
+    #[sea_orm(has_many = "super::device_lifecycle_events::Entity")]
+    DeviceLifecycleEvents,
 }
 
 impl Related<super::accounts::Entity> for Entity {
@@ -80,6 +92,18 @@ impl Related<super::device_connections::Entity> for Entity {
     }
 }
 
+impl Related<super::device_credentials::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::DeviceCredentials.def()
+    }
+}
+
+impl Related<super::device_lifecycle_events::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::DeviceLifecycleEvents.def()
+    }
+}
+
 impl Related<super::labels::Entity> for Entity {
     fn to() -> RelationDef {
         super::labels_device_relation::Relation::Labels.def()
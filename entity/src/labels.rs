@@ -9,6 +9,7 @@ pub struct Model {
     pub id: String,
     pub account_id: String,
     pub name: String,
+    pub value: Option<String>,
     pub created_at: DateTimeWithTimeZone,
     pub updated_at: Option<DateTimeWithTimeZone>,
 }
@@ -23,6 +24,8 @@ pub enum Relation {
         on_delete = "Cascade"
     )]
     Accounts,
+    #[sea_orm(has_many = "super::command_request_logs::Entity")]
+    CommandRequestLogs,
     #[sea_orm(has_many = "super::labels_device_relation::Entity")]
     LabelsDeviceRelation,
 }
@@ -33,6 +36,12 @@ impl Related<super::accounts::Entity> for Entity {
     }
 }
 
+impl Related<super::command_request_logs::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CommandRequestLogs.def()
+    }
+}
+
 impl Related<super::labels_device_relation::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::LabelsDeviceRelation.def()
@@ -3,13 +3,44 @@
 pub mod prelude;
 
 pub mod accounts;
+pub mod alarms;
+pub mod audit_logs;
+pub mod command_job_devices;
+pub mod command_jobs;
 pub mod command_request_logs;
 pub mod command_response_logs;
+pub mod config_assignments;
+pub mod config_profile_versions;
+pub mod config_profiles;
+pub mod d2d_message_logs;
+pub mod d2d_rules;
+pub mod data_routes;
+pub mod device_cas;
+pub mod device_certificates;
+pub mod device_configs;
 pub mod device_connections;
+pub mod device_credentials;
+pub mod device_lifecycle_events;
+pub mod device_upload_chunks;
+pub mod device_uploads;
 pub mod devices;
 pub mod fields;
+pub mod firmwares;
 pub mod labels;
 pub mod labels_device_relation;
+pub mod login_attempts;
+pub mod ota_campaign_devices;
+pub mod ota_campaigns;
+pub mod provisioning_profile_labels;
+pub mod provisioning_profiles;
+pub mod rule_executions;
+pub mod rule_states;
+pub mod rules;
+pub mod scheduled_command_runs;
+pub mod scheduled_commands;
 pub mod schemas;
 pub mod sea_orm_active_enums;
+pub mod system_settings;
+pub mod webhook_deliveries;
+pub mod webhooks;
 pub use sea_orm;
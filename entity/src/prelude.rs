@@ -1,14 +1,45 @@
 //! SeaORM Entity. Generated by sea-orm-codegen 0.6.0
 
 pub use super::accounts::Entity as Accounts;
+pub use super::alarms::Entity as Alarms;
+pub use super::audit_logs::Entity as AuditLogs;
+pub use super::command_job_devices::Entity as CommandJobDevices;
+pub use super::command_jobs::Entity as CommandJobs;
 pub use super::command_request_logs::Entity as CommandRequestLogs;
 pub use super::command_response_logs::Entity as CommandResponseLogs;
+pub use super::config_assignments::Entity as ConfigAssignments;
+pub use super::config_profile_versions::Entity as ConfigProfileVersions;
+pub use super::config_profiles::Entity as ConfigProfiles;
+pub use super::d2d_message_logs::Entity as D2dMessageLogs;
+pub use super::d2d_rules::Entity as D2dRules;
+pub use super::data_routes::Entity as DataRoutes;
+pub use super::device_cas::Entity as DeviceCas;
+pub use super::device_certificates::Entity as DeviceCertificates;
+pub use super::device_configs::Entity as DeviceConfigs;
 pub use super::device_connections::Entity as DeviceConnections;
+pub use super::device_credentials::Entity as DeviceCredentials;
+pub use super::device_lifecycle_events::Entity as DeviceLifecycleEvents;
+pub use super::device_upload_chunks::Entity as DeviceUploadChunks;
+pub use super::device_uploads::Entity as DeviceUploads;
 pub use super::devices::Entity as Devices;
 pub use super::fields::Entity as Fields;
+pub use super::firmwares::Entity as Firmwares;
 pub use super::labels::Entity as Labels;
 pub use super::labels_device_relation::Entity as LabelsDeviceRelation;
+pub use super::login_attempts::Entity as LoginAttempts;
+pub use super::ota_campaign_devices::Entity as OtaCampaignDevices;
+pub use super::ota_campaigns::Entity as OtaCampaigns;
+pub use super::provisioning_profile_labels::Entity as ProvisioningProfileLabels;
+pub use super::provisioning_profiles::Entity as ProvisioningProfiles;
+pub use super::rule_executions::Entity as RuleExecutions;
+pub use super::rule_states::Entity as RuleStates;
+pub use super::rules::Entity as Rules;
+pub use super::scheduled_command_runs::Entity as ScheduledCommandRuns;
+pub use super::scheduled_commands::Entity as ScheduledCommands;
 pub use super::schemas::Entity as Schemas;
+pub use super::system_settings::Entity as SystemSettings;
+pub use super::webhook_deliveries::Entity as WebhookDeliveries;
+pub use super::webhooks::Entity as Webhooks;
 
 pub use sea_orm;
 
@@ -16,6 +47,22 @@ pub use super::accounts::{
     ActiveModel as AccountActiveModel, Column as AccountColumn, Entity as AccountEntity,
     Model as AccountModel,
 };
+pub use super::alarms::{
+    ActiveModel as AlarmActiveModel, Column as AlarmColumn, Entity as AlarmEntity,
+    Model as AlarmModel,
+};
+pub use super::audit_logs::{
+    ActiveModel as AuditLogActiveModel, Column as AuditLogColumn, Entity as AuditLogEntity,
+    Model as AuditLogModel,
+};
+pub use super::command_job_devices::{
+    ActiveModel as CommandJobDeviceActiveModel, Column as CommandJobDeviceColumn,
+    Entity as CommandJobDeviceEntity, Model as CommandJobDeviceModel,
+};
+pub use super::command_jobs::{
+    ActiveModel as CommandJobActiveModel, Column as CommandJobColumn, Entity as CommandJobEntity,
+    Model as CommandJobModel,
+};
 pub use super::command_request_logs::{
     ActiveModel as CommandRequestLogActiveModel, Column as CommandRequestLogColumn,
     Entity as CommandRequestLogEntity, Model as CommandRequestLogModel,
@@ -24,10 +71,62 @@ pub use super::command_response_logs::{
     ActiveModel as CommandResponseLogActiveModel, Column as CommandResponseLogColumn,
     Entity as CommandResponseLogEntity, Model as CommandResponseLogModel,
 };
+pub use super::config_assignments::{
+    ActiveModel as ConfigAssignmentActiveModel, Column as ConfigAssignmentColumn,
+    Entity as ConfigAssignmentEntity, Model as ConfigAssignmentModel,
+};
+pub use super::config_profile_versions::{
+    ActiveModel as ConfigProfileVersionActiveModel, Column as ConfigProfileVersionColumn,
+    Entity as ConfigProfileVersionEntity, Model as ConfigProfileVersionModel,
+};
+pub use super::config_profiles::{
+    ActiveModel as ConfigProfileActiveModel, Column as ConfigProfileColumn,
+    Entity as ConfigProfileEntity, Model as ConfigProfileModel,
+};
+pub use super::d2d_message_logs::{
+    ActiveModel as D2dMessageLogActiveModel, Column as D2dMessageLogColumn,
+    Entity as D2dMessageLogEntity, Model as D2dMessageLogModel,
+};
+pub use super::d2d_rules::{
+    ActiveModel as D2dRuleActiveModel, Column as D2dRuleColumn, Entity as D2dRuleEntity,
+    Model as D2dRuleModel,
+};
+pub use super::data_routes::{
+    ActiveModel as DataRouteActiveModel, Column as DataRouteColumn, Entity as DataRouteEntity,
+    Model as DataRouteModel,
+};
+pub use super::device_cas::{
+    ActiveModel as DeviceCaActiveModel, Column as DeviceCaColumn, Entity as DeviceCaEntity,
+    Model as DeviceCaModel,
+};
+pub use super::device_certificates::{
+    ActiveModel as DeviceCertificateActiveModel, Column as DeviceCertificateColumn,
+    Entity as DeviceCertificateEntity, Model as DeviceCertificateModel,
+};
+pub use super::device_configs::{
+    ActiveModel as DeviceConfigActiveModel, Column as DeviceConfigColumn,
+    Entity as DeviceConfigEntity, Model as DeviceConfigModel,
+};
 pub use super::device_connections::{
     ActiveModel as DeviceConnectionActiveModel, Column as DeviceConnectionColumn,
     Entity as DeviceConnectionEntity, Model as DeviceConnectionModel,
 };
+pub use super::device_credentials::{
+    ActiveModel as DeviceCredentialActiveModel, Column as DeviceCredentialColumn,
+    Entity as DeviceCredentialEntity, Model as DeviceCredentialModel,
+};
+pub use super::device_lifecycle_events::{
+    ActiveModel as DeviceLifecycleEventActiveModel, Column as DeviceLifecycleEventColumn,
+    Entity as DeviceLifecycleEventEntity, Model as DeviceLifecycleEventModel,
+};
+pub use super::device_upload_chunks::{
+    ActiveModel as DeviceUploadChunkActiveModel, Column as DeviceUploadChunkColumn,
+    Entity as DeviceUploadChunkEntity, Model as DeviceUploadChunkModel,
+};
+pub use super::device_uploads::{
+    ActiveModel as DeviceUploadActiveModel, Column as DeviceUploadColumn,
+    Entity as DeviceUploadEntity, Model as DeviceUploadModel,
+};
 pub use super::devices::{
     ActiveModel as DeviceActiveModel, Column as DeviceColumn, Entity as DeviceEntity,
     Model as DeviceModel,
@@ -36,6 +135,10 @@ pub use super::fields::{
     ActiveModel as FieldActiveModel, Column as FieldColumn, Entity as FieldEntity,
     Model as FieldModel,
 };
+pub use super::firmwares::{
+    ActiveModel as FirmwareActiveModel, Column as FirmwareColumn, Entity as FirmwareEntity,
+    Model as FirmwareModel,
+};
 pub use super::labels::{
     ActiveModel as LabelActiveModel, Column as LabelColumn, Entity as LabelEntity,
     Model as LabelModel,
@@ -44,7 +147,58 @@ pub use super::labels_device_relation::{
     ActiveModel as LabelDeviceRelationActiveModel, Column as LabelDeviceRelationColumn,
     Entity as LabelDeviceRelationEntity, Model as LabelDeviceRelationModel,
 };
+pub use super::login_attempts::{
+    ActiveModel as LoginAttemptActiveModel, Column as LoginAttemptColumn,
+    Entity as LoginAttemptEntity, Model as LoginAttemptModel,
+};
+pub use super::ota_campaign_devices::{
+    ActiveModel as OtaCampaignDeviceActiveModel, Column as OtaCampaignDeviceColumn,
+    Entity as OtaCampaignDeviceEntity, Model as OtaCampaignDeviceModel,
+};
+pub use super::ota_campaigns::{
+    ActiveModel as OtaCampaignActiveModel, Column as OtaCampaignColumn,
+    Entity as OtaCampaignEntity, Model as OtaCampaignModel,
+};
+pub use super::provisioning_profile_labels::{
+    ActiveModel as ProvisioningProfileLabelActiveModel, Column as ProvisioningProfileLabelColumn,
+    Entity as ProvisioningProfileLabelEntity, Model as ProvisioningProfileLabelModel,
+};
+pub use super::provisioning_profiles::{
+    ActiveModel as ProvisioningProfileActiveModel, Column as ProvisioningProfileColumn,
+    Entity as ProvisioningProfileEntity, Model as ProvisioningProfileModel,
+};
+pub use super::rule_executions::{
+    ActiveModel as RuleExecutionActiveModel, Column as RuleExecutionColumn,
+    Entity as RuleExecutionEntity, Model as RuleExecutionModel,
+};
+pub use super::rule_states::{
+    ActiveModel as RuleStateActiveModel, Column as RuleStateColumn, Entity as RuleStateEntity,
+    Model as RuleStateModel,
+};
+pub use super::rules::{
+    ActiveModel as RuleActiveModel, Column as RuleColumn, Entity as RuleEntity, Model as RuleModel,
+};
+pub use super::scheduled_command_runs::{
+    ActiveModel as ScheduledCommandRunActiveModel, Column as ScheduledCommandRunColumn,
+    Entity as ScheduledCommandRunEntity, Model as ScheduledCommandRunModel,
+};
+pub use super::scheduled_commands::{
+    ActiveModel as ScheduledCommandActiveModel, Column as ScheduledCommandColumn,
+    Entity as ScheduledCommandEntity, Model as ScheduledCommandModel,
+};
 pub use super::schemas::{
     ActiveModel as SchemaActiveModel, Column as SchemaColumn, Entity as SchemaEntity,
     Model as SchemaModel,
 };
+pub use super::system_settings::{
+    ActiveModel as SystemSettingActiveModel, Column as SystemSettingColumn,
+    Entity as SystemSettingEntity, Model as SystemSettingModel,
+};
+pub use super::webhook_deliveries::{
+    ActiveModel as WebhookDeliveryActiveModel, Column as WebhookDeliveryColumn,
+    Entity as WebhookDeliveryEntity, Model as WebhookDeliveryModel,
+};
+pub use super::webhooks::{
+    ActiveModel as WebhookActiveModel, Column as WebhookColumn, Entity as WebhookEntity,
+    Model as WebhookModel,
+};
@@ -17,3 +17,323 @@ pub enum DataType {
     #[sea_orm(string_value = "time")]
     Time,
 }
+
+#[derive(EnumIter, DeriveActiveEnum, Clone, Copy, Debug, Eq, PartialEq, Enum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(32))")]
+#[oai(rename_all = "snake_case")]
+pub enum LifecycleState {
+    #[sea_orm(string_value = "provisioned")]
+    Provisioned,
+    #[sea_orm(string_value = "active")]
+    Active,
+    #[sea_orm(string_value = "suspended")]
+    Suspended,
+    #[sea_orm(string_value = "maintenance")]
+    Maintenance,
+    #[sea_orm(string_value = "decommissioned")]
+    Decommissioned,
+}
+
+#[derive(EnumIter, DeriveActiveEnum, Clone, Copy, Debug, Eq, PartialEq, Enum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+#[oai(rename_all = "snake_case")]
+pub enum RuleOperator {
+    #[sea_orm(string_value = "gt")]
+    Gt,
+    #[sea_orm(string_value = "gte")]
+    Gte,
+    #[sea_orm(string_value = "lt")]
+    Lt,
+    #[sea_orm(string_value = "lte")]
+    Lte,
+    #[sea_orm(string_value = "eq")]
+    Eq,
+    #[sea_orm(string_value = "ne")]
+    Ne,
+}
+
+#[derive(EnumIter, DeriveActiveEnum, Clone, Copy, Debug, Eq, PartialEq, Enum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(32))")]
+#[oai(rename_all = "snake_case")]
+pub enum RuleAction {
+    #[sea_orm(string_value = "command")]
+    Command,
+    #[sea_orm(string_value = "webhook")]
+    Webhook,
+    #[sea_orm(string_value = "alarm")]
+    Alarm,
+}
+
+/// 告警级别, 按严重程度从低到高排列
+#[derive(EnumIter, DeriveActiveEnum, Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord, Enum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+#[oai(rename_all = "snake_case")]
+pub enum AlarmSeverity {
+    #[sea_orm(string_value = "warning")]
+    Warning,
+    #[sea_orm(string_value = "minor")]
+    Minor,
+    #[sea_orm(string_value = "major")]
+    Major,
+    #[sea_orm(string_value = "critical")]
+    Critical,
+}
+
+#[derive(EnumIter, DeriveActiveEnum, Clone, Copy, Debug, Eq, PartialEq, Enum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+#[oai(rename_all = "snake_case")]
+pub enum AlarmStatus {
+    #[sea_orm(string_value = "active")]
+    Active,
+    #[sea_orm(string_value = "acknowledged")]
+    Acknowledged,
+    #[sea_orm(string_value = "cleared")]
+    Cleared,
+}
+
+#[derive(EnumIter, DeriveActiveEnum, Clone, Copy, Debug, Eq, PartialEq, Enum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(32))")]
+#[oai(rename_all = "snake_case")]
+pub enum AlarmSource {
+    #[sea_orm(string_value = "rule")]
+    Rule,
+    #[sea_orm(string_value = "offline")]
+    Offline,
+    #[sea_orm(string_value = "schema_validation")]
+    SchemaValidation,
+}
+
+/// 平台事件类型
+#[derive(EnumIter, DeriveActiveEnum, Clone, Copy, Debug, Eq, PartialEq, Enum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(32))")]
+#[oai(rename_all = "snake_case")]
+pub enum EventType {
+    #[sea_orm(string_value = "device_connected")]
+    DeviceConnected,
+    #[sea_orm(string_value = "device_disconnected")]
+    DeviceDisconnected,
+    #[sea_orm(string_value = "command_responded")]
+    CommandResponded,
+    #[sea_orm(string_value = "telemetry_received")]
+    TelemetryReceived,
+    #[sea_orm(string_value = "device_created")]
+    DeviceCreated,
+    #[sea_orm(string_value = "device_deleted")]
+    DeviceDeleted,
+    #[sea_orm(string_value = "schema_created")]
+    SchemaCreated,
+    #[sea_orm(string_value = "schema_deleted")]
+    SchemaDeleted,
+}
+
+#[derive(EnumIter, DeriveActiveEnum, Clone, Copy, Debug, Eq, PartialEq, Enum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+#[oai(rename_all = "snake_case")]
+pub enum WebhookDeliveryStatus {
+    #[sea_orm(string_value = "pending")]
+    Pending,
+    #[sea_orm(string_value = "succeeded")]
+    Succeeded,
+    /// 超过最大重试次数, 进入死信
+    #[sea_orm(string_value = "dead")]
+    Dead,
+}
+
+/// 数据转发的目标类型
+#[derive(EnumIter, DeriveActiveEnum, Clone, Copy, Debug, Eq, PartialEq, Enum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+#[oai(rename_all = "snake_case")]
+pub enum SinkType {
+    #[sea_orm(string_value = "pulsar")]
+    Pulsar,
+    /// 通过Kafka REST Proxy写入Kafka兼容的集群
+    #[sea_orm(string_value = "kafka")]
+    Kafka,
+    #[sea_orm(string_value = "http")]
+    Http,
+    /// 按小时滚动的本地NDJSON文件
+    #[sea_orm(string_value = "file")]
+    File,
+}
+
+/// 定时指令的下发目标
+#[derive(EnumIter, DeriveActiveEnum, Clone, Copy, Debug, Eq, PartialEq, Enum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+#[oai(rename_all = "snake_case")]
+pub enum ScheduleTarget {
+    #[sea_orm(string_value = "device")]
+    Device,
+    #[sea_orm(string_value = "label")]
+    Label,
+}
+
+/// 批量指令任务的状态
+#[derive(EnumIter, DeriveActiveEnum, Clone, Copy, Debug, Eq, PartialEq, Enum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+#[oai(rename_all = "snake_case")]
+pub enum CommandJobStatus {
+    #[sea_orm(string_value = "running")]
+    Running,
+    /// 所有设备都已回复、失败或超时
+    #[sea_orm(string_value = "completed")]
+    Completed,
+    #[sea_orm(string_value = "cancelled")]
+    Cancelled,
+}
+
+/// 批量指令任务中单台设备的状态
+#[derive(EnumIter, DeriveActiveEnum, Clone, Copy, Debug, Eq, PartialEq, Enum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+#[oai(rename_all = "snake_case")]
+pub enum CommandJobDeviceStatus {
+    #[sea_orm(string_value = "pending")]
+    Pending,
+    #[sea_orm(string_value = "sent")]
+    Sent,
+    #[sea_orm(string_value = "responded")]
+    Responded,
+    /// 指令下发失败, 如设备已停用
+    #[sea_orm(string_value = "failed")]
+    Failed,
+    /// 超时未收到设备回复
+    #[sea_orm(string_value = "timed_out")]
+    TimedOut,
+    /// 任务取消时尚未下发
+    #[sea_orm(string_value = "cancelled")]
+    Cancelled,
+}
+
+/// 升级活动的阶段
+#[derive(EnumIter, DeriveActiveEnum, Clone, Copy, Debug, Eq, PartialEq, Enum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+#[oai(rename_all = "snake_case")]
+pub enum OtaStage {
+    /// 金丝雀阶段, 只推送到部分设备
+    #[sea_orm(string_value = "canary")]
+    Canary,
+    /// 推送到所有设备
+    #[sea_orm(string_value = "full")]
+    Full,
+}
+
+/// 升级活动的状态
+#[derive(EnumIter, DeriveActiveEnum, Clone, Copy, Debug, Eq, PartialEq, Enum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+#[oai(rename_all = "snake_case")]
+pub enum OtaCampaignStatus {
+    #[sea_orm(string_value = "running")]
+    Running,
+    /// 所有设备都已升级成功或失败
+    #[sea_orm(string_value = "completed")]
+    Completed,
+    /// 手动中止或失败率超过阈值后自动中止
+    #[sea_orm(string_value = "aborted")]
+    Aborted,
+}
+
+/// 升级活动中单台设备的状态
+#[derive(EnumIter, DeriveActiveEnum, Clone, Copy, Debug, Eq, PartialEq, Enum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+#[oai(rename_all = "snake_case")]
+pub enum OtaDeviceStatus {
+    #[sea_orm(string_value = "pending")]
+    Pending,
+    /// 已推送升级通知, 等待设备上报进度
+    #[sea_orm(string_value = "notified")]
+    Notified,
+    #[sea_orm(string_value = "downloading")]
+    Downloading,
+    #[sea_orm(string_value = "installing")]
+    Installing,
+    /// 设备上报的固件版本与活动的固件版本一致
+    #[sea_orm(string_value = "succeeded")]
+    Succeeded,
+    /// 设备上报失败或超时未完成
+    #[sea_orm(string_value = "failed")]
+    Failed,
+    /// 活动中止时尚未推送
+    #[sea_orm(string_value = "skipped")]
+    Skipped,
+}
+
+/// 设备文件上传状态
+#[derive(EnumIter, DeriveActiveEnum, Clone, Copy, Debug, Eq, PartialEq, Enum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+#[oai(rename_all = "snake_case")]
+pub enum DeviceUploadStatus {
+    /// 已向设备发送上传指令, 尚未收到分片
+    #[sea_orm(string_value = "requested")]
+    Requested,
+    #[sea_orm(string_value = "receiving")]
+    Receiving,
+    /// 分片已全部到达, 正在拼接
+    #[sea_orm(string_value = "assembling")]
+    Assembling,
+    #[sea_orm(string_value = "completed")]
+    Completed,
+    #[sea_orm(string_value = "failed")]
+    Failed,
+    /// 超时未收到全部分片
+    #[sea_orm(string_value = "expired")]
+    Expired,
+}
+
+#[derive(EnumIter, DeriveActiveEnum, Clone, Debug, Eq, PartialEq, Enum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(32))")]
+pub enum AuditAction {
+    #[sea_orm(string_value = "create")]
+    Create,
+    #[sea_orm(string_value = "update")]
+    Update,
+    #[sea_orm(string_value = "delete")]
+    Delete,
+    #[sea_orm(string_value = "command")]
+    Command,
+}
+
+#[derive(EnumIter, DeriveActiveEnum, Clone, Debug, Eq, PartialEq, Enum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(32))")]
+pub enum AuditResource {
+    #[sea_orm(string_value = "account")]
+    Account,
+    #[sea_orm(string_value = "security_policy")]
+    SecurityPolicy,
+    #[sea_orm(string_value = "device")]
+    Device,
+    #[sea_orm(string_value = "label")]
+    Label,
+    #[sea_orm(string_value = "schema")]
+    Schema,
+    #[sea_orm(string_value = "field")]
+    Field,
+    #[sea_orm(string_value = "device_credential")]
+    DeviceCredential,
+    #[sea_orm(string_value = "device_certificate")]
+    DeviceCertificate,
+    #[sea_orm(string_value = "provisioning_profile")]
+    ProvisioningProfile,
+    #[sea_orm(string_value = "d2d_rule")]
+    D2dRule,
+    #[sea_orm(string_value = "rule")]
+    Rule,
+    #[sea_orm(string_value = "alarm")]
+    Alarm,
+    #[sea_orm(string_value = "webhook")]
+    Webhook,
+    #[sea_orm(string_value = "data_route")]
+    DataRoute,
+    #[sea_orm(string_value = "scheduled_command")]
+    ScheduledCommand,
+    #[sea_orm(string_value = "command_job")]
+    CommandJob,
+    #[sea_orm(string_value = "firmware")]
+    Firmware,
+    #[sea_orm(string_value = "ota_campaign")]
+    OtaCampaign,
+    #[sea_orm(string_value = "device_upload")]
+    DeviceUpload,
+    #[sea_orm(string_value = "config_profile")]
+    ConfigProfile,
+    #[sea_orm(string_value = "config_assignment")]
+    ConfigAssignment,
+}
@@ -0,0 +1,18 @@
+-- ----------------------------
+-- Table structure for login_attempts
+-- ----------------------------
+CREATE TABLE "login_attempts" (
+  "id" varchar NOT NULL,
+  "account_id" varchar,
+  "email" varchar NOT NULL,
+  "ip_address" varchar NOT NULL,
+  "success" bool NOT NULL,
+  "reason" varchar,
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  CONSTRAINT "login_attempts_pkey" PRIMARY KEY ("id"),
+  CONSTRAINT "fk_account_id" FOREIGN KEY ("account_id") REFERENCES "accounts" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+CREATE INDEX "idx_login_attempts_account" ON "login_attempts" USING btree (
+  "account_id" "text_ops" ASC NULLS LAST,
+  "created_at" DESC NULLS LAST
+);
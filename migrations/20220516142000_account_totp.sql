@@ -0,0 +1,19 @@
+-- ----------------------------
+-- TOTP two-factor authentication for accounts
+-- ----------------------------
+ALTER TABLE "accounts"
+  ADD COLUMN "totp_secret" varchar,
+  ADD COLUMN "totp_enabled" bool NOT NULL DEFAULT false,
+  ADD COLUMN "recovery_codes" jsonb NOT NULL DEFAULT '[]';
+
+-- ----------------------------
+-- Table structure for system_settings
+-- ----------------------------
+CREATE TABLE "system_settings" (
+  "key" varchar NOT NULL,
+  "value" jsonb NOT NULL,
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  "updated_at" timestamptz(6),
+  CONSTRAINT "system_settings_pkey" PRIMARY KEY ("key")
+);
+CREATE TRIGGER "on_system_settings_update" BEFORE UPDATE ON "system_settings" FOR EACH ROW EXECUTE PROCEDURE "trigger_set_timestamp"();
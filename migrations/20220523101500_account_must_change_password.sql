@@ -0,0 +1,4 @@
+-- ----------------------------
+-- Force a password change on first login
+-- ----------------------------
+ALTER TABLE "accounts" ADD COLUMN "must_change_password" bool NOT NULL DEFAULT false;
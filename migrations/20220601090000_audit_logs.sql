@@ -0,0 +1,28 @@
+-- ----------------------------
+-- Table structure for audit_logs
+-- ----------------------------
+CREATE TABLE "audit_logs" (
+  "id" varchar NOT NULL,
+  "account_id" varchar NOT NULL,
+  "actor_id" varchar NOT NULL,
+  "action" varchar NOT NULL,
+  "resource_type" varchar NOT NULL,
+  "resource_id" varchar NOT NULL,
+  "before" jsonb,
+  "after" jsonb,
+  "ip_address" varchar NOT NULL,
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  CONSTRAINT "audit_logs_pkey" PRIMARY KEY ("id")
+);
+CREATE INDEX "idx_audit_logs_account" ON "audit_logs" USING btree (
+  "account_id" "text_ops" ASC NULLS LAST,
+  "created_at" DESC NULLS LAST
+);
+CREATE INDEX "idx_audit_logs_actor" ON "audit_logs" USING btree (
+  "actor_id" "text_ops" ASC NULLS LAST,
+  "created_at" DESC NULLS LAST
+);
+CREATE INDEX "idx_audit_logs_resource" ON "audit_logs" USING btree (
+  "resource_type" "text_ops" ASC NULLS LAST,
+  "resource_id" "text_ops" ASC NULLS LAST
+);
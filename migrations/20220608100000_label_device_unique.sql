@@ -0,0 +1,12 @@
+-- ----------------------------
+-- Deduplicate labels_device_relation
+-- ----------------------------
+DELETE FROM "labels_device_relation" a
+USING "labels_device_relation" b
+WHERE a."id" > b."id"
+  AND a."label_id" = b."label_id"
+  AND a."device_id" = b."device_id";
+CREATE UNIQUE INDEX "idx_labels_device_relation_unique" ON "labels_device_relation" USING btree (
+  "label_id" "text_ops" ASC NULLS LAST,
+  "device_id" "text_ops" ASC NULLS LAST
+);
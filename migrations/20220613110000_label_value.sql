@@ -0,0 +1,9 @@
+-- ----------------------------
+-- Key/value labels
+-- ----------------------------
+ALTER TABLE "labels" ADD COLUMN "value" varchar;
+CREATE INDEX "idx_labels_account_name_value" ON "labels" USING btree (
+  "account_id" "text_ops" ASC NULLS LAST,
+  "name" "text_ops" ASC NULLS LAST,
+  "value" "text_ops" ASC NULLS LAST
+);
@@ -0,0 +1,25 @@
+-- ----------------------------
+-- Table structure for device_credentials
+-- ----------------------------
+CREATE TABLE "device_credentials" (
+  "id" varchar NOT NULL,
+  "device_id" varchar NOT NULL,
+  "name" varchar,
+  "secret_hash" varchar NOT NULL,
+  "expires_at" timestamptz(6),
+  "revoked_at" timestamptz(6),
+  "last_used_at" timestamptz(6),
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  CONSTRAINT "device_credentials_pkey" PRIMARY KEY ("id"),
+  CONSTRAINT "fk_device_id" FOREIGN KEY ("device_id") REFERENCES "devices" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+CREATE INDEX "idx_device_credentials_device" ON "device_credentials" USING btree (
+  "device_id" "text_ops" ASC NULLS LAST
+);
+
+-- 已有设备的明文密码迁移为凭据, 只保留摘要
+INSERT INTO "device_credentials" ("id", "device_id", "name", "secret_hash")
+SELECT "id" || '_legacy', "id", 'legacy', encode(sha256(convert_to("mqtt_password", 'UTF8')), 'hex')
+FROM "devices";
+
+ALTER TABLE "devices" DROP COLUMN "mqtt_password";
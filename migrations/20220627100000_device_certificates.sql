@@ -0,0 +1,38 @@
+-- ----------------------------
+-- Table structure for device_cas
+-- ----------------------------
+CREATE TABLE "device_cas" (
+  "account_id" varchar NOT NULL,
+  "cert_pem" text NOT NULL,
+  "key_pem" text NOT NULL,
+  "crl_number" int8 NOT NULL DEFAULT 1,
+  "not_after" timestamptz(6) NOT NULL,
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  CONSTRAINT "device_cas_pkey" PRIMARY KEY ("account_id"),
+  CONSTRAINT "fk_account_id" FOREIGN KEY ("account_id") REFERENCES "accounts" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+
+-- ----------------------------
+-- Table structure for device_certificates
+-- 设备删除后证书记录仍需保留在CRL中, 因此device_id不设外键
+-- ----------------------------
+CREATE TABLE "device_certificates" (
+  "serial" varchar NOT NULL,
+  "account_id" varchar NOT NULL,
+  "device_id" varchar NOT NULL,
+  "fingerprint" varchar NOT NULL,
+  "cert_pem" text NOT NULL,
+  "not_before" timestamptz(6) NOT NULL,
+  "not_after" timestamptz(6) NOT NULL,
+  "revoked_at" timestamptz(6),
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  CONSTRAINT "device_certificates_pkey" PRIMARY KEY ("serial"),
+  CONSTRAINT "fk_account_id" FOREIGN KEY ("account_id") REFERENCES "accounts" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+CREATE INDEX "idx_device_certificates_device" ON "device_certificates" USING btree (
+  "device_id" "text_ops" ASC NULLS LAST
+);
+CREATE INDEX "idx_device_certificates_account_revoked" ON "device_certificates" USING btree (
+  "account_id" "text_ops" ASC NULLS LAST,
+  "revoked_at" ASC NULLS LAST
+);
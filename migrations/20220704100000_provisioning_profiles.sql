@@ -0,0 +1,47 @@
+-- ----------------------------
+-- Table structure for provisioning_profiles
+-- ----------------------------
+CREATE TABLE "provisioning_profiles" (
+  "id" varchar NOT NULL,
+  "account_id" varchar NOT NULL,
+  "schema_id" varchar NOT NULL,
+  "name" varchar NOT NULL,
+  "claim_token_hash" varchar NOT NULL,
+  "max_devices" int4,
+  "registered_count" int4 NOT NULL DEFAULT 0,
+  "is_active" bool NOT NULL DEFAULT true,
+  "expires_at" timestamptz(6),
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  "updated_at" timestamptz(6),
+  CONSTRAINT "provisioning_profiles_pkey" PRIMARY KEY ("id"),
+  CONSTRAINT "fk_account_id" FOREIGN KEY ("account_id") REFERENCES "accounts" ("id") ON DELETE CASCADE ON UPDATE NO ACTION,
+  CONSTRAINT "fk_schema_id" FOREIGN KEY ("schema_id") REFERENCES "schemas" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+CREATE INDEX "idx_provisioning_profiles_account" ON "provisioning_profiles" USING btree (
+  "account_id" "text_ops" ASC NULLS LAST
+);
+CREATE UNIQUE INDEX "uniq_provisioning_profiles_claim_token" ON "provisioning_profiles" USING btree (
+  "claim_token_hash" "text_ops" ASC NULLS LAST
+);
+
+-- ----------------------------
+-- Table structure for provisioning_profile_labels
+-- ----------------------------
+CREATE TABLE "provisioning_profile_labels" (
+  "profile_id" varchar NOT NULL,
+  "label_id" varchar NOT NULL,
+  CONSTRAINT "provisioning_profile_labels_pkey" PRIMARY KEY ("profile_id", "label_id"),
+  CONSTRAINT "fk_profile_id" FOREIGN KEY ("profile_id") REFERENCES "provisioning_profiles" ("id") ON DELETE CASCADE ON UPDATE NO ACTION,
+  CONSTRAINT "fk_label_id" FOREIGN KEY ("label_id") REFERENCES "labels" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+
+-- ----------------------------
+-- 设备自注册信息
+-- ----------------------------
+ALTER TABLE "devices" ADD COLUMN "hardware_serial" varchar;
+ALTER TABLE "devices" ADD COLUMN "provisioning_profile_id" varchar;
+ALTER TABLE "devices" ADD CONSTRAINT "fk_provisioning_profile_id" FOREIGN KEY ("provisioning_profile_id") REFERENCES "provisioning_profiles" ("id") ON DELETE SET NULL ON UPDATE NO ACTION;
+CREATE UNIQUE INDEX "uniq_devices_hardware_serial" ON "devices" USING btree (
+  "account_id" "text_ops" ASC NULLS LAST,
+  "hardware_serial" "text_ops" ASC NULLS LAST
+) WHERE "hardware_serial" IS NOT NULL;
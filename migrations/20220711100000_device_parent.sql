@@ -0,0 +1,8 @@
+-- ----------------------------
+-- 网关与子设备: 子设备通过parent_id关联到网关(is_super_device)
+-- ----------------------------
+ALTER TABLE "devices" ADD COLUMN "parent_id" varchar;
+ALTER TABLE "devices" ADD CONSTRAINT "fk_parent_id" FOREIGN KEY ("parent_id") REFERENCES "devices" ("id") ON DELETE SET NULL ON UPDATE NO ACTION;
+CREATE INDEX "idx_devices_parent" ON "devices" USING btree (
+  "parent_id" "text_ops" ASC NULLS LAST
+);
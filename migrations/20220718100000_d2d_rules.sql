@@ -0,0 +1,58 @@
+-- ----------------------------
+-- Table structure for d2d_rules
+-- 设备间通信白名单: 发送方可以向目标设备或带有目标标签的设备发送消息
+-- ----------------------------
+CREATE TABLE "d2d_rules" (
+  "id" varchar NOT NULL,
+  "account_id" varchar NOT NULL,
+  "sender_id" varchar NOT NULL,
+  "target_device_id" varchar,
+  "target_label_id" varchar,
+  "log_messages" bool NOT NULL DEFAULT false,
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  CONSTRAINT "d2d_rules_pkey" PRIMARY KEY ("id"),
+  CONSTRAINT "fk_account_id" FOREIGN KEY ("account_id") REFERENCES "accounts" ("id") ON DELETE CASCADE ON UPDATE NO ACTION,
+  CONSTRAINT "fk_sender_id" FOREIGN KEY ("sender_id") REFERENCES "devices" ("id") ON DELETE CASCADE ON UPDATE NO ACTION,
+  CONSTRAINT "fk_target_device_id" FOREIGN KEY ("target_device_id") REFERENCES "devices" ("id") ON DELETE CASCADE ON UPDATE NO ACTION,
+  CONSTRAINT "fk_target_label_id" FOREIGN KEY ("target_label_id") REFERENCES "labels" ("id") ON DELETE CASCADE ON UPDATE NO ACTION,
+  CONSTRAINT "chk_d2d_rules_target" CHECK (("target_device_id" IS NULL) <> ("target_label_id" IS NULL))
+);
+CREATE INDEX "idx_d2d_rules_sender" ON "d2d_rules" USING btree (
+  "sender_id" "text_ops" ASC NULLS LAST
+);
+CREATE INDEX "idx_d2d_rules_target_label" ON "d2d_rules" USING btree (
+  "target_label_id" "text_ops" ASC NULLS LAST
+);
+CREATE UNIQUE INDEX "uniq_d2d_rules_target" ON "d2d_rules" USING btree (
+  "sender_id" "text_ops" ASC NULLS LAST,
+  COALESCE("target_device_id", '') ASC NULLS LAST,
+  COALESCE("target_label_id", '') ASC NULLS LAST
+);
+
+-- ----------------------------
+-- Table structure for d2d_message_logs
+-- ----------------------------
+CREATE TABLE "d2d_message_logs" (
+  "id" varchar NOT NULL,
+  "account_id" varchar NOT NULL,
+  "rule_id" varchar,
+  "sender_id" varchar NOT NULL,
+  "target" varchar NOT NULL,
+  "topic" varchar NOT NULL,
+  "payload" text NOT NULL,
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  CONSTRAINT "d2d_message_logs_pkey" PRIMARY KEY ("id"),
+  CONSTRAINT "fk_account_id" FOREIGN KEY ("account_id") REFERENCES "accounts" ("id") ON DELETE CASCADE ON UPDATE NO ACTION,
+  CONSTRAINT "fk_rule_id" FOREIGN KEY ("rule_id") REFERENCES "d2d_rules" ("id") ON DELETE SET NULL ON UPDATE NO ACTION
+);
+CREATE INDEX "idx_d2d_message_logs_account_created" ON "d2d_message_logs" USING btree (
+  "account_id" "text_ops" ASC NULLS LAST,
+  "created_at" DESC NULLS LAST
+);
+
+-- ----------------------------
+-- 收回设备原有的d2d通配发布权限, 改由白名单生成; 增加按标签接收d2d消息的订阅权限
+-- ----------------------------
+UPDATE "devices" SET
+  "acl_pubs" = "acl_pubs" - ('d2d/' || "account_id" || '/+/' || "id" || '/+'),
+  "acl_subs" = "acl_subs" || jsonb_build_array('d2l/' || "account_id" || '/+/+/+');
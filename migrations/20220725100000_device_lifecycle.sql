@@ -0,0 +1,28 @@
+-- ----------------------------
+-- 设备生命周期状态: provisioned, active, suspended, maintenance, decommissioned
+-- ----------------------------
+ALTER TABLE "devices" ADD COLUMN "lifecycle_state" varchar(32) NOT NULL DEFAULT 'active';
+ALTER TABLE "devices" ADD COLUMN "decommissioned_at" timestamptz(6);
+UPDATE "devices" SET "lifecycle_state" = 'suspended' WHERE "is_active" = false;
+CREATE INDEX "idx_devices_decommissioned_at" ON "devices" USING btree (
+  "decommissioned_at" ASC NULLS LAST
+) WHERE "decommissioned_at" IS NOT NULL;
+
+-- ----------------------------
+-- Table structure for device_lifecycle_events
+-- ----------------------------
+CREATE TABLE "device_lifecycle_events" (
+  "id" varchar NOT NULL,
+  "device_id" varchar NOT NULL,
+  "from_state" varchar(32) NOT NULL,
+  "to_state" varchar(32) NOT NULL,
+  "reason" varchar,
+  "actor" varchar NOT NULL,
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  CONSTRAINT "device_lifecycle_events_pkey" PRIMARY KEY ("id"),
+  CONSTRAINT "fk_device_id" FOREIGN KEY ("device_id") REFERENCES "devices" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+CREATE INDEX "idx_device_lifecycle_events_device" ON "device_lifecycle_events" USING btree (
+  "device_id" "text_ops" ASC NULLS LAST,
+  "created_at" DESC NULLS LAST
+);
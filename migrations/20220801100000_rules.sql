@@ -0,0 +1,68 @@
+-- ----------------------------
+-- Table structure for rules
+-- 遥测规则: 设备上报的字段满足条件并持续一段时间后执行动作
+-- ----------------------------
+CREATE TABLE "rules" (
+  "id" varchar NOT NULL,
+  "account_id" varchar NOT NULL,
+  "name" varchar NOT NULL,
+  "selector" varchar,
+  "field" varchar NOT NULL,
+  "operator" varchar(16) NOT NULL,
+  "threshold" float8 NOT NULL,
+  "duration_secs" int4 NOT NULL DEFAULT 0,
+  "action" varchar(32) NOT NULL,
+  "command" varchar,
+  "command_payload" text,
+  "webhook_url" varchar,
+  "is_active" bool NOT NULL DEFAULT true,
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  "updated_at" timestamptz(6),
+  CONSTRAINT "rules_pkey" PRIMARY KEY ("id"),
+  CONSTRAINT "fk_account_id" FOREIGN KEY ("account_id") REFERENCES "accounts" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+CREATE INDEX "idx_rules_account_field" ON "rules" USING btree (
+  "account_id" "text_ops" ASC NULLS LAST,
+  "field" "text_ops" ASC NULLS LAST
+);
+
+-- ----------------------------
+-- Table structure for rule_states
+-- 每个设备上规则的求值状态, 重启后继续计时
+-- ----------------------------
+CREATE TABLE "rule_states" (
+  "rule_id" varchar NOT NULL,
+  "device_id" varchar NOT NULL,
+  "last_value" float8 NOT NULL,
+  "matched_since" timestamptz(6),
+  "fired_at" timestamptz(6),
+  "updated_at" timestamptz(6) NOT NULL DEFAULT now(),
+  CONSTRAINT "rule_states_pkey" PRIMARY KEY ("rule_id", "device_id"),
+  CONSTRAINT "fk_rule_id" FOREIGN KEY ("rule_id") REFERENCES "rules" ("id") ON DELETE CASCADE ON UPDATE NO ACTION,
+  CONSTRAINT "fk_device_id" FOREIGN KEY ("device_id") REFERENCES "devices" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+CREATE INDEX "idx_rule_states_pending" ON "rule_states" USING btree (
+  "matched_since" ASC NULLS LAST
+) WHERE "matched_since" IS NOT NULL AND "fired_at" IS NULL;
+
+-- ----------------------------
+-- Table structure for rule_executions
+-- ----------------------------
+CREATE TABLE "rule_executions" (
+  "id" varchar NOT NULL,
+  "account_id" varchar NOT NULL,
+  "rule_id" varchar NOT NULL,
+  "device_id" varchar NOT NULL,
+  "value" float8 NOT NULL,
+  "action" varchar(32) NOT NULL,
+  "error" varchar,
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  CONSTRAINT "rule_executions_pkey" PRIMARY KEY ("id"),
+  CONSTRAINT "fk_account_id" FOREIGN KEY ("account_id") REFERENCES "accounts" ("id") ON DELETE CASCADE ON UPDATE NO ACTION,
+  CONSTRAINT "fk_rule_id" FOREIGN KEY ("rule_id") REFERENCES "rules" ("id") ON DELETE CASCADE ON UPDATE NO ACTION,
+  CONSTRAINT "fk_device_id" FOREIGN KEY ("device_id") REFERENCES "devices" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+CREATE INDEX "idx_rule_executions_rule_created" ON "rule_executions" USING btree (
+  "rule_id" "text_ops" ASC NULLS LAST,
+  "created_at" DESC NULLS LAST
+);
@@ -0,0 +1,44 @@
+-- ----------------------------
+-- Table structure for alarms
+-- 同一设备同一类型的告警在清除前只保留一条, 重复触发时累加次数
+-- ----------------------------
+CREATE TABLE "alarms" (
+  "id" varchar NOT NULL,
+  "account_id" varchar NOT NULL,
+  "device_id" varchar NOT NULL,
+  "alarm_type" varchar NOT NULL,
+  "source" varchar(32) NOT NULL,
+  "rule_id" varchar,
+  "severity" varchar(16) NOT NULL,
+  "status" varchar(16) NOT NULL DEFAULT 'active',
+  "message" varchar NOT NULL,
+  "count" int4 NOT NULL DEFAULT 1,
+  "raised_at" timestamptz(6) NOT NULL DEFAULT now(),
+  "last_raised_at" timestamptz(6) NOT NULL DEFAULT now(),
+  "escalated_at" timestamptz(6),
+  "acknowledged_at" timestamptz(6),
+  "acknowledged_by" varchar,
+  "ack_note" varchar,
+  "cleared_at" timestamptz(6),
+  "cleared_by" varchar,
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  "updated_at" timestamptz(6),
+  CONSTRAINT "alarms_pkey" PRIMARY KEY ("id"),
+  CONSTRAINT "fk_account_id" FOREIGN KEY ("account_id") REFERENCES "accounts" ("id") ON DELETE CASCADE ON UPDATE NO ACTION,
+  CONSTRAINT "fk_device_id" FOREIGN KEY ("device_id") REFERENCES "devices" ("id") ON DELETE CASCADE ON UPDATE NO ACTION,
+  CONSTRAINT "fk_rule_id" FOREIGN KEY ("rule_id") REFERENCES "rules" ("id") ON DELETE SET NULL ON UPDATE NO ACTION
+);
+CREATE UNIQUE INDEX "uniq_alarms_open" ON "alarms" USING btree (
+  "device_id" "text_ops" ASC NULLS LAST,
+  "alarm_type" "text_ops" ASC NULLS LAST
+) WHERE "status" <> 'cleared';
+CREATE INDEX "idx_alarms_account_status" ON "alarms" USING btree (
+  "account_id" "text_ops" ASC NULLS LAST,
+  "status" "text_ops" ASC NULLS LAST,
+  "severity" "text_ops" ASC NULLS LAST
+);
+
+-- ----------------------------
+-- 规则触发告警时的告警级别
+-- ----------------------------
+ALTER TABLE "rules" ADD COLUMN "alarm_severity" varchar(16) NOT NULL DEFAULT 'major';
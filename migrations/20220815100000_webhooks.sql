@@ -0,0 +1,50 @@
+-- ----------------------------
+-- Table structure for webhooks
+-- 平台事件订阅, 事件以HMAC-SHA256签名后推送到订阅地址
+-- ----------------------------
+CREATE TABLE "webhooks" (
+  "id" varchar NOT NULL,
+  "account_id" varchar NOT NULL,
+  "url" varchar NOT NULL,
+  "description" varchar,
+  "secret" varchar NOT NULL,
+  "event_types" jsonb NOT NULL DEFAULT '[]'::jsonb,
+  "is_active" bool NOT NULL DEFAULT true,
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  "updated_at" timestamptz(6),
+  CONSTRAINT "webhooks_pkey" PRIMARY KEY ("id"),
+  CONSTRAINT "fk_account_id" FOREIGN KEY ("account_id") REFERENCES "accounts" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+CREATE INDEX "idx_webhooks_account" ON "webhooks" USING btree (
+  "account_id" "text_ops" ASC NULLS LAST
+);
+
+-- ----------------------------
+-- Table structure for webhook_deliveries
+-- 投递记录, 失败后按指数退避重试, 超过最大次数后进入死信(dead)
+-- ----------------------------
+CREATE TABLE "webhook_deliveries" (
+  "id" varchar NOT NULL,
+  "account_id" varchar NOT NULL,
+  "webhook_id" varchar NOT NULL,
+  "event_type" varchar(32) NOT NULL,
+  "payload" jsonb NOT NULL,
+  "status" varchar(16) NOT NULL DEFAULT 'pending',
+  "attempts" int4 NOT NULL DEFAULT 0,
+  "next_attempt_at" timestamptz(6) NOT NULL DEFAULT now(),
+  "last_status_code" int4,
+  "last_error" varchar,
+  "delivered_at" timestamptz(6),
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  "updated_at" timestamptz(6),
+  CONSTRAINT "webhook_deliveries_pkey" PRIMARY KEY ("id"),
+  CONSTRAINT "fk_account_id" FOREIGN KEY ("account_id") REFERENCES "accounts" ("id") ON DELETE CASCADE ON UPDATE NO ACTION,
+  CONSTRAINT "fk_webhook_id" FOREIGN KEY ("webhook_id") REFERENCES "webhooks" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+CREATE INDEX "idx_webhook_deliveries_webhook_created" ON "webhook_deliveries" USING btree (
+  "webhook_id" "text_ops" ASC NULLS LAST,
+  "created_at" DESC NULLS LAST
+);
+CREATE INDEX "idx_webhook_deliveries_pending" ON "webhook_deliveries" USING btree (
+  "next_attempt_at" ASC NULLS LAST
+) WHERE "status" = 'pending';
@@ -0,0 +1,30 @@
+-- ----------------------------
+-- Table structure for data_routes
+-- 数据转发: 将校验通过的遥测数据和设备事件转发到Pulsar、Kafka REST Proxy、HTTP或本地NDJSON文件
+-- ----------------------------
+CREATE TABLE "data_routes" (
+  "id" varchar NOT NULL,
+  "account_id" varchar NOT NULL,
+  "name" varchar NOT NULL,
+  "sink_type" varchar(16) NOT NULL,
+  "endpoint" varchar,
+  "topic" varchar,
+  "selector" varchar,
+  "schema_id" varchar,
+  "event_types" jsonb NOT NULL DEFAULT '[]'::jsonb,
+  "fields" jsonb NOT NULL DEFAULT '[]'::jsonb,
+  "is_active" bool NOT NULL DEFAULT true,
+  "forwarded_count" int8 NOT NULL DEFAULT 0,
+  "error_count" int8 NOT NULL DEFAULT 0,
+  "last_forwarded_at" timestamptz(6),
+  "last_error" varchar,
+  "last_error_at" timestamptz(6),
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  "updated_at" timestamptz(6),
+  CONSTRAINT "data_routes_pkey" PRIMARY KEY ("id"),
+  CONSTRAINT "fk_account_id" FOREIGN KEY ("account_id") REFERENCES "accounts" ("id") ON DELETE CASCADE ON UPDATE NO ACTION,
+  CONSTRAINT "fk_schema_id" FOREIGN KEY ("schema_id") REFERENCES "schemas" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+CREATE INDEX "idx_data_routes_account" ON "data_routes" USING btree (
+  "account_id" "text_ops" ASC NULLS LAST
+);
@@ -0,0 +1,50 @@
+-- ----------------------------
+-- Table structure for scheduled_commands
+-- 定时指令: 按cron表达式和时区向设备或标签下发指令
+-- ----------------------------
+CREATE TABLE "scheduled_commands" (
+  "id" varchar NOT NULL,
+  "account_id" varchar NOT NULL,
+  "name" varchar NOT NULL,
+  "target_type" varchar(16) NOT NULL,
+  "target_id" varchar NOT NULL,
+  "command" varchar NOT NULL,
+  "payload" text NOT NULL DEFAULT '',
+  "qos" int2 NOT NULL DEFAULT 1,
+  "ttl" int4,
+  "cron" varchar NOT NULL,
+  "timezone" varchar NOT NULL DEFAULT 'UTC',
+  "is_active" bool NOT NULL DEFAULT true,
+  "next_run_at" timestamptz(6),
+  "last_run_at" timestamptz(6),
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  "updated_at" timestamptz(6),
+  CONSTRAINT "scheduled_commands_pkey" PRIMARY KEY ("id"),
+  CONSTRAINT "fk_account_id" FOREIGN KEY ("account_id") REFERENCES "accounts" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+CREATE INDEX "idx_scheduled_commands_account" ON "scheduled_commands" USING btree (
+  "account_id" "text_ops" ASC NULLS LAST
+);
+CREATE INDEX "idx_scheduled_commands_next_run" ON "scheduled_commands" USING btree (
+  "next_run_at" ASC NULLS LAST
+) WHERE "is_active";
+
+-- ----------------------------
+-- Table structure for scheduled_command_runs
+-- 定时指令的每次执行结果, 下发到设备的指令同时记录在command_request_logs中
+-- ----------------------------
+CREATE TABLE "scheduled_command_runs" (
+  "id" varchar NOT NULL,
+  "schedule_id" varchar NOT NULL,
+  "account_id" varchar NOT NULL,
+  "scheduled_at" timestamptz(6) NOT NULL,
+  "message_id" varchar,
+  "error" varchar,
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  CONSTRAINT "scheduled_command_runs_pkey" PRIMARY KEY ("id"),
+  CONSTRAINT "fk_schedule_id" FOREIGN KEY ("schedule_id") REFERENCES "scheduled_commands" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+CREATE INDEX "idx_scheduled_command_runs_schedule_created" ON "scheduled_command_runs" USING btree (
+  "schedule_id" "text_ops" ASC NULLS LAST,
+  "created_at" DESC NULLS LAST
+);
@@ -0,0 +1,59 @@
+-- ----------------------------
+-- Table structure for command_jobs
+-- 批量指令任务: 将标签或设备列表展开为设备, 按速率逐个下发可追踪的s2d指令
+-- ----------------------------
+CREATE TABLE "command_jobs" (
+  "id" varchar NOT NULL,
+  "account_id" varchar NOT NULL,
+  "label_id" varchar,
+  "command" varchar NOT NULL,
+  "payload" text NOT NULL DEFAULT '',
+  "qos" int2 NOT NULL DEFAULT 1,
+  "ttl" int4,
+  "rate_limit" int4 NOT NULL DEFAULT 10,
+  "timeout_secs" int4 NOT NULL DEFAULT 60,
+  "status" varchar(16) NOT NULL DEFAULT 'running',
+  "total" int4 NOT NULL DEFAULT 0,
+  "created_by" varchar NOT NULL,
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  "updated_at" timestamptz(6),
+  "finished_at" timestamptz(6),
+  CONSTRAINT "command_jobs_pkey" PRIMARY KEY ("id"),
+  CONSTRAINT "fk_account_id" FOREIGN KEY ("account_id") REFERENCES "accounts" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+CREATE INDEX "idx_command_jobs_account_created" ON "command_jobs" USING btree (
+  "account_id" "text_ops" ASC NULLS LAST,
+  "created_at" DESC NULLS LAST
+);
+CREATE INDEX "idx_command_jobs_running" ON "command_jobs" USING btree (
+  "created_at" ASC NULLS LAST
+) WHERE "status" = 'running';
+
+-- ----------------------------
+-- Table structure for command_job_devices
+-- 批量指令任务中每台设备的进度: pending -> sent -> responded/timed_out, 下发失败为failed
+-- ----------------------------
+CREATE TABLE "command_job_devices" (
+  "id" varchar NOT NULL,
+  "job_id" varchar NOT NULL,
+  "device_id" varchar NOT NULL,
+  "status" varchar(16) NOT NULL DEFAULT 'pending',
+  "message_id" varchar,
+  "attempts" int4 NOT NULL DEFAULT 0,
+  "error" varchar,
+  "response" text,
+  "sent_at" timestamptz(6),
+  "responded_at" timestamptz(6),
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  "updated_at" timestamptz(6),
+  CONSTRAINT "command_job_devices_pkey" PRIMARY KEY ("id"),
+  CONSTRAINT "fk_job_id" FOREIGN KEY ("job_id") REFERENCES "command_jobs" ("id") ON DELETE CASCADE ON UPDATE NO ACTION,
+  CONSTRAINT "fk_device_id" FOREIGN KEY ("device_id") REFERENCES "devices" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+CREATE UNIQUE INDEX "idx_command_job_devices_job_device" ON "command_job_devices" USING btree (
+  "job_id" "text_ops" ASC NULLS LAST,
+  "device_id" "text_ops" ASC NULLS LAST
+);
+CREATE INDEX "idx_command_job_devices_message" ON "command_job_devices" USING btree (
+  "message_id" "text_ops" ASC NULLS LAST
+);
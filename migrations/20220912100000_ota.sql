@@ -0,0 +1,119 @@
+-- ----------------------------
+-- 设备上报的固件版本
+-- ----------------------------
+ALTER TABLE "devices" ADD COLUMN "firmware_version" varchar;
+ALTER TABLE "devices" ADD COLUMN "firmware_reported_at" timestamptz(6);
+
+-- ----------------------------
+-- Table structure for firmwares
+-- 固件文件保存在blob存储中, 数据库只记录元数据
+-- ----------------------------
+CREATE TABLE "firmwares" (
+  "id" varchar NOT NULL,
+  "account_id" varchar NOT NULL,
+  "schema_id" varchar NOT NULL,
+  "version" varchar NOT NULL,
+  "checksum" varchar(64) NOT NULL,
+  "size" int8 NOT NULL,
+  "blob_key" varchar NOT NULL,
+  "filename" varchar NOT NULL,
+  "description" varchar NOT NULL DEFAULT '',
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  CONSTRAINT "firmwares_pkey" PRIMARY KEY ("id"),
+  CONSTRAINT "fk_account_id" FOREIGN KEY ("account_id") REFERENCES "accounts" ("id") ON DELETE CASCADE ON UPDATE NO ACTION,
+  CONSTRAINT "fk_schema_id" FOREIGN KEY ("schema_id") REFERENCES "schemas" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+CREATE UNIQUE INDEX "idx_firmwares_schema_version" ON "firmwares" USING btree (
+  "account_id" "text_ops" ASC NULLS LAST,
+  "schema_id" "text_ops" ASC NULLS LAST,
+  "version" "text_ops" ASC NULLS LAST
+);
+
+-- ----------------------------
+-- Table structure for ota_campaigns
+-- 升级活动: 先向金丝雀比例的设备推送, 金丝雀阶段结束后推送到其余设备,
+-- 失败率超过阈值时自动中止
+-- ----------------------------
+CREATE TABLE "ota_campaigns" (
+  "id" varchar NOT NULL,
+  "account_id" varchar NOT NULL,
+  "firmware_id" varchar NOT NULL,
+  "name" varchar NOT NULL,
+  "label_ids" jsonb NOT NULL DEFAULT '[]',
+  "canary_percent" int2 NOT NULL DEFAULT 10,
+  "failure_threshold" int2 NOT NULL DEFAULT 20,
+  "min_samples" int4 NOT NULL DEFAULT 5,
+  "auto_promote" bool NOT NULL DEFAULT false,
+  "rate_limit" int4 NOT NULL DEFAULT 10,
+  "timeout_secs" int4 NOT NULL DEFAULT 3600,
+  "stage" varchar(16) NOT NULL DEFAULT 'canary',
+  "status" varchar(16) NOT NULL DEFAULT 'running',
+  "total" int4 NOT NULL DEFAULT 0,
+  "abort_reason" varchar,
+  "created_by" varchar NOT NULL,
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  "updated_at" timestamptz(6),
+  "finished_at" timestamptz(6),
+  CONSTRAINT "ota_campaigns_pkey" PRIMARY KEY ("id"),
+  CONSTRAINT "fk_account_id" FOREIGN KEY ("account_id") REFERENCES "accounts" ("id") ON DELETE CASCADE ON UPDATE NO ACTION,
+  CONSTRAINT "fk_firmware_id" FOREIGN KEY ("firmware_id") REFERENCES "firmwares" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+CREATE INDEX "idx_ota_campaigns_account_created" ON "ota_campaigns" USING btree (
+  "account_id" "text_ops" ASC NULLS LAST,
+  "created_at" DESC NULLS LAST
+);
+CREATE INDEX "idx_ota_campaigns_running" ON "ota_campaigns" USING btree (
+  "created_at" ASC NULLS LAST
+) WHERE "status" = 'running';
+
+-- ----------------------------
+-- Table structure for ota_campaign_devices
+-- 升级活动中每台设备的进度: pending -> notified -> downloading -> installing -> succeeded/failed,
+-- 活动中止时尚未推送的设备为skipped
+-- ----------------------------
+CREATE TABLE "ota_campaign_devices" (
+  "id" varchar NOT NULL,
+  "campaign_id" varchar NOT NULL,
+  "device_id" varchar NOT NULL,
+  "stage" varchar(16) NOT NULL,
+  "status" varchar(16) NOT NULL DEFAULT 'pending',
+  "progress" int2 NOT NULL DEFAULT 0,
+  "from_version" varchar,
+  "error" varchar,
+  "notified_at" timestamptz(6),
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  "updated_at" timestamptz(6),
+  CONSTRAINT "ota_campaign_devices_pkey" PRIMARY KEY ("id"),
+  CONSTRAINT "fk_campaign_id" FOREIGN KEY ("campaign_id") REFERENCES "ota_campaigns" ("id") ON DELETE CASCADE ON UPDATE NO ACTION,
+  CONSTRAINT "fk_device_id" FOREIGN KEY ("device_id") REFERENCES "devices" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+CREATE UNIQUE INDEX "idx_ota_campaign_devices_campaign_device" ON "ota_campaign_devices" USING btree (
+  "campaign_id" "text_ops" ASC NULLS LAST,
+  "device_id" "text_ops" ASC NULLS LAST
+);
+CREATE INDEX "idx_ota_campaign_devices_device" ON "ota_campaign_devices" USING btree (
+  "device_id" "text_ops" ASC NULLS LAST,
+  "status" "text_ops" ASC NULLS LAST
+);
+
+-- ----------------------------
+-- 增加接收升级通知的订阅权限和上报固件版本、升级进度的发布权限
+-- ----------------------------
+UPDATE "devices" SET
+  "acl_pubs" = "acl_pubs" || jsonb_build_array('otar/' || "account_id" || '/' || "id" || '/+'),
+  "acl_subs" = "acl_subs" || jsonb_build_array('ota/' || "account_id" || '/' || "id" || '/notify');
+
+-- 网关代子设备升级
+UPDATE "devices" AS "gateway" SET
+  "acl_pubs" = "gateway"."acl_pubs" || "proxied"."pubs",
+  "acl_subs" = "gateway"."acl_subs" || "proxied"."subs"
+FROM (
+  SELECT
+    "parent_id",
+    jsonb_agg('otar/' || "account_id" || '/' || "id" || '/+') AS "pubs",
+    jsonb_agg('ota/' || "account_id" || '/' || "id" || '/notify') AS "subs"
+  FROM "devices"
+  WHERE "parent_id" IS NOT NULL
+  GROUP BY "parent_id"
+) AS "proxied"
+WHERE "gateway"."id" = "proxied"."parent_id";
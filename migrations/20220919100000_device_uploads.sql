@@ -0,0 +1,67 @@
+-- ----------------------------
+-- Table structure for device_uploads
+-- 设备文件上传: 平台通过指令请求设备上传日志等文件, 设备经MQTT或HTTP分片上传,
+-- 分片全部到达后拼接为完整文件写入blob存储
+-- requested -> receiving -> assembling -> completed, 超时未完成为expired
+-- ----------------------------
+CREATE TABLE "device_uploads" (
+  "id" varchar NOT NULL,
+  "account_id" varchar NOT NULL,
+  "device_id" varchar NOT NULL,
+  "kind" varchar NOT NULL DEFAULT 'log',
+  "status" varchar(16) NOT NULL DEFAULT 'requested',
+  "message_id" varchar,
+  "total_chunks" int4,
+  "received_chunks" int4 NOT NULL DEFAULT 0,
+  "size" int8,
+  "checksum" varchar(64),
+  "blob_key" varchar,
+  "error" varchar,
+  "requested_by" varchar NOT NULL,
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  "updated_at" timestamptz(6),
+  "completed_at" timestamptz(6),
+  "expires_at" timestamptz(6) NOT NULL,
+  CONSTRAINT "device_uploads_pkey" PRIMARY KEY ("id"),
+  CONSTRAINT "fk_account_id" FOREIGN KEY ("account_id") REFERENCES "accounts" ("id") ON DELETE CASCADE ON UPDATE NO ACTION,
+  CONSTRAINT "fk_device_id" FOREIGN KEY ("device_id") REFERENCES "devices" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+CREATE INDEX "idx_device_uploads_device_created" ON "device_uploads" USING btree (
+  "device_id" "text_ops" ASC NULLS LAST,
+  "created_at" DESC NULLS LAST
+);
+CREATE INDEX "idx_device_uploads_unfinished" ON "device_uploads" USING btree (
+  "expires_at" ASC NULLS LAST
+) WHERE "status" IN ('requested', 'receiving');
+
+-- ----------------------------
+-- Table structure for device_upload_chunks
+-- 已收到的分片, 重复上传同一分片时覆盖
+-- ----------------------------
+CREATE TABLE "device_upload_chunks" (
+  "upload_id" varchar NOT NULL,
+  "seq" int4 NOT NULL,
+  "size" int4 NOT NULL,
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  CONSTRAINT "device_upload_chunks_pkey" PRIMARY KEY ("upload_id", "seq"),
+  CONSTRAINT "fk_upload_id" FOREIGN KEY ("upload_id") REFERENCES "device_uploads" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+
+-- ----------------------------
+-- 增加经MQTT分片上传文件的发布权限
+-- ----------------------------
+UPDATE "devices" SET
+  "acl_pubs" = "acl_pubs" || jsonb_build_array('upload/' || "account_id" || '/' || "id" || '/+/+/+');
+
+-- 网关代子设备上传
+UPDATE "devices" AS "gateway" SET
+  "acl_pubs" = "gateway"."acl_pubs" || "proxied"."pubs"
+FROM (
+  SELECT
+    "parent_id",
+    jsonb_agg('upload/' || "account_id" || '/' || "id" || '/+/+/+') AS "pubs"
+  FROM "devices"
+  WHERE "parent_id" IS NOT NULL
+  GROUP BY "parent_id"
+) AS "proxied"
+WHERE "gateway"."id" = "proxied"."parent_id";
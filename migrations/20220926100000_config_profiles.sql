@@ -0,0 +1,110 @@
+-- ----------------------------
+-- 设备的生效配置需要重新计算, 标签关系、配置分配或配置模板变化时置位
+-- ----------------------------
+ALTER TABLE "devices" ADD COLUMN "config_stale" bool NOT NULL DEFAULT false;
+CREATE INDEX "idx_devices_config_stale" ON "devices" USING btree (
+  "id" "text_ops" ASC NULLS LAST
+) WHERE "config_stale";
+
+-- ----------------------------
+-- Table structure for config_profiles
+-- 配置模板, 每次修改配置文档生成新版本, 文档须符合模板的JSON Schema
+-- ----------------------------
+CREATE TABLE "config_profiles" (
+  "id" varchar NOT NULL,
+  "account_id" varchar NOT NULL,
+  "name" varchar NOT NULL,
+  "description" varchar NOT NULL DEFAULT '',
+  "schema" jsonb NOT NULL DEFAULT '{}',
+  "version" int4 NOT NULL DEFAULT 1,
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  "updated_at" timestamptz(6),
+  CONSTRAINT "config_profiles_pkey" PRIMARY KEY ("id"),
+  CONSTRAINT "fk_account_id" FOREIGN KEY ("account_id") REFERENCES "accounts" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+CREATE UNIQUE INDEX "idx_config_profiles_name" ON "config_profiles" USING btree (
+  "account_id" "text_ops" ASC NULLS LAST,
+  "name" "text_ops" ASC NULLS LAST
+);
+
+-- ----------------------------
+-- Table structure for config_profile_versions
+-- ----------------------------
+CREATE TABLE "config_profile_versions" (
+  "profile_id" varchar NOT NULL,
+  "version" int4 NOT NULL,
+  "document" jsonb NOT NULL,
+  "created_by" varchar NOT NULL,
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  CONSTRAINT "config_profile_versions_pkey" PRIMARY KEY ("profile_id", "version"),
+  CONSTRAINT "fk_profile_id" FOREIGN KEY ("profile_id") REFERENCES "config_profiles" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+
+-- ----------------------------
+-- Table structure for config_assignments
+-- 配置模板分配到标签或单个设备, 设备的生效配置依次合并标签级和设备级的文档,
+-- 同一级别内按优先级从低到高合并
+-- ----------------------------
+CREATE TABLE "config_assignments" (
+  "id" varchar NOT NULL,
+  "account_id" varchar NOT NULL,
+  "profile_id" varchar NOT NULL,
+  "label_id" varchar,
+  "device_id" varchar,
+  "priority" int4 NOT NULL DEFAULT 0,
+  "created_at" timestamptz(6) NOT NULL DEFAULT now(),
+  CONSTRAINT "config_assignments_pkey" PRIMARY KEY ("id"),
+  CONSTRAINT "config_assignments_target" CHECK (("label_id" IS NULL) <> ("device_id" IS NULL)),
+  CONSTRAINT "fk_account_id" FOREIGN KEY ("account_id") REFERENCES "accounts" ("id") ON DELETE CASCADE ON UPDATE NO ACTION,
+  CONSTRAINT "fk_profile_id" FOREIGN KEY ("profile_id") REFERENCES "config_profiles" ("id") ON DELETE CASCADE ON UPDATE NO ACTION,
+  CONSTRAINT "fk_label_id" FOREIGN KEY ("label_id") REFERENCES "labels" ("id") ON DELETE CASCADE ON UPDATE NO ACTION,
+  CONSTRAINT "fk_device_id" FOREIGN KEY ("device_id") REFERENCES "devices" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+CREATE UNIQUE INDEX "idx_config_assignments_label" ON "config_assignments" USING btree (
+  "profile_id" "text_ops" ASC NULLS LAST,
+  "label_id" "text_ops" ASC NULLS LAST
+) WHERE "label_id" IS NOT NULL;
+CREATE UNIQUE INDEX "idx_config_assignments_device" ON "config_assignments" USING btree (
+  "profile_id" "text_ops" ASC NULLS LAST,
+  "device_id" "text_ops" ASC NULLS LAST
+) WHERE "device_id" IS NOT NULL;
+
+-- ----------------------------
+-- Table structure for device_configs
+-- 设备的生效配置及推送、应用情况, 版本号为配置文档的摘要
+-- ----------------------------
+CREATE TABLE "device_configs" (
+  "device_id" varchar NOT NULL,
+  "version" varchar(16) NOT NULL,
+  "document" jsonb NOT NULL,
+  "pushed_version" varchar(16),
+  "pushed_at" timestamptz(6),
+  "applied_version" varchar(16),
+  "applied_at" timestamptz(6),
+  "apply_error" varchar,
+  "updated_at" timestamptz(6) NOT NULL DEFAULT now(),
+  CONSTRAINT "device_configs_pkey" PRIMARY KEY ("device_id"),
+  CONSTRAINT "fk_device_id" FOREIGN KEY ("device_id") REFERENCES "devices" ("id") ON DELETE CASCADE ON UPDATE NO ACTION
+);
+
+-- ----------------------------
+-- 增加接收配置推送和回复配置应用结果的权限
+-- ----------------------------
+UPDATE "devices" SET
+  "acl_pubs" = "acl_pubs" || jsonb_build_array('cfgr/' || "account_id" || '/' || "id" || '/applied'),
+  "acl_subs" = "acl_subs" || jsonb_build_array('cfg/' || "account_id" || '/' || "id" || '/push');
+
+-- 网关代子设备接收配置和回复
+UPDATE "devices" AS "gateway" SET
+  "acl_pubs" = "gateway"."acl_pubs" || "proxied"."pubs",
+  "acl_subs" = "gateway"."acl_subs" || "proxied"."subs"
+FROM (
+  SELECT
+    "parent_id",
+    jsonb_agg('cfgr/' || "account_id" || '/' || "id" || '/applied') AS "pubs",
+    jsonb_agg('cfg/' || "account_id" || '/' || "id" || '/push') AS "subs"
+  FROM "devices"
+  WHERE "parent_id" IS NOT NULL
+  GROUP BY "parent_id"
+) AS "proxied"
+WHERE "gateway"."id" = "proxied"."parent_id";
@@ -0,0 +1,15 @@
+-- ----------------------------
+-- 收回设备按标签接收消息的通配订阅权限, 改为只订阅设备所带标签的主题
+-- ----------------------------
+UPDATE "devices" d SET
+  "acl_subs" = (d."acl_subs" - ('d2l/' || d."account_id" || '/+/+/+')) || COALESCE((
+    SELECT jsonb_agg(
+      'd2l/' || d."account_id" || '/' ||
+      CASE WHEN l."value" IS NULL THEN l."name" ELSE l."name" || '=' || l."value" END ||
+      '/+/+'
+      ORDER BY l."id"
+    )
+    FROM "labels_device_relation" r
+    JOIN "labels" l ON l."id" = r."label_id"
+    WHERE r."device_id" = d."id"
+  ), '[]'::jsonb);
@@ -0,0 +1,9 @@
+-- ----------------------------
+-- 指令记录也记录按标签下发和下发失败的指令(如定时指令的每次执行):
+-- 按标签下发时记录label_id, 下发失败时记录错误原因
+-- ----------------------------
+ALTER TABLE "command_request_logs" ALTER COLUMN "device_id" DROP NOT NULL;
+ALTER TABLE "command_request_logs" ADD COLUMN "label_id" varchar;
+ALTER TABLE "command_request_logs" ADD COLUMN "error" varchar;
+ALTER TABLE "command_request_logs" ADD CONSTRAINT "fk_label_id" FOREIGN KEY ("label_id") REFERENCES "labels" ("id") ON DELETE CASCADE ON UPDATE NO ACTION;
+ALTER TABLE "command_request_logs" ADD CONSTRAINT "chk_command_request_logs_target" CHECK (("device_id" IS NULL) <> ("label_id" IS NULL));
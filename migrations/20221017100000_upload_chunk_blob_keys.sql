@@ -0,0 +1,10 @@
+-- ----------------------------
+-- 分片每次写入使用新的blob键, 分片记录中保存被采用的键,
+-- 迟到或重复的分片不会覆盖正在拼接的分片; 已有分片沿用原来按序号生成的键
+-- ----------------------------
+ALTER TABLE "device_upload_chunks" ADD COLUMN "blob_key" varchar;
+UPDATE "device_upload_chunks" AS "chunk" SET
+  "blob_key" = 'upload/' || "upload"."account_id" || '/' || "upload"."id" || '/chunk/' || "chunk"."seq"
+FROM "device_uploads" AS "upload"
+WHERE "upload"."id" = "chunk"."upload_id";
+ALTER TABLE "device_upload_chunks" ALTER COLUMN "blob_key" SET NOT NULL;
@@ -0,0 +1,4 @@
+-- ----------------------------
+-- 记录账号最后一次修改密码的时间, 修改或重置密码前签发的Token随之失效
+-- ----------------------------
+ALTER TABLE "accounts" ADD COLUMN "password_changed_at" timestamptz(6);
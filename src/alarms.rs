@@ -0,0 +1,82 @@
+//! 设备告警
+use entity::alarms::{AlarmSeverity, AlarmSource};
+
+/// 设备离线告警的类型
+pub const DEVICE_OFFLINE: &str = "device_offline";
+
+/// 一条待触发的告警
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewAlarm {
+    pub account_id: String,
+    pub device_id: String,
+    /// 告警类型, 同一设备同一类型的告警在清除前只保留一条
+    pub alarm_type: String,
+    pub source: AlarmSource,
+    pub rule_id: Option<String>,
+    pub severity: AlarmSeverity,
+    pub message: String,
+}
+
+impl NewAlarm {
+    pub fn new(
+        account_id: &str,
+        device_id: &str,
+        alarm_type: &str,
+        source: AlarmSource,
+        severity: AlarmSeverity,
+        message: &str,
+    ) -> Self {
+        Self {
+            account_id: account_id.to_string(),
+            device_id: device_id.to_string(),
+            alarm_type: alarm_type.to_string(),
+            source,
+            rule_id: None,
+            severity,
+            message: message.to_string(),
+        }
+    }
+
+    /// 由规则触发的告警
+    pub fn rule(mut self, rule_id: &str) -> Self {
+        self.rule_id = Some(rule_id.to_string());
+        self
+    }
+}
+
+/// 规则触发的告警类型
+pub fn rule_alarm_type(rule_id: &str) -> String {
+    format!("rule:{}", rule_id)
+}
+
+/// 字段校验失败的告警类型
+pub fn schema_alarm_type(field: &str) -> String {
+    format!("schema_validation:{}", field)
+}
+
+/// 升级后的告警级别, 已是最高级别时返回None
+pub fn escalate(severity: AlarmSeverity) -> Option<AlarmSeverity> {
+    match severity {
+        AlarmSeverity::Warning => Some(AlarmSeverity::Minor),
+        AlarmSeverity::Minor => Some(AlarmSeverity::Major),
+        AlarmSeverity::Major => Some(AlarmSeverity::Critical),
+        AlarmSeverity::Critical => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escalate() {
+        assert_eq!(escalate(AlarmSeverity::Warning), Some(AlarmSeverity::Minor));
+        assert_eq!(
+            escalate(AlarmSeverity::Major),
+            Some(AlarmSeverity::Critical)
+        );
+        assert_eq!(escalate(AlarmSeverity::Critical), None);
+        assert!(AlarmSeverity::Critical > AlarmSeverity::Major);
+        assert!(AlarmSeverity::Warning < AlarmSeverity::Minor);
+    }
+}
@@ -0,0 +1,140 @@
+//! 操作审计
+use chrono::{DateTime, Local};
+use entity::audit_logs::{AuditAction, AuditResource};
+use poem_openapi::types::ToJSON;
+use serde_json::{Map, Value};
+
+/// 一条待写入的审计记录
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditLog {
+    /// 资源所属账号
+    pub account_id: String,
+    /// 操作人
+    pub actor_id: String,
+    pub action: AuditAction,
+    pub resource_type: AuditResource,
+    pub resource_id: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub ip_address: String,
+}
+
+impl AuditLog {
+    /// 默认资源属于操作人自己的账号
+    pub fn new(
+        actor_id: &str,
+        ip_address: &str,
+        action: AuditAction,
+        resource_type: AuditResource,
+        resource_id: &str,
+    ) -> Self {
+        Self {
+            account_id: actor_id.to_string(),
+            actor_id: actor_id.to_string(),
+            action,
+            resource_type,
+            resource_id: resource_id.to_string(),
+            before: None,
+            after: None,
+            ip_address: ip_address.to_string(),
+        }
+    }
+
+    /// 指定资源所属账号，用于管理员操作其他账号
+    pub fn account(mut self, account_id: &str) -> Self {
+        self.account_id = account_id.to_string();
+        self
+    }
+
+    pub fn before<T: ToJSON>(mut self, before: &T) -> Self {
+        self.before = before.to_json();
+        self
+    }
+
+    pub fn after<T: ToJSON>(mut self, after: &T) -> Self {
+        self.after = after.to_json();
+        self
+    }
+
+    /// 附加不对应资源快照的信息，如下发的指令内容
+    pub fn detail(mut self, detail: Value) -> Self {
+        self.after = Some(detail);
+        self
+    }
+
+    /// 更新操作只保留发生变化的字段
+    pub fn changes(&self) -> (Option<Value>, Option<Value>) {
+        match (&self.before, &self.after) {
+            (Some(before), Some(after)) => {
+                let (before, after) = diff(before, after);
+                (Some(before), Some(after))
+            }
+            (before, after) => (before.clone(), after.clone()),
+        }
+    }
+}
+
+/// 审计记录查询条件
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditLogQuery {
+    /// 为空时查询所有账号
+    pub account_id: Option<String>,
+    pub actor_id: Option<String>,
+    pub action: Option<AuditAction>,
+    pub resource_type: Option<AuditResource>,
+    pub resource_id: Option<String>,
+    pub created_after: Option<DateTime<Local>>,
+    pub created_before: Option<DateTime<Local>>,
+}
+
+/// 比较两个JSON对象，返回变化字段的旧值和新值
+pub fn diff(before: &Value, after: &Value) -> (Value, Value) {
+    let (before, after) = match (before, after) {
+        (Value::Object(before), Value::Object(after)) => (before, after),
+        _ if before == after => return (Value::Object(Map::new()), Value::Object(Map::new())),
+        _ => return (before.clone(), after.clone()),
+    };
+    let mut old = Map::new();
+    let mut new = Map::new();
+    for (key, value) in before {
+        match after.get(key) {
+            Some(v) if v == value => {}
+            Some(v) => {
+                old.insert(key.clone(), value.clone());
+                new.insert(key.clone(), v.clone());
+            }
+            None => {
+                old.insert(key.clone(), value.clone());
+                new.insert(key.clone(), Value::Null);
+            }
+        }
+    }
+    for (key, value) in after {
+        if !before.contains_key(key) {
+            old.insert(key.clone(), Value::Null);
+            new.insert(key.clone(), value.clone());
+        }
+    }
+    (Value::Object(old), Value::Object(new))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff() {
+        let before = json!({"id": "1", "name": "a", "labels": ["x"], "unit": null});
+        let after = json!({"id": "1", "name": "b", "labels": ["x", "y"], "comment": "c"});
+        assert_eq!(
+            diff(&before, &after),
+            (
+                json!({"name": "a", "labels": ["x"], "unit": null, "comment": null}),
+                json!({"name": "b", "labels": ["x", "y"], "unit": null, "comment": "c"}),
+            )
+        );
+        assert_eq!(diff(&json!(1), &json!(1)), (json!({}), json!({})));
+        assert_eq!(diff(&json!(1), &json!(2)), (json!(1), json!(2)));
+    }
+}
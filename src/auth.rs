@@ -1,8 +1,18 @@
+use entity::prelude::{AccountModel, DeviceModel};
 use jwt_simple::prelude::*;
 use poem::Request;
-use poem_openapi::{auth::Bearer, SecurityScheme};
+use poem_openapi::{
+    auth::{ApiKey, Basic, Bearer},
+    SecurityScheme,
+};
 
-use crate::config::SETTINGS;
+use crate::{
+    config::SETTINGS,
+    credential::constant_time_eq,
+    errors::{NeoiotError, Result},
+    repository::Repository,
+    service::AppState,
+};
 
 /// ApiKey authorization
 #[derive(SecurityScheme)]
@@ -14,10 +24,137 @@ use crate::config::SETTINGS;
 )]
 pub struct JWTAuthorization(pub String);
 
-async fn api_checker(_: &Request, api_key: Bearer) -> Option<String> {
+async fn api_checker(req: &Request, api_key: Bearer) -> Option<String> {
+    let state = req.data::<AppState>()?;
+    verify_token(&state.repo, &api_key.token).await
+}
+
+/// Token中的自定义声明
+#[derive(Serialize, Deserialize)]
+struct AccountClaims {
+    /// 签发时账号最后一次修改密码的时间(毫秒), 之后修改或重置密码时Token失效
+    #[serde(default)]
+    pwd: Option<i64>,
+}
+
+fn password_version(account: &AccountModel) -> Option<i64> {
+    account.password_changed_at.map(|at| at.timestamp_millis())
+}
+
+/// 为账号签发Token
+pub fn issue_token(account: &AccountModel) -> Result<String> {
+    sign_token(
+        &HS256Key::from_bytes(SETTINGS.core.secret.as_bytes()),
+        account,
+    )
+}
+
+fn sign_token(key: &HS256Key, account: &AccountModel) -> Result<String> {
+    let custom = AccountClaims {
+        pwd: password_version(account),
+    };
+    let claims =
+        Claims::with_custom_claims(custom, Duration::from_days(1)).with_subject(account.id.clone());
+    key.authenticate(claims)
+        .map_err(|_| NeoiotError::AuthenticateError)
+}
+
+// Token签发给该账号, 且签发之后没有修改过密码
+fn is_current_token(claims: &JWTClaims<AccountClaims>, account: &AccountModel) -> bool {
+    claims.subject.as_deref() == Some(account.id.as_str())
+        && claims.custom.pwd == password_version(account)
+}
+
+/// 校验JWT并返回其中的账号ID, 账号已删除或签发后修改过密码时校验失败;
+/// 也供无法设置请求头的WebSocket连接使用
+pub async fn verify_token<R: Repository>(repo: &R, token: &str) -> Option<String> {
     let key = HS256Key::from_bytes(SETTINGS.core.secret.as_bytes());
-    let ret = key
-        .verify_token::<JWTClaims<NoCustomClaims>>(api_key.token.as_str(), None)
-        .ok()?;
-    ret.subject
+    let claims = key.verify_token::<AccountClaims>(token, None).ok()?;
+    match repo.get_account(claims.subject.as_deref()?).await {
+        Ok(account) if is_current_token(&claims, &account) => Some(account.id),
+        Ok(_) | Err(NeoiotError::ObjectNotFound(_)) => None,
+        Err(err) => {
+            tracing::error!(?err, "failed to verify token");
+            None
+        }
+    }
+}
+
+/// EMQX Hook authorization
+#[derive(SecurityScheme)]
+#[oai(
+    type = "api_key",
+    key_name = "X-Hook-Token",
+    in = "header",
+    checker = "hook_checker"
+)]
+pub struct HookAuthorization(());
+
+async fn hook_checker(_: &Request, api_key: ApiKey) -> Option<()> {
+    let token = SETTINGS.emqx.hook_token()?;
+    constant_time_eq(token.as_bytes(), api_key.key.as_bytes()).then_some(())
+}
+
+/// 设备凭据认证, 用户名和密码与连接MQTT时相同, 供设备直接调用的接口使用
+#[derive(SecurityScheme)]
+#[oai(type = "basic", checker = "device_checker")]
+pub struct DeviceAuthorization(pub DeviceModel);
+
+async fn device_checker(req: &Request, basic: Basic) -> Option<DeviceModel> {
+    let state = req.data::<AppState>()?;
+    match state
+        .repo
+        .authenticate_device(&basic.username, &basic.password)
+        .await
+    {
+        Ok(device) => device,
+        Err(err) => {
+            tracing::error!(?err, "failed to authenticate device");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_revoked_by_password_change() {
+        let key = HS256Key::generate();
+        let mut account = AccountModel {
+            id: "acc".to_string(),
+            email: "a@example.com".to_string(),
+            password: String::new(),
+            name: "a".to_string(),
+            is_superuser: false,
+            last_login_at: None,
+            created_at: chrono::Local::now().into(),
+            updated_at: None,
+            totp_secret: None,
+            totp_enabled: false,
+            recovery_codes: serde_json::json!([]),
+            must_change_password: false,
+            password_changed_at: None,
+        };
+        let token = sign_token(&key, &account).unwrap();
+        let claims = key.verify_token::<AccountClaims>(&token, None).unwrap();
+        assert!(is_current_token(&claims, &account));
+
+        // 修改密码前签发的Token失效, 修改后重新签发的有效
+        account.password_changed_at = Some(chrono::Local::now().into());
+        assert!(!is_current_token(&claims, &account));
+        let token = sign_token(&key, &account).unwrap();
+        let claims = key.verify_token::<AccountClaims>(&token, None).unwrap();
+        assert!(is_current_token(&claims, &account));
+
+        // 升级前签发的Token没有该声明, 只在从未修改过密码时有效
+        let legacy = key
+            .authenticate(Claims::create(Duration::from_days(1)).with_subject("acc".to_string()))
+            .unwrap();
+        let claims = key.verify_token::<AccountClaims>(&legacy, None).unwrap();
+        assert!(!is_current_token(&claims, &account));
+        account.password_changed_at = None;
+        assert!(is_current_token(&claims, &account));
+    }
 }
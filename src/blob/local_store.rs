@@ -0,0 +1,77 @@
+use std::{io::ErrorKind, path::PathBuf};
+
+use crate::errors::{NeoiotError, Result};
+use poem::async_trait;
+use tokio::io::AsyncWriteExt;
+
+/// 以本地目录保存对象, 对象键中的`/`对应子目录
+#[derive(Clone)]
+pub struct LocalBlobStore {
+    root: PathBuf,
+}
+
+impl LocalBlobStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        let valid = !key.is_empty()
+            && key
+                .split('/')
+                .all(|part| !part.is_empty() && part != "." && part != "..");
+        if !valid {
+            return Err(NeoiotError::BlobError(format!("invalid key `{}`", key)));
+        }
+        Ok(self.root.join(key))
+    }
+}
+
+fn to_error(err: std::io::Error) -> NeoiotError {
+    NeoiotError::BlobError(err.to_string())
+}
+
+#[async_trait]
+impl super::BlobStore for LocalBlobStore {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<()> {
+        let path = self.path(key)?;
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await.map_err(to_error)?;
+        }
+        // 先写临时文件再改名, 读取方不会看到写了一半的对象
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, data).await.map_err(to_error)?;
+        tokio::fs::rename(&tmp, &path).await.map_err(to_error)
+    }
+
+    async fn append(&self, key: &str, data: Vec<u8>) -> Result<()> {
+        let path = self.path(key)?;
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await.map_err(to_error)?;
+        }
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .map_err(to_error)?;
+        file.write_all(&data).await.map_err(to_error)?;
+        file.flush().await.map_err(to_error)
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match tokio::fs::read(self.path(key)?).await {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(to_error(err)),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(to_error(err)),
+        }
+    }
+}
@@ -0,0 +1,16 @@
+mod local_store;
+use crate::errors::Result;
+pub use local_store::LocalBlobStore;
+use poem::async_trait;
+
+#[async_trait]
+pub trait BlobStore: Send + Sync + 'static {
+    /// 写入对象, 已存在时覆盖
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<()>;
+    /// 在对象末尾追加写入, 不存在时创建
+    async fn append(&self, key: &str, data: Vec<u8>) -> Result<()>;
+    /// 读取对象, 不存在时返回None
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    /// 删除对象, 不存在时忽略
+    async fn delete(&self, key: &str) -> Result<()>;
+}
@@ -5,6 +5,18 @@ pub use redis_cache::RedisCache;
 
 #[async_trait]
 pub trait Cache: Send + Sync + 'static {
-    async fn block_pop(&self, key: &str, timeout: usize) -> Result<String>;
-    async fn lpush(&self, key: &str, value: &str) -> Result<()>;
+    /// 阻塞读取列表头部的值, 超时(秒)后返回None
+    async fn block_pop(&self, key: &str, timeout: usize) -> Result<Option<String>>;
+    /// 写入列表尾部并设置过期时间(秒)
+    async fn lpush(&self, key: &str, value: &str, expire: usize) -> Result<()>;
+    /// 计数器加一，计数器首次创建时设置过期时间(秒)
+    async fn incr(&self, key: &str, expire: usize) -> Result<i64>;
+    async fn get(&self, key: &str) -> Result<Option<String>>;
+    /// 写入一个带过期时间(秒)的值
+    async fn set_ex(&self, key: &str, value: &str, expire: usize) -> Result<()>;
+    /// 剩余过期时间(秒)，不存在时返回None
+    async fn ttl(&self, key: &str) -> Result<Option<i64>>;
+    async fn del(&self, key: &str) -> Result<()>;
+    /// 读取并删除一个值，并发调用时只有一方能取到
+    async fn take(&self, key: &str) -> Result<Option<String>>;
 }
@@ -5,24 +5,86 @@ use redis::AsyncCommands;
 #[derive(Clone)]
 pub struct RedisCache {
     pub conn: redis::aio::MultiplexedConnection,
+    /// 阻塞命令使用的独立连接由此创建, 不占用共享的多路复用连接
+    client: redis::Client,
 }
 
 impl RedisCache {
     pub async fn new(redis_dsn: impl Into<String>) -> Self {
         let client = redis::Client::open(redis_dsn.into()).unwrap();
         let conn = client.get_multiplexed_async_connection().await.unwrap();
-        Self { conn }
+        Self { conn, client }
     }
 }
 
 #[async_trait]
 impl super::Cache for RedisCache {
-    async fn block_pop(&self, key: &str, timeout: usize) -> Result<String> {
-        Ok(self.conn.clone().blpop(key, timeout).await?)
+    async fn block_pop(&self, key: &str, timeout: usize) -> Result<Option<String>> {
+        // BLPOP会占住连接直到超时, 使用单独的连接, 避免阻塞共享连接上的其他命令;
+        // 返回[key, value], 超时返回nil
+        let mut conn = self.client.get_async_connection().await?;
+        let popped: Option<(String, String)> = conn.blpop(key, timeout).await?;
+        Ok(popped.map(|(_, value)| value))
     }
 
-    async fn lpush(&self, key: &str, value: &str) -> Result<()> {
-        self.conn.clone().rpush(key, value).await?;
+    async fn lpush(&self, key: &str, value: &str, expire: usize) -> Result<()> {
+        redis::pipe()
+            .atomic()
+            .rpush(key, value)
+            .ignore()
+            .expire(key, expire)
+            .ignore()
+            .query_async::<_, ()>(&mut self.conn.clone())
+            .await?;
         Ok(())
     }
+
+    async fn incr(&self, key: &str, expire: usize) -> Result<i64> {
+        // 计数和设置过期时间在同一个事务中执行, 计数器不会遗留为永不过期;
+        // EXPIRE NX只在没有过期时间时设置, 需要Redis 7.0及以上
+        let (count,): (i64,) = redis::pipe()
+            .atomic()
+            .incr(key, 1)
+            .cmd("EXPIRE")
+            .arg(key)
+            .arg(expire)
+            .arg("NX")
+            .ignore()
+            .query_async(&mut self.conn.clone())
+            .await?;
+        Ok(count)
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.conn.clone().get(key).await?)
+    }
+
+    async fn set_ex(&self, key: &str, value: &str, expire: usize) -> Result<()> {
+        self.conn
+            .clone()
+            .set_ex::<_, _, ()>(key, value, expire)
+            .await?;
+        Ok(())
+    }
+
+    async fn ttl(&self, key: &str) -> Result<Option<i64>> {
+        // -2: key不存在, -1: key未设置过期时间
+        let ttl: i64 = self.conn.clone().ttl(key).await?;
+        Ok((ttl >= 0).then_some(ttl))
+    }
+
+    async fn del(&self, key: &str) -> Result<()> {
+        self.conn.clone().del::<_, ()>(key).await?;
+        Ok(())
+    }
+
+    async fn take(&self, key: &str) -> Result<Option<String>> {
+        let (value, _): (Option<String>, i64) = redis::pipe()
+            .atomic()
+            .get(key)
+            .del(key)
+            .query_async(&mut self.conn.clone())
+            .await?;
+        Ok(value)
+    }
 }
@@ -1,5 +1,5 @@
-use config::{Config, ConfigError, Environment, File};
-use std::{env, sync::Arc};
+use config::{Config, ConfigError, Environment, File, Map};
+use std::{env, net::IpAddr, sync::Arc};
 
 lazy_static! {
     pub static ref SETTINGS: Arc<Settings> = Arc::new(Settings::default().unwrap());
@@ -9,6 +9,22 @@ lazy_static! {
 pub struct Settings {
     pub core: CoreConfig,
     pub emqx: EmqxConfig,
+    #[serde(default)]
+    pub login: LoginConfig,
+    #[serde(default)]
+    pub bootstrap: BootstrapConfig,
+    #[serde(default)]
+    pub lifecycle: LifecycleConfig,
+    #[serde(default)]
+    pub alarm: AlarmConfig,
+    #[serde(default)]
+    pub webhook: WebhookConfig,
+    #[serde(default)]
+    pub forward: ForwardConfig,
+    #[serde(default)]
+    pub ota: OtaConfig,
+    #[serde(default)]
+    pub upload: UploadConfig,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -24,6 +40,203 @@ pub struct EmqxConfig {
     pub management_host: String,
     pub app_id: String,
     pub app_secret: String,
+    /// EMQX调用认证/ACL Hook时携带的`X-Hook-Token`, 未配置时拒绝所有Hook请求
+    #[serde(default)]
+    pub hook_token: Option<String>,
+}
+
+impl EmqxConfig {
+    /// 配置的Hook Token, 空字符串视为未配置
+    pub fn hook_token(&self) -> Option<&str> {
+        self.hook_token.as_deref().filter(|token| !token.is_empty())
+    }
+}
+
+/// 登录防护配置
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct LoginConfig {
+    /// 频率统计窗口(秒)
+    pub window_secs: usize,
+    /// 窗口内单个邮箱允许的最大尝试次数
+    pub max_attempts_per_email: i64,
+    /// 窗口内单个IP允许的最大尝试次数
+    pub max_attempts_per_ip: i64,
+    /// 触发锁定的连续失败次数
+    pub max_failures: i64,
+    /// 锁定时长(秒)
+    pub lockout_secs: usize,
+    /// 密码重置凭证有效期(秒)
+    pub password_reset_ttl_secs: usize,
+    /// 密码重置页面地址，凭证会以`token`参数附加在地址后
+    pub password_reset_url: Option<String>,
+    /// 受信任的反向代理地址，只有来自这些地址的请求才读取`X-Forwarded-For`
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl Default for LoginConfig {
+    fn default() -> Self {
+        Self {
+            window_secs: 60,
+            max_attempts_per_email: 10,
+            max_attempts_per_ip: 30,
+            max_failures: 5,
+            lockout_secs: 900,
+            password_reset_ttl_secs: 1800,
+            password_reset_url: None,
+            trusted_proxies: vec![],
+        }
+    }
+}
+
+/// 首次启动时创建的管理员账号，
+/// 可通过环境变量覆盖，如`NEOIOT__BOOTSTRAP__ADMIN_PASSWORD`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct BootstrapConfig {
+    pub admin_email: String,
+    pub admin_name: String,
+    /// 未设置时随机生成并在启动日志中输出一次，首次登录必须修改密码
+    pub admin_password: Option<String>,
+}
+
+impl Default for BootstrapConfig {
+    fn default() -> Self {
+        Self {
+            admin_email: "admin@neoiot.com".into(),
+            admin_name: "admin".into(),
+            admin_password: None,
+        }
+    }
+}
+
+/// 设备生命周期配置
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct LifecycleConfig {
+    /// 退役设备的数据保留天数, 到期后彻底删除
+    pub retention_days: i64,
+    /// 清理到期退役设备的间隔(秒)
+    pub purge_interval_secs: u64,
+}
+
+impl Default for LifecycleConfig {
+    fn default() -> Self {
+        Self {
+            retention_days: 90,
+            purge_interval_secs: 3600,
+        }
+    }
+}
+
+/// 告警配置
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct AlarmConfig {
+    /// 告警未被确认多久(秒)后升级一级, 为0时不自动升级
+    pub escalate_after_secs: i64,
+}
+
+impl Default for AlarmConfig {
+    fn default() -> Self {
+        Self {
+            escalate_after_secs: 1800,
+        }
+    }
+}
+
+/// Webhook投递配置
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    /// 最大投递次数, 超过后进入死信
+    pub max_attempts: i32,
+    /// 首次重试的间隔(秒), 之后每次翻倍
+    pub backoff_base_secs: i64,
+    /// 重试间隔的上限(秒)
+    pub backoff_max_secs: i64,
+    /// 单次投递的超时时间(秒)
+    pub timeout_secs: u64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 8,
+            backoff_base_secs: 10,
+            backoff_max_secs: 3600,
+            timeout_secs: 10,
+        }
+    }
+}
+
+/// 数据转发配置
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct ForwardConfig {
+    /// 文件转发的根目录, 路由只能指定文件名前缀
+    pub file_dir: String,
+    /// HTTP和Kafka REST Proxy请求的超时时间(秒)
+    pub timeout_secs: u64,
+}
+
+impl Default for ForwardConfig {
+    fn default() -> Self {
+        Self {
+            file_dir: "data/forward".into(),
+            timeout_secs: 10,
+        }
+    }
+}
+
+/// 固件升级配置
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct OtaConfig {
+    /// 本地blob存储的根目录
+    pub blob_dir: String,
+    /// 设备访问平台API的地址, 用于生成固件下载链接
+    pub public_url: String,
+    /// 固件下载链接的有效期(秒)
+    pub download_ttl_secs: i64,
+    /// 固件文件的大小上限(字节)
+    pub max_firmware_size: usize,
+}
+
+impl Default for OtaConfig {
+    fn default() -> Self {
+        Self {
+            blob_dir: "data/blobs".into(),
+            public_url: "http://localhost:3000".into(),
+            download_ttl_secs: 86400,
+            max_firmware_size: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// 设备文件上传配置
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct UploadConfig {
+    /// 上传指令中建议设备使用的分片大小(字节)
+    pub chunk_size: usize,
+    /// 单个分片的大小上限(字节)
+    pub max_chunk_size: usize,
+    /// 单次上传的分片数上限
+    pub max_chunks: i32,
+    /// 上传请求的有效期(秒), 超时未收到全部分片即过期
+    pub expire_secs: i64,
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size: 256 * 1024,
+            max_chunk_size: 1024 * 1024,
+            max_chunks: 1024,
+            expire_secs: 3600,
+        }
+    }
 }
 
 impl Settings {
@@ -35,8 +248,66 @@ impl Settings {
             .add_source(File::with_name("./config.toml").required(false))
             .add_source(File::with_name("~/.config/neoiot/config.toml").required(false))
             .add_source(File::with_name("/etc/neoiot/config.toml").required(false))
-            .add_source(Environment::with_prefix("NEOIOT"))
+            .add_source(Environment::default().source(Some(legacy_env_vars(env::vars()))))
+            .add_source(Environment::with_prefix("NEOIOT").separator("__"))
             .set_default("endpoint", "0.0.0.0:3000")?;
         builder.build()?.try_deserialize()
     }
 }
+
+/// 兼容旧的`NEOIOT_SECTION_KEY`环境变量: 配置段名不含下划线, 第一个下划线之前为配置段,
+/// 例如`NEOIOT_CORE_POSTGRES_DSN`对应`core.postgres_dsn`; 与`NEOIOT__SECTION__KEY`同时设置时后者优先
+fn legacy_env_vars(vars: impl Iterator<Item = (String, String)>) -> Map<String, String> {
+    vars.filter_map(|(key, value)| {
+        let rest = key.strip_prefix("NEOIOT_")?;
+        if rest.is_empty() || rest.starts_with('_') {
+            return None;
+        }
+        let key = match rest.split_once('_') {
+            Some((section, name)) => format!("{}.{}", section, name),
+            None => rest.to_string(),
+        };
+        Some((key, value))
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_legacy_env_vars() {
+        let vars = [
+            ("NEOIOT_CORE_POSTGRES_DSN", "postgres://old"),
+            ("NEOIOT_EMQX_APP_ID", "app"),
+            ("NEOIOT__CORE__SECRET", "new"),
+            ("PATH", "/bin"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+        let map = legacy_env_vars(vars);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["CORE.POSTGRES_DSN"], "postgres://old");
+        assert_eq!(map["EMQX.APP_ID"], "app");
+
+        let config = Config::builder()
+            .add_source(Environment::default().source(Some(map)))
+            .add_source(
+                Environment::with_prefix("NEOIOT")
+                    .separator("__")
+                    .source(Some(Map::from([
+                        ("NEOIOT__CORE__SECRET".to_string(), "new".to_string()),
+                        ("NEOIOT__EMQX__APP_ID".to_string(), "override".to_string()),
+                    ]))),
+            )
+            .build()
+            .unwrap();
+        assert_eq!(
+            config.get_string("core.postgres_dsn").unwrap(),
+            "postgres://old"
+        );
+        assert_eq!(config.get_string("core.secret").unwrap(), "new");
+        assert_eq!(config.get_string("emqx.app_id").unwrap(), "override");
+    }
+}
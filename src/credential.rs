@@ -0,0 +1,46 @@
+//! 设备连接凭据
+use rand::{distributions::Alphanumeric, Rng};
+use sha2::{Digest, Sha256};
+
+/// 服务端生成的凭据长度
+const SECRET_LEN: usize = 32;
+/// 调用方自带凭据时要求的最小长度
+pub const MIN_SECRET_LEN: usize = 16;
+
+/// 生成一个随机的设备凭据
+pub fn generate_secret() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(SECRET_LEN)
+        .map(char::from)
+        .collect()
+}
+
+/// 凭据只保存摘要, 凭据本身是高熵随机串, 使用sha256即可
+pub fn hash_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+/// 常量时间比较, 用于校验Hook令牌
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret() {
+        let secret = generate_secret();
+        assert_eq!(secret.len(), SECRET_LEN);
+        assert_ne!(secret, generate_secret());
+        assert_eq!(
+            hash_secret("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert!(constant_time_eq(b"token", b"token"));
+        assert!(!constant_time_eq(b"token", b"tokem"));
+        assert!(!constant_time_eq(b"token", b"token1"));
+    }
+}
@@ -0,0 +1,347 @@
+//! 远程配置: 配置模板的文档须符合模板的JSON Schema, 模板分配到标签或单个设备;
+//! 设备的生效配置按JSON Merge Patch依次合并标签级和设备级分配的最新文档, 版本号为文档摘要。
+//! 生效配置变化时推送到`cfg/{account_id}/{device_id}/push`, 设备应用后向
+//! `cfgr/{account_id}/{device_id}/applied`回复应用的版本
+use entity::prelude::*;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    errors::{NeoiotError, Result},
+    topics::{ConfigPush, Message, Topics},
+};
+
+const TYPES: [&str; 7] = [
+    "object", "array", "string", "number", "integer", "boolean", "null",
+];
+
+/// 校验JSON Schema本身, 支持`type`、`properties`、`required`、`additionalProperties`、
+/// `items`、`enum`、`minimum`、`maximum`、`minLength`和`maxLength`, 其余关键字忽略
+pub fn check_schema(schema: &Value) -> std::result::Result<(), String> {
+    let schema = schema
+        .as_object()
+        .ok_or_else(|| "schema must be an object".to_string())?;
+    match schema.get("type") {
+        None => {}
+        Some(Value::String(t)) if TYPES.contains(&t.as_str()) => {}
+        Some(Value::Array(types))
+            if types
+                .iter()
+                .all(|t| t.as_str().is_some_and(|t| TYPES.contains(&t))) => {}
+        Some(t) => return Err(format!("unsupported type {}", t)),
+    }
+    if let Some(properties) = schema.get("properties") {
+        let properties = properties
+            .as_object()
+            .ok_or_else(|| "properties must be an object".to_string())?;
+        for (key, property) in properties {
+            check_schema(property).map_err(|err| format!("{}: {}", key, err))?;
+        }
+    }
+    if let Some(required) = schema.get("required") {
+        if !required
+            .as_array()
+            .is_some_and(|keys| keys.iter().all(Value::is_string))
+        {
+            return Err("required must be an array of strings".to_string());
+        }
+    }
+    match schema.get("additionalProperties") {
+        None | Some(Value::Bool(_)) => {}
+        Some(additional) => check_schema(additional)?,
+    }
+    if let Some(items) = schema.get("items") {
+        check_schema(items)?;
+    }
+    if !schema.get("enum").is_none_or(Value::is_array) {
+        return Err("enum must be an array".to_string());
+    }
+    for key in ["minimum", "maximum"] {
+        if !schema.get(key).is_none_or(Value::is_number) {
+            return Err(format!("{} must be a number", key));
+        }
+    }
+    for key in ["minLength", "maxLength"] {
+        if !schema.get(key).is_none_or(Value::is_u64) {
+            return Err(format!("{} must be a non-negative integer", key));
+        }
+    }
+    Ok(())
+}
+
+/// 按JSON Schema校验文档, 错误信息包含出错位置
+pub fn validate(schema: &Value, document: &Value) -> std::result::Result<(), String> {
+    validate_at(schema, document, "$")
+}
+
+fn type_matches(t: &str, value: &Value) -> bool {
+    match t {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.as_f64().is_some_and(|n| n.fract() == 0.0),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => false,
+    }
+}
+
+fn validate_at(schema: &Value, value: &Value, path: &str) -> std::result::Result<(), String> {
+    let schema = match schema.as_object() {
+        Some(schema) => schema,
+        None => return Ok(()),
+    };
+    if let Some(t) = schema.get("type") {
+        let types: Vec<&str> = match t {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        if !types.iter().any(|t| type_matches(t, value)) {
+            return Err(format!("{}: expected {}", path, types.join(" or ")));
+        }
+    }
+    if let Some(Value::Array(options)) = schema.get("enum") {
+        if !options.contains(value) {
+            return Err(format!(
+                "{}: must be one of {}",
+                path,
+                Value::from(options.clone())
+            ));
+        }
+    }
+    match value {
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or_default();
+            if let Some(minimum) = schema.get("minimum").and_then(Value::as_f64) {
+                if n < minimum {
+                    return Err(format!("{}: must be at least {}", path, minimum));
+                }
+            }
+            if let Some(maximum) = schema.get("maximum").and_then(Value::as_f64) {
+                if n > maximum {
+                    return Err(format!("{}: must be at most {}", path, maximum));
+                }
+            }
+        }
+        Value::String(s) => {
+            let len = s.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+                if len < min {
+                    return Err(format!("{}: must be at least {} characters", path, min));
+                }
+            }
+            if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+                if len > max {
+                    return Err(format!("{}: must be at most {} characters", path, max));
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    validate_at(item_schema, item, &format!("{}[{}]", path, i))?;
+                }
+            }
+        }
+        Value::Object(map) => {
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for key in required.iter().filter_map(Value::as_str) {
+                    if !map.contains_key(key) {
+                        return Err(format!("{}: missing required property `{}`", path, key));
+                    }
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for (key, value) in map {
+                let child = format!("{}.{}", path, key);
+                match (
+                    properties.and_then(|p| p.get(key)),
+                    schema.get("additionalProperties"),
+                ) {
+                    (Some(property), _) => validate_at(property, value, &child)?,
+                    (None, Some(Value::Bool(false))) => {
+                        return Err(format!("{}: is not allowed", child))
+                    }
+                    (None, Some(additional)) => validate_at(additional, value, &child)?,
+                    (None, None) => {}
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// 按JSON Merge Patch(RFC 7386)将`patch`合并到`target`, 值为null的键被删除
+pub fn merge(target: &mut Value, patch: &Value) {
+    let patch = match patch {
+        Value::Object(patch) => patch,
+        _ => {
+            *target = patch.clone();
+            return;
+        }
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    if let Value::Object(map) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                map.remove(key);
+            } else {
+                merge(map.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+/// 计算设备的生效配置: 先合并标签级再合并设备级的文档, 同一级别内按优先级从低到高、创建时间从早到晚合并
+pub fn effective(mut layers: Vec<(ConfigAssignmentModel, Value)>) -> Value {
+    layers.sort_by(|(a, _), (b, _)| {
+        (a.device_id.is_some(), a.priority, a.created_at).cmp(&(
+            b.device_id.is_some(),
+            b.priority,
+            b.created_at,
+        ))
+    });
+    let mut document = json!({});
+    for (_, layer) in &layers {
+        merge(&mut document, layer);
+    }
+    document
+}
+
+/// 配置文档的版本号, 取文档摘要的前16位
+pub fn version(document: &Value) -> String {
+    let mut version = hex::encode(Sha256::digest(document.to_string().as_bytes()));
+    version.truncate(16);
+    version
+}
+
+/// 设备回复的配置应用结果
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ConfigAck {
+    /// 设备应用的配置版本
+    pub version: String,
+    /// 应用失败的原因
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl ConfigAck {
+    pub fn parse(payload: &str) -> Result<Self> {
+        serde_json::from_str(payload)
+            .map_err(|err| NeoiotError::InvalidArgument(format!("invalid config ack: {}", err)))
+    }
+}
+
+/// 向设备推送生效配置, 标签版本号供设备判断是否需要重新拉取
+pub async fn push(device: &DeviceModel, config: &DeviceConfigModel) -> Result<()> {
+    let payload = json!({
+        "version": config.version,
+        "label_version": device.label_version,
+        "config": config.document,
+    });
+    Message::new(
+        Topics::Config(ConfigPush::new(&device.account_id, &device.id)),
+        payload.to_string(),
+    )
+    .publish(1)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Local;
+
+    #[test]
+    fn test_schema_and_merge() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "interval": {"type": "integer", "minimum": 1, "maximum": 3600},
+                "mode": {"enum": ["eco", "full"]},
+                "servers": {"type": "array", "items": {"type": "string", "minLength": 1}},
+            },
+            "required": ["interval"],
+            "additionalProperties": false,
+        });
+        assert!(check_schema(&schema).is_ok());
+        assert!(check_schema(&json!({"type": "date"})).is_err());
+        assert!(check_schema(&json!({"properties": {"a": {"minLength": -1}}})).is_err());
+        assert!(check_schema(&json!([])).is_err());
+
+        assert!(validate(&schema, &json!({"interval": 60, "mode": "eco"})).is_ok());
+        assert!(validate(&schema, &json!({"interval": 60.0})).is_ok());
+        assert_eq!(
+            validate(&schema, &json!({"mode": "eco"})),
+            Err("$: missing required property `interval`".to_string())
+        );
+        assert_eq!(
+            validate(&schema, &json!({"interval": 0})),
+            Err("$.interval: must be at least 1".to_string())
+        );
+        assert!(validate(&schema, &json!({"interval": 1.5})).is_err());
+        assert!(validate(&schema, &json!({"interval": 1, "mode": "off"})).is_err());
+        assert_eq!(
+            validate(&schema, &json!({"interval": 1, "servers": ["a", ""]})),
+            Err("$.servers[1]: must be at least 1 characters".to_string())
+        );
+        assert_eq!(
+            validate(&schema, &json!({"interval": 1, "debug": true})),
+            Err("$.debug: is not allowed".to_string())
+        );
+        assert!(validate(&json!({}), &json!({"anything": [1, "a"]})).is_ok());
+
+        let mut document = json!({"interval": 60, "net": {"host": "a", "port": 1}, "debug": true});
+        merge(
+            &mut document,
+            &json!({"net": {"port": 2}, "debug": null, "mode": "eco"}),
+        );
+        assert_eq!(
+            document,
+            json!({"interval": 60, "net": {"host": "a", "port": 2}, "mode": "eco"})
+        );
+
+        let now = Local::now();
+        let assignment = |id: &str, device: bool, priority: i32| ConfigAssignmentModel {
+            id: id.to_string(),
+            account_id: "acc".to_string(),
+            profile_id: id.to_string(),
+            label_id: (!device).then(|| "label".to_string()),
+            device_id: device.then(|| "dev".to_string()),
+            priority,
+            created_at: now.into(),
+        };
+        let document = effective(vec![
+            (assignment("device", true, 0), json!({"interval": 5})),
+            (
+                assignment("high", false, 10),
+                json!({"interval": 30, "mode": "full"}),
+            ),
+            (
+                assignment("low", false, 0),
+                json!({"interval": 60, "mode": "eco", "debug": true}),
+            ),
+        ]);
+        assert_eq!(
+            document,
+            json!({"interval": 5, "mode": "full", "debug": true})
+        );
+        assert_eq!(version(&document), version(&document.clone()));
+        assert_eq!(version(&document).len(), 16);
+        assert_ne!(version(&document), version(&json!({})));
+
+        assert_eq!(
+            ConfigAck::parse(r#"{"version": "abc"}"#).unwrap(),
+            ConfigAck {
+                version: "abc".to_string(),
+                error: None
+            }
+        );
+        assert!(ConfigAck::parse("abc").is_err());
+    }
+}
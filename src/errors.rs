@@ -22,6 +22,28 @@ pub enum NeoiotError {
     AuthenticateError,
     #[error("permission denied")]
     PermissionDenied,
+    #[error("invalid argument:{0}")]
+    InvalidArgument(String),
+    #[error("too many requests, retry after {0} seconds")]
+    TooManyRequests(i64),
+    #[error("account locked, retry after {0} seconds")]
+    AccountLocked(i64),
+    #[error("pki error:{0}")]
+    PkiError(String),
+    #[error("{0} already exists")]
+    AlreadyExists(String),
+    #[error("quota exceeded:{0}")]
+    QuotaExceeded(String),
+    #[error("device {0} is inactive")]
+    DeviceInactive(String),
+    #[error("webhook error:{0}")]
+    WebhookError(String),
+    #[error("command {0} timed out waiting for device response")]
+    CommandTimeout(String),
+    #[error("data forwarding error:{0}")]
+    ForwardError(String),
+    #[error("blob store error:{0}")]
+    BlobError(String),
 }
 
 impl ResponseError for NeoiotError {
@@ -35,6 +57,17 @@ impl ResponseError for NeoiotError {
             NeoiotError::AuthenticateError => StatusCode::UNAUTHORIZED,
             NeoiotError::PermissionDenied => StatusCode::FORBIDDEN,
             NeoiotError::RedisError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            NeoiotError::InvalidArgument(_) => StatusCode::BAD_REQUEST,
+            NeoiotError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            NeoiotError::AccountLocked(_) => StatusCode::TOO_MANY_REQUESTS,
+            NeoiotError::PkiError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            NeoiotError::AlreadyExists(_) => StatusCode::CONFLICT,
+            NeoiotError::QuotaExceeded(_) => StatusCode::FORBIDDEN,
+            NeoiotError::DeviceInactive(_) => StatusCode::CONFLICT,
+            NeoiotError::WebhookError(_) => StatusCode::BAD_GATEWAY,
+            NeoiotError::CommandTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            NeoiotError::ForwardError(_) => StatusCode::BAD_GATEWAY,
+            NeoiotError::BlobError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
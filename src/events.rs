@@ -0,0 +1,103 @@
+//! 平台事件, 发生时为账号下订阅了该事件的Webhook生成投递记录, 由后台任务异步推送,
+//! 同时按数据转发路由转发到外部系统, 并推送给实时订阅的连接
+use chrono::{DateTime, Local};
+use entity::sea_orm::ActiveEnum;
+use entity::webhook_deliveries::EventType;
+use poem_openapi::types::ToJSON;
+use serde_json::{json, Value};
+
+use crate::{forward, repository::Repository, stream};
+
+/// 一个平台事件
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub account_id: String,
+    /// 事件关联的设备, 用于按标签或数据模型过滤转发
+    pub device_id: Option<String>,
+    pub event_type: EventType,
+    pub data: Value,
+    pub occurred_at: DateTime<Local>,
+}
+
+impl Event {
+    pub fn new(account_id: &str, event_type: EventType) -> Self {
+        Self {
+            account_id: account_id.to_string(),
+            device_id: None,
+            event_type,
+            data: Value::Null,
+            occurred_at: Local::now(),
+        }
+    }
+
+    pub fn device(mut self, device_id: &str) -> Self {
+        self.device_id = Some(device_id.to_string());
+        self
+    }
+
+    /// 附加资源快照
+    pub fn object<T: ToJSON>(mut self, object: &T) -> Self {
+        self.data = object.to_json().unwrap_or_default();
+        self
+    }
+
+    /// 附加不对应资源快照的信息
+    pub fn data(mut self, data: Value) -> Self {
+        self.data = data;
+        self
+    }
+
+    /// 推送给订阅方的消息体
+    pub fn payload(&self) -> Value {
+        json!({
+            "type": self.event_type.to_value(),
+            "account_id": self.account_id,
+            "occurred_at": self.occurred_at.to_rfc3339(),
+            "data": self.data,
+        })
+    }
+}
+
+/// Webhook是否订阅了事件, 未指定事件类型时订阅所有事件
+pub fn subscribed(event_types: &Value, event_type: EventType) -> bool {
+    match event_types.as_array() {
+        Some(types) if !types.is_empty() => types
+            .iter()
+            .any(|t| t.as_str() == Some(event_type.to_value().as_str())),
+        _ => true,
+    }
+}
+
+/// 发布事件, 失败时只记录日志, 不影响触发事件的操作
+pub async fn emit<R: Repository + Clone>(repo: &R, event: Event) {
+    if let Err(err) = repo.enqueue_webhook_deliveries(&event).await {
+        tracing::error!(?err, event_type = ?event.event_type, "failed to enqueue webhook deliveries");
+    }
+    stream::publish(&event);
+    // 转发目标可能较慢, 不阻塞触发事件的请求
+    tokio::spawn(forward::forward(repo.clone(), event));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscribed() {
+        assert!(subscribed(&json!([]), EventType::DeviceCreated));
+        assert!(subscribed(
+            &json!(["device_created", "device_deleted"]),
+            EventType::DeviceCreated
+        ));
+        assert!(!subscribed(
+            &json!(["device_deleted"]),
+            EventType::DeviceCreated
+        ));
+
+        let event = Event::new("acc", EventType::TelemetryReceived)
+            .data(json!({"device_id": "dev", "field": "temperature", "value": "8.5"}));
+        let payload = event.payload();
+        assert_eq!(payload["type"], "telemetry_received");
+        assert_eq!(payload["data"]["field"], "temperature");
+    }
+}
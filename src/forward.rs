@@ -0,0 +1,195 @@
+//! 数据转发: 校验通过的遥测数据和设备事件按账号配置的路由转发到外部系统,
+//! 每条记录为事件的JSON消息体, 与Webhook推送的内容一致
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Local};
+use entity::data_routes::SinkType;
+use entity::prelude::*;
+use entity::webhook_deliveries::EventType;
+use pulsar::{producer::MultiTopicProducer, Pulsar, TokioExecutor};
+use serde_json::{json, Value};
+use tokio::{io::AsyncWriteExt, sync::Mutex};
+
+use crate::{
+    config::SETTINGS,
+    errors::{NeoiotError, Result},
+    events::Event,
+    repository::Repository,
+};
+
+/// 一个Pulsar服务地址的生产者, 连接失效后置空, 下次转发时重新建立
+type PulsarProducer = Arc<Mutex<Option<MultiTopicProducer<TokioExecutor>>>>;
+
+lazy_static! {
+    /// 按Pulsar服务地址缓存的生产者, 同一地址下的主题共用一个连接;
+    /// 表锁只在查找时持有, 各地址的收发互不阻塞
+    static ref PULSAR_PRODUCERS: std::sync::Mutex<HashMap<String, PulsarProducer>> =
+        std::sync::Mutex::new(HashMap::new());
+}
+
+/// 遥测数据是否在路由的字段投影内, 未指定字段时转发所有字段, 其他事件不受影响
+pub fn projected(fields: &Value, event: &Event) -> bool {
+    if event.event_type != EventType::TelemetryReceived {
+        return true;
+    }
+    match fields.as_array() {
+        Some(fields) if !fields.is_empty() => fields.contains(&event.data["field"]),
+        _ => true,
+    }
+}
+
+/// 文件转发的目标文件, 按账号分目录、按小时滚动:
+/// `{file_dir}/{account_id}/{prefix}-{yyyymmddhh}.ndjson`
+pub fn file_path(file_dir: &str, account_id: &str, prefix: &str, now: DateTime<Local>) -> PathBuf {
+    PathBuf::from(file_dir).join(account_id).join(format!(
+        "{}-{}.ndjson",
+        prefix,
+        now.format("%Y%m%d%H")
+    ))
+}
+
+/// 按路由转发事件并记录结果
+pub async fn forward<R: Repository>(repo: R, event: Event) {
+    let routes = match repo.matching_data_routes(&event).await {
+        Ok(routes) => routes,
+        Err(err) => {
+            tracing::error!(?err, "failed to match data routes");
+            return;
+        }
+    };
+    if routes.is_empty() {
+        return;
+    }
+    let record = event.payload();
+    for route in routes {
+        let error = send(&route, &record, event.device_id.as_deref())
+            .await
+            .err()
+            .map(|err| err.to_string());
+        if let Some(error) = &error {
+            tracing::warn!(route_id = %route.id, %error, "data forwarding failed");
+        }
+        if let Err(err) = repo.record_data_route_result(&route.id, error).await {
+            tracing::error!(?err, "failed to record data route result");
+        }
+    }
+}
+
+async fn send(route: &DataRouteModel, record: &Value, device_id: Option<&str>) -> Result<()> {
+    let endpoint = route.endpoint.as_deref().unwrap_or_default();
+    let topic = route.topic.as_deref().unwrap_or_default();
+    match route.sink_type {
+        SinkType::Pulsar => send_pulsar(endpoint, topic, record).await,
+        SinkType::Kafka => {
+            // Kafka REST Proxy v2
+            let body = json!({ "records": [{ "key": device_id, "value": record }] });
+            let url = format!("{}/topics/{}", endpoint.trim_end_matches('/'), topic);
+            post(&url, "application/vnd.kafka.json.v2+json", body.to_string()).await
+        }
+        SinkType::Http => post(endpoint, "application/json", record.to_string()).await,
+        SinkType::File => {
+            let path = file_path(
+                &SETTINGS.forward.file_dir,
+                &route.account_id,
+                topic,
+                Local::now(),
+            );
+            append_line(&path, record).await
+        }
+    }
+}
+
+async fn post(url: &str, content_type: &str, body: String) -> Result<()> {
+    reqwest::Client::new()
+        .post(url)
+        .timeout(Duration::from_secs(SETTINGS.forward.timeout_secs))
+        .header(reqwest::header::CONTENT_TYPE, content_type)
+        .body(body)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| NeoiotError::ForwardError(e.to_string()))?;
+    Ok(())
+}
+
+async fn send_pulsar(endpoint: &str, topic: &str, record: &Value) -> Result<()> {
+    let slot = PULSAR_PRODUCERS
+        .lock()
+        .unwrap()
+        .entry(endpoint.to_string())
+        .or_default()
+        .clone();
+    let sent = {
+        let mut producer = slot.lock().await;
+        if producer.is_none() {
+            let client = Pulsar::builder(endpoint, TokioExecutor)
+                .build()
+                .await
+                .map_err(|e| NeoiotError::ForwardError(e.to_string()))?;
+            *producer = Some(client.producer().build_multi_topic());
+        }
+        producer
+            .as_mut()
+            .expect("producer created above")
+            .send(topic, record.to_string())
+            .await
+    };
+    // 等待回执时不再占用生产者
+    let result = match sent {
+        Ok(receipt) => receipt.await.map(|_| ()),
+        Err(err) => Err(err),
+    };
+    if let Err(err) = result {
+        // 连接可能已失效, 下次转发时重新建立
+        slot.lock().await.take();
+        return Err(NeoiotError::ForwardError(err.to_string()));
+    }
+    Ok(())
+}
+
+async fn append_line(path: &PathBuf, record: &Value) -> Result<()> {
+    let to_error = |e: std::io::Error| NeoiotError::ForwardError(e.to_string());
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await.map_err(to_error)?;
+    }
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .map_err(to_error)?;
+    file.write_all(format!("{}\n", record).as_bytes())
+        .await
+        .map_err(to_error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_projected() {
+        let telemetry = Event::new("acc", EventType::TelemetryReceived)
+            .data(json!({"device_id": "dev", "field": "temperature", "value": "8.5"}));
+        assert!(projected(&json!([]), &telemetry));
+        assert!(projected(&json!(["temperature", "humidity"]), &telemetry));
+        assert!(!projected(&json!(["humidity"]), &telemetry));
+        let connected = Event::new("acc", EventType::DeviceConnected);
+        assert!(projected(&json!(["humidity"]), &connected));
+    }
+
+    #[test]
+    fn test_file_path() {
+        let now = Local.ymd(2022, 8, 22).and_hms(9, 30, 0);
+        assert_eq!(
+            file_path("data/forward", "acc", "telemetry", now),
+            PathBuf::from("data/forward/acc/telemetry-2022082209.ndjson")
+        );
+        assert_ne!(
+            file_path("data/forward", "acc", "telemetry", now),
+            file_path("data/forward", "other", "telemetry", now)
+        );
+    }
+}
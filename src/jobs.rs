@@ -0,0 +1,23 @@
+//! 批量指令任务: 将标签或设备列表展开为设备, 按任务的速率逐个下发可追踪的`s2d`指令,
+//! 设备通过`s2dr`回复后标记为已回复, 超时未回复的设备标记为超时, 可重新下发
+use entity::prelude::*;
+
+use crate::repository::Repository;
+
+/// 依次向领取到的设备下发指令, 下发失败的设备标记为失败
+pub async fn process<R: Repository>(
+    repo: &R,
+    job: CommandJobModel,
+    targets: Vec<CommandJobDeviceModel>,
+) {
+    for target in targets {
+        let error = match repo.send_command_to_job_device(&job, &target).await {
+            Ok(_) => continue,
+            Err(err) => err.to_string(),
+        };
+        tracing::warn!(job_id = %job.id, device_id = %target.device_id, %error, "command job dispatch failed");
+        if let Err(err) = repo.fail_command_job_device(&target, &error).await {
+            tracing::error!(?err, "failed to record command job dispatch");
+        }
+    }
+}
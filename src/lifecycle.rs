@@ -0,0 +1,69 @@
+//! 设备生命周期状态机
+use entity::sea_orm_active_enums::LifecycleState;
+
+use crate::errors::{NeoiotError, Result};
+
+/// 状态迁移是否合法, 退役是终态
+pub fn can_transition(from: LifecycleState, to: LifecycleState) -> bool {
+    use LifecycleState::*;
+    matches!(
+        (from, to),
+        (Provisioned, Active | Suspended | Decommissioned)
+            | (Active, Suspended | Maintenance | Decommissioned)
+            | (Suspended, Active | Decommissioned)
+            | (Maintenance, Active | Suspended | Decommissioned)
+    )
+}
+
+pub fn check_transition(from: LifecycleState, to: LifecycleState) -> Result<()> {
+    if can_transition(from, to) {
+        return Ok(());
+    }
+    Err(NeoiotError::InvalidArgument(format!(
+        "device can not transition from {} to {}",
+        name(from),
+        name(to)
+    )))
+}
+
+/// 处于该状态的设备是否允许连接和接收指令
+pub fn is_active(state: LifecycleState) -> bool {
+    matches!(
+        state,
+        LifecycleState::Provisioned | LifecycleState::Active | LifecycleState::Maintenance
+    )
+}
+
+/// 状态的文本形式
+pub fn name(state: LifecycleState) -> &'static str {
+    match state {
+        LifecycleState::Provisioned => "provisioned",
+        LifecycleState::Active => "active",
+        LifecycleState::Suspended => "suspended",
+        LifecycleState::Maintenance => "maintenance",
+        LifecycleState::Decommissioned => "decommissioned",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transition() {
+        use LifecycleState::*;
+        assert!(can_transition(Provisioned, Active));
+        assert!(can_transition(Active, Maintenance));
+        assert!(can_transition(Maintenance, Active));
+        assert!(can_transition(Suspended, Decommissioned));
+        assert!(!can_transition(Active, Active));
+        assert!(!can_transition(Suspended, Maintenance));
+        assert!(!can_transition(Active, Provisioned));
+        for to in [Provisioned, Active, Suspended, Maintenance] {
+            assert!(!can_transition(Decommissioned, to));
+        }
+        assert!(check_transition(Decommissioned, Active).is_err());
+        assert!(!is_active(Suspended));
+        assert!(is_active(Maintenance));
+    }
+}
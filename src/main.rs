@@ -2,15 +2,35 @@
 extern crate lazy_static;
 #[macro_use]
 extern crate serde_derive;
+mod alarms;
+mod audit;
 mod auth;
+mod blob;
 mod cache;
 mod config;
+mod credential;
+mod device_config;
 mod errors;
+mod events;
+mod forward;
+mod jobs;
+mod lifecycle;
 mod mqtt_client;
+mod notifier;
 mod oai_schema;
+mod ota;
+mod pki;
 mod repository;
+mod rules;
+mod schedule;
+mod selector;
 mod service;
+mod stream;
+mod telemetry;
 mod topics;
+mod totp;
+mod uploads;
+mod webhook;
 
 #[tokio::main]
 async fn main() {
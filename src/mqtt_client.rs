@@ -1,4 +1,5 @@
-use serde_json::json;
+use reqwest::{StatusCode, Url};
+use serde_json::{json, Value};
 
 use crate::{config::SETTINGS, errors::NeoiotError};
 
@@ -24,4 +25,55 @@ impl Client {
             .map_err(|e| NeoiotError::EmqxManagementError(e.to_string()))?;
         Ok(())
     }
+
+    /// 断开使用该用户名登录的所有会话, 返回被断开的clientid
+    pub async fn kick_username(username: &str) -> Result<Vec<String>, NeoiotError> {
+        let config = &SETTINGS.emqx;
+        let client = reqwest::Client::new();
+        let url = management_url(&["clients", "username", username])?;
+        let resp: Value = client
+            .get(url)
+            .basic_auth(config.app_id.clone(), Some(config.app_secret.clone()))
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| NeoiotError::EmqxManagementError(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| NeoiotError::EmqxManagementError(e.to_string()))?;
+        let client_ids = resp["data"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|c| c["clientid"].as_str())
+            .map(|c| c.to_string())
+            .collect::<Vec<_>>();
+        for client_id in &client_ids {
+            let url = management_url(&["clients", client_id])?;
+            let resp = client
+                .delete(url)
+                .basic_auth(config.app_id.clone(), Some(config.app_secret.clone()))
+                .send()
+                .await
+                .map_err(|e| NeoiotError::EmqxManagementError(e.to_string()))?;
+            // 会话可能已经自行断开
+            if resp.status() != StatusCode::NOT_FOUND {
+                resp.error_for_status()
+                    .map_err(|e| NeoiotError::EmqxManagementError(e.to_string()))?;
+            }
+        }
+        Ok(client_ids)
+    }
+}
+
+/// 拼接管理API地址, 路径参数会被转义
+fn management_url(segments: &[&str]) -> Result<Url, NeoiotError> {
+    let mut url = Url::parse(&SETTINGS.emqx.management_host)
+        .map_err(|e| NeoiotError::EmqxManagementError(e.to_string()))?;
+    url.path_segments_mut()
+        .map_err(|_| NeoiotError::EmqxManagementError("invalid management host".to_string()))?
+        .pop_if_empty()
+        .extend(["api", "v4"])
+        .extend(segments);
+    Ok(url)
 }
@@ -0,0 +1,14 @@
+use crate::errors::Result;
+use poem::async_trait;
+
+/// 只把通知内容写入日志，用于开发环境
+#[derive(Clone, Default)]
+pub struct LogNotifier;
+
+#[async_trait]
+impl super::Notifier for LogNotifier {
+    async fn send_email(&self, to: &str, subject: &str, body: &str) -> Result<()> {
+        tracing::info!(to, subject, body, "email notification");
+        Ok(())
+    }
+}
@@ -0,0 +1,10 @@
+mod log_notifier;
+use crate::errors::Result;
+pub use log_notifier::LogNotifier;
+use poem::async_trait;
+
+#[async_trait]
+pub trait Notifier: Send + Sync + 'static {
+    /// 发送邮件
+    async fn send_email(&self, to: &str, subject: &str, body: &str) -> Result<()>;
+}
@@ -1,9 +1,26 @@
+use crate::selector::format_label;
 use chrono::{DateTime, Local};
-use entity::{fields, prelude::*, sea_orm::prelude::DateTimeWithTimeZone};
+use entity::{
+    alarms::{AlarmSeverity, AlarmSource, AlarmStatus},
+    audit_logs::{AuditAction, AuditResource},
+    command_job_devices::CommandJobDeviceStatus,
+    command_jobs::CommandJobStatus,
+    data_routes::SinkType,
+    device_uploads::DeviceUploadStatus,
+    devices::LifecycleState,
+    fields,
+    ota_campaign_devices::OtaDeviceStatus,
+    ota_campaigns::{OtaCampaignStatus, OtaStage},
+    prelude::*,
+    rules::{RuleAction, RuleOperator},
+    scheduled_commands::ScheduleTarget,
+    sea_orm::{prelude::DateTimeWithTimeZone, ActiveEnum},
+    webhook_deliveries::{EventType, WebhookDeliveryStatus},
+};
 use poem_openapi::{
-    payload::Json,
-    types::{Email, MaybeUndefined, Password},
-    ApiResponse, Enum, Object,
+    payload::{Attachment, Json, PlainText},
+    types::{multipart::Upload, Email, MaybeUndefined, Password},
+    ApiRequest, ApiResponse, Enum, Multipart, Object,
 };
 
 #[derive(Debug, Object, PartialEq)]
@@ -15,6 +32,10 @@ pub struct Account {
     pub name: String,
     /// 是否超级用户
     pub is_superuser: bool,
+    /// 是否已启用两步验证
+    pub totp_enabled: bool,
+    /// 下次登录时是否必须修改密码
+    pub must_change_password: bool,
     /// 上次登录时间
     pub last_login_at: Option<DateTime<Local>>,
     /// 账户创建时间
@@ -28,6 +49,8 @@ impl From<AccountModel> for Account {
             email: Email(obj.email),
             name: obj.name,
             is_superuser: obj.is_superuser,
+            totp_enabled: obj.totp_enabled,
+            must_change_password: obj.must_change_password,
             last_login_at: obj.last_login_at.map(|v| v.into()),
             created_at: obj.created_at.into(),
         }
@@ -45,6 +68,9 @@ pub struct CreateAccount {
     #[oai(validator(min_length = 8))]
     pub password: Password,
     pub is_super: bool,
+    /// 首次登录时是否必须修改密码
+    #[oai(default)]
+    pub must_change_password: bool,
 }
 
 #[derive(Debug, Object, PartialEq)]
@@ -55,171 +81,2169 @@ pub struct UpdateAccount {
     pub name: Option<String>,
     /// 账户密码
     pub password: Option<Password>,
+    /// 下次登录时是否必须修改密码
+    pub must_change_password: Option<bool>,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct UpdateProfile {
+    /// 账户唯一邮箱，修改时需要同时提供当前密码
+    pub email: Option<Email>,
+    /// 账户名称
+    #[oai(validator(min_length = 3, max_length = 64))]
+    pub name: Option<String>,
+    /// 当前密码
+    pub current_password: Option<Password>,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct ChangePassword {
+    /// 当前密码
+    pub current_password: Password,
+    /// 新密码
+    #[oai(validator(min_length = 8))]
+    pub new_password: Password,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct ForgotPassword {
+    /// 账户邮箱
+    pub email: Email,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct ResetPassword {
+    /// 邮件中收到的重置凭证
+    pub token: String,
+    /// 新密码
+    #[oai(validator(min_length = 8))]
+    pub new_password: Password,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct Accounts {
+    /// 数据列表
+    pub results: Vec<Account>,
+    /// 总数
+    pub total: usize,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct Login {
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct TokenResponse {
+    pub token: String,
+    /// 登录过程中完成两步验证绑定时返回的恢复码，仅返回一次
+    #[oai(skip_serializing_if_is_none)]
+    pub recovery_codes: Option<Vec<String>>,
+}
+
+#[derive(Debug, PartialEq, Enum)]
+pub enum ChallengeKind {
+    /// 需要提交TOTP动态码或恢复码
+    Totp,
+    /// 账号必须先绑定TOTP才能登录
+    TotpEnrollment,
+    /// 账号必须先修改密码才能登录
+    PasswordChange,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct LoginChallenge {
+    /// 用于完成后续验证步骤的临时凭证
+    pub challenge_token: String,
+    /// 需要完成的验证
+    pub challenge: ChallengeKind,
+}
+
+#[derive(ApiResponse)]
+pub enum ObtainTokenResponse {
+    /// 登录成功
+    #[oai(status = "200")]
+    Token(Json<TokenResponse>),
+    /// 密码正确，还需要完成下一步验证
+    #[oai(status = "202")]
+    Challenge(Json<LoginChallenge>),
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct ChallengeRequest {
+    pub challenge_token: String,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct TotpLogin {
+    pub challenge_token: String,
+    /// TOTP动态码或恢复码
+    pub code: String,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct PasswordChangeLogin {
+    pub challenge_token: String,
+    /// 新密码
+    #[oai(validator(min_length = 8))]
+    pub new_password: Password,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct TotpCode {
+    /// TOTP动态码或恢复码
+    pub code: String,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct TotpEnrollment {
+    /// Base32编码的密钥
+    pub secret: String,
+    /// 认证器App扫码使用的地址
+    pub provisioning_uri: String,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct RecoveryCodes {
+    /// 恢复码，仅返回一次，每个只能使用一次
+    pub codes: Vec<String>,
+}
+
+#[derive(Debug, Object, PartialEq, Default)]
+pub struct SecurityPolicy {
+    /// 是否要求所有超级用户启用两步验证
+    pub require_superuser_totp: bool,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct UpdateSecurityPolicy {
+    /// 是否要求所有超级用户启用两步验证
+    pub require_superuser_totp: Option<bool>,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct LoginAttempt {
+    pub id: String,
+    /// 登录邮箱
+    pub email: String,
+    /// 客户端IP地址
+    pub ip_address: String,
+    /// 是否登录成功
+    pub success: bool,
+    /// 失败原因
+    pub reason: Option<String>,
+    /// 登录时间
+    pub created_at: DateTime<Local>,
+}
+
+impl From<LoginAttemptModel> for LoginAttempt {
+    fn from(obj: LoginAttemptModel) -> Self {
+        Self {
+            id: obj.id,
+            email: obj.email,
+            ip_address: obj.ip_address,
+            success: obj.success,
+            reason: obj.reason,
+            created_at: obj.created_at.into(),
+        }
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct LoginAttempts {
+    /// 数据列表
+    pub results: Vec<LoginAttempt>,
+    /// 总数
+    pub total: usize,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct AuditLog {
+    pub id: String,
+    /// 资源所属账号
+    pub account_id: String,
+    /// 操作人
+    pub actor_id: String,
+    /// 操作类型
+    pub action: AuditAction,
+    /// 资源类型
+    pub resource_type: AuditResource,
+    /// 资源ID
+    pub resource_id: String,
+    /// 变更前的字段值
+    pub before: Option<serde_json::Value>,
+    /// 变更后的字段值
+    pub after: Option<serde_json::Value>,
+    /// 操作人IP地址
+    pub ip_address: String,
+    /// 操作时间
+    pub created_at: DateTime<Local>,
+}
+
+impl From<AuditLogModel> for AuditLog {
+    fn from(obj: AuditLogModel) -> Self {
+        Self {
+            id: obj.id,
+            account_id: obj.account_id,
+            actor_id: obj.actor_id,
+            action: obj.action,
+            resource_type: obj.resource_type,
+            resource_id: obj.resource_id,
+            before: obj.before,
+            after: obj.after,
+            ip_address: obj.ip_address,
+            created_at: obj.created_at.into(),
+        }
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct AuditLogs {
+    /// 数据列表
+    pub results: Vec<AuditLog>,
+    /// 总数
+    pub total: usize,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct DeviceConnection {
+    pub id: String,
+    /// 所属设备原因
+    pub device_id: String,
+    /// 连接状态
+    pub connected: bool,
+    /// client_id
+    pub client_id: String,
+    /// 连接节点
+    pub node: String,
+    pub keep_alive: String,
+    /// IP地址`
+    pub ip_address: String,
+    /// 协议版本
+    pub proto_ver: i64,
+    /// 连接时间
+    pub connected_at: DateTimeWithTimeZone,
+    /// 断开连接的时间
+    pub disconnected_at: DateTimeWithTimeZone,
+    /// 断开连接原因
+    pub disconnected_reason: String,
+}
+impl From<DeviceConnectionModel> for DeviceConnection {
+    fn from(model: DeviceConnectionModel) -> Self {
+        Self {
+            id: model.id,
+            device_id: model.device_id,
+            connected: model.connected,
+            client_id: model.client_id,
+            node: model.node,
+            keep_alive: model.keep_alive,
+            ip_address: model.ip_address,
+            proto_ver: model.proto_ver,
+            connected_at: model.connected_at,
+            disconnected_at: model.disconnected_at,
+            disconnected_reason: model.disconnected_reason,
+        }
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct DeviceConnections {
+    total: usize,
+    results: Vec<DeviceConnection>,
+}
+impl From<(Vec<DeviceConnectionModel>, usize)> for DeviceConnections {
+    fn from(tuple: (Vec<DeviceConnectionModel>, usize)) -> Self {
+        let (models, total) = tuple;
+        Self {
+            total,
+            results: models.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct DeviceModelWithRelated {
+    pub device: DeviceModel,
+    pub labels: Vec<LabelModel>,
+    pub schema: SchemaModel,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct DeviceWithLables {
+    /// 设备ID
+    pub id: String,
+    /// 设备名称
+    pub name: String,
+    /// 设备标签列表
+    pub labels: Vec<String>,
+    /// 数据模型
+    pub schema: Schema,
+    /// 设备是否激活
+    pub is_active: bool,
+    /// 设备是否在线
+    pub is_online: bool,
+    /// 硬件序列号, 自注册的设备才有
+    pub hardware_serial: Option<String>,
+    /// 是否为网关
+    pub is_super_device: bool,
+    /// 所属网关ID
+    pub parent_id: Option<String>,
+    /// 生命周期状态
+    pub lifecycle_state: LifecycleState,
+    /// 退役时间
+    pub decommissioned_at: Option<DateTime<Local>>,
+    /// 设备创建时间
+    pub created_at: DateTime<Local>,
+    /// 设备的连接凭据, 只在创建设备时返回一次
+    #[oai(skip_serializing_if_is_none)]
+    pub credential: Option<DeviceCredentialSecret>,
+}
+
+impl From<DeviceModelWithRelated> for DeviceWithLables {
+    fn from(obj: DeviceModelWithRelated) -> Self {
+        DeviceWithLables {
+            id: obj.device.id,
+            name: obj.device.name,
+            labels: obj
+                .labels
+                .into_iter()
+                .map(|x| format_label(&x.name, x.value.as_deref()))
+                .collect(),
+            schema: obj.schema.into(),
+            is_active: obj.device.is_active,
+            is_online: obj.device.is_online,
+            hardware_serial: obj.device.hardware_serial,
+            is_super_device: obj.device.is_super_device,
+            parent_id: obj.device.parent_id,
+            lifecycle_state: obj.device.lifecycle_state,
+            decommissioned_at: obj.device.decommissioned_at.map(Into::into),
+            created_at: obj.device.created_at.into(),
+            credential: None,
+        }
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct Device {
+    /// 设备ID
+    pub id: String,
+    /// 设备名称
+    pub name: String,
+    /// 数据模型ID
+    pub schema_id: String,
+    /// 设备是否激活
+    pub is_active: bool,
+    /// 设备是否在线
+    pub is_online: bool,
+    /// 硬件序列号, 自注册的设备才有
+    pub hardware_serial: Option<String>,
+    /// 是否为网关
+    pub is_super_device: bool,
+    /// 所属网关ID
+    pub parent_id: Option<String>,
+    /// 生命周期状态
+    pub lifecycle_state: LifecycleState,
+    /// 退役时间
+    pub decommissioned_at: Option<DateTime<Local>>,
+    /// 设备上报的固件版本
+    pub firmware_version: Option<String>,
+    /// 设备创建时间
+    pub created_at: DateTime<Local>,
+}
+impl From<DeviceModel> for Device {
+    fn from(obj: DeviceModel) -> Self {
+        Device {
+            id: obj.id,
+            name: obj.name,
+            schema_id: obj.schema_id.to_string(),
+            is_active: obj.is_active,
+            is_online: obj.is_online,
+            hardware_serial: obj.hardware_serial,
+            is_super_device: obj.is_super_device,
+            parent_id: obj.parent_id,
+            lifecycle_state: obj.lifecycle_state,
+            decommissioned_at: obj.decommissioned_at.map(Into::into),
+            firmware_version: obj.firmware_version,
+            created_at: obj.created_at.into(),
+        }
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct CreateDevice {
+    /// 设备名称
+    pub name: String,
+    /// 数据模型ID
+    pub schema_id: String,
+    /// 标签列表
+    pub label_ids: Vec<String>,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct BulkDeviceRow {
+    /// 设备名称
+    pub name: String,
+    /// 数据模型ID或名称
+    pub schema: String,
+    /// 标签列表, 例如 `site=sh01`
+    #[oai(default)]
+    pub labels: Vec<String>,
+    /// 设备MQTT连接密码, 为空时自动生成, 至少16位
+    pub mqtt_password: Option<String>,
+}
+
+#[derive(ApiRequest)]
+pub enum BulkCreateDevices {
+    /// JSON格式
+    Json(Json<Vec<BulkDeviceRow>>),
+    /// CSV格式, 表头为`name,schema,labels,mqtt_password`, 多个标签以`;`分隔
+    #[oai(content_type = "text/csv")]
+    Csv(PlainText<String>),
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct BulkDeviceError {
+    /// 数据行号, 从1开始, 不含CSV表头
+    pub row: usize,
+    /// 错误信息
+    pub message: String,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct BulkDeviceReport {
+    /// 总行数
+    pub total: usize,
+    /// 校验通过的行数
+    pub valid: usize,
+    /// 校验失败的行
+    pub errors: Vec<BulkDeviceError>,
+}
+
+#[derive(ApiResponse)]
+pub enum BulkCreateDevicesResponse {
+    /// 试运行, 仅返回校验结果
+    #[oai(status = "200")]
+    DryRun(Json<BulkDeviceReport>),
+    /// 创建成功, 返回包含设备凭据的CSV文件, 凭据只会返回这一次
+    #[oai(status = "201")]
+    Created(Attachment<Vec<u8>>),
+    /// 校验失败, 没有创建任何设备
+    #[oai(status = "422")]
+    Invalid(Json<BulkDeviceReport>),
+}
+
+/// 新签发的设备凭据, 明文只在签发时可见
+#[derive(Debug, Clone)]
+pub struct IssuedCredential {
+    pub credential: DeviceCredentialModel,
+    pub secret: String,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct DeviceCredential {
+    /// 凭据ID
+    pub id: String,
+    /// 凭据备注
+    pub name: Option<String>,
+    /// 过期时间, 为空表示永不过期
+    pub expires_at: Option<DateTime<Local>>,
+    /// 吊销时间
+    pub revoked_at: Option<DateTime<Local>>,
+    /// 最近一次用于连接的时间
+    pub last_used_at: Option<DateTime<Local>>,
+    /// 创建时间
+    pub created_at: DateTime<Local>,
+}
+
+impl From<DeviceCredentialModel> for DeviceCredential {
+    fn from(obj: DeviceCredentialModel) -> Self {
+        Self {
+            id: obj.id,
+            name: obj.name,
+            expires_at: obj.expires_at.map(Into::into),
+            revoked_at: obj.revoked_at.map(Into::into),
+            last_used_at: obj.last_used_at.map(Into::into),
+            created_at: obj.created_at.into(),
+        }
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct DeviceCredentials {
+    /// 数据列表
+    pub results: Vec<DeviceCredential>,
+    /// 总数
+    pub total: usize,
+}
+
+#[derive(Debug, Object, Clone, PartialEq)]
+pub struct DeviceCredentialSecret {
+    /// 凭据ID
+    pub id: String,
+    /// MQTT用户名
+    pub username: String,
+    /// MQTT密码, 只返回这一次
+    pub password: String,
+    /// 过期时间, 为空表示永不过期
+    pub expires_at: Option<DateTime<Local>>,
+}
+
+impl DeviceCredentialSecret {
+    pub fn new(username: &str, issued: IssuedCredential) -> Self {
+        Self {
+            id: issued.credential.id,
+            username: username.to_string(),
+            password: issued.secret,
+            expires_at: issued.credential.expires_at.map(Into::into),
+        }
+    }
+}
+
+const fn default_overlap_secs() -> u32 {
+    86400
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct CreateDeviceCredential {
+    /// 凭据备注
+    pub name: Option<String>,
+    /// 有效期(秒), 为空表示永不过期
+    #[oai(validator(minimum(value = "60")))]
+    pub expires_in: Option<u32>,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct RotateDeviceCredential {
+    /// 新凭据备注
+    pub name: Option<String>,
+    /// 新旧凭据同时可用的时长(秒), 之后旧凭据全部失效
+    #[oai(
+        default = "default_overlap_secs",
+        validator(maximum(value = "2592000"), minimum(value = "0"))
+    )]
+    pub overlap_secs: u32,
+}
+
+const fn default_cert_validity_days() -> u32 {
+    365
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct IssueDeviceCertificate {
+    /// PEM格式的证书签名请求, 证书的CN和SAN由服务端设置为设备ID
+    pub csr: String,
+    /// 证书有效期(天)
+    #[oai(
+        default = "default_cert_validity_days",
+        validator(maximum(value = "3650"), minimum(value = "1"))
+    )]
+    pub validity_days: u32,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct DeviceCertificate {
+    /// 证书序列号(hex)
+    pub serial: String,
+    /// 证书SHA256指纹(hex)
+    pub fingerprint: String,
+    /// PEM格式的证书
+    pub certificate: String,
+    /// 生效时间
+    pub not_before: DateTime<Local>,
+    /// 过期时间
+    pub not_after: DateTime<Local>,
+    /// 吊销时间
+    pub revoked_at: Option<DateTime<Local>>,
+    /// 签发时间
+    pub created_at: DateTime<Local>,
+}
+
+impl From<DeviceCertificateModel> for DeviceCertificate {
+    fn from(obj: DeviceCertificateModel) -> Self {
+        Self {
+            serial: obj.serial,
+            fingerprint: obj.fingerprint,
+            certificate: obj.cert_pem,
+            not_before: obj.not_before.into(),
+            not_after: obj.not_after.into(),
+            revoked_at: obj.revoked_at.map(Into::into),
+            created_at: obj.created_at.into(),
+        }
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct DeviceCertificates {
+    /// 数据列表
+    pub results: Vec<DeviceCertificate>,
+    /// 总数
+    pub total: usize,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct UpdateDevice {
+    /// 设备名称
+    pub name: Option<String>,
+    /// 设备激活状态, 即在active和suspended之间变更生命周期状态, 停用后设备会被断开且无法再连接或接收指令
+    pub is_active: Option<bool>,
+    /// 设备标签
+    pub label_ids: Option<Vec<String>>,
+    /// 数据模型
+    pub schema_id: Option<String>,
+    /// 是否为网关, 网关可以代子设备收发消息, 仍有子设备时不能取消
+    pub is_super_device: Option<bool>,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct DeviceChildren {
+    /// 子设备ID列表
+    #[oai(validator(max_items = 1000))]
+    pub device_ids: Vec<String>,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct TransitionDevice {
+    /// 目标状态
+    pub state: LifecycleState,
+    /// 变更原因
+    #[oai(validator(max_length = 256))]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct DeviceLifecycleEvent {
+    /// 事件ID
+    pub id: String,
+    /// 变更前状态
+    pub from_state: LifecycleState,
+    /// 变更后状态
+    pub to_state: LifecycleState,
+    /// 变更原因
+    pub reason: Option<String>,
+    /// 操作者, 账号ID或`device`
+    pub actor: String,
+    /// 变更时间
+    pub created_at: DateTime<Local>,
+}
+impl From<DeviceLifecycleEventModel> for DeviceLifecycleEvent {
+    fn from(obj: DeviceLifecycleEventModel) -> Self {
+        Self {
+            id: obj.id,
+            from_state: obj.from_state,
+            to_state: obj.to_state,
+            reason: obj.reason,
+            actor: obj.actor,
+            created_at: obj.created_at.into(),
+        }
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct DeviceLifecycleEvents {
+    /// 数据列表
+    pub results: Vec<DeviceLifecycleEvent>,
+    /// 总数
+    pub total: usize,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct D2dRule {
+    /// 规则ID
+    pub id: String,
+    /// 发送方设备ID
+    pub sender_id: String,
+    /// 目标设备ID
+    pub target_device_id: Option<String>,
+    /// 目标标签ID, 发送方可以向带有该标签的设备广播
+    pub target_label_id: Option<String>,
+    /// 是否在服务端记录消息
+    pub log_messages: bool,
+    /// 创建时间
+    pub created_at: DateTime<Local>,
+}
+
+impl From<D2dRuleModel> for D2dRule {
+    fn from(obj: D2dRuleModel) -> Self {
+        Self {
+            id: obj.id,
+            sender_id: obj.sender_id,
+            target_device_id: obj.target_device_id,
+            target_label_id: obj.target_label_id,
+            log_messages: obj.log_messages,
+            created_at: obj.created_at.into(),
+        }
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct D2dRules {
+    /// 数据列表
+    pub results: Vec<D2dRule>,
+    /// 总数
+    pub total: usize,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct CreateD2dRule {
+    /// 发送方设备ID
+    pub sender_id: String,
+    /// 目标设备ID, 与目标标签二选一
+    pub target_device_id: Option<String>,
+    /// 目标标签ID, 与目标设备二选一
+    pub target_label_id: Option<String>,
+    /// 是否在服务端记录消息, 需要在EMQX中配置消息发布的WebHook
+    #[oai(default)]
+    pub log_messages: bool,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct D2dMessage {
+    /// 日志ID
+    pub id: String,
+    /// 匹配的规则ID, 规则删除后为空
+    pub rule_id: Option<String>,
+    /// 发送方设备ID
+    pub sender_id: String,
+    /// 目标设备ID或标签
+    pub target: String,
+    /// 消息主题
+    pub topic: String,
+    /// 消息内容
+    pub payload: String,
+    /// 记录时间
+    pub created_at: DateTime<Local>,
+}
+
+impl From<D2dMessageLogModel> for D2dMessage {
+    fn from(obj: D2dMessageLogModel) -> Self {
+        Self {
+            id: obj.id,
+            rule_id: obj.rule_id,
+            sender_id: obj.sender_id,
+            target: obj.target,
+            topic: obj.topic,
+            payload: obj.payload,
+            created_at: obj.created_at.into(),
+        }
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct D2dMessages {
+    /// 数据列表
+    pub results: Vec<D2dMessage>,
+    /// 总数
+    pub total: usize,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct Rule {
+    /// 规则ID
+    pub id: String,
+    /// 规则名称
+    pub name: String,
+    /// 生效设备的标签选择器, 为空时对账号下所有设备生效
+    pub selector: Option<String>,
+    /// 字段标识
+    pub field: String,
+    /// 比较运算符
+    pub operator: RuleOperator,
+    /// 阈值
+    pub threshold: f64,
+    /// 条件需要持续的时长(秒)
+    pub duration_secs: i32,
+    /// 触发后执行的动作
+    pub action: RuleAction,
+    /// 下发的指令名称
+    pub command: Option<String>,
+    /// 下发的指令负载
+    pub command_payload: Option<String>,
+    /// Webhook地址
+    pub webhook_url: Option<String>,
+    /// 动作为alarm时的告警级别
+    pub alarm_severity: AlarmSeverity,
+    /// 是否启用
+    pub is_active: bool,
+    /// 创建时间
+    pub created_at: DateTime<Local>,
+    /// 更新时间
+    pub updated_at: Option<DateTime<Local>>,
+}
+impl From<RuleModel> for Rule {
+    fn from(obj: RuleModel) -> Self {
+        Self {
+            id: obj.id,
+            name: obj.name,
+            selector: obj.selector,
+            field: obj.field,
+            operator: obj.operator,
+            threshold: obj.threshold,
+            duration_secs: obj.duration_secs,
+            action: obj.action,
+            command: obj.command,
+            command_payload: obj.command_payload,
+            webhook_url: obj.webhook_url,
+            alarm_severity: obj.alarm_severity,
+            is_active: obj.is_active,
+            created_at: obj.created_at.into(),
+            updated_at: obj.updated_at.map(Into::into),
+        }
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct Rules {
+    /// 数据列表
+    pub results: Vec<Rule>,
+    /// 总数
+    pub total: usize,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct CreateRule {
+    /// 规则名称
+    #[oai(validator(min_length = 1, max_length = 128))]
+    pub name: String,
+    /// 生效设备的标签选择器, 例如: `cold-room`, 为空时对账号下所有设备生效
+    pub selector: Option<String>,
+    /// 字段标识, 对应主题`metrics/{account_id}/{device_id}/{field}`
+    pub field: String,
+    /// 比较运算符
+    pub operator: RuleOperator,
+    /// 阈值
+    pub threshold: f64,
+    /// 条件需要持续的时长(秒), 为0时立即触发
+    #[oai(default, validator(minimum(value = "0"), maximum(value = "86400")))]
+    pub duration_secs: i32,
+    /// 触发后执行的动作
+    pub action: RuleAction,
+    /// 下发的指令名称, 动作为command时必填
+    pub command: Option<String>,
+    /// 下发的指令负载
+    pub command_payload: Option<String>,
+    /// Webhook地址, 动作为webhook时必填
+    pub webhook_url: Option<String>,
+    /// 动作为alarm时的告警级别, 条件不再满足后告警自动清除
+    #[oai(default = "default_alarm_severity")]
+    pub alarm_severity: AlarmSeverity,
+}
+
+const fn default_alarm_severity() -> AlarmSeverity {
+    AlarmSeverity::Major
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct UpdateRule {
+    /// 规则名称
+    #[oai(validator(min_length = 1, max_length = 128))]
+    pub name: Option<String>,
+    /// 生效设备的标签选择器
+    pub selector: MaybeUndefined<String>,
+    /// 比较运算符
+    pub operator: Option<RuleOperator>,
+    /// 阈值
+    pub threshold: Option<f64>,
+    /// 条件需要持续的时长(秒)
+    #[oai(validator(minimum(value = "0"), maximum(value = "86400")))]
+    pub duration_secs: Option<i32>,
+    /// 触发后执行的动作
+    pub action: Option<RuleAction>,
+    /// 下发的指令名称
+    pub command: MaybeUndefined<String>,
+    /// 下发的指令负载
+    pub command_payload: MaybeUndefined<String>,
+    /// Webhook地址
+    pub webhook_url: MaybeUndefined<String>,
+    /// 动作为alarm时的告警级别
+    pub alarm_severity: Option<AlarmSeverity>,
+    /// 是否启用
+    pub is_active: Option<bool>,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct RuleExecution {
+    /// 执行记录ID
+    pub id: String,
+    /// 规则ID
+    pub rule_id: String,
+    /// 设备ID
+    pub device_id: String,
+    /// 触发时的字段值
+    pub value: f64,
+    /// 执行的动作
+    pub action: RuleAction,
+    /// 执行失败的原因
+    pub error: Option<String>,
+    /// 执行时间
+    pub created_at: DateTime<Local>,
+}
+impl From<RuleExecutionModel> for RuleExecution {
+    fn from(obj: RuleExecutionModel) -> Self {
+        Self {
+            id: obj.id,
+            rule_id: obj.rule_id,
+            device_id: obj.device_id,
+            value: obj.value,
+            action: obj.action,
+            error: obj.error,
+            created_at: obj.created_at.into(),
+        }
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct RuleExecutions {
+    /// 数据列表
+    pub results: Vec<RuleExecution>,
+    /// 总数
+    pub total: usize,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct Alarm {
+    /// 告警ID
+    pub id: String,
+    /// 设备ID
+    pub device_id: String,
+    /// 告警类型
+    pub alarm_type: String,
+    /// 告警来源
+    pub source: AlarmSource,
+    /// 触发告警的规则ID
+    pub rule_id: Option<String>,
+    /// 告警级别
+    pub severity: AlarmSeverity,
+    /// 告警状态
+    pub status: AlarmStatus,
+    /// 告警内容
+    pub message: String,
+    /// 清除前重复触发的次数
+    pub count: i32,
+    /// 首次触发时间
+    pub raised_at: DateTime<Local>,
+    /// 最近一次触发时间
+    pub last_raised_at: DateTime<Local>,
+    /// 最近一次升级时间
+    pub escalated_at: Option<DateTime<Local>>,
+    /// 确认时间
+    pub acknowledged_at: Option<DateTime<Local>>,
+    /// 确认人
+    pub acknowledged_by: Option<String>,
+    /// 确认备注
+    pub ack_note: Option<String>,
+    /// 清除时间
+    pub cleared_at: Option<DateTime<Local>>,
+    /// 清除人, 自动清除时为`system`
+    pub cleared_by: Option<String>,
+}
+impl From<AlarmModel> for Alarm {
+    fn from(obj: AlarmModel) -> Self {
+        Self {
+            id: obj.id,
+            device_id: obj.device_id,
+            alarm_type: obj.alarm_type,
+            source: obj.source,
+            rule_id: obj.rule_id,
+            severity: obj.severity,
+            status: obj.status,
+            message: obj.message,
+            count: obj.count,
+            raised_at: obj.raised_at.into(),
+            last_raised_at: obj.last_raised_at.into(),
+            escalated_at: obj.escalated_at.map(Into::into),
+            acknowledged_at: obj.acknowledged_at.map(Into::into),
+            acknowledged_by: obj.acknowledged_by,
+            ack_note: obj.ack_note,
+            cleared_at: obj.cleared_at.map(Into::into),
+            cleared_by: obj.cleared_by,
+        }
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct Alarms {
+    /// 数据列表
+    pub results: Vec<Alarm>,
+    /// 总数
+    pub total: usize,
+}
+
+/// 未清除告警的数量
+#[derive(Debug, Object, PartialEq, Default)]
+pub struct AlarmCounts {
+    pub critical: usize,
+    pub major: usize,
+    pub minor: usize,
+    pub warning: usize,
+    pub total: usize,
+}
+impl From<Vec<(AlarmSeverity, usize)>> for AlarmCounts {
+    fn from(counts: Vec<(AlarmSeverity, usize)>) -> Self {
+        let mut result = AlarmCounts::default();
+        for (severity, count) in counts {
+            match severity {
+                AlarmSeverity::Critical => result.critical += count,
+                AlarmSeverity::Major => result.major += count,
+                AlarmSeverity::Minor => result.minor += count,
+                AlarmSeverity::Warning => result.warning += count,
+            }
+            result.total += count;
+        }
+        result
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct AcknowledgeAlarm {
+    /// 确认备注
+    #[oai(validator(max_length = 1024))]
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct Webhook {
+    /// Webhook ID
+    pub id: String,
+    /// 推送地址
+    pub url: String,
+    /// 备注
+    pub description: Option<String>,
+    /// 订阅的事件类型, 为空表示订阅所有事件
+    pub event_types: Vec<EventType>,
+    /// 签名密钥, 只在创建和重置时返回
+    pub secret: Option<String>,
+    /// 是否启用, 停用期间的投递暂停, 重新启用后继续
+    pub is_active: bool,
+    /// 创建时间
+    pub created_at: DateTime<Local>,
+    /// 更新时间
+    pub updated_at: Option<DateTime<Local>>,
+}
+impl From<WebhookModel> for Webhook {
+    fn from(obj: WebhookModel) -> Self {
+        Self {
+            id: obj.id,
+            url: obj.url,
+            description: obj.description,
+            event_types: event_types(&obj.event_types),
+            secret: None,
+            is_active: obj.is_active,
+            created_at: obj.created_at.into(),
+            updated_at: obj.updated_at.map(Into::into),
+        }
+    }
+}
+impl Webhook {
+    pub fn with_secret(obj: WebhookModel) -> Self {
+        let secret = obj.secret.clone();
+        Self {
+            secret: Some(secret),
+            ..obj.into()
+        }
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct Webhooks {
+    /// 数据列表
+    pub results: Vec<Webhook>,
+    /// 总数
+    pub total: usize,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct CreateWebhook {
+    /// 推送地址, 支持http和https
+    #[oai(validator(min_length = 1, max_length = 2048))]
+    pub url: String,
+    /// 备注
+    #[oai(validator(max_length = 256))]
+    pub description: Option<String>,
+    /// 订阅的事件类型, 为空表示订阅所有事件
+    #[oai(default)]
+    pub event_types: Vec<EventType>,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct UpdateWebhook {
+    /// 推送地址
+    #[oai(validator(min_length = 1, max_length = 2048))]
+    pub url: Option<String>,
+    /// 备注
+    pub description: MaybeUndefined<String>,
+    /// 订阅的事件类型, 为空表示订阅所有事件
+    pub event_types: Option<Vec<EventType>>,
+    /// 是否启用
+    pub is_active: Option<bool>,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct WebhookDelivery {
+    /// 投递记录ID, 与请求头`X-Neoiot-Delivery`一致
+    pub id: String,
+    /// Webhook ID
+    pub webhook_id: String,
+    /// 事件类型
+    pub event_type: EventType,
+    /// 推送的消息体
+    pub payload: serde_json::Value,
+    /// 投递状态
+    pub status: WebhookDeliveryStatus,
+    /// 已投递次数
+    pub attempts: i32,
+    /// 下次投递时间
+    pub next_attempt_at: DateTime<Local>,
+    /// 最近一次投递的响应状态码
+    pub last_status_code: Option<i32>,
+    /// 最近一次投递失败的原因
+    pub last_error: Option<String>,
+    /// 投递成功时间
+    pub delivered_at: Option<DateTime<Local>>,
+    /// 创建时间
+    pub created_at: DateTime<Local>,
+}
+impl From<WebhookDeliveryModel> for WebhookDelivery {
+    fn from(obj: WebhookDeliveryModel) -> Self {
+        Self {
+            id: obj.id,
+            webhook_id: obj.webhook_id,
+            event_type: obj.event_type,
+            payload: obj.payload,
+            status: obj.status,
+            attempts: obj.attempts,
+            next_attempt_at: obj.next_attempt_at.into(),
+            last_status_code: obj.last_status_code,
+            last_error: obj.last_error,
+            delivered_at: obj.delivered_at.map(Into::into),
+            created_at: obj.created_at.into(),
+        }
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct WebhookDeliveries {
+    /// 数据列表
+    pub results: Vec<WebhookDelivery>,
+    /// 总数
+    pub total: usize,
+}
+
+/// 以名称数组保存的事件类型
+fn event_types(value: &serde_json::Value) -> Vec<EventType> {
+    value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|t| t.as_str())
+        .filter_map(|t| EventType::try_from_value(&t.to_string()).ok())
+        .collect()
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct DataRoute {
+    /// 路由ID
+    pub id: String,
+    /// 路由名称
+    pub name: String,
+    /// 转发目标类型
+    pub sink_type: SinkType,
+    /// 目标地址
+    pub endpoint: Option<String>,
+    /// 目标主题, 文件转发时为文件名前缀
+    pub topic: Option<String>,
+    /// 设备的标签选择器
+    pub selector: Option<String>,
+    /// 设备的数据模型ID
+    pub schema_id: Option<String>,
+    /// 转发的事件类型, 为空表示转发所有事件
+    pub event_types: Vec<EventType>,
+    /// 转发的遥测字段, 为空表示转发所有字段
+    pub fields: Vec<String>,
+    /// 是否启用
+    pub is_active: bool,
+    /// 已转发的记录数
+    pub forwarded_count: i64,
+    /// 转发失败的记录数
+    pub error_count: i64,
+    /// 最近一次转发成功的时间
+    pub last_forwarded_at: Option<DateTime<Local>>,
+    /// 最近一次转发失败的原因
+    pub last_error: Option<String>,
+    /// 最近一次转发失败的时间
+    pub last_error_at: Option<DateTime<Local>>,
+    /// 创建时间
+    pub created_at: DateTime<Local>,
+    /// 更新时间
+    pub updated_at: Option<DateTime<Local>>,
+}
+impl From<DataRouteModel> for DataRoute {
+    fn from(obj: DataRouteModel) -> Self {
+        Self {
+            id: obj.id,
+            name: obj.name,
+            sink_type: obj.sink_type,
+            endpoint: obj.endpoint,
+            topic: obj.topic,
+            selector: obj.selector,
+            schema_id: obj.schema_id,
+            event_types: event_types(&obj.event_types),
+            fields: obj
+                .fields
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|f| f.as_str().map(ToString::to_string))
+                .collect(),
+            is_active: obj.is_active,
+            forwarded_count: obj.forwarded_count,
+            error_count: obj.error_count,
+            last_forwarded_at: obj.last_forwarded_at.map(Into::into),
+            last_error: obj.last_error,
+            last_error_at: obj.last_error_at.map(Into::into),
+            created_at: obj.created_at.into(),
+            updated_at: obj.updated_at.map(Into::into),
+        }
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct DataRoutes {
+    /// 数据列表
+    pub results: Vec<DataRoute>,
+    /// 总数
+    pub total: usize,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct CreateDataRoute {
+    /// 路由名称
+    #[oai(validator(min_length = 1, max_length = 128))]
+    pub name: String,
+    /// 转发目标类型
+    pub sink_type: SinkType,
+    /// 目标地址: pulsar为`pulsar://host:6650`, kafka为Kafka REST Proxy地址, http为接收地址
+    pub endpoint: Option<String>,
+    /// 目标主题, 文件转发时为文件名前缀, 只能包含字母、数字、`-`和`_`, 文件写在账号自己的目录下
+    pub topic: Option<String>,
+    /// 设备的标签选择器, 为空时不按标签过滤
+    pub selector: Option<String>,
+    /// 设备的数据模型ID, 为空时不按数据模型过滤
+    pub schema_id: Option<String>,
+    /// 转发的事件类型, 为空表示转发所有事件
+    #[oai(default)]
+    pub event_types: Vec<EventType>,
+    /// 转发的遥测字段, 为空表示转发所有字段
+    #[oai(default)]
+    pub fields: Vec<String>,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct UpdateDataRoute {
+    /// 路由名称
+    #[oai(validator(min_length = 1, max_length = 128))]
+    pub name: Option<String>,
+    /// 转发目标类型
+    pub sink_type: Option<SinkType>,
+    /// 目标地址
+    pub endpoint: MaybeUndefined<String>,
+    /// 目标主题
+    pub topic: MaybeUndefined<String>,
+    /// 设备的标签选择器
+    pub selector: MaybeUndefined<String>,
+    /// 设备的数据模型ID
+    pub schema_id: MaybeUndefined<String>,
+    /// 转发的事件类型
+    pub event_types: Option<Vec<EventType>>,
+    /// 转发的遥测字段
+    pub fields: Option<Vec<String>>,
+    /// 是否启用
+    pub is_active: Option<bool>,
+}
+
+fn default_timezone() -> String {
+    "UTC".to_string()
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct ScheduledCommand {
+    /// 定时指令ID
+    pub id: String,
+    /// 定时指令名称
+    pub name: String,
+    /// 下发目标类型
+    pub target_type: ScheduleTarget,
+    /// 设备ID或标签ID
+    pub target_id: String,
+    /// 指令名称
+    pub command: String,
+    /// 负载信息
+    pub payload: String,
+    /// 指令QOS
+    pub qos: i16,
+    /// 指令过期时间（秒）
+    pub ttl: Option<i32>,
+    /// cron表达式
+    pub cron: String,
+    /// 计算执行时间使用的时区
+    pub timezone: String,
+    /// 是否启用
+    pub is_active: bool,
+    /// 下次执行时间
+    pub next_run_at: Option<DateTime<Local>>,
+    /// 上次执行时间
+    pub last_run_at: Option<DateTime<Local>>,
+    /// 创建时间
+    pub created_at: DateTime<Local>,
+    /// 更新时间
+    pub updated_at: Option<DateTime<Local>>,
+}
+impl From<ScheduledCommandModel> for ScheduledCommand {
+    fn from(obj: ScheduledCommandModel) -> Self {
+        Self {
+            id: obj.id,
+            name: obj.name,
+            target_type: obj.target_type,
+            target_id: obj.target_id,
+            command: obj.command,
+            payload: obj.payload,
+            qos: obj.qos,
+            ttl: obj.ttl,
+            cron: obj.cron,
+            timezone: obj.timezone,
+            is_active: obj.is_active,
+            next_run_at: obj.next_run_at.map(Into::into),
+            last_run_at: obj.last_run_at.map(Into::into),
+            created_at: obj.created_at.into(),
+            updated_at: obj.updated_at.map(Into::into),
+        }
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct ScheduledCommands {
+    /// 数据列表
+    pub results: Vec<ScheduledCommand>,
+    /// 总数
+    pub total: usize,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct CreateScheduledCommand {
+    /// 定时指令名称
+    #[oai(validator(min_length = 1, max_length = 128))]
+    pub name: String,
+    /// 下发目标类型, 创建后不可修改
+    pub target_type: ScheduleTarget,
+    /// 设备ID或标签ID, 创建后不可修改
+    pub target_id: String,
+    /// 指令名称
+    #[oai(validator(min_length = 1))]
+    pub command: String,
+    /// 负载信息
+    #[oai(default)]
+    pub payload: String,
+    /// 指令QOS
+    #[oai(
+        default = "default_qos",
+        validator(maximum(value = "2"), minimum(value = "0"))
+    )]
+    pub qos: u8,
+    /// 指令过期时间（秒）
+    pub ttl: Option<usize>,
+    /// cron表达式, 例如每天03:00: `0 3 * * *`, 也支持带秒的六段格式;
+    /// 五段格式的星期按标准cron计数(0和7为周日), 六段格式建议使用英文缩写, 如`Mon-Fri`
+    pub cron: String,
+    /// IANA时区名称, 例如: `Asia/Shanghai`, 默认为UTC
+    #[oai(default = "default_timezone")]
+    pub timezone: String,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct UpdateScheduledCommand {
+    /// 定时指令名称
+    #[oai(validator(min_length = 1, max_length = 128))]
+    pub name: Option<String>,
+    /// 指令名称
+    #[oai(validator(min_length = 1))]
+    pub command: Option<String>,
+    /// 负载信息
+    pub payload: Option<String>,
+    /// 指令QOS
+    #[oai(validator(maximum(value = "2"), minimum(value = "0")))]
+    pub qos: Option<u8>,
+    /// 指令过期时间（秒）
+    pub ttl: MaybeUndefined<usize>,
+    /// cron表达式
+    pub cron: Option<String>,
+    /// IANA时区名称
+    pub timezone: Option<String>,
+    /// 是否启用, 重新启用后从当前时间开始计算下次执行时间
+    pub is_active: Option<bool>,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct ScheduledCommandRun {
+    /// 执行记录ID
+    pub id: String,
+    /// 定时指令ID
+    pub schedule_id: String,
+    /// 计划执行时间
+    pub scheduled_at: DateTime<Local>,
+    /// 下发的指令ID, 下发失败时为空
+    pub message_id: Option<String>,
+    /// 下发失败的原因
+    pub error: Option<String>,
+    /// 实际执行时间
+    pub created_at: DateTime<Local>,
+}
+impl From<ScheduledCommandRunModel> for ScheduledCommandRun {
+    fn from(obj: ScheduledCommandRunModel) -> Self {
+        Self {
+            id: obj.id,
+            schedule_id: obj.schedule_id,
+            scheduled_at: obj.scheduled_at.into(),
+            message_id: obj.message_id,
+            error: obj.error,
+            created_at: obj.created_at.into(),
+        }
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct ScheduledCommandRuns {
+    /// 数据列表
+    pub results: Vec<ScheduledCommandRun>,
+    /// 总数
+    pub total: usize,
+}
+
+const fn default_job_rate_limit() -> i32 {
+    10
+}
+const fn default_job_timeout_secs() -> i32 {
+    60
+}
+const fn default_canary_percent() -> i16 {
+    10
+}
+const fn default_failure_threshold() -> i16 {
+    20
+}
+const fn default_min_samples() -> i32 {
+    5
+}
+const fn default_ota_rate_limit() -> i32 {
+    10
+}
+const fn default_ota_timeout_secs() -> i32 {
+    3600
+}
+
+/// 批量指令任务中各状态的设备数
+#[derive(Debug, Object, PartialEq, Default)]
+pub struct CommandJobProgress {
+    pub pending: usize,
+    pub sent: usize,
+    pub responded: usize,
+    pub failed: usize,
+    pub timed_out: usize,
+    pub cancelled: usize,
+}
+impl From<Vec<(CommandJobDeviceStatus, usize)>> for CommandJobProgress {
+    fn from(counts: Vec<(CommandJobDeviceStatus, usize)>) -> Self {
+        let mut result = CommandJobProgress::default();
+        for (status, count) in counts {
+            match status {
+                CommandJobDeviceStatus::Pending => result.pending += count,
+                CommandJobDeviceStatus::Sent => result.sent += count,
+                CommandJobDeviceStatus::Responded => result.responded += count,
+                CommandJobDeviceStatus::Failed => result.failed += count,
+                CommandJobDeviceStatus::TimedOut => result.timed_out += count,
+                CommandJobDeviceStatus::Cancelled => result.cancelled += count,
+            }
+        }
+        result
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct CommandJob {
+    /// 任务ID
+    pub id: String,
+    /// 标签ID, 按设备列表创建的任务为空
+    pub label_id: Option<String>,
+    /// 指令名称
+    pub command: String,
+    /// 负载信息
+    pub payload: String,
+    /// 指令QOS
+    pub qos: i16,
+    /// 指令过期时间（秒）
+    pub ttl: Option<i32>,
+    /// 每秒最多下发的设备数
+    pub rate_limit: i32,
+    /// 下发后等待设备回复的时长（秒）
+    pub timeout_secs: i32,
+    /// 任务状态
+    pub status: CommandJobStatus,
+    /// 设备总数
+    pub total: i32,
+    /// 各状态的设备数
+    pub progress: CommandJobProgress,
+    /// 创建人
+    pub created_by: String,
+    /// 创建时间
+    pub created_at: DateTime<Local>,
+    /// 更新时间
+    pub updated_at: Option<DateTime<Local>>,
+    /// 结束时间
+    pub finished_at: Option<DateTime<Local>>,
+}
+impl CommandJob {
+    pub fn new(obj: CommandJobModel, progress: Vec<(CommandJobDeviceStatus, usize)>) -> Self {
+        Self {
+            id: obj.id,
+            label_id: obj.label_id,
+            command: obj.command,
+            payload: obj.payload,
+            qos: obj.qos,
+            ttl: obj.ttl,
+            rate_limit: obj.rate_limit,
+            timeout_secs: obj.timeout_secs,
+            status: obj.status,
+            total: obj.total,
+            progress: progress.into(),
+            created_by: obj.created_by,
+            created_at: obj.created_at.into(),
+            updated_at: obj.updated_at.map(Into::into),
+            finished_at: obj.finished_at.map(Into::into),
+        }
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct CommandJobs {
+    /// 数据列表
+    pub results: Vec<CommandJob>,
+    /// 总数
+    pub total: usize,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct CreateCommandJob {
+    /// 标签ID, 任务创建时展开为带有该标签的设备, 与`device_ids`二选一
+    pub label_id: Option<String>,
+    /// 设备ID列表, 与`label_id`二选一
+    #[oai(default)]
+    pub device_ids: Vec<String>,
+    /// 指令名称
+    #[oai(validator(min_length = 1))]
+    pub command: String,
+    /// 编码类型
+    #[oai(default = "default_codec")]
+    pub codec: PayloadCodec,
+    /// 负载信息
+    pub payload: String,
+    /// 指令过期时间（秒）
+    pub ttl: Option<usize>,
+    /// 指令QOS
+    #[oai(
+        default = "default_qos",
+        validator(maximum(value = "2"), minimum(value = "0"))
+    )]
+    pub qos: u8,
+    /// 每秒最多下发的设备数
+    #[oai(
+        default = "default_job_rate_limit",
+        validator(minimum(value = "1"), maximum(value = "1000"))
+    )]
+    pub rate_limit: i32,
+    /// 下发后等待设备回复的时长（秒）, 超时未回复的设备标记为timed_out
+    #[oai(
+        default = "default_job_timeout_secs",
+        validator(minimum(value = "1"), maximum(value = "86400"))
+    )]
+    pub timeout_secs: i32,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct CommandJobDevice {
+    /// 设备ID
+    pub device_id: String,
+    /// 状态
+    pub status: CommandJobDeviceStatus,
+    /// 指令ID, 重试后为最近一次下发的指令ID
+    pub message_id: Option<String>,
+    /// 下发次数
+    pub attempts: i32,
+    /// 失败原因
+    pub error: Option<String>,
+    /// 设备回复的内容
+    pub response: Option<String>,
+    /// 最近一次下发时间
+    pub sent_at: Option<DateTime<Local>>,
+    /// 回复时间
+    pub responded_at: Option<DateTime<Local>>,
+    /// 更新时间
+    pub updated_at: Option<DateTime<Local>>,
+}
+impl From<CommandJobDeviceModel> for CommandJobDevice {
+    fn from(obj: CommandJobDeviceModel) -> Self {
+        Self {
+            device_id: obj.device_id,
+            status: obj.status,
+            message_id: obj.message_id,
+            attempts: obj.attempts,
+            error: obj.error,
+            response: obj.response,
+            sent_at: obj.sent_at.map(Into::into),
+            responded_at: obj.responded_at.map(Into::into),
+            updated_at: obj.updated_at.map(Into::into),
+        }
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct CommandJobDevices {
+    /// 数据列表
+    pub results: Vec<CommandJobDevice>,
+    /// 总数
+    pub total: usize,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct Firmware {
+    /// 固件ID
+    pub id: String,
+    /// 适用的数据模型ID
+    pub schema_id: String,
+    /// 固件版本
+    pub version: String,
+    /// 文件的SHA-256摘要
+    pub checksum: String,
+    /// 文件大小（字节）
+    pub size: i64,
+    /// 文件名
+    pub filename: String,
+    /// 描述
+    pub description: String,
+    /// 上传时间
+    pub created_at: DateTime<Local>,
+}
+impl From<FirmwareModel> for Firmware {
+    fn from(obj: FirmwareModel) -> Self {
+        Self {
+            id: obj.id,
+            schema_id: obj.schema_id,
+            version: obj.version,
+            checksum: obj.checksum,
+            size: obj.size,
+            filename: obj.filename,
+            description: obj.description,
+            created_at: obj.created_at.into(),
+        }
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct Firmwares {
+    /// 数据列表
+    pub results: Vec<Firmware>,
+    /// 总数
+    pub total: usize,
+}
+
+#[derive(Multipart)]
+pub struct UploadFirmware {
+    /// 适用的数据模型ID
+    pub schema_id: String,
+    /// 固件版本, 同一数据模型下不能重复
+    #[oai(validator(min_length = 1, max_length = 64))]
+    pub version: String,
+    /// 文件的SHA-256摘要(十六进制), 提供时校验上传的文件
+    pub checksum: Option<String>,
+    /// 描述
+    #[oai(default)]
+    pub description: String,
+    /// 固件文件
+    pub file: Upload,
+}
+
+#[derive(Debug, Object, Clone, Default, PartialEq)]
+pub struct OtaCampaignProgress {
+    pub pending: usize,
+    pub notified: usize,
+    pub downloading: usize,
+    pub installing: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub skipped: usize,
+}
+impl From<Vec<(OtaStage, OtaDeviceStatus, usize)>> for OtaCampaignProgress {
+    fn from(counts: Vec<(OtaStage, OtaDeviceStatus, usize)>) -> Self {
+        let mut result = OtaCampaignProgress::default();
+        for (_, status, count) in counts {
+            match status {
+                OtaDeviceStatus::Pending => result.pending += count,
+                OtaDeviceStatus::Notified => result.notified += count,
+                OtaDeviceStatus::Downloading => result.downloading += count,
+                OtaDeviceStatus::Installing => result.installing += count,
+                OtaDeviceStatus::Succeeded => result.succeeded += count,
+                OtaDeviceStatus::Failed => result.failed += count,
+                OtaDeviceStatus::Skipped => result.skipped += count,
+            }
+        }
+        result
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct OtaCampaign {
+    /// 活动ID
+    pub id: String,
+    /// 活动名称
+    pub name: String,
+    /// 固件ID
+    pub firmware_id: String,
+    /// 目标标签ID列表
+    pub label_ids: Vec<String>,
+    /// 金丝雀阶段的设备比例（%）
+    pub canary_percent: i16,
+    /// 失败率阈值（%）, 超过后自动中止
+    pub failure_threshold: i16,
+    /// 开始计算失败率所需的最少已结束设备数
+    pub min_samples: i32,
+    /// 金丝雀设备全部结束后是否自动推送到其余设备
+    pub auto_promote: bool,
+    /// 每秒最多推送的设备数
+    pub rate_limit: i32,
+    /// 推送后等待设备完成升级的时长（秒）
+    pub timeout_secs: i32,
+    /// 当前阶段
+    pub stage: OtaStage,
+    /// 活动状态
+    pub status: OtaCampaignStatus,
+    /// 中止原因
+    pub abort_reason: Option<String>,
+    /// 设备总数
+    pub total: i32,
+    /// 各状态的设备数
+    pub progress: OtaCampaignProgress,
+    /// 创建人
+    pub created_by: String,
+    /// 创建时间
+    pub created_at: DateTime<Local>,
+    /// 更新时间
+    pub updated_at: Option<DateTime<Local>>,
+    /// 结束时间
+    pub finished_at: Option<DateTime<Local>>,
+}
+impl OtaCampaign {
+    pub fn new(obj: OtaCampaignModel, progress: Vec<(OtaStage, OtaDeviceStatus, usize)>) -> Self {
+        Self {
+            id: obj.id,
+            name: obj.name,
+            firmware_id: obj.firmware_id,
+            label_ids: serde_json::from_value(obj.label_ids).unwrap_or_default(),
+            canary_percent: obj.canary_percent,
+            failure_threshold: obj.failure_threshold,
+            min_samples: obj.min_samples,
+            auto_promote: obj.auto_promote,
+            rate_limit: obj.rate_limit,
+            timeout_secs: obj.timeout_secs,
+            stage: obj.stage,
+            status: obj.status,
+            abort_reason: obj.abort_reason,
+            total: obj.total,
+            progress: progress.into(),
+            created_by: obj.created_by,
+            created_at: obj.created_at.into(),
+            updated_at: obj.updated_at.map(Into::into),
+            finished_at: obj.finished_at.map(Into::into),
+        }
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct OtaCampaigns {
+    /// 数据列表
+    pub results: Vec<OtaCampaign>,
+    /// 总数
+    pub total: usize,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct CreateOtaCampaign {
+    /// 活动名称
+    #[oai(validator(min_length = 1))]
+    pub name: String,
+    /// 固件ID
+    pub firmware_id: String,
+    /// 目标标签ID列表, 带有任一标签且数据模型与固件一致的设备参与升级, 已是该版本的设备除外
+    #[oai(validator(min_items = 1))]
+    pub label_ids: Vec<String>,
+    /// 金丝雀阶段的设备比例（%）, 为0时直接推送到所有设备
+    #[oai(
+        default = "default_canary_percent",
+        validator(minimum(value = "0"), maximum(value = "100"))
+    )]
+    pub canary_percent: i16,
+    /// 失败率阈值（%）, 已结束设备的失败率超过后自动中止
+    #[oai(
+        default = "default_failure_threshold",
+        validator(minimum(value = "0"), maximum(value = "100"))
+    )]
+    pub failure_threshold: i16,
+    /// 开始计算失败率所需的最少已结束设备数
+    #[oai(default = "default_min_samples", validator(minimum(value = "1")))]
+    pub min_samples: i32,
+    /// 金丝雀设备全部结束后是否自动推送到其余设备, 否则需要手动推进
+    #[oai(default)]
+    pub auto_promote: bool,
+    /// 每秒最多推送的设备数
+    #[oai(
+        default = "default_ota_rate_limit",
+        validator(minimum(value = "1"), maximum(value = "1000"))
+    )]
+    pub rate_limit: i32,
+    /// 推送后等待设备完成升级的时长（秒）, 超时未完成的设备标记为失败
+    #[oai(
+        default = "default_ota_timeout_secs",
+        validator(minimum(value = "60"), maximum(value = "604800"))
+    )]
+    pub timeout_secs: i32,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct OtaCampaignDevice {
+    /// 设备ID
+    pub device_id: String,
+    /// 所属阶段
+    pub stage: OtaStage,
+    /// 状态
+    pub status: OtaDeviceStatus,
+    /// 设备上报的进度（%）
+    pub progress: i16,
+    /// 升级前的固件版本
+    pub from_version: Option<String>,
+    /// 失败原因
+    pub error: Option<String>,
+    /// 推送时间
+    pub notified_at: Option<DateTime<Local>>,
+    /// 更新时间
+    pub updated_at: Option<DateTime<Local>>,
+}
+impl From<OtaCampaignDeviceModel> for OtaCampaignDevice {
+    fn from(obj: OtaCampaignDeviceModel) -> Self {
+        Self {
+            device_id: obj.device_id,
+            stage: obj.stage,
+            status: obj.status,
+            progress: obj.progress,
+            from_version: obj.from_version,
+            error: obj.error,
+            notified_at: obj.notified_at.map(Into::into),
+            updated_at: obj.updated_at.map(Into::into),
+        }
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct OtaCampaignDevices {
+    /// 数据列表
+    pub results: Vec<OtaCampaignDevice>,
+    /// 总数
+    pub total: usize,
+}
+
+fn default_upload_kind() -> String {
+    "log".to_string()
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct RequestDeviceUpload {
+    /// 设备ID
+    pub device_id: String,
+    /// 文件类型, 由设备解释, 如log、coredump
+    #[oai(
+        default = "default_upload_kind",
+        validator(min_length = 1, max_length = 32)
+    )]
+    pub kind: String,
+    /// 附加参数, 原样放入上传指令, 如日志的时间范围
+    pub params: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct DeviceUpload {
+    /// 上传ID
+    pub id: String,
+    /// 设备ID
+    pub device_id: String,
+    /// 文件类型
+    pub kind: String,
+    /// 状态
+    pub status: DeviceUploadStatus,
+    /// 上传指令的消息ID
+    pub message_id: Option<String>,
+    /// 分片总数, 收到第一个分片后确定
+    pub total_chunks: Option<i32>,
+    /// 已收到的分片数
+    pub received_chunks: i32,
+    /// 文件大小（字节）
+    pub size: Option<i64>,
+    /// 文件的SHA-256摘要
+    pub checksum: Option<String>,
+    /// 失败原因
+    pub error: Option<String>,
+    /// 请求人
+    pub requested_by: String,
+    /// 请求时间
+    pub created_at: DateTime<Local>,
+    /// 完成时间
+    pub completed_at: Option<DateTime<Local>>,
+    /// 过期时间, 之前未收到全部分片即过期
+    pub expires_at: DateTime<Local>,
+}
+impl From<DeviceUploadModel> for DeviceUpload {
+    fn from(obj: DeviceUploadModel) -> Self {
+        Self {
+            id: obj.id,
+            device_id: obj.device_id,
+            kind: obj.kind,
+            status: obj.status,
+            message_id: obj.message_id,
+            total_chunks: obj.total_chunks,
+            received_chunks: obj.received_chunks,
+            size: obj.size,
+            checksum: obj.checksum,
+            error: obj.error,
+            requested_by: obj.requested_by,
+            created_at: obj.created_at.into(),
+            completed_at: obj.completed_at.map(Into::into),
+            expires_at: obj.expires_at.into(),
+        }
+    }
 }
 
 #[derive(Debug, Object, PartialEq)]
-pub struct Accounts {
+pub struct DeviceUploads {
     /// 数据列表
-    pub results: Vec<Account>,
+    pub results: Vec<DeviceUpload>,
     /// 总数
     pub total: usize,
 }
 
+fn default_config_schema() -> serde_json::Value {
+    serde_json::json!({ "type": "object" })
+}
+
 #[derive(Debug, Object, PartialEq)]
-pub struct Login {
-    pub email: String,
-    pub password: String,
+pub struct CreateConfigProfile {
+    /// 模板名称, 账号内唯一
+    #[oai(validator(min_length = 1, max_length = 128))]
+    pub name: String,
+    /// 描述
+    #[oai(default)]
+    pub description: String,
+    /// 配置文档须符合的JSON Schema, 支持type、properties、required、additionalProperties、
+    /// items、enum、minimum、maximum、minLength和maxLength
+    #[oai(default = "default_config_schema")]
+    pub schema: serde_json::Value,
+    /// 第一个版本的配置文档, 须为JSON对象
+    pub document: serde_json::Value,
 }
 
 #[derive(Debug, Object, PartialEq)]
-pub struct TokenResponse {
-    pub token: String,
+pub struct UpdateConfigProfile {
+    /// 模板名称
+    #[oai(validator(min_length = 1, max_length = 128))]
+    pub name: Option<String>,
+    /// 描述
+    pub description: Option<String>,
+    /// JSON Schema, 最新版本的配置文档须符合新的Schema
+    pub schema: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Object, PartialEq)]
-pub struct DeviceConnection {
+pub struct UpdateConfigDocument {
+    /// 新版本的配置文档, 须为JSON对象
+    pub document: serde_json::Value,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct ConfigProfile {
+    /// 模板ID
     pub id: String,
-    /// 所属设备原因
-    pub device_id: String,
-    /// 连接状态
-    pub connected: bool,
-    /// client_id
-    pub client_id: String,
-    /// 连接节点
-    pub node: String,
-    pub keep_alive: String,
-    /// IP地址`
-    pub ip_address: String,
-    /// 协议版本
-    pub proto_ver: i64,
-    /// 连接时间
-    pub connected_at: DateTimeWithTimeZone,
-    /// 断开连接的时间
-    pub disconnected_at: DateTimeWithTimeZone,
-    /// 断开连接原因
-    pub disconnected_reason: String,
+    /// 模板名称
+    pub name: String,
+    /// 描述
+    pub description: String,
+    /// JSON Schema
+    pub schema: serde_json::Value,
+    /// 最新版本号
+    pub version: i32,
+    /// 最新版本的配置文档
+    pub document: serde_json::Value,
+    /// 创建时间
+    pub created_at: DateTime<Local>,
+    /// 更新时间
+    pub updated_at: Option<DateTime<Local>>,
 }
-impl From<DeviceConnectionModel> for DeviceConnection {
-    fn from(model: DeviceConnectionModel) -> Self {
+impl From<(ConfigProfileModel, ConfigProfileVersionModel)> for ConfigProfile {
+    fn from((obj, version): (ConfigProfileModel, ConfigProfileVersionModel)) -> Self {
         Self {
-            id: model.id,
-            device_id: model.device_id,
-            connected: model.connected,
-            client_id: model.client_id,
-            node: model.node,
-            keep_alive: model.keep_alive,
-            ip_address: model.ip_address,
-            proto_ver: model.proto_ver,
-            connected_at: model.connected_at,
-            disconnected_at: model.disconnected_at,
-            disconnected_reason: model.disconnected_reason,
+            id: obj.id,
+            name: obj.name,
+            description: obj.description,
+            schema: obj.schema,
+            version: obj.version,
+            document: version.document,
+            created_at: obj.created_at.into(),
+            updated_at: obj.updated_at.map(Into::into),
         }
     }
 }
 
 #[derive(Debug, Object, PartialEq)]
-pub struct DeviceConnections {
-    total: usize,
-    results: Vec<DeviceConnection>,
+pub struct ConfigProfiles {
+    /// 数据列表
+    pub results: Vec<ConfigProfile>,
+    /// 总数
+    pub total: usize,
 }
-impl From<(Vec<DeviceConnectionModel>, usize)> for DeviceConnections {
-    fn from(tuple: (Vec<DeviceConnectionModel>, usize)) -> Self {
-        let (models, total) = tuple;
+
+#[derive(Debug, Object, PartialEq)]
+pub struct ConfigProfileVersion {
+    /// 模板ID
+    pub profile_id: String,
+    /// 版本号
+    pub version: i32,
+    /// 配置文档
+    pub document: serde_json::Value,
+    /// 创建人
+    pub created_by: String,
+    /// 创建时间
+    pub created_at: DateTime<Local>,
+}
+impl From<ConfigProfileVersionModel> for ConfigProfileVersion {
+    fn from(obj: ConfigProfileVersionModel) -> Self {
         Self {
-            total,
-            results: models.into_iter().map(Into::into).collect(),
+            profile_id: obj.profile_id,
+            version: obj.version,
+            document: obj.document,
+            created_by: obj.created_by,
+            created_at: obj.created_at.into(),
         }
     }
 }
 
-pub struct DeviceModelWithRelated {
-    pub device: DeviceModel,
-    pub labels: Vec<LabelModel>,
-    pub schema: SchemaModel,
-}
-
 #[derive(Debug, Object, PartialEq)]
-pub struct DeviceWithLables {
-    /// 设备ID
-    pub id: String,
-    /// 设备名称
-    pub name: String,
-    /// 设备标签列表
-    pub labels: Vec<String>,
-    /// 数据模型
-    pub schema: Schema,
-    /// 设备是否激活
-    pub is_active: bool,
-    /// 设备是否在线
-    pub is_online: bool,
-    /// 设备创建时间
-    pub created_at: DateTime<Local>,
+pub struct ConfigProfileVersions {
+    /// 数据列表
+    pub results: Vec<ConfigProfileVersion>,
+    /// 总数
+    pub total: usize,
 }
 
-impl From<DeviceModelWithRelated> for DeviceWithLables {
-    fn from(obj: DeviceModelWithRelated) -> Self {
-        DeviceWithLables {
-            id: obj.device.id,
-            name: obj.device.name,
-            labels: obj.labels.into_iter().map(|x| x.name).collect(),
-            schema: obj.schema.into(),
-            is_active: obj.device.is_active,
-            is_online: obj.device.is_online,
-            created_at: obj.device.created_at.into(),
-        }
-    }
+#[derive(Debug, Object, PartialEq)]
+pub struct CreateConfigAssignment {
+    /// 模板ID
+    pub profile_id: String,
+    /// 分配到的标签ID, 与设备ID二选一
+    pub label_id: Option<String>,
+    /// 分配到的设备ID, 与标签ID二选一; 设备级的文档合并在标签级之后
+    pub device_id: Option<String>,
+    /// 优先级, 同一级别内优先级高的文档后合并
+    #[oai(default)]
+    pub priority: i32,
 }
 
 #[derive(Debug, Object, PartialEq)]
-pub struct Device {
-    /// 设备ID
+pub struct ConfigAssignment {
+    /// 分配ID
     pub id: String,
-    /// 设备名称
-    pub name: String,
-    /// 数据模型ID
-    pub schema_id: String,
-    /// 设备是否激活
-    pub is_active: bool,
-    /// 设备是否在线
-    pub is_online: bool,
-    /// 设备创建时间
+    /// 模板ID
+    pub profile_id: String,
+    /// 标签ID
+    pub label_id: Option<String>,
+    /// 设备ID
+    pub device_id: Option<String>,
+    /// 优先级
+    pub priority: i32,
+    /// 创建时间
     pub created_at: DateTime<Local>,
 }
-impl From<DeviceModel> for Device {
-    fn from(obj: DeviceModel) -> Self {
-        Device {
+impl From<ConfigAssignmentModel> for ConfigAssignment {
+    fn from(obj: ConfigAssignmentModel) -> Self {
+        Self {
             id: obj.id,
-            name: obj.name,
-            schema_id: obj.schema_id.to_string(),
-            is_active: obj.is_active,
-            is_online: obj.is_online,
+            profile_id: obj.profile_id,
+            label_id: obj.label_id,
+            device_id: obj.device_id,
+            priority: obj.priority,
             created_at: obj.created_at.into(),
         }
     }
 }
 
 #[derive(Debug, Object, PartialEq)]
-pub struct CreateDevice {
-    /// 设备名称
-    pub name: String,
-    /// 数据模型ID
-    pub schema_id: String,
-    /// 标签列表
-    pub label_ids: Vec<String>,
-    /// 设备MQTT连接密码
-    pub mqtt_password: String,
+pub struct ConfigAssignments {
+    /// 数据列表
+    pub results: Vec<ConfigAssignment>,
+    /// 总数
+    pub total: usize,
 }
 
 #[derive(Debug, Object, PartialEq)]
-pub struct UpdateDevice {
-    /// 设备名称
-    pub name: Option<String>,
-    /// 设备激活状态ID
-    pub is_active: Option<bool>,
-    /// 设备标签
-    pub label_ids: Option<Vec<String>>,
-    /// 数据模型
-    pub schema_id: Option<String>,
+pub struct DeviceConfig {
+    /// 设备ID
+    pub device_id: String,
+    /// 设备的标签版本号
+    pub label_version: i64,
+    /// 生效配置的版本号, 尚未分配任何模板时为空
+    pub version: Option<String>,
+    /// 生效配置
+    pub config: serde_json::Value,
+    /// 最近推送的版本号
+    pub pushed_version: Option<String>,
+    /// 最近推送时间
+    pub pushed_at: Option<DateTime<Local>>,
+    /// 设备回复已应用的版本号
+    pub applied_version: Option<String>,
+    /// 设备回复已应用的时间
+    pub applied_at: Option<DateTime<Local>>,
+    /// 设备回复的应用失败原因
+    pub apply_error: Option<String>,
+}
+impl DeviceConfig {
+    pub fn new(device: &DeviceModel, config: Option<DeviceConfigModel>) -> Self {
+        match config {
+            Some(config) => Self {
+                device_id: device.id.clone(),
+                label_version: device.label_version,
+                version: Some(config.version),
+                config: config.document,
+                pushed_version: config.pushed_version,
+                pushed_at: config.pushed_at.map(Into::into),
+                applied_version: config.applied_version,
+                applied_at: config.applied_at.map(Into::into),
+                apply_error: config.apply_error,
+            },
+            None => Self {
+                device_id: device.id.clone(),
+                label_version: device.label_version,
+                version: None,
+                config: serde_json::json!({}),
+                pushed_version: None,
+                pushed_at: None,
+                applied_version: None,
+                applied_at: None,
+                apply_error: None,
+            },
+        }
+    }
 }
 
 #[derive(Debug, Object, PartialEq)]
@@ -300,6 +2324,43 @@ pub struct SendCommandToDeviceBatch {
     pub qos: u8,
 }
 
+#[derive(Debug, Object, PartialEq)]
+pub struct SendCommandToSelector {
+    /// 标签选择器, 例如: `site=sh01,floor in (2,3),!decommissioned`
+    pub selector: String,
+    /// 指令名称
+    pub command: String,
+    /// 编码类型
+    #[oai(default = "default_codec")]
+    pub codec: PayloadCodec,
+    /// 负载信息
+    pub payload: String,
+    /// 指令过期时间（秒）
+    pub ttl: Option<usize>,
+    /// 指令QOS
+    #[oai(
+        default = "default_qos",
+        validator(maximum(value = "2"), minimum(value = "0"))
+    )]
+    pub qos: u8,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct DeviceCommand {
+    /// 设备ID
+    pub device_id: String,
+    /// 指令ID
+    pub message_id: String,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct SelectorCommandResponse {
+    /// 每个设备的指令ID
+    pub results: Vec<DeviceCommand>,
+    /// 总数
+    pub total: usize,
+}
+
 #[derive(Debug, Object, PartialEq)]
 pub struct SyncCommandResponse {
     /// 设备响应
@@ -331,11 +2392,13 @@ impl CommandResponse {
 
 #[derive(Debug, Object, PartialEq)]
 pub struct Label {
-    /// 设备ID
+    /// 标签ID
     pub id: String,
-    /// 设备名称
+    /// 标签名称
     pub name: String,
-    /// 设备创建时间
+    /// 标签值
+    pub value: Option<String>,
+    /// 标签创建时间
     pub created_at: DateTime<Local>,
 }
 
@@ -344,21 +2407,144 @@ impl From<LabelModel> for Label {
         Label {
             id: obj.id,
             name: obj.name,
+            value: obj.value,
             created_at: obj.created_at.into(),
         }
     }
 }
 #[derive(Debug, Object, PartialEq)]
 pub struct Labels {
+    /// 数据列表
     pub results: Vec<Label>,
+    /// 总数
+    pub total: usize,
 }
 #[derive(Debug, Object, PartialEq)]
 pub struct UpdateLabel {
+    /// 标签名称
     pub name: String,
+    /// 标签值, 传null清空
+    pub value: MaybeUndefined<String>,
 }
 #[derive(Debug, Object, PartialEq)]
 pub struct CreateLabel {
+    /// 标签名称
+    pub name: String,
+    /// 标签值
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct LabelDevices {
+    /// 设备ID列表
+    #[oai(validator(max_items = 1000))]
+    pub device_ids: Vec<String>,
+}
+
+#[derive(Clone)]
+pub struct ProvisioningProfileModelWithRelated {
+    pub profile: ProvisioningProfileModel,
+    pub labels: Vec<LabelModel>,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct ProvisioningProfile {
+    /// 模板ID
+    pub id: String,
+    /// 模板名称
+    pub name: String,
+    /// 注册设备使用的数据模型ID
+    pub schema_id: String,
+    /// 注册设备默认带有的标签
+    pub labels: Vec<Label>,
+    /// 最多可注册的设备数, 为空表示不限制
+    pub max_devices: Option<i32>,
+    /// 已注册的设备数
+    pub registered_count: i32,
+    /// 是否允许注册
+    pub is_active: bool,
+    /// 认领令牌过期时间, 为空表示永不过期
+    pub expires_at: Option<DateTime<Local>>,
+    /// 创建时间
+    pub created_at: DateTime<Local>,
+    /// 认领令牌, 只在创建或重置时返回一次
+    #[oai(skip_serializing_if_is_none)]
+    pub claim_token: Option<String>,
+}
+
+impl From<ProvisioningProfileModelWithRelated> for ProvisioningProfile {
+    fn from(obj: ProvisioningProfileModelWithRelated) -> Self {
+        Self {
+            id: obj.profile.id,
+            name: obj.profile.name,
+            schema_id: obj.profile.schema_id,
+            labels: obj.labels.into_iter().map(Into::into).collect(),
+            max_devices: obj.profile.max_devices,
+            registered_count: obj.profile.registered_count,
+            is_active: obj.profile.is_active,
+            expires_at: obj.profile.expires_at.map(Into::into),
+            created_at: obj.profile.created_at.into(),
+            claim_token: None,
+        }
+    }
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct ProvisioningProfiles {
+    /// 数据列表
+    pub results: Vec<ProvisioningProfile>,
+    /// 总数
+    pub total: usize,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct CreateProvisioningProfile {
+    /// 模板名称
     pub name: String,
+    /// 注册设备使用的数据模型ID
+    pub schema_id: String,
+    /// 注册设备默认带有的标签ID
+    #[oai(default)]
+    pub label_ids: Vec<String>,
+    /// 最多可注册的设备数, 为空表示不限制
+    #[oai(validator(minimum(value = "0")))]
+    pub max_devices: Option<i32>,
+    /// 认领令牌过期时间
+    pub expires_at: Option<DateTime<Local>>,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct UpdateProvisioningProfile {
+    /// 模板名称
+    pub name: Option<String>,
+    /// 注册设备默认带有的标签ID, 只影响之后注册的设备
+    pub label_ids: Option<Vec<String>>,
+    /// 最多可注册的设备数, 传null取消限制
+    #[oai(validator(minimum(value = "0")))]
+    pub max_devices: MaybeUndefined<i32>,
+    /// 是否允许注册
+    pub is_active: Option<bool>,
+    /// 认领令牌过期时间, 传null取消过期
+    pub expires_at: MaybeUndefined<DateTime<Local>>,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct RegisterDevice {
+    /// 模板的认领令牌
+    pub claim_token: String,
+    /// 设备硬件序列号, 同一账号下唯一
+    #[oai(validator(min_length = 1, max_length = 128))]
+    pub hardware_serial: String,
+    /// 设备名称, 默认使用硬件序列号
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct RegisteredDevice {
+    /// 设备ID
+    pub device_id: String,
+    /// 设备的连接凭据
+    pub credential: DeviceCredentialSecret,
 }
 
 #[derive(Debug, Object, PartialEq)]
@@ -380,6 +2566,7 @@ impl From<SchemaModel> for Schema {
     }
 }
 
+#[derive(Clone)]
 pub struct SchemaModelWithRelated {
     pub schema: SchemaModel,
     pub fields: Vec<FieldModel>,
@@ -478,3 +2665,90 @@ pub struct UpdateField {
     // 单位
     pub unit: MaybeUndefined<String>,
 }
+
+#[derive(Debug, Object, PartialEq)]
+pub struct MqttAuthRequest {
+    /// MQTT客户端ID
+    pub clientid: Option<String>,
+    /// MQTT用户名
+    pub username: String,
+    /// MQTT密码
+    pub password: Option<String>,
+    /// 双向TLS连接时客户端证书的CN
+    pub cert_common_name: Option<String>,
+    /// 双向TLS连接时客户端证书的序列号(十六进制, 可以带冒号)
+    pub cert_serial: Option<String>,
+    /// 双向TLS连接时客户端证书DER编码的SHA-256指纹(十六进制, 可以带冒号)
+    pub cert_fingerprint: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Enum, PartialEq)]
+#[oai(rename_all = "lowercase")]
+pub enum MqttAction {
+    Publish,
+    Subscribe,
+}
+
+#[derive(Debug, Object, PartialEq)]
+pub struct MqttAclRequest {
+    /// MQTT客户端ID
+    pub clientid: Option<String>,
+    /// MQTT用户名
+    pub username: String,
+    /// 发布或订阅
+    pub action: MqttAction,
+    /// 发布的主题或订阅的主题过滤器
+    pub topic: String,
+}
+
+/// EMQX WebHook的`message.publish`事件
+#[derive(Debug, Object, PartialEq)]
+pub struct MqttMessageEvent {
+    /// 消息主题
+    pub topic: String,
+    /// 消息内容
+    pub payload: String,
+}
+
+/// EMQX WebHook的`client.connected`事件
+#[derive(Debug, Object, PartialEq)]
+pub struct MqttConnectedEvent {
+    /// MQTT客户端ID
+    pub clientid: String,
+    /// MQTT用户名
+    pub username: String,
+    /// 客户端IP地址
+    #[oai(default)]
+    pub ipaddress: String,
+    /// 心跳间隔(秒)
+    #[oai(default)]
+    pub keepalive: i64,
+    /// MQTT协议版本
+    #[oai(default)]
+    pub proto_ver: i64,
+    /// EMQX节点
+    #[oai(default)]
+    pub node: String,
+}
+
+/// EMQX WebHook的`client.disconnected`事件
+#[derive(Debug, Object, PartialEq)]
+pub struct MqttDisconnectedEvent {
+    /// MQTT客户端ID
+    pub clientid: String,
+    /// MQTT用户名
+    pub username: String,
+    /// 断开原因
+    #[oai(default)]
+    pub reason: String,
+}
+
+#[derive(ApiResponse)]
+pub enum HookResponse {
+    /// 允许
+    #[oai(status = "200")]
+    Allow,
+    /// 拒绝
+    #[oai(status = "403")]
+    Deny,
+}
@@ -0,0 +1,351 @@
+//! 固件升级: 固件文件保存在blob存储中, 升级活动在创建时按标签选出同一数据模型的设备,
+//! 先向金丝雀比例的设备推送升级通知, 金丝雀设备全部结束后自动或手动推送到其余设备;
+//! 已结束设备的失败率超过阈值时自动中止活动, 尚未推送的设备不再推送
+use chrono::{DateTime, Local};
+use entity::ota_campaign_devices::OtaDeviceStatus;
+use entity::ota_campaigns::OtaStage;
+use entity::prelude::*;
+use hmac::{Hmac, Mac};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::SETTINGS,
+    errors::{NeoiotError, Result},
+    repository::Repository,
+    topics::{Message, OtaNotify, Topics},
+};
+
+/// 待保存的固件元数据, 文件已写入blob存储
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewFirmware {
+    pub id: String,
+    pub schema_id: String,
+    pub version: String,
+    pub checksum: String,
+    pub size: i64,
+    pub blob_key: String,
+    pub filename: String,
+    pub description: String,
+}
+
+/// 固件文件在blob存储中的键
+pub fn blob_key(account_id: &str, firmware_id: &str) -> String {
+    format!("firmware/{}/{}", account_id, firmware_id)
+}
+
+/// 固件文件的SHA-256摘要
+pub fn checksum(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// 金丝雀阶段的设备数, 比例大于0时至少一台
+pub fn canary_size(total: usize, percent: i16) -> usize {
+    let percent = percent.clamp(0, 100) as usize;
+    (total * percent).div_ceil(100).min(total)
+}
+
+/// 活动中各类设备的数量, 用于决定活动的下一步
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RolloutCounts {
+    /// 已升级成功的设备数
+    pub succeeded: usize,
+    /// 已失败的设备数
+    pub failed: usize,
+    /// 金丝雀阶段中尚未结束的设备数
+    pub canary_unfinished: usize,
+    /// 所有阶段中尚未结束的设备数
+    pub unfinished: usize,
+}
+
+impl From<Vec<(OtaStage, OtaDeviceStatus, usize)>> for RolloutCounts {
+    fn from(counts: Vec<(OtaStage, OtaDeviceStatus, usize)>) -> Self {
+        let mut result = RolloutCounts::default();
+        for (stage, status, count) in counts {
+            match status {
+                OtaDeviceStatus::Succeeded => result.succeeded += count,
+                OtaDeviceStatus::Failed => result.failed += count,
+                OtaDeviceStatus::Skipped => {}
+                _ => {
+                    result.unfinished += count;
+                    if stage == OtaStage::Canary {
+                        result.canary_unfinished += count;
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+/// 活动的下一步
+#[derive(Debug, Clone, PartialEq)]
+pub enum Transition {
+    /// 失败率超过阈值, 中止活动
+    Abort(String),
+    /// 金丝雀设备全部结束, 推送到其余设备
+    Promote,
+    /// 所有设备都已结束
+    Complete,
+}
+
+/// 根据已结束设备的失败率和各阶段的进度决定活动的下一步
+///
+/// 已结束的设备数达到`min_samples`后才计算失败率, 避免少量设备失败就中止活动
+pub fn next_transition(campaign: &OtaCampaignModel, counts: &RolloutCounts) -> Option<Transition> {
+    let finished = counts.succeeded + counts.failed;
+    let min_samples = campaign.min_samples.max(1) as usize;
+    if finished >= min_samples
+        && counts.failed * 100 > finished * campaign.failure_threshold.max(0) as usize
+    {
+        return Some(Transition::Abort(format!(
+            "failure rate {}/{} exceeds {}%",
+            counts.failed, finished, campaign.failure_threshold
+        )));
+    }
+    if counts.unfinished == 0 {
+        return Some(Transition::Complete);
+    }
+    if campaign.stage == OtaStage::Canary && campaign.auto_promote && counts.canary_unfinished == 0
+    {
+        return Some(Transition::Promote);
+    }
+    None
+}
+
+fn download_mac(secret: &str, firmware_id: &str, device_id: &str, expires: i64) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any length");
+    mac.update(format!("{}.{}.{}", firmware_id, device_id, expires).as_bytes());
+    mac
+}
+
+/// 固件下载链接的签名, 链接只对指定的设备在有效期内可用
+pub fn sign_download(secret: &str, firmware_id: &str, device_id: &str, expires: i64) -> String {
+    hex::encode(
+        download_mac(secret, firmware_id, device_id, expires)
+            .finalize()
+            .into_bytes(),
+    )
+}
+
+/// 校验固件下载链接的签名和有效期
+pub fn verify_download(
+    secret: &str,
+    firmware_id: &str,
+    device_id: &str,
+    expires: i64,
+    signature: &str,
+    now: DateTime<Local>,
+) -> bool {
+    if expires < now.timestamp() {
+        return false;
+    }
+    let signature = match hex::decode(signature) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+    download_mac(secret, firmware_id, device_id, expires)
+        .verify_slice(&signature)
+        .is_ok()
+}
+
+/// 设备下载固件的地址
+pub fn download_url(firmware_id: &str, device_id: &str, now: DateTime<Local>) -> String {
+    let expires = now.timestamp() + SETTINGS.ota.download_ttl_secs;
+    let signature = sign_download(&SETTINGS.core.secret, firmware_id, device_id, expires);
+    format!(
+        "{}/api/ota/firmware/{}/download?device_id={}&expires={}&signature={}",
+        SETTINGS.ota.public_url.trim_end_matches('/'),
+        firmware_id,
+        device_id,
+        expires,
+        signature
+    )
+}
+
+/// 设备上报的升级状态
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProgressStatus {
+    Downloading,
+    Installing,
+    Failed,
+}
+
+impl From<ProgressStatus> for OtaDeviceStatus {
+    fn from(status: ProgressStatus) -> Self {
+        match status {
+            ProgressStatus::Downloading => OtaDeviceStatus::Downloading,
+            ProgressStatus::Installing => OtaDeviceStatus::Installing,
+            ProgressStatus::Failed => OtaDeviceStatus::Failed,
+        }
+    }
+}
+
+/// 设备上报到`otar/{account_id}/{device_id}/progress`的升级进度,
+/// 升级成功以设备重启后上报到`otar/{account_id}/{device_id}/version`的固件版本为准
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ProgressReport {
+    /// 省略时为设备最近一次收到通知的活动
+    pub campaign_id: Option<String>,
+    pub status: ProgressStatus,
+    /// 百分比
+    #[serde(default)]
+    pub progress: i16,
+    pub error: Option<String>,
+}
+
+impl ProgressReport {
+    pub fn parse(payload: &str) -> Result<Self> {
+        let mut report: Self = serde_json::from_str(payload).map_err(|err| {
+            NeoiotError::InvalidArgument(format!("invalid ota progress: {}", err))
+        })?;
+        report.progress = report.progress.clamp(0, 100);
+        Ok(report)
+    }
+}
+
+/// 向领取到的设备推送升级通知, 推送失败的设备直接标记为失败
+pub async fn process<R: Repository>(
+    repo: &R,
+    campaign: OtaCampaignModel,
+    firmware: FirmwareModel,
+    targets: Vec<OtaCampaignDeviceModel>,
+) {
+    let now = Local::now();
+    for target in targets {
+        let payload = json!({
+            "campaign_id": campaign.id,
+            "firmware_id": firmware.id,
+            "version": firmware.version,
+            "checksum": firmware.checksum,
+            "size": firmware.size,
+            "url": download_url(&firmware.id, &target.device_id, now),
+        });
+        let message = Message::new(
+            Topics::Ota(OtaNotify::new(&campaign.account_id, &target.device_id)),
+            payload.to_string(),
+        );
+        if let Err(err) = message.publish(1).await {
+            tracing::warn!(campaign_id = %campaign.id, device_id = %target.device_id, ?err, "failed to notify ota");
+            if let Err(err) = repo.fail_ota_device(&target, &err.to_string()).await {
+                tracing::error!(?err, "failed to record ota notify failure");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollout() {
+        assert_eq!(canary_size(100, 10), 10);
+        assert_eq!(canary_size(7, 10), 1);
+        assert_eq!(canary_size(7, 0), 0);
+        assert_eq!(canary_size(7, 100), 7);
+        assert_eq!(canary_size(0, 10), 0);
+
+        let now = Local::now();
+        let mut campaign = OtaCampaignModel {
+            id: "c".to_string(),
+            account_id: "acc".to_string(),
+            firmware_id: "fw".to_string(),
+            name: "v2".to_string(),
+            label_ids: json!(["l"]),
+            canary_percent: 10,
+            failure_threshold: 20,
+            min_samples: 5,
+            auto_promote: true,
+            rate_limit: 10,
+            timeout_secs: 3600,
+            stage: OtaStage::Canary,
+            status: entity::ota_campaigns::OtaCampaignStatus::Running,
+            total: 100,
+            abort_reason: None,
+            created_by: "acc".to_string(),
+            created_at: now.into(),
+            updated_at: None,
+            finished_at: None,
+        };
+        let counts = RolloutCounts::from(vec![
+            (OtaStage::Canary, OtaDeviceStatus::Succeeded, 8),
+            (OtaStage::Canary, OtaDeviceStatus::Failed, 1),
+            (OtaStage::Canary, OtaDeviceStatus::Downloading, 1),
+            (OtaStage::Full, OtaDeviceStatus::Pending, 90),
+        ]);
+        assert_eq!(counts.canary_unfinished, 1);
+        assert_eq!(counts.unfinished, 91);
+        assert_eq!(next_transition(&campaign, &counts), None);
+
+        // 金丝雀设备全部结束
+        let counts = RolloutCounts::from(vec![
+            (OtaStage::Canary, OtaDeviceStatus::Succeeded, 9),
+            (OtaStage::Canary, OtaDeviceStatus::Failed, 1),
+            (OtaStage::Full, OtaDeviceStatus::Pending, 90),
+        ]);
+        assert_eq!(
+            next_transition(&campaign, &counts),
+            Some(Transition::Promote)
+        );
+        campaign.auto_promote = false;
+        assert_eq!(next_transition(&campaign, &counts), None);
+
+        // 失败率超过阈值
+        let counts = RolloutCounts::from(vec![
+            (OtaStage::Canary, OtaDeviceStatus::Succeeded, 7),
+            (OtaStage::Canary, OtaDeviceStatus::Failed, 3),
+            (OtaStage::Full, OtaDeviceStatus::Pending, 90),
+        ]);
+        assert!(matches!(
+            next_transition(&campaign, &counts),
+            Some(Transition::Abort(_))
+        ));
+        // 样本不足时不中止
+        let counts = RolloutCounts::from(vec![
+            (OtaStage::Canary, OtaDeviceStatus::Failed, 2),
+            (OtaStage::Canary, OtaDeviceStatus::Notified, 8),
+        ]);
+        assert_eq!(next_transition(&campaign, &counts), None);
+
+        campaign.stage = OtaStage::Full;
+        let counts = RolloutCounts::from(vec![
+            (OtaStage::Canary, OtaDeviceStatus::Succeeded, 10),
+            (OtaStage::Full, OtaDeviceStatus::Succeeded, 89),
+            (OtaStage::Full, OtaDeviceStatus::Failed, 1),
+        ]);
+        assert_eq!(
+            next_transition(&campaign, &counts),
+            Some(Transition::Complete)
+        );
+
+        let expires = now.timestamp() + 60;
+        let signature = sign_download("secret", "fw", "dev", expires);
+        assert!(verify_download(
+            "secret", "fw", "dev", expires, &signature, now
+        ));
+        assert!(!verify_download(
+            "secret", "fw", "other", expires, &signature, now
+        ));
+        assert!(!verify_download(
+            "other", "fw", "dev", expires, &signature, now
+        ));
+        assert!(!verify_download(
+            "secret",
+            "fw",
+            "dev",
+            expires,
+            &signature,
+            now + chrono::Duration::seconds(61)
+        ));
+        assert!(!verify_download("secret", "fw", "dev", expires, "zz", now));
+
+        let report = ProgressReport::parse(r#"{"status":"downloading","progress":120}"#).unwrap();
+        assert_eq!(report.status, ProgressStatus::Downloading);
+        assert_eq!(report.progress, 100);
+        assert_eq!(report.campaign_id, None);
+        assert!(ProgressReport::parse(r#"{"status":"succeeded"}"#).is_err());
+    }
+}
@@ -0,0 +1,246 @@
+//! 设备CA, 为使用双向TLS认证的设备签发客户端证书
+use chrono::{DateTime, Local, TimeZone};
+use rand::RngCore;
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, CertificateRevocationList,
+    CertificateRevocationListParams, CertificateSigningRequest, DistinguishedName, DnType,
+    ExtendedKeyUsagePurpose, IsCa, KeyIdMethod, KeyPair, KeyUsagePurpose, RevocationReason,
+    RevokedCertParams, SanType, SerialNumber, PKCS_ECDSA_P256_SHA256,
+};
+use sha2::{Digest, Sha256};
+use time::{Duration, OffsetDateTime};
+
+use crate::errors::{NeoiotError, Result};
+
+/// CA证书有效期
+const CA_VALIDITY_DAYS: i64 = 3650;
+/// CRL的更新周期
+const CRL_VALIDITY_HOURS: i64 = 24;
+
+/// 新生成的CA
+pub struct GeneratedCa {
+    pub cert_pem: String,
+    pub key_pem: String,
+    pub not_after: DateTime<Local>,
+}
+
+/// 新签发的设备证书
+pub struct SignedCertificate {
+    pub serial: String,
+    pub fingerprint: String,
+    pub cert_pem: String,
+    pub not_before: DateTime<Local>,
+    pub not_after: DateTime<Local>,
+}
+
+/// 设备证书中携带设备ID的URI SAN
+pub fn device_uri(device_id: &str) -> String {
+    format!("urn:neoiot:device:{}", device_id)
+}
+
+/// 为账号生成设备CA
+pub fn generate_ca(account_id: &str) -> Result<GeneratedCa> {
+    let now = OffsetDateTime::now_utc();
+    let mut params = CertificateParams::default();
+    params.alg = &PKCS_ECDSA_P256_SHA256;
+    params.not_before = now - Duration::minutes(5);
+    params.not_after = now + Duration::days(CA_VALIDITY_DAYS);
+    params.serial_number = Some(random_serial());
+    params.distinguished_name = DistinguishedName::new();
+    params
+        .distinguished_name
+        .push(DnType::OrganizationName, "NEOIOT");
+    params.distinguished_name.push(
+        DnType::CommonName,
+        format!("NEOIOT Device CA {}", account_id),
+    );
+    params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+    params.key_usages = vec![
+        KeyUsagePurpose::KeyCertSign,
+        KeyUsagePurpose::CrlSign,
+        KeyUsagePurpose::DigitalSignature,
+    ];
+    params.key_identifier_method = KeyIdMethod::Sha256;
+    let not_after = params.not_after;
+
+    let cert = Certificate::from_params(params).map_err(pki_error)?;
+    Ok(GeneratedCa {
+        cert_pem: cert.serialize_pem().map_err(pki_error)?,
+        key_pem: cert.serialize_private_key_pem(),
+        not_after: to_local(not_after),
+    })
+}
+
+/// 使用CA签发设备证书, 证书的CN和SAN由服务端决定, 忽略CSR中的主题
+pub fn sign_csr(
+    ca_cert_pem: &str,
+    ca_key_pem: &str,
+    csr_pem: &str,
+    device_id: &str,
+    validity_days: u32,
+) -> Result<SignedCertificate> {
+    let ca = load_ca(ca_cert_pem, ca_key_pem)?;
+    let mut csr = CertificateSigningRequest::from_pem(csr_pem)
+        .map_err(|e| NeoiotError::InvalidArgument(format!("invalid csr: {}", e)))?;
+
+    let now = OffsetDateTime::now_utc();
+    let serial = random_serial();
+    let params = &mut csr.params;
+    params.not_before = now - Duration::minutes(5);
+    params.not_after = now + Duration::days(validity_days as i64);
+    params.serial_number = Some(serial.clone());
+    params.distinguished_name = DistinguishedName::new();
+    params
+        .distinguished_name
+        .push(DnType::CommonName, device_id);
+    params.subject_alt_names = vec![SanType::URI(device_uri(device_id))];
+    params.is_ca = IsCa::ExplicitNoCa;
+    params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+    params.use_authority_key_identifier_extension = true;
+    let (not_before, not_after) = (params.not_before, params.not_after);
+
+    let der = csr.serialize_der_with_signer(&ca).map_err(pki_error)?;
+    let cert_pem = pem::encode(&pem::Pem::new("CERTIFICATE", der.clone()));
+    Ok(SignedCertificate {
+        serial: hex::encode(serial.as_ref()),
+        fingerprint: hex::encode(Sha256::digest(&der)),
+        cert_pem,
+        not_before: to_local(not_before),
+        not_after: to_local(not_after),
+    })
+}
+
+/// 生成CRL, `revoked`为(序列号, 吊销时间)
+pub fn generate_crl(
+    ca_cert_pem: &str,
+    ca_key_pem: &str,
+    crl_number: i64,
+    revoked: &[(String, DateTime<Local>)],
+) -> Result<String> {
+    let ca = load_ca(ca_cert_pem, ca_key_pem)?;
+    let now = OffsetDateTime::now_utc();
+    let revoked_certs = revoked
+        .iter()
+        .map(|(serial, revoked_at)| {
+            Ok(RevokedCertParams {
+                serial_number: SerialNumber::from_slice(&hex::decode(serial).map_err(pki_error)?),
+                revocation_time: to_offset(*revoked_at),
+                reason_code: Some(RevocationReason::Unspecified),
+                invalidity_date: None,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let params = CertificateRevocationListParams {
+        this_update: now,
+        next_update: now + Duration::hours(CRL_VALIDITY_HOURS),
+        crl_number: SerialNumber::from(crl_number as u64),
+        issuing_distribution_point: None,
+        revoked_certs,
+        alg: &PKCS_ECDSA_P256_SHA256,
+        key_identifier_method: KeyIdMethod::Sha256,
+    };
+    CertificateRevocationList::from_params(params)
+        .and_then(|crl| crl.serialize_pem_with_signer(&ca))
+        .map_err(pki_error)
+}
+
+fn load_ca(cert_pem: &str, key_pem: &str) -> Result<Certificate> {
+    let key = KeyPair::from_pem(key_pem).map_err(pki_error)?;
+    let params = CertificateParams::from_ca_cert_pem(cert_pem, key).map_err(pki_error)?;
+    Certificate::from_params(params).map_err(pki_error)
+}
+
+/// 统一EMQX传入的序列号或指纹格式: 去掉冒号和空白并转为小写十六进制
+pub fn normalize_hex(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| *c != ':' && !c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// 16字节的随机正整数序列号
+fn random_serial() -> SerialNumber {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes[0] &= 0x7f;
+    bytes[0] |= 0x01;
+    SerialNumber::from_slice(&bytes)
+}
+
+fn to_local(t: OffsetDateTime) -> DateTime<Local> {
+    Local.timestamp(t.unix_timestamp(), 0)
+}
+
+fn to_offset(t: DateTime<Local>) -> OffsetDateTime {
+    OffsetDateTime::from_unix_timestamp(t.timestamp()).unwrap_or(OffsetDateTime::UNIX_EPOCH)
+}
+
+fn pki_error(err: impl std::fmt::Display) -> NeoiotError {
+    NeoiotError::PkiError(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x509_parser::{
+        certificate::X509Certificate, extensions::GeneralName, prelude::FromDer,
+        revocation_list::CertificateRevocationList as X509Crl,
+    };
+
+    #[test]
+    fn test_issue_and_revoke() {
+        let ca = generate_ca("account").unwrap();
+
+        let mut params = CertificateParams::new(vec!["ignored.example.com".to_string()]);
+        params.alg = &PKCS_ECDSA_P256_SHA256;
+        let csr = Certificate::from_params(params)
+            .unwrap()
+            .serialize_request_pem()
+            .unwrap();
+        let signed = sign_csr(&ca.cert_pem, &ca.key_pem, &csr, "device", 30).unwrap();
+
+        let der = pem::parse(&signed.cert_pem).unwrap();
+        let (_, cert) = X509Certificate::from_der(der.contents()).unwrap();
+        let cn = cert.subject().iter_common_name().next().unwrap();
+        assert_eq!(cn.as_str().unwrap(), "device");
+        assert_eq!(hex::encode(cert.raw_serial()), signed.serial);
+        let colon_serial = signed
+            .serial
+            .to_uppercase()
+            .as_bytes()
+            .chunks(2)
+            .map(|b| std::str::from_utf8(b).unwrap())
+            .collect::<Vec<_>>()
+            .join(":");
+        assert_eq!(normalize_hex(&colon_serial), signed.serial);
+        let san = cert.subject_alternative_name().unwrap().unwrap();
+        assert_eq!(
+            san.value.general_names,
+            vec![GeneralName::URI("urn:neoiot:device:device")]
+        );
+
+        let ca_der = pem::parse(&ca.cert_pem).unwrap();
+        let (_, ca_cert) = X509Certificate::from_der(ca_der.contents()).unwrap();
+        cert.verify_signature(Some(ca_cert.public_key())).unwrap();
+
+        let crl = generate_crl(
+            &ca.cert_pem,
+            &ca.key_pem,
+            1,
+            &[(signed.serial.clone(), Local::now())],
+        )
+        .unwrap();
+        let crl_der = pem::parse(&crl).unwrap();
+        let (_, crl) = X509Crl::from_der(crl_der.contents()).unwrap();
+        let serials = crl
+            .iter_revoked_certificates()
+            .map(|r| hex::encode(r.raw_serial()))
+            .collect::<Vec<_>>();
+        assert_eq!(serials, vec![signed.serial]);
+        crl.verify_signature(ca_cert.public_key()).unwrap();
+
+        assert!(sign_csr(&ca.cert_pem, &ca.key_pem, "not a csr", "device", 30).is_err());
+    }
+}
@@ -1,5 +1,22 @@
+use crate::alarms::NewAlarm;
+use crate::audit::{AuditLog, AuditLogQuery};
+use crate::device_config::ConfigAck;
 use crate::errors::Result;
+use crate::events::Event;
+use crate::ota::{NewFirmware, ProgressReport};
+use crate::rules::FiredRule;
+use crate::selector::Selector;
+use crate::webhook::Attempt;
+use chrono::{DateTime, Local};
+use entity::alarms::{AlarmSeverity, AlarmStatus};
+use entity::command_job_devices::CommandJobDeviceStatus;
+use entity::command_jobs::CommandJobStatus;
+use entity::device_uploads::DeviceUploadStatus;
+use entity::devices::LifecycleState;
+use entity::ota_campaign_devices::{OtaDeviceStatus, OtaStage};
+use entity::ota_campaigns::OtaCampaignStatus;
 use entity::prelude::*;
+use entity::webhook_deliveries::{EventType, WebhookDeliveryStatus};
 use poem::async_trait;
 
 mod postgres;
@@ -14,7 +31,7 @@ pub trait Repository: Send + Sync + 'static {
     async fn create_account(&self, req: &oai_schema::CreateAccount) -> Result<AccountModel>;
     /// 获取一个账号信息
     async fn get_account(&self, account_id: &str) -> Result<AccountModel>;
-    /// 获取一个账号信息(通过邮箱)
+    /// 获取一个账号信息(通过邮箱, 不区分大小写)
     async fn get_account_by_email(&self, email: &str) -> Result<AccountModel>;
     /// 更新账号登录时间
     async fn after_account_logined(&self, email: &str) -> Result<()>;
@@ -34,8 +51,69 @@ pub trait Repository: Send + Sync + 'static {
     ) -> Result<AccountModel>;
     /// 删除账号
     async fn delete_account(&self, account_id: &str) -> Result<()>;
+    /// 保存待激活的TOTP密钥
+    async fn set_totp_secret(&self, account_id: &str, secret: &str) -> Result<AccountModel>;
+    /// 启用TOTP并保存恢复码摘要
+    async fn enable_totp(
+        &self,
+        account_id: &str,
+        recovery_codes: &[String],
+    ) -> Result<AccountModel>;
+    /// 关闭TOTP，清除密钥和恢复码
+    async fn disable_totp(&self, account_id: &str) -> Result<AccountModel>;
+    /// 替换账号的恢复码摘要
+    async fn update_recovery_codes(
+        &self,
+        account_id: &str,
+        recovery_codes: &[String],
+    ) -> Result<AccountModel>;
+    /// 使用一个恢复码(摘要), 恢复码无效或已被使用时返回false, 并发使用同一个恢复码时只有一方成功
+    async fn consume_recovery_code(&self, account_id: &str, code_hash: &str) -> Result<bool>;
+    /// 获取安全策略
+    async fn get_security_policy(&self) -> Result<oai_schema::SecurityPolicy>;
+    /// 更新安全策略
+    async fn update_security_policy(
+        &self,
+        req: &oai_schema::UpdateSecurityPolicy,
+    ) -> Result<oai_schema::SecurityPolicy>;
+    /// 记录一次登录尝试
+    async fn create_login_attempt(
+        &self,
+        email: &str,
+        ip_address: &str,
+        account_id: Option<&str>,
+        success: bool,
+        reason: Option<&str>,
+    ) -> Result<()>;
+    /// 获取账号的登录记录
+    async fn list_login_attempts(
+        &self,
+        account_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<LoginAttemptModel>, usize)>;
 
-    async fn list_labels(&self, account_id: &str, q: Option<String>) -> Result<Vec<LabelModel>>;
+    ////////////////////////////// 审计日志相关//////////////////////////////////////////////////////////
+    /// 写入一条审计记录
+    async fn create_audit_log(&self, log: AuditLog) -> Result<()>;
+    /// 查询审计记录
+    async fn list_audit_logs(
+        &self,
+        query: &AuditLogQuery,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<AuditLogModel>, usize)>;
+
+    ////////////////////////////// 标签相关//////////////////////////////////////////////////////////
+    /// 获取标签列表
+    async fn list_labels(
+        &self,
+        account_id: &str,
+        page: usize,
+        page_size: usize,
+        q: Option<String>,
+    ) -> Result<(Vec<LabelModel>, usize)>;
+    /// 获取一个标签
     async fn get_label(&self, account_id: &str, label_id: &str) -> Result<LabelModel>;
     async fn update_label(
         &self,
@@ -49,6 +127,28 @@ pub trait Repository: Send + Sync + 'static {
         req: &oai_schema::CreateLabel,
     ) -> Result<LabelModel>;
     async fn delete_label(&self, account_id: &str, label_id: &str) -> Result<()>;
+    /// 批量给设备打上标签, 返回实际新增的设备ID
+    async fn add_label_devices(
+        &self,
+        account_id: &str,
+        label_id: &str,
+        device_ids: &[String],
+    ) -> Result<Vec<String>>;
+    /// 批量移除设备的标签, 返回实际移除的设备ID
+    async fn remove_label_devices(
+        &self,
+        account_id: &str,
+        label_id: &str,
+        device_ids: &[String],
+    ) -> Result<Vec<String>>;
+    /// 获取带有某个标签的设备列表
+    async fn list_label_devices(
+        &self,
+        account_id: &str,
+        label_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<DeviceModel>, usize)>;
     ////////////////////////////// 设备相关//////////////////////////////////////////////////////////
     /// 获取一条设备信息
     async fn get_device(&self, account_id: &str, device_id: &str) -> Result<DeviceModel>;
@@ -59,6 +159,7 @@ pub trait Repository: Send + Sync + 'static {
         device_id: &str,
     ) -> Result<oai_schema::DeviceModelWithRelated>;
     /// 获取设备列表
+    #[allow(clippy::too_many_arguments)]
     async fn list_device(
         &self,
         account_id: &str,
@@ -66,6 +167,8 @@ pub trait Repository: Send + Sync + 'static {
         page_size: usize,
         id_in: Option<Vec<String>>,
         labels_in: Option<Vec<String>>,
+        selector: Option<&Selector>,
+        lifecycle_state: Option<LifecycleState>,
         q: Option<String>,
     ) -> Result<(Vec<DeviceModel>, usize)>;
     /// 更新设备信息
@@ -82,7 +185,90 @@ pub trait Repository: Send + Sync + 'static {
         &self,
         account_id: &str,
         req: &oai_schema::CreateDevice,
-    ) -> Result<oai_schema::DeviceModelWithRelated>;
+    ) -> Result<(
+        oai_schema::DeviceModelWithRelated,
+        oai_schema::IssuedCredential,
+    )>;
+    /// 校验批量创建设备的数据, 逐行返回(创建请求, 连接密码)或错误信息
+    async fn resolve_device_rows(
+        &self,
+        account_id: &str,
+        rows: &[oai_schema::BulkDeviceRow],
+    ) -> Result<Vec<std::result::Result<(oai_schema::CreateDevice, String), String>>>;
+    /// 在一个事务中批量创建设备及其连接凭据
+    async fn bulk_create_devices(
+        &self,
+        account_id: &str,
+        reqs: &[(oai_schema::CreateDevice, String)],
+    ) -> Result<Vec<DeviceModel>>;
+    /// 获取设备的连接凭据
+    async fn list_device_credentials(
+        &self,
+        account_id: &str,
+        device_id: &str,
+    ) -> Result<Vec<DeviceCredentialModel>>;
+    /// 为设备签发一个新的连接凭据
+    async fn create_device_credential(
+        &self,
+        account_id: &str,
+        device_id: &str,
+        req: &oai_schema::CreateDeviceCredential,
+    ) -> Result<oai_schema::IssuedCredential>;
+    /// 轮换设备凭据, 旧凭据在重叠期结束后失效
+    async fn rotate_device_credential(
+        &self,
+        account_id: &str,
+        device_id: &str,
+        req: &oai_schema::RotateDeviceCredential,
+    ) -> Result<oai_schema::IssuedCredential>;
+    /// 吊销设备凭据
+    async fn revoke_device_credential(
+        &self,
+        account_id: &str,
+        device_id: &str,
+        credential_id: &str,
+    ) -> Result<DeviceCredentialModel>;
+    /// 校验设备的MQTT用户名和密码, 成功时记录凭据的使用时间
+    async fn authenticate_device(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<DeviceModel>>;
+    /// 根据MQTT用户名获取设备, 使用证书认证的设备用户名即设备ID
+    async fn get_device_by_username(&self, username: &str) -> Result<DeviceModel>;
+
+    ////////////////////////////// 设备证书相关//////////////////////////////////////////////////////////
+    /// 获取账号的设备CA, 不存在时自动创建
+    async fn get_or_create_device_ca(&self, account_id: &str) -> Result<DeviceCaModel>;
+    /// 根据CSR为设备签发客户端证书
+    async fn issue_device_certificate(
+        &self,
+        account_id: &str,
+        device_id: &str,
+        req: &oai_schema::IssueDeviceCertificate,
+    ) -> Result<DeviceCertificateModel>;
+    /// 获取设备的证书
+    async fn list_device_certificates(
+        &self,
+        account_id: &str,
+        device_id: &str,
+    ) -> Result<Vec<DeviceCertificateModel>>;
+    /// 吊销设备证书
+    async fn revoke_device_certificate(
+        &self,
+        account_id: &str,
+        device_id: &str,
+        serial: &str,
+    ) -> Result<DeviceCertificateModel>;
+    /// 生成账号设备CA的证书吊销列表
+    async fn generate_device_crl(&self, account_id: &str) -> Result<String>;
+    /// 根据证书CN(设备ID)以及证书序列号或指纹认证设备, 要求出示的正是设备一张未过期且未吊销的证书
+    async fn authenticate_device_certificate(
+        &self,
+        common_name: &str,
+        serial: Option<&str>,
+        fingerprint: Option<&str>,
+    ) -> Result<Option<DeviceModel>>;
     /// 获取设备的连接信息
     async fn list_device_connections(
         &self,
@@ -91,6 +277,58 @@ pub trait Repository: Send + Sync + 'static {
         page: usize,
         page_size: usize,
     ) -> Result<(Vec<DeviceConnectionModel>, usize)>;
+    /// 变更设备的生命周期状态并记录变更事件, 退役时吊销设备的凭证和证书
+    async fn transition_device(
+        &self,
+        account_id: &str,
+        device_id: &str,
+        to: LifecycleState,
+        reason: Option<String>,
+        actor: &str,
+    ) -> Result<oai_schema::DeviceModelWithRelated>;
+    /// 获取设备的生命周期变更事件
+    async fn list_device_lifecycle_events(
+        &self,
+        account_id: &str,
+        device_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<DeviceLifecycleEventModel>, usize)>;
+    /// 删除退役时间早于`before`的设备, 返回删除的数量
+    async fn purge_decommissioned_devices(&self, before: DateTime<Local>) -> Result<usize>;
+    /// 记录设备上线, 返回对应的设备
+    async fn device_connected(
+        &self,
+        event: &oai_schema::MqttConnectedEvent,
+    ) -> Result<Option<DeviceModel>>;
+    /// 记录设备下线, 返回对应的设备, 设备的所有会话都断开后才标记为离线
+    async fn device_disconnected(
+        &self,
+        event: &oai_schema::MqttDisconnectedEvent,
+    ) -> Result<Option<DeviceModel>>;
+    /// 获取网关的子设备
+    async fn list_device_children(
+        &self,
+        account_id: &str,
+        gateway_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<DeviceModel>, usize)>;
+    /// 将设备挂到网关下, 返回实际变更的设备ID, 网关的ACL随之更新
+    async fn add_device_children(
+        &self,
+        account_id: &str,
+        gateway_id: &str,
+        device_ids: &[String],
+    ) -> Result<Vec<String>>;
+    /// 将设备从网关下移除, 返回实际变更的设备ID
+    async fn remove_device_children(
+        &self,
+        account_id: &str,
+        gateway_id: &str,
+        device_ids: &[String],
+    ) -> Result<Vec<String>>;
+    /// 向单个设备发送指令, 设备已停用时返回错误
     async fn send_command_to_device(
         &self,
         account_id: &str,
@@ -104,6 +342,632 @@ pub trait Repository: Send + Sync + 'static {
         label_id: &str,
         req: &oai_schema::SendCommandToDeviceBatch,
     ) -> Result<String>;
+    /// 向选择器匹配的已激活设备逐个发送指令, 返回(设备ID, 指令ID)
+    async fn send_command_to_selector(
+        &self,
+        account_id: &str,
+        selector: &Selector,
+        req: &oai_schema::SendCommandToSelector,
+    ) -> Result<Vec<(String, String)>>;
+    /// 获取选择器匹配的已激活设备ID
+    async fn select_device_ids(&self, account_id: &str, selector: &Selector)
+        -> Result<Vec<String>>;
+
+    ////////////////////////////// 设备间通信相关//////////////////////////////////////////////////////////
+    /// 创建设备间通信白名单, 发送方的ACL随之更新
+    async fn create_d2d_rule(
+        &self,
+        account_id: &str,
+        req: &oai_schema::CreateD2dRule,
+    ) -> Result<D2dRuleModel>;
+    /// 获取设备间通信白名单
+    async fn get_d2d_rule(&self, account_id: &str, rule_id: &str) -> Result<D2dRuleModel>;
+    /// 获取设备间通信白名单列表
+    async fn list_d2d_rules(
+        &self,
+        account_id: &str,
+        sender_id: Option<String>,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<D2dRuleModel>, usize)>;
+    /// 删除设备间通信白名单, 发送方的ACL随之更新
+    async fn delete_d2d_rule(&self, account_id: &str, rule_id: &str) -> Result<()>;
+    /// 记录设备间消息, 只有匹配到开启记录的白名单时才写入, 返回是否写入
+    async fn log_d2d_message(&self, topic: &str, payload: &str) -> Result<bool>;
+    /// 获取设备间消息记录, 可按发送方或目标设备过滤
+    async fn list_d2d_messages(
+        &self,
+        account_id: &str,
+        device_id: Option<String>,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<D2dMessageLogModel>, usize)>;
+
+    ////////////////////////////// 规则相关//////////////////////////////////////////////////////////
+    /// 创建规则
+    async fn create_rule(
+        &self,
+        account_id: &str,
+        req: &oai_schema::CreateRule,
+    ) -> Result<RuleModel>;
+    /// 获取规则
+    async fn get_rule(&self, account_id: &str, rule_id: &str) -> Result<RuleModel>;
+    /// 获取规则列表
+    async fn list_rules(
+        &self,
+        account_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<RuleModel>, usize)>;
+    /// 更新规则, 条件变更后已有的求值状态被清空
+    async fn update_rule(
+        &self,
+        account_id: &str,
+        rule_id: &str,
+        req: &oai_schema::UpdateRule,
+    ) -> Result<RuleModel>;
+    /// 删除规则
+    async fn delete_rule(&self, account_id: &str, rule_id: &str) -> Result<()>;
+    /// 用设备上报的字段值对规则求值, 返回需要执行动作的规则
+    async fn evaluate_rules(
+        &self,
+        account_id: &str,
+        device_id: &str,
+        field: &str,
+        value: f64,
+    ) -> Result<Vec<FiredRule>>;
+    /// 找出条件已持续足够时长但设备之后没有再上报的规则
+    async fn fire_pending_rules(&self) -> Result<Vec<FiredRule>>;
+    /// 记录规则的执行结果
+    async fn create_rule_execution(&self, fired: &FiredRule, error: Option<String>) -> Result<()>;
+    /// 获取规则的执行记录
+    async fn list_rule_executions(
+        &self,
+        account_id: &str,
+        rule_id: &str,
+        device_id: Option<String>,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<RuleExecutionModel>, usize)>;
+
+    ////////////////////////////// 告警相关//////////////////////////////////////////////////////////
+    /// 触发告警, 设备已有未清除的同类型告警时累加次数, 维护中或停用的设备不触发告警返回None
+    async fn raise_alarm(&self, alarm: &NewAlarm) -> Result<Option<AlarmModel>>;
+    /// 获取告警
+    async fn get_alarm(&self, account_id: &str, alarm_id: &str) -> Result<AlarmModel>;
+    /// 获取告警列表
+    #[allow(clippy::too_many_arguments)]
+    async fn list_alarms(
+        &self,
+        account_id: &str,
+        device_id: Option<String>,
+        label_id: Option<String>,
+        severity: Option<AlarmSeverity>,
+        status: Option<AlarmStatus>,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<AlarmModel>, usize)>;
+    /// 按级别统计未清除的告警数量
+    async fn count_alarms(
+        &self,
+        account_id: &str,
+        device_id: Option<String>,
+        label_id: Option<String>,
+    ) -> Result<Vec<(AlarmSeverity, usize)>>;
+    /// 确认告警
+    async fn acknowledge_alarm(
+        &self,
+        account_id: &str,
+        alarm_id: &str,
+        actor: &str,
+        note: Option<String>,
+    ) -> Result<AlarmModel>;
+    /// 清除告警
+    async fn clear_alarm(
+        &self,
+        account_id: &str,
+        alarm_id: &str,
+        actor: &str,
+    ) -> Result<AlarmModel>;
+    /// 清除设备上指定类型的告警, 没有未清除的告警时返回None
+    async fn clear_device_alarm(
+        &self,
+        device_id: &str,
+        alarm_type: &str,
+        actor: &str,
+    ) -> Result<Option<AlarmModel>>;
+    /// 告警升级一级
+    async fn escalate_alarm(&self, account_id: &str, alarm_id: &str) -> Result<AlarmModel>;
+    /// 将在`before`之前触发或升级且仍未确认的告警升级一级, 返回升级的数量
+    async fn escalate_stale_alarms(&self, before: DateTime<Local>) -> Result<usize>;
+
+    ////////////////////////////// Webhook相关//////////////////////////////////////////////////////////
+    /// 创建Webhook
+    async fn create_webhook(
+        &self,
+        account_id: &str,
+        req: &oai_schema::CreateWebhook,
+    ) -> Result<WebhookModel>;
+    /// 获取Webhook
+    async fn get_webhook(&self, account_id: &str, webhook_id: &str) -> Result<WebhookModel>;
+    /// 获取Webhook列表
+    async fn list_webhooks(
+        &self,
+        account_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<WebhookModel>, usize)>;
+    /// 更新Webhook
+    async fn update_webhook(
+        &self,
+        account_id: &str,
+        webhook_id: &str,
+        req: &oai_schema::UpdateWebhook,
+    ) -> Result<WebhookModel>;
+    /// 删除Webhook, 投递记录一并删除
+    async fn delete_webhook(&self, account_id: &str, webhook_id: &str) -> Result<()>;
+    /// 重新生成签名密钥
+    async fn rotate_webhook_secret(
+        &self,
+        account_id: &str,
+        webhook_id: &str,
+    ) -> Result<WebhookModel>;
+    /// 为订阅了事件的Webhook生成投递记录, 返回生成的数量
+    async fn enqueue_webhook_deliveries(&self, event: &Event) -> Result<usize>;
+    /// 领取到期待投递的记录, 领取后在租约期内不会被再次领取
+    async fn claim_webhook_deliveries(
+        &self,
+        limit: u64,
+    ) -> Result<Vec<(WebhookDeliveryModel, WebhookModel)>>;
+    /// 记录投递结果, 失败时安排重试或进入死信
+    async fn finish_webhook_delivery(
+        &self,
+        delivery: &WebhookDeliveryModel,
+        attempt: &Attempt,
+    ) -> Result<()>;
+    /// 获取Webhook的投递记录
+    #[allow(clippy::too_many_arguments)]
+    async fn list_webhook_deliveries(
+        &self,
+        account_id: &str,
+        webhook_id: &str,
+        status: Option<WebhookDeliveryStatus>,
+        event_type: Option<EventType>,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<WebhookDeliveryModel>, usize)>;
+    /// 重新投递, 重置投递次数
+    async fn retry_webhook_delivery(
+        &self,
+        account_id: &str,
+        webhook_id: &str,
+        delivery_id: &str,
+    ) -> Result<WebhookDeliveryModel>;
+
+    ////////////////////////////// 数据转发相关//////////////////////////////////////////////////////////
+    /// 创建数据转发路由
+    async fn create_data_route(
+        &self,
+        account_id: &str,
+        req: &oai_schema::CreateDataRoute,
+    ) -> Result<DataRouteModel>;
+    /// 获取数据转发路由
+    async fn get_data_route(&self, account_id: &str, route_id: &str) -> Result<DataRouteModel>;
+    /// 获取数据转发路由列表
+    async fn list_data_routes(
+        &self,
+        account_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<DataRouteModel>, usize)>;
+    /// 更新数据转发路由
+    async fn update_data_route(
+        &self,
+        account_id: &str,
+        route_id: &str,
+        req: &oai_schema::UpdateDataRoute,
+    ) -> Result<DataRouteModel>;
+    /// 删除数据转发路由
+    async fn delete_data_route(&self, account_id: &str, route_id: &str) -> Result<()>;
+    /// 找出需要转发事件的路由
+    async fn matching_data_routes(&self, event: &Event) -> Result<Vec<DataRouteModel>>;
+    /// 累加路由的转发或失败次数
+    async fn record_data_route_result(&self, route_id: &str, error: Option<String>) -> Result<()>;
+
+    ////////////////////////////// 定时指令相关//////////////////////////////////////////////////////////
+    /// 创建定时指令
+    async fn create_scheduled_command(
+        &self,
+        account_id: &str,
+        req: &oai_schema::CreateScheduledCommand,
+    ) -> Result<ScheduledCommandModel>;
+    /// 获取定时指令
+    async fn get_scheduled_command(
+        &self,
+        account_id: &str,
+        schedule_id: &str,
+    ) -> Result<ScheduledCommandModel>;
+    /// 获取定时指令列表
+    async fn list_scheduled_commands(
+        &self,
+        account_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<ScheduledCommandModel>, usize)>;
+    /// 更新定时指令, 重新计算下次执行时间
+    async fn update_scheduled_command(
+        &self,
+        account_id: &str,
+        schedule_id: &str,
+        req: &oai_schema::UpdateScheduledCommand,
+    ) -> Result<ScheduledCommandModel>;
+    /// 删除定时指令, 执行记录一并删除
+    async fn delete_scheduled_command(&self, account_id: &str, schedule_id: &str) -> Result<()>;
+    /// 领取到期的定时指令并顺延到下次执行时间, 返回(定时指令, 计划执行时间)
+    async fn claim_due_scheduled_commands(
+        &self,
+        limit: u64,
+    ) -> Result<Vec<(ScheduledCommandModel, DateTime<Local>)>>;
+    /// 记录定时指令的执行结果, 无论成败、目标是设备还是标签, 同时写入指令日志
+    async fn create_scheduled_command_run(
+        &self,
+        schedule: &ScheduledCommandModel,
+        scheduled_at: DateTime<Local>,
+        message_id: Option<String>,
+        error: Option<String>,
+    ) -> Result<()>;
+    /// 获取定时指令的执行记录
+    async fn list_scheduled_command_runs(
+        &self,
+        account_id: &str,
+        schedule_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<ScheduledCommandRunModel>, usize)>;
+
+    ////////////////////////////// 批量指令任务相关//////////////////////////////////////////////////////////
+    /// 创建批量指令任务, 标签在创建时展开为设备
+    async fn create_command_job(
+        &self,
+        account_id: &str,
+        actor: &str,
+        req: &oai_schema::CreateCommandJob,
+    ) -> Result<CommandJobModel>;
+    /// 获取批量指令任务
+    async fn get_command_job(&self, account_id: &str, job_id: &str) -> Result<CommandJobModel>;
+    /// 获取批量指令任务列表
+    async fn list_command_jobs(
+        &self,
+        account_id: &str,
+        status: Option<CommandJobStatus>,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<CommandJobModel>, usize)>;
+    /// 按状态统计任务中的设备数
+    async fn count_command_job_devices(
+        &self,
+        job_id: &str,
+    ) -> Result<Vec<(CommandJobDeviceStatus, usize)>>;
+    /// 获取任务中设备的进度
+    async fn list_command_job_devices(
+        &self,
+        account_id: &str,
+        job_id: &str,
+        status: Option<CommandJobDeviceStatus>,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<CommandJobDeviceModel>, usize)>;
+    /// 取消任务, 尚未下发的设备不再下发
+    async fn cancel_command_job(&self, account_id: &str, job_id: &str) -> Result<CommandJobModel>;
+    /// 重新下发失败、超时和已取消的设备, 任务恢复为运行中
+    async fn retry_command_job(&self, account_id: &str, job_id: &str) -> Result<CommandJobModel>;
+    /// 按任务的速率领取待下发的设备, 领取后即标记为已下发
+    async fn claim_command_job_devices(
+        &self,
+    ) -> Result<Vec<(CommandJobModel, Vec<CommandJobDeviceModel>)>>;
+    /// 向任务中的设备下发指令, 先记录指令ID再发布, 设备的回复不会早于记录
+    async fn send_command_to_job_device(
+        &self,
+        job: &CommandJobModel,
+        target: &CommandJobDeviceModel,
+    ) -> Result<String>;
+    /// 记录设备下发失败
+    async fn fail_command_job_device(
+        &self,
+        target: &CommandJobDeviceModel,
+        error: &str,
+    ) -> Result<()>;
+    /// 记录设备对任务指令的回复, 返回指令是否属于某个任务
+    async fn record_command_job_response(&self, message_id: &str, payload: &str) -> Result<bool>;
+    /// 将超时未回复的设备标记为超时, 并结束没有待处理设备的任务, 返回结束的任务数
+    async fn sweep_command_jobs(&self) -> Result<usize>;
+
+    ////////////////////////////// 固件升级相关//////////////////////////////////////////////////////////
+    /// 保存固件元数据, 同一数据模型下版本不能重复
+    async fn create_firmware(&self, account_id: &str, req: &NewFirmware) -> Result<FirmwareModel>;
+    /// 获取固件
+    async fn get_firmware(&self, account_id: &str, firmware_id: &str) -> Result<FirmwareModel>;
+    /// 获取固件(不校验账号), 用于设备通过签名链接下载
+    async fn find_firmware(&self, firmware_id: &str) -> Result<FirmwareModel>;
+    /// 获取固件列表
+    async fn list_firmwares(
+        &self,
+        account_id: &str,
+        schema_id: Option<String>,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<FirmwareModel>, usize)>;
+    /// 删除固件元数据, 有进行中的升级活动时拒绝删除
+    async fn delete_firmware(&self, account_id: &str, firmware_id: &str) -> Result<FirmwareModel>;
+    /// 创建升级活动, 标签在创建时展开为设备并随机选出金丝雀设备
+    async fn create_ota_campaign(
+        &self,
+        account_id: &str,
+        actor: &str,
+        req: &oai_schema::CreateOtaCampaign,
+    ) -> Result<OtaCampaignModel>;
+    /// 获取升级活动
+    async fn get_ota_campaign(
+        &self,
+        account_id: &str,
+        campaign_id: &str,
+    ) -> Result<OtaCampaignModel>;
+    /// 获取升级活动列表
+    async fn list_ota_campaigns(
+        &self,
+        account_id: &str,
+        status: Option<OtaCampaignStatus>,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<OtaCampaignModel>, usize)>;
+    /// 按阶段和状态统计活动中的设备数
+    async fn count_ota_campaign_devices(
+        &self,
+        campaign_id: &str,
+    ) -> Result<Vec<(OtaStage, OtaDeviceStatus, usize)>>;
+    /// 获取活动中设备的进度
+    async fn list_ota_campaign_devices(
+        &self,
+        account_id: &str,
+        campaign_id: &str,
+        status: Option<OtaDeviceStatus>,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<OtaCampaignDeviceModel>, usize)>;
+    /// 结束金丝雀阶段, 推送到其余设备
+    async fn promote_ota_campaign(
+        &self,
+        account_id: &str,
+        campaign_id: &str,
+    ) -> Result<OtaCampaignModel>;
+    /// 中止升级活动, 尚未推送的设备标记为跳过, 已推送的设备继续上报进度
+    async fn abort_ota_campaign(
+        &self,
+        account_id: &str,
+        campaign_id: &str,
+        reason: &str,
+    ) -> Result<OtaCampaignModel>;
+    /// 按活动的速率领取当前阶段待推送的设备, 领取后即标记为已推送
+    async fn claim_ota_campaign_devices(
+        &self,
+    ) -> Result<Vec<(OtaCampaignModel, FirmwareModel, Vec<OtaCampaignDeviceModel>)>>;
+    /// 记录升级通知推送失败
+    async fn fail_ota_device(&self, target: &OtaCampaignDeviceModel, error: &str) -> Result<()>;
+    /// 记录设备上报的固件版本, 与进行中的升级一致时标记为成功, 返回设备升级结束的活动ID
+    async fn report_firmware_version(
+        &self,
+        account_id: &str,
+        device_id: &str,
+        version: &str,
+    ) -> Result<Option<String>>;
+    /// 记录设备上报的升级进度, 返回设备升级失败的活动ID
+    async fn record_ota_progress(
+        &self,
+        account_id: &str,
+        device_id: &str,
+        report: &ProgressReport,
+    ) -> Result<Option<String>>;
+    /// 按失败率和各阶段的进度中止、推进或结束活动, 返回发生变化的活动
+    async fn evaluate_ota_campaign(&self, campaign_id: &str) -> Result<Option<OtaCampaignModel>>;
+    /// 将超时未完成升级的设备标记为失败, 并评估所有进行中的活动, 返回发生变化的活动
+    async fn sweep_ota_campaigns(&self) -> Result<Vec<OtaCampaignModel>>;
+
+    ////////////////////////////// 设备文件上传相关//////////////////////////////////////////////////////////
+    /// 创建上传请求并向设备发送上传指令, 设备已停用时返回错误
+    async fn create_device_upload(
+        &self,
+        account_id: &str,
+        actor: &str,
+        req: &oai_schema::RequestDeviceUpload,
+    ) -> Result<DeviceUploadModel>;
+    /// 获取上传请求
+    async fn get_device_upload(
+        &self,
+        account_id: &str,
+        upload_id: &str,
+    ) -> Result<DeviceUploadModel>;
+    /// 获取上传请求(不校验账号), 用于设备上传分片
+    async fn find_device_upload(&self, upload_id: &str) -> Result<DeviceUploadModel>;
+    /// 获取上传请求列表, 按请求时间倒序
+    async fn list_device_uploads(
+        &self,
+        account_id: &str,
+        device_id: Option<String>,
+        status: Option<DeviceUploadStatus>,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<DeviceUploadModel>, usize)>;
+    /// 删除上传请求, 正在拼接时拒绝删除; 返回删除的上传及其分片以便清理blob
+    async fn delete_device_upload(
+        &self,
+        account_id: &str,
+        upload_id: &str,
+    ) -> Result<(DeviceUploadModel, Vec<DeviceUploadChunkModel>)>;
+    /// 锁住上传记录确认仍在接收分片并确定分片总数, 在写入分片之前调用
+    async fn claim_upload_chunk(&self, upload_id: &str, total: i32) -> Result<()>;
+    /// 记录写入blob的分片, 重复的分片替换之前的记录; 分片全部到达时转为拼接中,
+    /// 返回更新后的上传记录和被替换的分片的键
+    async fn record_upload_chunk(
+        &self,
+        upload_id: &str,
+        seq: i32,
+        total: i32,
+        size: i32,
+        blob_key: &str,
+    ) -> Result<(DeviceUploadModel, Option<String>)>;
+    /// 获取上传已收到的分片, 按序号排列
+    async fn list_upload_chunks(&self, upload_id: &str) -> Result<Vec<DeviceUploadChunkModel>>;
+    /// 记录拼接完成的文件
+    async fn complete_device_upload(
+        &self,
+        upload_id: &str,
+        blob_key: &str,
+        size: i64,
+        checksum: &str,
+    ) -> Result<()>;
+    /// 记录拼接失败
+    async fn fail_device_upload(&self, upload_id: &str, error: &str) -> Result<()>;
+    /// 将超时未收到全部分片的上传标记为过期, 返回过期的上传以便清理分片
+    async fn expire_device_uploads(&self) -> Result<Vec<DeviceUploadModel>>;
+
+    ////////////////////////////// 远程配置相关//////////////////////////////////////////////////////////
+    /// 创建配置模板及第一个版本的文档
+    async fn create_config_profile(
+        &self,
+        account_id: &str,
+        actor: &str,
+        req: &oai_schema::CreateConfigProfile,
+    ) -> Result<(ConfigProfileModel, ConfigProfileVersionModel)>;
+    /// 获取配置模板及最新版本的文档
+    async fn get_config_profile(
+        &self,
+        account_id: &str,
+        profile_id: &str,
+    ) -> Result<(ConfigProfileModel, ConfigProfileVersionModel)>;
+    /// 获取配置模板列表
+    async fn list_config_profiles(
+        &self,
+        account_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<(ConfigProfileModel, ConfigProfileVersionModel)>, usize)>;
+    /// 修改配置模板的名称、描述或Schema, 最新版本的文档须符合新的Schema
+    async fn update_config_profile(
+        &self,
+        account_id: &str,
+        profile_id: &str,
+        req: &oai_schema::UpdateConfigProfile,
+    ) -> Result<(ConfigProfileModel, ConfigProfileVersionModel)>;
+    /// 保存新版本的配置文档, 分配了该模板的设备重新计算生效配置
+    async fn create_config_profile_version(
+        &self,
+        account_id: &str,
+        actor: &str,
+        profile_id: &str,
+        document: &serde_json::Value,
+    ) -> Result<(ConfigProfileModel, ConfigProfileVersionModel)>;
+    /// 获取配置模板的某个版本
+    async fn get_config_profile_version(
+        &self,
+        account_id: &str,
+        profile_id: &str,
+        version: i32,
+    ) -> Result<ConfigProfileVersionModel>;
+    /// 获取配置模板的版本列表, 按版本号倒序
+    async fn list_config_profile_versions(
+        &self,
+        account_id: &str,
+        profile_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<ConfigProfileVersionModel>, usize)>;
+    /// 删除配置模板及其分配, 相关设备重新计算生效配置
+    async fn delete_config_profile(
+        &self,
+        account_id: &str,
+        profile_id: &str,
+    ) -> Result<ConfigProfileModel>;
+    /// 将配置模板分配到标签或设备
+    async fn create_config_assignment(
+        &self,
+        account_id: &str,
+        req: &oai_schema::CreateConfigAssignment,
+    ) -> Result<ConfigAssignmentModel>;
+    /// 获取配置分配列表
+    async fn list_config_assignments(
+        &self,
+        account_id: &str,
+        profile_id: Option<String>,
+        label_id: Option<String>,
+        device_id: Option<String>,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<ConfigAssignmentModel>, usize)>;
+    /// 删除配置分配
+    async fn delete_config_assignment(
+        &self,
+        account_id: &str,
+        assignment_id: &str,
+    ) -> Result<ConfigAssignmentModel>;
+    /// 获取设备的生效配置, 尚未计算时返回None
+    async fn get_device_config(&self, device_id: &str) -> Result<Option<DeviceConfigModel>>;
+    /// 领取需要重新计算生效配置的设备并保存计算结果, 返回生效配置与已推送版本不一致、需要推送的设备
+    async fn sync_device_configs(
+        &self,
+        limit: u64,
+    ) -> Result<Vec<(DeviceModel, DeviceConfigModel)>>;
+    /// 记录已推送的配置版本
+    async fn mark_device_config_pushed(&self, device_id: &str, version: &str) -> Result<()>;
+    /// 将设备标记为需要重新计算生效配置, 推送失败时用于重试
+    async fn mark_device_configs_stale(&self, device_ids: Vec<String>) -> Result<()>;
+    /// 记录设备回复的配置应用结果
+    async fn ack_device_config(
+        &self,
+        account_id: &str,
+        device_id: &str,
+        ack: &ConfigAck,
+    ) -> Result<()>;
+
+    ////////////////////////////// 设备自注册相关//////////////////////////////////////////////////////////
+    /// 创建注册模板, 返回模板和认领令牌
+    async fn create_provisioning_profile(
+        &self,
+        account_id: &str,
+        req: &oai_schema::CreateProvisioningProfile,
+    ) -> Result<(oai_schema::ProvisioningProfileModelWithRelated, String)>;
+    /// 获取注册模板
+    async fn get_provisioning_profile(
+        &self,
+        account_id: &str,
+        profile_id: &str,
+    ) -> Result<oai_schema::ProvisioningProfileModelWithRelated>;
+    /// 获取注册模板列表
+    async fn list_provisioning_profiles(
+        &self,
+        account_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<oai_schema::ProvisioningProfileModelWithRelated>, usize)>;
+    /// 更新注册模板
+    async fn update_provisioning_profile(
+        &self,
+        account_id: &str,
+        profile_id: &str,
+        req: &oai_schema::UpdateProvisioningProfile,
+    ) -> Result<oai_schema::ProvisioningProfileModelWithRelated>;
+    /// 重置认领令牌, 旧令牌立即失效
+    async fn reset_provisioning_claim_token(
+        &self,
+        account_id: &str,
+        profile_id: &str,
+    ) -> Result<(oai_schema::ProvisioningProfileModelWithRelated, String)>;
+    /// 删除注册模板, 已注册的设备保留
+    async fn delete_provisioning_profile(&self, account_id: &str, profile_id: &str) -> Result<()>;
+    /// 设备凭认领令牌和硬件序列号自注册
+    async fn register_device(
+        &self,
+        req: &oai_schema::RegisterDevice,
+    ) -> Result<(DeviceModel, oai_schema::IssuedCredential)>;
 
     ////////////////////////////// 数据模型相关//////////////////////////////////////////////////////////
     /// 创建一个数据模型
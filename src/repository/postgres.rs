@@ -1,28 +1,77 @@
 use std::time::SystemTime;
-use std::{collections::HashSet, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
 
 use crate::{
+    alarms::{self, NewAlarm},
+    audit::{AuditLog, AuditLogQuery},
+    config::SETTINGS,
+    credential,
+    device_config::{self, ConfigAck},
     errors::NeoiotError,
     errors::Result,
+    events::{self, Event},
+    forward, lifecycle,
     oai_schema::{
-        CreateAccount, CreateDevice, CreateField, CreateLabel, CreateSchema,
-        DeviceModelWithRelated, SchemaModelWithRelated, SendCommandToDevice, UpdateAccount,
-        UpdateDevice, UpdateField, UpdateLabel, UpdateSchema,
+        BulkDeviceRow, CreateAccount, CreateCommandJob, CreateConfigAssignment,
+        CreateConfigProfile, CreateD2dRule, CreateDataRoute, CreateDevice, CreateDeviceCredential,
+        CreateField, CreateLabel, CreateOtaCampaign, CreateProvisioningProfile, CreateRule,
+        CreateSchema, CreateWebhook, DeviceModelWithRelated, IssueDeviceCertificate,
+        IssuedCredential, MqttConnectedEvent, MqttDisconnectedEvent, RequestDeviceUpload,
+        RotateDeviceCredential, SchemaModelWithRelated, SecurityPolicy, SendCommandToDevice,
+        UpdateAccount, UpdateConfigProfile, UpdateDataRoute, UpdateDevice, UpdateField,
+        UpdateLabel, UpdateRule, UpdateSchema, UpdateSecurityPolicy, UpdateWebhook,
     },
+    ota::{self, NewFirmware, ProgressReport, Transition},
+    pki,
+    rules::{self, FiredRule},
+    schedule,
     topics::{self, Message, Topics},
+    totp, uploads,
+    webhook::{self, Attempt},
+};
+use crate::{
+    oai_schema::{
+        CreateScheduledCommand, ProvisioningProfileModelWithRelated, RegisterDevice,
+        SendCommandToDeviceBatch, SendCommandToSelector, UpdateProvisioningProfile,
+        UpdateScheduledCommand,
+    },
+    selector::{self, Requirement, Selector},
+    topics::ACLRules,
 };
-use crate::{oai_schema::SendCommandToDeviceBatch, topics::ACLRules};
 use argon2::password_hash::SaltString;
-use argon2::{Argon2, PasswordHasher};
-use chrono::Local;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use chrono::{DateTime, Local};
+use entity::alarms::{AlarmSeverity, AlarmStatus};
+use entity::command_job_devices::CommandJobDeviceStatus;
+use entity::command_jobs::CommandJobStatus;
+use entity::data_routes::SinkType;
+use entity::device_uploads::DeviceUploadStatus;
+use entity::devices::LifecycleState;
+use entity::ota_campaign_devices::{OtaDeviceStatus, OtaStage};
+use entity::ota_campaigns::OtaCampaignStatus;
+use entity::rules::RuleAction;
+use entity::scheduled_commands::ScheduleTarget;
 use entity::sea_orm::{
-    ActiveModelTrait, ColumnTrait, Database, DatabaseConnection, EntityTrait, ModelTrait,
-    PaginatorTrait, QueryFilter, QueryOrder, Set,
+    sea_query::{Expr, Query, SelectStatement},
+    ActiveEnum, ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, Database,
+    DatabaseConnection, EntityTrait, ModelTrait, PaginatorTrait, QueryFilter, QueryOrder,
+    QuerySelect, QueryTrait, Set, TransactionTrait,
+};
+use entity::webhook_deliveries::{EventType, WebhookDeliveryStatus};
+use entity::{
+    accounts, audit_logs, device_connections, devices, fields, labels, labels_device_relation,
+    login_attempts, schemas,
+};
+use entity::{
+    prelude::*,
+    sea_orm::{prelude::DateTimeWithTimeZone, ConnectOptions},
 };
-use entity::{accounts, device_connections, devices, fields, labels, schemas};
-use entity::{prelude::*, sea_orm::ConnectOptions};
 use poem::async_trait;
 use poem_openapi::types::{Email, MaybeUndefined, Password};
+use rand::{distributions::Alphanumeric, seq::SliceRandom, Rng};
 use rand_core::OsRng;
 use serde_json::json;
 
@@ -32,9 +81,37 @@ use super::Repository;
 pub struct PostgresRepository {
     pub conn: DatabaseConnection,
 }
-const ADMIN_EMAIL: &str = "admin@neoiot.com";
-const ADMIN_NAME: &str = "admin";
-const ADMIN_PASSWORD: &str = "123123";
+const LEGACY_ADMIN_EMAIL: &str = "admin@neoiot.com";
+const LEGACY_ADMIN_PASSWORD: &str = "123123";
+const SECURITY_POLICY_KEY: &str = "security_policy";
+/// 通过选择器批量下发指令时, 单次最多匹配的设备数
+const MAX_SELECTOR_DEVICES: usize = 1000;
+/// 批量写入时每条SQL的行数, 避免超过Postgres的参数个数限制
+const BULK_INSERT_CHUNK: usize = 1000;
+/// 单个网关最多的子设备数, 子设备的主题全部写入网关的ACL
+const MAX_GATEWAY_CHILDREN: usize = 1000;
+/// Webhook投递领取后的租约在超时时间之外额外保留的秒数
+const WEBHOOK_LEASE_MARGIN_SECS: i64 = 30;
+/// 单个批量指令任务最多的设备数
+const MAX_JOB_DEVICES: usize = 10000;
+/// 单个升级活动最多的设备数
+const MAX_OTA_DEVICES: usize = 10000;
+/// 已推送升级通知、尚未结束的设备状态
+const OTA_IN_FLIGHT: [OtaDeviceStatus; 3] = [
+    OtaDeviceStatus::Notified,
+    OtaDeviceStatus::Downloading,
+    OtaDeviceStatus::Installing,
+];
+/// 仍在接收分片的上传状态
+const UPLOAD_RECEIVING: [DeviceUploadStatus; 2] =
+    [DeviceUploadStatus::Requested, DeviceUploadStatus::Receiving];
+/// 尚未结束的设备状态
+const OTA_UNFINISHED: [OtaDeviceStatus; 4] = [
+    OtaDeviceStatus::Pending,
+    OtaDeviceStatus::Notified,
+    OtaDeviceStatus::Downloading,
+    OtaDeviceStatus::Installing,
+];
 
 impl PostgresRepository {
     pub async fn new(dsn: impl Into<String>) -> Self {
@@ -49,16 +126,127 @@ impl PostgresRepository {
     }
     // 初始化管理员账号
     pub async fn initial_admin(&self) {
-        let check_admin = self.get_account_by_email(ADMIN_EMAIL).await;
-        if check_admin.is_err() {
-            let req = CreateAccount {
-                email: Email(ADMIN_EMAIL.into()),
-                name: ADMIN_NAME.into(),
-                password: Password(ADMIN_PASSWORD.into()),
-                is_super: true,
-            };
-            self.create_account(&req).await.unwrap();
+        self.expire_legacy_admin_password().await;
+        let superusers = AccountEntity::find()
+            .filter(accounts::Column::IsSuperuser.eq(true))
+            .count(&self.conn)
+            .await
+            .unwrap();
+        if superusers > 0 {
+            return;
+        }
+        let config = &SETTINGS.bootstrap;
+        let (password, generated) = match &config.admin_password {
+            Some(password) => (password.clone(), false),
+            None => (generate_password(), true),
+        };
+        let req = CreateAccount {
+            email: Email(config.admin_email.clone()),
+            name: config.admin_name.clone(),
+            password: Password(password.clone()),
+            is_super: true,
+            must_change_password: generated,
+        };
+        self.create_account(&req).await.unwrap();
+        if generated {
+            tracing::warn!(
+                "\n{line}\n  Created superuser {email}\n  Password: {password}\n  This password is shown only once and must be changed on first login.\n{line}\n",
+                line = "=".repeat(72),
+                email = config.admin_email,
+                password = password,
+            );
+        } else {
+            tracing::info!("created superuser {}", config.admin_email);
+        }
+    }
+
+    /// 设备上未清除的指定类型告警
+    async fn open_alarm(&self, device_id: &str, alarm_type: &str) -> Result<Option<AlarmModel>> {
+        let alarm = AlarmEntity::find()
+            .filter(AlarmColumn::DeviceId.eq(device_id))
+            .filter(AlarmColumn::AlarmType.eq(alarm_type))
+            .filter(AlarmColumn::Status.ne(AlarmStatus::Cleared))
+            .one(&self.conn)
+            .await?;
+        Ok(alarm)
+    }
+
+    /// 告警重复触发, 累加次数, 级别只升不降
+    async fn repeat_alarm(&self, existing: AlarmModel, alarm: &NewAlarm) -> Result<AlarmModel> {
+        let now: DateTimeWithTimeZone = Local::now().into();
+        AlarmEntity::update_many()
+            .col_expr(AlarmColumn::Count, Expr::col(AlarmColumn::Count).add(1))
+            .col_expr(AlarmColumn::LastRaisedAt, Expr::value(now))
+            .col_expr(AlarmColumn::Message, Expr::value(alarm.message.clone()))
+            .col_expr(
+                AlarmColumn::Severity,
+                Expr::value(existing.severity.max(alarm.severity)),
+            )
+            .col_expr(AlarmColumn::UpdatedAt, Expr::value(now))
+            .filter(AlarmColumn::Id.eq(existing.id.as_str()))
+            .exec(&self.conn)
+            .await?;
+        self.get_alarm(&existing.account_id, &existing.id).await
+    }
+
+    /// 获取未退役的设备
+    async fn get_live_device(&self, account_id: &str, device_id: &str) -> Result<DeviceModel> {
+        let device = self.get_device(account_id, device_id).await?;
+        if device.lifecycle_state == LifecycleState::Decommissioned {
+            return Err(NeoiotError::DeviceInactive(device.id));
+        }
+        Ok(device)
+    }
+
+    /// 设备首次连接成功后由provisioned转为active
+    async fn activate_on_first_connect(&self, device: &DeviceModel) -> Result<()> {
+        if device.lifecycle_state != LifecycleState::Provisioned {
+            return Ok(());
+        }
+        let result = self
+            .transition_device(
+                &device.account_id,
+                &device.id,
+                LifecycleState::Active,
+                Some("first connection".to_string()),
+                "device",
+            )
+            .await;
+        if let Err(err) = result {
+            // 同一设备并发连接时, 另一个连接可能已经完成激活
+            let current = self.get_device(&device.account_id, &device.id).await?;
+            if current.lifecycle_state != LifecycleState::Active {
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    // 早期版本会创建使用默认密码的管理员账号，要求其下次登录时修改密码
+    async fn expire_legacy_admin_password(&self) {
+        let admin = match self.get_account_by_email(LEGACY_ADMIN_EMAIL).await {
+            Ok(admin) => admin,
+            Err(_) => return,
         };
+        if admin.must_change_password {
+            return;
+        }
+        let uses_default = PasswordHash::new(&admin.password)
+            .map(|hash| {
+                Argon2::default()
+                    .verify_password(LEGACY_ADMIN_PASSWORD.as_bytes(), &hash)
+                    .is_ok()
+            })
+            .unwrap_or(false);
+        if uses_default {
+            tracing::warn!(
+                "superuser {} still uses the default password, a password change is required on next login",
+                LEGACY_ADMIN_EMAIL
+            );
+            let mut admin: AccountActiveModel = admin.into();
+            admin.must_change_password = Set(true);
+            admin.update(&self.conn).await.unwrap();
+        }
     }
 }
 
@@ -71,6 +259,7 @@ impl super::Repository for PostgresRepository {
             name: Set(req.name.clone()),
             password: Set(hash_password(&req.password)),
             is_superuser: Set(req.is_super),
+            must_change_password: Set(req.must_change_password),
             ..Default::default()
         };
         let account = new_account.insert(&self.conn).await?;
@@ -85,7 +274,10 @@ impl super::Repository for PostgresRepository {
     }
     async fn get_account_by_email(&self, email: &str) -> Result<AccountModel> {
         let obj = AccountEntity::find()
-            .filter(accounts::Column::Email.eq(email))
+            .filter(Expr::cust_with_values(
+                "lower(\"email\") = ?",
+                vec![email.to_lowercase()],
+            ))
             .one(&self.conn)
             .await?
             .ok_or_else(|| NeoiotError::ObjectNotFound("account".to_string()))?;
@@ -132,6 +324,10 @@ impl super::Repository for PostgresRepository {
         }
         if let Some(password) = &req.password {
             obj.password = Set(hash_password(password));
+            obj.password_changed_at = Set(Some(Local::now().into()));
+        }
+        if let Some(must_change_password) = req.must_change_password {
+            obj.must_change_password = Set(must_change_password);
         }
         let account = obj.update(&self.conn).await?;
         Ok(account)
@@ -143,16 +339,209 @@ impl super::Repository for PostgresRepository {
         Ok(())
     }
 
-    async fn list_labels(&self, account_id: &str, q: Option<String>) -> Result<Vec<LabelModel>> {
+    async fn set_totp_secret(&self, account_id: &str, secret: &str) -> Result<AccountModel> {
+        let mut account: AccountActiveModel = self.get_account(account_id).await?.into();
+        account.totp_secret = Set(Some(secret.to_string()));
+        Ok(account.update(&self.conn).await?)
+    }
+
+    async fn enable_totp(
+        &self,
+        account_id: &str,
+        recovery_codes: &[String],
+    ) -> Result<AccountModel> {
+        let mut account: AccountActiveModel = self.get_account(account_id).await?.into();
+        account.totp_enabled = Set(true);
+        account.recovery_codes = Set(json!(recovery_codes));
+        Ok(account.update(&self.conn).await?)
+    }
+
+    async fn disable_totp(&self, account_id: &str) -> Result<AccountModel> {
+        let mut account: AccountActiveModel = self.get_account(account_id).await?.into();
+        account.totp_enabled = Set(false);
+        account.totp_secret = Set(None);
+        account.recovery_codes = Set(json!([]));
+        Ok(account.update(&self.conn).await?)
+    }
+
+    async fn update_recovery_codes(
+        &self,
+        account_id: &str,
+        recovery_codes: &[String],
+    ) -> Result<AccountModel> {
+        let mut account: AccountActiveModel = self.get_account(account_id).await?.into();
+        account.recovery_codes = Set(json!(recovery_codes));
+        Ok(account.update(&self.conn).await?)
+    }
+
+    async fn consume_recovery_code(&self, account_id: &str, code_hash: &str) -> Result<bool> {
+        let txn = self.conn.begin().await?;
+        // 锁住账号行, 并发的请求读到的是已经去掉该恢复码的列表
+        let account = AccountEntity::find_by_id(account_id.to_string())
+            .lock_exclusive()
+            .one(&txn)
+            .await?
+            .ok_or_else(|| NeoiotError::ObjectNotFound("account".to_string()))?;
+        let remaining = match totp::consume_recovery_code(&account.recovery_codes, code_hash) {
+            Some(remaining) => remaining,
+            None => return Ok(false),
+        };
+        let mut account: AccountActiveModel = account.into();
+        account.recovery_codes = Set(json!(remaining));
+        account.update(&txn).await?;
+        txn.commit().await?;
+        Ok(true)
+    }
+
+    async fn get_security_policy(&self) -> Result<SecurityPolicy> {
+        let setting = SystemSettingEntity::find_by_id(SECURITY_POLICY_KEY.to_string())
+            .one(&self.conn)
+            .await?;
+        let mut policy = SecurityPolicy::default();
+        if let Some(setting) = setting {
+            if let Some(value) = setting.value["require_superuser_totp"].as_bool() {
+                policy.require_superuser_totp = value;
+            }
+        }
+        Ok(policy)
+    }
+
+    async fn update_security_policy(&self, req: &UpdateSecurityPolicy) -> Result<SecurityPolicy> {
+        let mut policy = self.get_security_policy().await?;
+        if let Some(require_superuser_totp) = req.require_superuser_totp {
+            policy.require_superuser_totp = require_superuser_totp;
+        }
+        let value = json!({
+            "require_superuser_totp": policy.require_superuser_totp,
+        });
+        let exists = SystemSettingEntity::find_by_id(SECURITY_POLICY_KEY.to_string())
+            .one(&self.conn)
+            .await?;
+        match exists {
+            Some(setting) => {
+                let mut setting: SystemSettingActiveModel = setting.into();
+                setting.value = Set(value);
+                setting.update(&self.conn).await?;
+            }
+            None => {
+                let setting = SystemSettingActiveModel {
+                    key: Set(SECURITY_POLICY_KEY.to_string()),
+                    value: Set(value),
+                    ..Default::default()
+                };
+                setting.insert(&self.conn).await?;
+            }
+        }
+        Ok(policy)
+    }
+
+    async fn create_login_attempt(
+        &self,
+        email: &str,
+        ip_address: &str,
+        account_id: Option<&str>,
+        success: bool,
+        reason: Option<&str>,
+    ) -> Result<()> {
+        let attempt = LoginAttemptActiveModel {
+            id: Set(xid::new().to_string()),
+            account_id: Set(account_id.map(ToString::to_string)),
+            email: Set(email.to_string()),
+            ip_address: Set(ip_address.to_string()),
+            success: Set(success),
+            reason: Set(reason.map(ToString::to_string)),
+            ..Default::default()
+        };
+        attempt.insert(&self.conn).await?;
+        Ok(())
+    }
+
+    async fn list_login_attempts(
+        &self,
+        account_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<LoginAttemptModel>, usize)> {
+        let paginator = LoginAttemptEntity::find()
+            .filter(login_attempts::Column::AccountId.eq(account_id))
+            .order_by_desc(login_attempts::Column::CreatedAt)
+            .paginate(&self.conn, page_size);
+        let attempts = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        Ok((attempts, total))
+    }
+
+    async fn create_audit_log(&self, log: AuditLog) -> Result<()> {
+        let (before, after) = log.changes();
+        let log = AuditLogActiveModel {
+            id: Set(xid::new().to_string()),
+            account_id: Set(log.account_id),
+            actor_id: Set(log.actor_id),
+            action: Set(log.action),
+            resource_type: Set(log.resource_type),
+            resource_id: Set(log.resource_id),
+            before: Set(before),
+            after: Set(after),
+            ip_address: Set(log.ip_address),
+            ..Default::default()
+        };
+        log.insert(&self.conn).await?;
+        Ok(())
+    }
+
+    async fn list_audit_logs(
+        &self,
+        query: &AuditLogQuery,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<AuditLogModel>, usize)> {
+        let mut stmt = AuditLogEntity::find();
+        if let Some(account_id) = &query.account_id {
+            stmt = stmt.filter(audit_logs::Column::AccountId.eq(account_id.as_str()));
+        }
+        if let Some(actor_id) = &query.actor_id {
+            stmt = stmt.filter(audit_logs::Column::ActorId.eq(actor_id.as_str()));
+        }
+        if let Some(action) = &query.action {
+            stmt = stmt.filter(audit_logs::Column::Action.eq(action.clone()));
+        }
+        if let Some(resource_type) = &query.resource_type {
+            stmt = stmt.filter(audit_logs::Column::ResourceType.eq(resource_type.clone()));
+        }
+        if let Some(resource_id) = &query.resource_id {
+            stmt = stmt.filter(audit_logs::Column::ResourceId.eq(resource_id.as_str()));
+        }
+        if let Some(created_after) = query.created_after {
+            stmt = stmt.filter(audit_logs::Column::CreatedAt.gte(created_after));
+        }
+        if let Some(created_before) = query.created_before {
+            stmt = stmt.filter(audit_logs::Column::CreatedAt.lt(created_before));
+        }
+        let paginator = stmt
+            .order_by_desc(audit_logs::Column::CreatedAt)
+            .paginate(&self.conn, page_size);
+        let logs = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        Ok((logs, total))
+    }
+
+    async fn list_labels(
+        &self,
+        account_id: &str,
+        page: usize,
+        page_size: usize,
+        q: Option<String>,
+    ) -> Result<(Vec<LabelModel>, usize)> {
         let mut stmt = LabelEntity::find().filter(labels::Column::AccountId.eq(account_id));
         if let Some(q) = q {
             stmt = stmt.filter(labels::Column::Name.starts_with(&q));
         }
-        let labels = stmt
+        let paginator = stmt
             .order_by_asc(labels::Column::Id)
-            .all(&self.conn)
-            .await?;
-        Ok(labels)
+            .paginate(&self.conn, page_size);
+        let labels = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        Ok((labels, total))
     }
 
     async fn get_label(&self, account_id: &str, label_id: &str) -> Result<LabelModel> {
@@ -173,16 +562,49 @@ impl super::Repository for PostgresRepository {
     ) -> Result<LabelModel> {
         let label = self.get_label(account_id, label_id).await?;
         let mut label: LabelActiveModel = label.into();
+        selector::validate_token(&req.name)?;
         label.name = Set(req.name.clone());
-        label.update(&self.conn).await?;
+        match &req.value {
+            MaybeUndefined::Value(value) => {
+                selector::validate_token(value)?;
+                label.value = Set(Some(value.clone()));
+            }
+            MaybeUndefined::Null => label.value = Set(None),
+            MaybeUndefined::Undefined => {}
+        }
+        let device_ids = LabelDeviceRelationEntity::find()
+            .filter(LabelDeviceRelationColumn::LabelId.eq(label_id))
+            .all(&self.conn)
+            .await?
+            .into_iter()
+            .map(|r| r.device_id)
+            .collect::<Vec<_>>();
+        let txn = self.conn.begin().await?;
+        label.update(&txn).await?;
+        // 按标签通信的主题包含标签名和值, 需要更新发送方和带有该标签的设备的ACL
+        touch_label_version(&txn, device_ids).await?;
+        let senders = d2d_senders(
+            &txn,
+            Condition::all().add(D2dRuleColumn::TargetLabelId.eq(label_id)),
+        )
+        .await?;
+        for sender_id in senders {
+            rebuild_acl(&txn, &sender_id).await?;
+        }
+        txn.commit().await?;
         self.get_label(account_id, label_id).await
     }
 
     async fn create_label(&self, account_id: &str, req: &CreateLabel) -> Result<LabelModel> {
+        selector::validate_token(&req.name)?;
+        if let Some(value) = &req.value {
+            selector::validate_token(value)?;
+        }
         let label = LabelActiveModel {
             id: Set(xid::new().to_string()),
             account_id: Set(account_id.to_string()),
             name: Set(req.name.clone()),
+            value: Set(req.value.clone()),
             ..Default::default()
         };
         let label = label.insert(&self.conn).await?;
@@ -191,10 +613,122 @@ impl super::Repository for PostgresRepository {
 
     async fn delete_label(&self, account_id: &str, label_id: &str) -> Result<()> {
         let label = self.get_label(account_id, label_id).await?;
-        label.delete(&self.conn).await?;
+        let device_ids = LabelDeviceRelationEntity::find()
+            .filter(LabelDeviceRelationColumn::LabelId.eq(label_id))
+            .all(&self.conn)
+            .await?
+            .into_iter()
+            .map(|r| r.device_id)
+            .collect::<Vec<_>>();
+        let senders = d2d_senders(
+            &self.conn,
+            Condition::all().add(D2dRuleColumn::TargetLabelId.eq(label_id)),
+        )
+        .await?;
+        let txn = self.conn.begin().await?;
+        label.delete(&txn).await?;
+        touch_label_version(&txn, device_ids).await?;
+        for sender_id in senders {
+            rebuild_acl(&txn, &sender_id).await?;
+        }
+        txn.commit().await?;
         Ok(())
     }
 
+    async fn add_label_devices(
+        &self,
+        account_id: &str,
+        label_id: &str,
+        device_ids: &[String],
+    ) -> Result<Vec<String>> {
+        self.get_label(account_id, label_id).await?;
+        let found = DeviceEntity::find()
+            .filter(devices::Column::AccountId.eq(account_id))
+            .filter(devices::Column::Id.is_in(device_ids.to_vec()))
+            .all(&self.conn)
+            .await?
+            .into_iter()
+            .map(|d| d.id)
+            .collect::<HashSet<_>>();
+        if let Some(missing) = device_ids.iter().find(|id| !found.contains(*id)) {
+            return Err(NeoiotError::ObjectNotFound(format!("device {}", missing)));
+        }
+        let existed = LabelDeviceRelationEntity::find()
+            .filter(LabelDeviceRelationColumn::LabelId.eq(label_id))
+            .filter(LabelDeviceRelationColumn::DeviceId.is_in(device_ids.to_vec()))
+            .all(&self.conn)
+            .await?
+            .into_iter()
+            .map(|r| r.device_id)
+            .collect::<HashSet<_>>();
+        let need_add = found.difference(&existed).cloned().collect::<Vec<_>>();
+        if need_add.is_empty() {
+            return Ok(need_add);
+        }
+
+        let txn = self.conn.begin().await?;
+        LabelDeviceRelationEntity::insert_many(need_add.iter().map(|id| {
+            LabelDeviceRelationActiveModel {
+                label_id: Set(label_id.to_string()),
+                device_id: Set(id.to_string()),
+                ..Default::default()
+            }
+        }))
+        .exec(&txn)
+        .await?;
+        touch_label_version(&txn, need_add.clone()).await?;
+        txn.commit().await?;
+        Ok(need_add)
+    }
+
+    async fn remove_label_devices(
+        &self,
+        account_id: &str,
+        label_id: &str,
+        device_ids: &[String],
+    ) -> Result<Vec<String>> {
+        self.get_label(account_id, label_id).await?;
+        let need_del = LabelDeviceRelationEntity::find()
+            .filter(LabelDeviceRelationColumn::LabelId.eq(label_id))
+            .filter(LabelDeviceRelationColumn::DeviceId.is_in(device_ids.to_vec()))
+            .all(&self.conn)
+            .await?
+            .into_iter()
+            .map(|r| r.device_id)
+            .collect::<Vec<_>>();
+        if need_del.is_empty() {
+            return Ok(need_del);
+        }
+
+        let txn = self.conn.begin().await?;
+        LabelDeviceRelationEntity::delete_many()
+            .filter(LabelDeviceRelationColumn::LabelId.eq(label_id))
+            .filter(LabelDeviceRelationColumn::DeviceId.is_in(need_del.clone()))
+            .exec(&txn)
+            .await?;
+        touch_label_version(&txn, need_del.clone()).await?;
+        txn.commit().await?;
+        Ok(need_del)
+    }
+
+    async fn list_label_devices(
+        &self,
+        account_id: &str,
+        label_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<DeviceModel>, usize)> {
+        let label = self.get_label(account_id, label_id).await?;
+        let paginator = label
+            .find_related(DeviceEntity)
+            .filter(devices::Column::AccountId.eq(account_id))
+            .order_by_asc(devices::Column::Id)
+            .paginate(&self.conn, page_size);
+        let devices = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        Ok((devices, total))
+    }
+
     async fn get_device(&self, account_id: &str, device_id: &str) -> Result<DeviceModel> {
         let device = DeviceEntity::find()
             .filter(devices::Column::Id.eq(device_id))
@@ -224,6 +758,7 @@ impl super::Repository for PostgresRepository {
             schema: schema.unwrap(),
         })
     }
+    #[allow(clippy::too_many_arguments)]
     async fn list_device(
         &self,
         account_id: &str,
@@ -231,9 +766,15 @@ impl super::Repository for PostgresRepository {
         page_size: usize,
         id_in: Option<Vec<String>>,
         labels_in: Option<Vec<String>>,
+        selector: Option<&Selector>,
+        lifecycle_state: Option<LifecycleState>,
         q: Option<String>,
     ) -> Result<(Vec<DeviceModel>, usize)> {
         let mut stmt = DeviceEntity::find().filter(devices::Column::AccountId.eq(account_id));
+        stmt = match lifecycle_state {
+            Some(state) => stmt.filter(devices::Column::LifecycleState.eq(state)),
+            None => stmt.filter(devices::Column::LifecycleState.ne(LifecycleState::Decommissioned)),
+        };
         if let Some(id_in) = id_in {
             stmt = stmt.filter(devices::Column::Id.is_in(id_in));
         }
@@ -241,9 +782,17 @@ impl super::Repository for PostgresRepository {
             stmt = stmt.filter(devices::Column::Name.starts_with(&q));
         }
         if let Some(labels) = labels_in {
-            stmt = stmt
-                .right_join(LabelEntity)
-                .filter(labels::Column::Name.is_in(labels));
+            let labeled = LabelDeviceRelationEntity::find()
+                .select_only()
+                .column(LabelDeviceRelationColumn::DeviceId)
+                .inner_join(LabelEntity)
+                .filter(labels::Column::AccountId.eq(account_id))
+                .filter(labels::Column::Name.is_in(labels))
+                .into_query();
+            stmt = stmt.filter(devices::Column::Id.in_subquery(labeled));
+        }
+        if let Some(selector) = selector {
+            stmt = stmt.filter(selector_condition(account_id, selector));
         }
         let stmt = stmt
             .order_by_asc(devices::Column::Id)
@@ -261,9 +810,23 @@ impl super::Repository for PostgresRepository {
         req: &UpdateDevice,
     ) -> Result<DeviceModelWithRelated> {
         let device_with_labels = self.get_device_with_labels(account_id, device_id).await?;
+        let current = device_with_labels.device.clone();
+        if let Some(is_active) = req.is_active {
+            if is_active != lifecycle::is_active(current.lifecycle_state) {
+                let to = if is_active {
+                    LifecycleState::Active
+                } else {
+                    LifecycleState::Suspended
+                };
+                self.transition_device(account_id, device_id, to, None, account_id)
+                    .await?;
+            }
+        }
         let mut device: devices::ActiveModel = device_with_labels.device.into();
+        let txn = self.conn.begin().await?;
         //1. change device tags
         if let Some(new_label_ids) = &req.label_ids {
+            ensure_labels(&txn, account_id, new_label_ids).await?;
             let old_label_ids = device_with_labels
                 .labels
                 .iter()
@@ -271,47 +834,81 @@ impl super::Repository for PostgresRepository {
                 .collect::<HashSet<_>>();
             let new_label_ids = new_label_ids.iter().cloned().collect::<HashSet<_>>();
             if old_label_ids != new_label_ids {
-                let need_add = new_label_ids.difference(&old_label_ids);
+                let need_add = new_label_ids.difference(&old_label_ids).collect::<Vec<_>>();
                 let need_del = old_label_ids.difference(&new_label_ids);
 
-                LabelDeviceRelationEntity::insert_many(need_add.map(|id| {
-                    LabelDeviceRelationActiveModel {
-                        label_id: Set(id.to_string()),
-                        device_id: Set(device_id.to_string()),
-                        ..Default::default()
-                    }
-                }))
-                .exec(&self.conn)
-                .await?;
+                if !need_add.is_empty() {
+                    LabelDeviceRelationEntity::insert_many(need_add.into_iter().map(|id| {
+                        LabelDeviceRelationActiveModel {
+                            label_id: Set(id.to_string()),
+                            device_id: Set(device_id.to_string()),
+                            ..Default::default()
+                        }
+                    }))
+                    .exec(&txn)
+                    .await?;
+                }
                 LabelDeviceRelationEntity::delete_many()
                     .filter(LabelDeviceRelationColumn::DeviceId.eq(device_id))
                     .filter(
                         LabelDeviceRelationColumn::LabelId.is_in(need_del.map(|id| id.to_string())),
                     )
-                    .exec(&self.conn)
+                    .exec(&txn)
                     .await?;
-                device.label_version = Set(SystemTime::now()
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_millis() as i64);
+                device.label_version = Set(label_version());
+                device.config_stale = Set(true);
             }
         }
+        let labels_changed = device.label_version.is_set();
         if let Some(name) = &req.name {
             device.name = Set(name.clone());
         }
-        if let Some(is_active) = &req.is_active {
-            device.is_active = Set(*is_active);
-        }
         if let Some(schema_id) = &req.schema_id {
             device.schema_id = Set(schema_id.clone());
         }
-        device.update(&self.conn).await?;
+        if let Some(is_super_device) = req.is_super_device {
+            if is_super_device && current.parent_id.is_some() {
+                return Err(NeoiotError::InvalidArgument(
+                    "a sub-device can not be a gateway".to_string(),
+                ));
+            }
+            if !is_super_device
+                && current.is_super_device
+                && DeviceEntity::find()
+                    .filter(devices::Column::ParentId.eq(device_id))
+                    .count(&txn)
+                    .await?
+                    > 0
+            {
+                return Err(NeoiotError::InvalidArgument(
+                    "gateway still has sub-devices".to_string(),
+                ));
+            }
+            device.is_super_device = Set(is_super_device);
+        }
+        device.update(&txn).await?;
+        if labels_changed {
+            rebuild_acl(&txn, device_id).await?;
+        }
+        txn.commit().await?;
         self.get_device_with_labels(account_id, device_id).await
     }
 
     async fn delete_device(&self, account_id: &str, device_id: &str) -> Result<()> {
         let device = self.get_device(account_id, device_id).await?;
-        device.delete(&self.conn).await?;
+        let txn = self.conn.begin().await?;
+        revoke_device_certificates(&txn, account_id, device_id).await?;
+        let mut affected = d2d_senders(
+            &txn,
+            Condition::all().add(D2dRuleColumn::TargetDeviceId.eq(device_id)),
+        )
+        .await?;
+        affected.extend(device.parent_id.clone());
+        device.delete(&txn).await?;
+        for id in affected {
+            rebuild_acl(&txn, &id).await?;
+        }
+        txn.commit().await?;
         Ok(())
     }
 
@@ -319,90 +916,4126 @@ impl super::Repository for PostgresRepository {
         &self,
         account_id: &str,
         req: &CreateDevice,
-    ) -> Result<DeviceModelWithRelated> {
+    ) -> Result<(DeviceModelWithRelated, IssuedCredential)> {
         let device_id = xid::new().to_string();
-        let acl = ACLRules::new(account_id.to_string(), device_id.clone());
-        let new_device = devices::ActiveModel {
-            id: Set(device_id.clone()),
-            account_id: Set(account_id.to_string()),
-            schema_id: Set(req.schema_id.clone()),
-            name: Set(req.name.clone()),
-            label_version: Set(0),
-            is_active: Set(true),
-            is_online: Set(false),
-            mqtt_username: Set(format!("{}/{}", &device_id, account_id)),
-            mqtt_password: Set(req.mqtt_password.clone()),
-            acl_pubs: Set(json!([
-                acl.pub_d2d(),
-                acl.pub_d2s(),
-                acl.pub_s2dr(),
-                acl.pub_metrics(),
-            ])),
-            acl_subs: Set(json!([acl.sub_s2d(), acl.sub_s2l(), acl.sub_d2d()])),
-            is_super_device: Set(false),
-            ..Default::default()
-        };
-        new_device.insert(&self.conn).await?;
-        LabelDeviceRelationEntity::insert_many(req.label_ids.iter().map(|id| {
-            LabelDeviceRelationActiveModel {
-                label_id: Set(id.to_string()),
-                device_id: Set(device_id.to_string()),
-                ..Default::default()
-            }
-        }))
-        .exec(&self.conn)
-        .await?;
-        self.get_device_with_labels(account_id, &device_id).await
+        let secret = credential::generate_secret();
+        let txn = self.conn.begin().await?;
+        ensure_labels(&txn, account_id, &req.label_ids).await?;
+        new_device(account_id, &device_id, req).insert(&txn).await?;
+        let credential = new_credential(&device_id, None, &secret, None)
+            .insert(&txn)
+            .await?;
+        if !req.label_ids.is_empty() {
+            LabelDeviceRelationEntity::insert_many(req.label_ids.iter().map(|id| {
+                LabelDeviceRelationActiveModel {
+                    label_id: Set(id.to_string()),
+                    device_id: Set(device_id.to_string()),
+                    ..Default::default()
+                }
+            }))
+            .exec(&txn)
+            .await?;
+            rebuild_acl(&txn, &device_id).await?;
+        }
+        txn.commit().await?;
+        let device = self.get_device_with_labels(account_id, &device_id).await?;
+        Ok((device, IssuedCredential { credential, secret }))
     }
-    async fn list_device_connections(
+
+    async fn resolve_device_rows(
         &self,
         account_id: &str,
-        device_id: &str,
-        page: usize,
-        page_size: usize,
-    ) -> Result<(Vec<DeviceConnectionModel>, usize)> {
-        self.get_device(account_id, device_id).await?;
-        let paginator = DeviceConnectionEntity::find()
-            .filter(device_connections::Column::DeviceId.eq(device_id))
-            .order_by_asc(device_connections::Column::Id)
-            .paginate(&self.conn, page_size);
-        let connections = paginator.fetch_page(page - 1).await?;
-        let total = paginator.num_items().await?;
+        rows: &[BulkDeviceRow],
+    ) -> Result<Vec<std::result::Result<(CreateDevice, String), String>>> {
+        let schemas = SchemaEntity::find()
+            .filter(schemas::Column::AccountId.eq(account_id))
+            .all(&self.conn)
+            .await?;
+        let labels = LabelEntity::find()
+            .filter(labels::Column::AccountId.eq(account_id))
+            .all(&self.conn)
+            .await?;
 
-        Ok((connections, total))
+        let resolve_schema = |schema: &str| {
+            if let Some(s) = schemas.iter().find(|s| s.id == schema) {
+                return Ok(s.id.clone());
+            }
+            let mut matched = schemas.iter().filter(|s| s.name == schema);
+            match (matched.next(), matched.next()) {
+                (Some(s), None) => Ok(s.id.clone()),
+                (Some(_), Some(_)) => Err(format!("schema name `{}` is ambiguous", schema)),
+                _ => Err(format!("schema `{}` not found", schema)),
+            }
+        };
+        let resolve_label = |label: &str| {
+            let (name, value) = match label.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim())),
+                None => (label.trim(), None),
+            };
+            labels
+                .iter()
+                .find(|l| l.name == name && l.value.as_deref() == value)
+                .map(|l| l.id.clone())
+                .ok_or_else(|| format!("label `{}` not found", label))
+        };
+
+        let resolved = rows
+            .iter()
+            .map(|row| {
+                let name = row.name.trim();
+                if name.is_empty() {
+                    return Err("name can not be empty".to_string());
+                }
+                let schema_id = resolve_schema(row.schema.trim())?;
+                let label_ids = row
+                    .labels
+                    .iter()
+                    .map(|l| resolve_label(l))
+                    .collect::<std::result::Result<HashSet<_>, _>>()?
+                    .into_iter()
+                    .collect();
+                let mqtt_password = match &row.mqtt_password {
+                    Some(password) if password.len() < credential::MIN_SECRET_LEN => {
+                        return Err(format!(
+                            "mqtt_password must be at least {} characters",
+                            credential::MIN_SECRET_LEN
+                        ))
+                    }
+                    Some(password) => password.clone(),
+                    None => credential::generate_secret(),
+                };
+                let req = CreateDevice {
+                    name: name.to_string(),
+                    schema_id,
+                    label_ids,
+                };
+                Ok((req, mqtt_password))
+            })
+            .collect();
+        Ok(resolved)
     }
-    async fn send_command_to_device(
+
+    async fn bulk_create_devices(
+        &self,
+        account_id: &str,
+        reqs: &[(CreateDevice, String)],
+    ) -> Result<Vec<DeviceModel>> {
+        let device_ids = reqs
+            .iter()
+            .map(|_| xid::new().to_string())
+            .collect::<Vec<_>>();
+        let new_devices = device_ids
+            .iter()
+            .zip(reqs)
+            .map(|(device_id, (req, _))| new_device(account_id, device_id, req))
+            .collect::<Vec<_>>();
+        let credentials = device_ids
+            .iter()
+            .zip(reqs)
+            .map(|(device_id, (_, secret))| new_credential(device_id, None, secret, None))
+            .collect::<Vec<_>>();
+        let txn = self.conn.begin().await?;
+        for chunk in new_devices.chunks(BULK_INSERT_CHUNK) {
+            DeviceEntity::insert_many(chunk.to_vec()).exec(&txn).await?;
+        }
+        for chunk in credentials.chunks(BULK_INSERT_CHUNK) {
+            DeviceCredentialEntity::insert_many(chunk.to_vec())
+                .exec(&txn)
+                .await?;
+        }
+        let relations = device_ids
+            .iter()
+            .zip(reqs)
+            .flat_map(|(device_id, (req, _))| {
+                req.label_ids
+                    .iter()
+                    .map(move |label_id| LabelDeviceRelationActiveModel {
+                        label_id: Set(label_id.clone()),
+                        device_id: Set(device_id.clone()),
+                        ..Default::default()
+                    })
+            })
+            .collect::<Vec<_>>();
+        for chunk in relations.chunks(BULK_INSERT_CHUNK) {
+            LabelDeviceRelationEntity::insert_many(chunk.to_vec())
+                .exec(&txn)
+                .await?;
+        }
+        for (device_id, (req, _)) in device_ids.iter().zip(reqs) {
+            if !req.label_ids.is_empty() {
+                rebuild_acl(&txn, device_id).await?;
+            }
+        }
+        let mut devices = DeviceEntity::find()
+            .filter(devices::Column::Id.is_in(device_ids.clone()))
+            .all(&txn)
+            .await?;
+        txn.commit().await?;
+        let order = device_ids
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (id.as_str(), i))
+            .collect::<HashMap<_, _>>();
+        devices.sort_by_key(|d| order.get(d.id.as_str()).copied());
+        Ok(devices)
+    }
+
+    async fn list_device_credentials(
         &self,
         account_id: &str,
         device_id: &str,
-        req: &SendCommandToDevice,
-    ) -> Result<String> {
-        let _device = self.get_device(account_id, device_id).await?;
-        let command =
-            topics::ServerToDevice::new(account_id, device_id, &req.command, req.is_sync, req.ttl);
-        let message_id = command.message_id.clone();
-        Message::new(Topics::S2D(command), req.payload.clone())
-            .publish(req.qos)
+    ) -> Result<Vec<DeviceCredentialModel>> {
+        let device = self.get_device(account_id, device_id).await?;
+        let credentials = device
+            .find_related(DeviceCredentialEntity)
+            .order_by_asc(DeviceCredentialColumn::CreatedAt)
+            .all(&self.conn)
             .await?;
-        Ok(message_id)
+        Ok(credentials)
     }
-    async fn send_command_to_label(
+
+    async fn create_device_credential(
         &self,
         account_id: &str,
-        label_id: &str,
+        device_id: &str,
+        req: &CreateDeviceCredential,
+    ) -> Result<IssuedCredential> {
+        self.get_live_device(account_id, device_id).await?;
+        let secret = credential::generate_secret();
+        let expires_at = req
+            .expires_in
+            .map(|secs| (Local::now() + chrono::Duration::seconds(secs as i64)).into());
+        let credential = new_credential(device_id, req.name.clone(), &secret, expires_at)
+            .insert(&self.conn)
+            .await?;
+        Ok(IssuedCredential { credential, secret })
+    }
+
+    async fn rotate_device_credential(
+        &self,
+        account_id: &str,
+        device_id: &str,
+        req: &RotateDeviceCredential,
+    ) -> Result<IssuedCredential> {
+        self.get_live_device(account_id, device_id).await?;
+        let overlap_until: DateTimeWithTimeZone =
+            (Local::now() + chrono::Duration::seconds(req.overlap_secs as i64)).into();
+        let secret = credential::generate_secret();
+        let txn = self.conn.begin().await?;
+        DeviceCredentialEntity::update_many()
+            .col_expr(
+                DeviceCredentialColumn::ExpiresAt,
+                Expr::value(overlap_until),
+            )
+            .filter(DeviceCredentialColumn::DeviceId.eq(device_id))
+            .filter(DeviceCredentialColumn::RevokedAt.is_null())
+            .filter(
+                Condition::any()
+                    .add(DeviceCredentialColumn::ExpiresAt.is_null())
+                    .add(DeviceCredentialColumn::ExpiresAt.gt(overlap_until)),
+            )
+            .exec(&txn)
+            .await?;
+        let credential = new_credential(device_id, req.name.clone(), &secret, None)
+            .insert(&txn)
+            .await?;
+        txn.commit().await?;
+        Ok(IssuedCredential { credential, secret })
+    }
+
+    async fn revoke_device_credential(
+        &self,
+        account_id: &str,
+        device_id: &str,
+        credential_id: &str,
+    ) -> Result<DeviceCredentialModel> {
+        self.get_device(account_id, device_id).await?;
+        let credential = DeviceCredentialEntity::find_by_id(credential_id.to_string())
+            .filter(DeviceCredentialColumn::DeviceId.eq(device_id))
+            .one(&self.conn)
+            .await?
+            .ok_or_else(|| NeoiotError::ObjectNotFound("credential".to_string()))?;
+        if credential.revoked_at.is_some() {
+            return Ok(credential);
+        }
+        let mut credential: DeviceCredentialActiveModel = credential.into();
+        credential.revoked_at = Set(Some(Local::now().into()));
+        let credential = credential.update(&self.conn).await?;
+        Ok(credential)
+    }
+
+    async fn authenticate_device(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<DeviceModel>> {
+        let device = match DeviceEntity::find()
+            .filter(devices::Column::MqttUsername.eq(username))
+            .filter(devices::Column::IsActive.eq(true))
+            .one(&self.conn)
+            .await?
+        {
+            Some(device) => device,
+            None => return Ok(None),
+        };
+        let now: DateTimeWithTimeZone = Local::now().into();
+        let credential = DeviceCredentialEntity::find()
+            .filter(DeviceCredentialColumn::DeviceId.eq(device.id.as_str()))
+            .filter(DeviceCredentialColumn::SecretHash.eq(credential::hash_secret(password)))
+            .filter(DeviceCredentialColumn::RevokedAt.is_null())
+            .filter(
+                Condition::any()
+                    .add(DeviceCredentialColumn::ExpiresAt.is_null())
+                    .add(DeviceCredentialColumn::ExpiresAt.gt(now)),
+            )
+            .one(&self.conn)
+            .await?;
+        let credential = match credential {
+            Some(credential) => credential,
+            None => return Ok(None),
+        };
+        let mut credential: DeviceCredentialActiveModel = credential.into();
+        credential.last_used_at = Set(Some(now));
+        credential.update(&self.conn).await?;
+        self.activate_on_first_connect(&device).await?;
+        Ok(Some(device))
+    }
+
+    async fn get_device_by_username(&self, username: &str) -> Result<DeviceModel> {
+        let device = DeviceEntity::find()
+            .filter(
+                Condition::any()
+                    .add(devices::Column::MqttUsername.eq(username))
+                    .add(devices::Column::Id.eq(username)),
+            )
+            .one(&self.conn)
+            .await?
+            .ok_or_else(|| NeoiotError::ObjectNotFound("device".to_string()))?;
+        Ok(device)
+    }
+
+    async fn get_or_create_device_ca(&self, account_id: &str) -> Result<DeviceCaModel> {
+        if let Some(ca) = DeviceCaEntity::find_by_id(account_id.to_string())
+            .one(&self.conn)
+            .await?
+        {
+            return Ok(ca);
+        }
+        self.get_account(account_id).await?;
+        let generated = pki::generate_ca(account_id)?;
+        let ca = DeviceCaActiveModel {
+            account_id: Set(account_id.to_string()),
+            cert_pem: Set(generated.cert_pem),
+            key_pem: Set(generated.key_pem),
+            not_after: Set(generated.not_after.into()),
+            ..Default::default()
+        };
+        match ca.insert(&self.conn).await {
+            Ok(ca) => Ok(ca),
+            // 并发创建时以先写入的CA为准
+            Err(err) => DeviceCaEntity::find_by_id(account_id.to_string())
+                .one(&self.conn)
+                .await?
+                .ok_or_else(|| err.into()),
+        }
+    }
+
+    async fn issue_device_certificate(
+        &self,
+        account_id: &str,
+        device_id: &str,
+        req: &IssueDeviceCertificate,
+    ) -> Result<DeviceCertificateModel> {
+        self.get_live_device(account_id, device_id).await?;
+        let ca = self.get_or_create_device_ca(account_id).await?;
+        let signed = pki::sign_csr(
+            &ca.cert_pem,
+            &ca.key_pem,
+            &req.csr,
+            device_id,
+            req.validity_days,
+        )?;
+        let certificate = DeviceCertificateActiveModel {
+            serial: Set(signed.serial),
+            account_id: Set(account_id.to_string()),
+            device_id: Set(device_id.to_string()),
+            fingerprint: Set(signed.fingerprint),
+            cert_pem: Set(signed.cert_pem),
+            not_before: Set(signed.not_before.into()),
+            not_after: Set(signed.not_after.into()),
+            ..Default::default()
+        }
+        .insert(&self.conn)
+        .await?;
+        Ok(certificate)
+    }
+
+    async fn list_device_certificates(
+        &self,
+        account_id: &str,
+        device_id: &str,
+    ) -> Result<Vec<DeviceCertificateModel>> {
+        self.get_device(account_id, device_id).await?;
+        let certificates = DeviceCertificateEntity::find()
+            .filter(DeviceCertificateColumn::AccountId.eq(account_id))
+            .filter(DeviceCertificateColumn::DeviceId.eq(device_id))
+            .order_by_asc(DeviceCertificateColumn::CreatedAt)
+            .all(&self.conn)
+            .await?;
+        Ok(certificates)
+    }
+
+    async fn revoke_device_certificate(
+        &self,
+        account_id: &str,
+        device_id: &str,
+        serial: &str,
+    ) -> Result<DeviceCertificateModel> {
+        let certificate = DeviceCertificateEntity::find_by_id(serial.to_lowercase())
+            .filter(DeviceCertificateColumn::AccountId.eq(account_id))
+            .filter(DeviceCertificateColumn::DeviceId.eq(device_id))
+            .one(&self.conn)
+            .await?
+            .ok_or_else(|| NeoiotError::ObjectNotFound("certificate".to_string()))?;
+        if certificate.revoked_at.is_some() {
+            return Ok(certificate);
+        }
+        let txn = self.conn.begin().await?;
+        let mut certificate: DeviceCertificateActiveModel = certificate.into();
+        certificate.revoked_at = Set(Some(Local::now().into()));
+        let certificate = certificate.update(&txn).await?;
+        bump_crl_number(&txn, account_id).await?;
+        txn.commit().await?;
+        Ok(certificate)
+    }
+
+    async fn generate_device_crl(&self, account_id: &str) -> Result<String> {
+        let ca = self.get_or_create_device_ca(account_id).await?;
+        let now: DateTimeWithTimeZone = Local::now().into();
+        // 已过期的证书无需继续出现在CRL中
+        let revoked = DeviceCertificateEntity::find()
+            .filter(DeviceCertificateColumn::AccountId.eq(account_id))
+            .filter(DeviceCertificateColumn::RevokedAt.is_not_null())
+            .filter(DeviceCertificateColumn::NotAfter.gt(now))
+            .order_by_asc(DeviceCertificateColumn::RevokedAt)
+            .all(&self.conn)
+            .await?
+            .into_iter()
+            .filter_map(|c| Some((c.serial, c.revoked_at?.into())))
+            .collect::<Vec<_>>();
+        pki::generate_crl(&ca.cert_pem, &ca.key_pem, ca.crl_number, &revoked)
+    }
+
+    async fn authenticate_device_certificate(
+        &self,
+        common_name: &str,
+        serial: Option<&str>,
+        fingerprint: Option<&str>,
+    ) -> Result<Option<DeviceModel>> {
+        // 只凭CN无法区分同一设备已吊销的证书和有效证书
+        if serial.is_none() && fingerprint.is_none() {
+            return Ok(None);
+        }
+        let mut query = DeviceCertificateEntity::find()
+            .filter(DeviceCertificateColumn::DeviceId.eq(common_name));
+        if let Some(serial) = serial {
+            query = query.filter(DeviceCertificateColumn::Serial.eq(serial));
+        }
+        if let Some(fingerprint) = fingerprint {
+            query = query.filter(DeviceCertificateColumn::Fingerprint.eq(fingerprint));
+        }
+        let now: DateTimeWithTimeZone = Local::now().into();
+        let certificate = query
+            .filter(DeviceCertificateColumn::RevokedAt.is_null())
+            .filter(DeviceCertificateColumn::NotBefore.lte(now))
+            .filter(DeviceCertificateColumn::NotAfter.gt(now))
+            .one(&self.conn)
+            .await?;
+        let certificate = match certificate {
+            Some(certificate) => certificate,
+            None => return Ok(None),
+        };
+        let device = DeviceEntity::find_by_id(common_name.to_string())
+            .filter(devices::Column::AccountId.eq(certificate.account_id))
+            .filter(devices::Column::IsActive.eq(true))
+            .one(&self.conn)
+            .await?;
+        if let Some(device) = &device {
+            self.activate_on_first_connect(device).await?;
+        }
+        Ok(device)
+    }
+
+    async fn list_device_connections(
+        &self,
+        account_id: &str,
+        device_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<DeviceConnectionModel>, usize)> {
+        self.get_device(account_id, device_id).await?;
+        let paginator = DeviceConnectionEntity::find()
+            .filter(device_connections::Column::DeviceId.eq(device_id))
+            .order_by_asc(device_connections::Column::Id)
+            .paginate(&self.conn, page_size);
+        let connections = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+
+        Ok((connections, total))
+    }
+    async fn transition_device(
+        &self,
+        account_id: &str,
+        device_id: &str,
+        to: LifecycleState,
+        reason: Option<String>,
+        actor: &str,
+    ) -> Result<DeviceModelWithRelated> {
+        let device = self.get_device(account_id, device_id).await?;
+        let from = device.lifecycle_state;
+        lifecycle::check_transition(from, to)?;
+        let now: DateTimeWithTimeZone = Local::now().into();
+        let parent_id = device.parent_id.clone();
+        let active_changed = device.is_active != lifecycle::is_active(to);
+
+        let txn = self.conn.begin().await?;
+        let mut device: DeviceActiveModel = device.into();
+        device.lifecycle_state = Set(to);
+        device.is_active = Set(lifecycle::is_active(to));
+        if to == LifecycleState::Decommissioned {
+            // 退役设备不能再连接, 但保留历史数据直到保留期结束
+            device.decommissioned_at = Set(Some(now));
+            device.parent_id = Set(None);
+            DeviceCredentialEntity::update_many()
+                .col_expr(DeviceCredentialColumn::RevokedAt, Expr::value(now))
+                .filter(DeviceCredentialColumn::DeviceId.eq(device_id))
+                .filter(DeviceCredentialColumn::RevokedAt.is_null())
+                .exec(&txn)
+                .await?;
+            revoke_device_certificates(&txn, account_id, device_id).await?;
+            DeviceEntity::update_many()
+                .col_expr(
+                    devices::Column::ParentId,
+                    Expr::value(Option::<String>::None),
+                )
+                .filter(devices::Column::ParentId.eq(device_id))
+                .exec(&txn)
+                .await?;
+        }
+        device.update(&txn).await?;
+        // 网关只能代理启用中的子设备, 子设备启停或退役后重新生成网关的ACL
+        if active_changed || to == LifecycleState::Decommissioned {
+            if let Some(parent_id) = parent_id {
+                rebuild_acl(&txn, &parent_id).await?;
+            }
+        }
+        DeviceLifecycleEventActiveModel {
+            id: Set(xid::new().to_string()),
+            device_id: Set(device_id.to_string()),
+            from_state: Set(from),
+            to_state: Set(to),
+            reason: Set(reason),
+            actor: Set(actor.to_string()),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+        txn.commit().await?;
+        self.get_device_with_labels(account_id, device_id).await
+    }
+
+    async fn list_device_lifecycle_events(
+        &self,
+        account_id: &str,
+        device_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<DeviceLifecycleEventModel>, usize)> {
+        self.get_device(account_id, device_id).await?;
+        let paginator = DeviceLifecycleEventEntity::find()
+            .filter(DeviceLifecycleEventColumn::DeviceId.eq(device_id))
+            .order_by_desc(DeviceLifecycleEventColumn::CreatedAt)
+            .paginate(&self.conn, page_size);
+        let events = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        Ok((events, total))
+    }
+
+    async fn purge_decommissioned_devices(&self, before: DateTime<Local>) -> Result<usize> {
+        let devices = DeviceEntity::find()
+            .filter(devices::Column::LifecycleState.eq(LifecycleState::Decommissioned))
+            .filter(devices::Column::DecommissionedAt.lt(before))
+            .all(&self.conn)
+            .await?;
+        for device in &devices {
+            self.delete_device(&device.account_id, &device.id).await?;
+        }
+        Ok(devices.len())
+    }
+
+    async fn device_connected(&self, event: &MqttConnectedEvent) -> Result<Option<DeviceModel>> {
+        let mut device = match self.get_device_by_username(&event.username).await {
+            Ok(device) => device,
+            Err(NeoiotError::ObjectNotFound(_)) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let now: DateTimeWithTimeZone = Local::now().into();
+        let connection = DeviceConnectionEntity::find()
+            .filter(device_connections::Column::DeviceId.eq(device.id.as_str()))
+            .filter(device_connections::Column::ClientId.eq(event.clientid.as_str()))
+            .one(&self.conn)
+            .await?;
+        let mut connection: DeviceConnectionActiveModel = match connection {
+            Some(connection) => connection.into(),
+            None => DeviceConnectionActiveModel {
+                id: Set(xid::new().to_string()),
+                device_id: Set(device.id.clone()),
+                client_id: Set(event.clientid.clone()),
+                disconnected_at: Set(now),
+                ..Default::default()
+            },
+        };
+        connection.connected = Set(true);
+        connection.node = Set(event.node.clone());
+        connection.keep_alive = Set(event.keepalive.to_string());
+        connection.ip_address = Set(event.ipaddress.clone());
+        connection.proto_ver = Set(event.proto_ver);
+        connection.connected_at = Set(now);
+        connection.disconnected_reason = Set(String::new());
+        connection.save(&self.conn).await?;
+
+        DeviceEntity::update_many()
+            .col_expr(devices::Column::IsOnline, Expr::value(true))
+            .filter(devices::Column::Id.eq(device.id.as_str()))
+            .exec(&self.conn)
+            .await?;
+        device.is_online = true;
+        Ok(Some(device))
+    }
+
+    async fn device_disconnected(
+        &self,
+        event: &MqttDisconnectedEvent,
+    ) -> Result<Option<DeviceModel>> {
+        let mut device = match self.get_device_by_username(&event.username).await {
+            Ok(device) => device,
+            Err(NeoiotError::ObjectNotFound(_)) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        DeviceConnectionEntity::update_many()
+            .col_expr(device_connections::Column::Connected, Expr::value(false))
+            .col_expr(
+                device_connections::Column::DisconnectedAt,
+                Expr::value(DateTimeWithTimeZone::from(Local::now())),
+            )
+            .col_expr(
+                device_connections::Column::DisconnectedReason,
+                Expr::value(event.reason.clone()),
+            )
+            .filter(device_connections::Column::DeviceId.eq(device.id.as_str()))
+            .filter(device_connections::Column::ClientId.eq(event.clientid.as_str()))
+            .exec(&self.conn)
+            .await?;
+        let connected = DeviceConnectionEntity::find()
+            .filter(device_connections::Column::DeviceId.eq(device.id.as_str()))
+            .filter(device_connections::Column::Connected.eq(true))
+            .count(&self.conn)
+            .await?;
+        device.is_online = connected > 0;
+        DeviceEntity::update_many()
+            .col_expr(devices::Column::IsOnline, Expr::value(device.is_online))
+            .filter(devices::Column::Id.eq(device.id.as_str()))
+            .exec(&self.conn)
+            .await?;
+        Ok(Some(device))
+    }
+
+    async fn list_device_children(
+        &self,
+        account_id: &str,
+        gateway_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<DeviceModel>, usize)> {
+        self.get_device(account_id, gateway_id).await?;
+        let paginator = DeviceEntity::find()
+            .filter(devices::Column::AccountId.eq(account_id))
+            .filter(devices::Column::ParentId.eq(gateway_id))
+            .order_by_asc(devices::Column::Id)
+            .paginate(&self.conn, page_size);
+        let devices = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        Ok((devices, total))
+    }
+
+    async fn add_device_children(
+        &self,
+        account_id: &str,
+        gateway_id: &str,
+        device_ids: &[String],
+    ) -> Result<Vec<String>> {
+        let gateway = self.get_device(account_id, gateway_id).await?;
+        if !gateway.is_super_device {
+            return Err(NeoiotError::InvalidArgument(format!(
+                "device {} is not a gateway",
+                gateway_id
+            )));
+        }
+        let found = DeviceEntity::find()
+            .filter(devices::Column::AccountId.eq(account_id))
+            .filter(devices::Column::Id.is_in(device_ids.to_vec()))
+            .all(&self.conn)
+            .await?;
+        let found_ids = found.iter().map(|d| d.id.as_str()).collect::<HashSet<_>>();
+        if let Some(missing) = device_ids
+            .iter()
+            .find(|id| !found_ids.contains(id.as_str()))
+        {
+            return Err(NeoiotError::ObjectNotFound(format!("device {}", missing)));
+        }
+        if let Some(device) = found.iter().find(|d| d.is_super_device) {
+            return Err(NeoiotError::InvalidArgument(format!(
+                "gateway {} can not be a sub-device",
+                device.id
+            )));
+        }
+        let need_add = found
+            .iter()
+            .filter(|d| d.parent_id.as_deref() != Some(gateway_id))
+            .collect::<Vec<_>>();
+        if need_add.is_empty() {
+            return Ok(vec![]);
+        }
+        let children = DeviceEntity::find()
+            .filter(devices::Column::ParentId.eq(gateway_id))
+            .count(&self.conn)
+            .await?;
+        if children + need_add.len() > MAX_GATEWAY_CHILDREN {
+            return Err(NeoiotError::QuotaExceeded(format!(
+                "a gateway can have at most {} sub-devices",
+                MAX_GATEWAY_CHILDREN
+            )));
+        }
+        let old_parents = need_add
+            .iter()
+            .filter_map(|d| d.parent_id.clone())
+            .collect::<HashSet<_>>();
+        let need_add = need_add
+            .into_iter()
+            .map(|d| d.id.clone())
+            .collect::<Vec<_>>();
+
+        let txn = self.conn.begin().await?;
+        DeviceEntity::update_many()
+            .col_expr(devices::Column::ParentId, Expr::value(gateway_id))
+            .filter(devices::Column::Id.is_in(need_add.clone()))
+            .exec(&txn)
+            .await?;
+        rebuild_acl(&txn, gateway_id).await?;
+        for parent_id in old_parents {
+            rebuild_acl(&txn, &parent_id).await?;
+        }
+        txn.commit().await?;
+        Ok(need_add)
+    }
+
+    async fn remove_device_children(
+        &self,
+        account_id: &str,
+        gateway_id: &str,
+        device_ids: &[String],
+    ) -> Result<Vec<String>> {
+        self.get_device(account_id, gateway_id).await?;
+        let need_del = DeviceEntity::find()
+            .filter(devices::Column::ParentId.eq(gateway_id))
+            .filter(devices::Column::Id.is_in(device_ids.to_vec()))
+            .all(&self.conn)
+            .await?
+            .into_iter()
+            .map(|d| d.id)
+            .collect::<Vec<_>>();
+        if need_del.is_empty() {
+            return Ok(need_del);
+        }
+
+        let txn = self.conn.begin().await?;
+        DeviceEntity::update_many()
+            .col_expr(
+                devices::Column::ParentId,
+                Expr::value(Option::<String>::None),
+            )
+            .filter(devices::Column::Id.is_in(need_del.clone()))
+            .exec(&txn)
+            .await?;
+        rebuild_acl(&txn, gateway_id).await?;
+        txn.commit().await?;
+        Ok(need_del)
+    }
+
+    async fn send_command_to_device(
+        &self,
+        account_id: &str,
+        device_id: &str,
+        req: &SendCommandToDevice,
+    ) -> Result<String> {
+        let device = self.get_device(account_id, device_id).await?;
+        if !device.is_active {
+            return Err(NeoiotError::DeviceInactive(device.id));
+        }
+        let command =
+            topics::ServerToDevice::new(account_id, device_id, &req.command, req.is_sync, req.ttl);
+        let message_id = command.message_id.clone();
+        Message::new(Topics::S2D(command), req.payload.clone())
+            .publish(req.qos)
+            .await?;
+        Ok(message_id)
+    }
+    async fn send_command_to_label(
+        &self,
+        account_id: &str,
+        label_id: &str,
         req: &SendCommandToDeviceBatch,
     ) -> Result<String> {
-        let label = LabelEntity::find_by_id(label_id.to_string())
+        let label = self.get_label(account_id, label_id).await?;
+        let command = topics::ServerToDeviceBatch::new(
+            account_id,
+            &selector::format_label(&label.name, label.value.as_deref()),
+            &req.command,
+            req.ttl,
+        );
+        let message_id = command.message_id.clone();
+        Message::new(Topics::S2L(command), req.payload.clone())
+            .publish(req.qos)
+            .await?;
+        Ok(message_id)
+    }
+
+    async fn send_command_to_selector(
+        &self,
+        account_id: &str,
+        selector: &Selector,
+        req: &SendCommandToSelector,
+    ) -> Result<Vec<(String, String)>> {
+        let device_ids = self.select_device_ids(account_id, selector).await?;
+        let mut results = Vec::with_capacity(device_ids.len());
+        for device_id in device_ids {
+            let command =
+                topics::ServerToDevice::new(account_id, &device_id, &req.command, false, req.ttl);
+            let message_id = command.message_id.clone();
+            Message::new(Topics::S2D(command), req.payload.clone())
+                .publish(req.qos)
+                .await?;
+            results.push((device_id, message_id));
+        }
+        Ok(results)
+    }
+
+    async fn select_device_ids(
+        &self,
+        account_id: &str,
+        selector: &Selector,
+    ) -> Result<Vec<String>> {
+        if selector.is_empty() {
+            return Err(NeoiotError::InvalidArgument(
+                "selector can not be empty".to_string(),
+            ));
+        }
+        let devices = DeviceEntity::find()
+            .filter(devices::Column::AccountId.eq(account_id))
+            .filter(selector_condition(account_id, selector))
+            .filter(devices::Column::IsActive.eq(true))
+            .order_by_asc(devices::Column::Id)
+            .limit(MAX_SELECTOR_DEVICES as u64 + 1)
+            .all(&self.conn)
+            .await?;
+        if devices.len() > MAX_SELECTOR_DEVICES {
+            return Err(NeoiotError::InvalidArgument(format!(
+                "selector matches more than {} devices",
+                MAX_SELECTOR_DEVICES
+            )));
+        }
+        Ok(devices.into_iter().map(|d| d.id).collect())
+    }
+
+    async fn create_d2d_rule(&self, account_id: &str, req: &CreateD2dRule) -> Result<D2dRuleModel> {
+        self.get_device(account_id, &req.sender_id).await?;
+        match (&req.target_device_id, &req.target_label_id) {
+            (Some(target_id), None) => {
+                if target_id == &req.sender_id {
+                    return Err(NeoiotError::InvalidArgument(
+                        "sender and target can not be the same device".to_string(),
+                    ));
+                }
+                self.get_device(account_id, target_id).await?;
+            }
+            (None, Some(label_id)) => {
+                self.get_label(account_id, label_id).await?;
+            }
+            _ => {
+                return Err(NeoiotError::InvalidArgument(
+                    "exactly one of target_device_id and target_label_id is required".to_string(),
+                ))
+            }
+        }
+        let existed = D2dRuleEntity::find()
+            .filter(D2dRuleColumn::SenderId.eq(req.sender_id.as_str()))
+            .filter(match &req.target_device_id {
+                Some(target_id) => D2dRuleColumn::TargetDeviceId.eq(target_id.as_str()),
+                None => D2dRuleColumn::TargetDeviceId.is_null(),
+            })
+            .filter(match &req.target_label_id {
+                Some(label_id) => D2dRuleColumn::TargetLabelId.eq(label_id.as_str()),
+                None => D2dRuleColumn::TargetLabelId.is_null(),
+            })
+            .one(&self.conn)
+            .await?;
+        if existed.is_some() {
+            return Err(NeoiotError::AlreadyExists("d2d rule".to_string()));
+        }
+
+        let txn = self.conn.begin().await?;
+        let rule = D2dRuleActiveModel {
+            id: Set(xid::new().to_string()),
+            account_id: Set(account_id.to_string()),
+            sender_id: Set(req.sender_id.clone()),
+            target_device_id: Set(req.target_device_id.clone()),
+            target_label_id: Set(req.target_label_id.clone()),
+            log_messages: Set(req.log_messages),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+        rebuild_acl(&txn, &req.sender_id).await?;
+        txn.commit().await?;
+        Ok(rule)
+    }
+
+    async fn get_d2d_rule(&self, account_id: &str, rule_id: &str) -> Result<D2dRuleModel> {
+        let rule = D2dRuleEntity::find_by_id(rule_id.to_string())
+            .filter(D2dRuleColumn::AccountId.eq(account_id))
+            .one(&self.conn)
+            .await?
+            .ok_or_else(|| NeoiotError::ObjectNotFound("d2d rule".to_string()))?;
+        Ok(rule)
+    }
+
+    async fn list_d2d_rules(
+        &self,
+        account_id: &str,
+        sender_id: Option<String>,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<D2dRuleModel>, usize)> {
+        let mut stmt = D2dRuleEntity::find().filter(D2dRuleColumn::AccountId.eq(account_id));
+        if let Some(sender_id) = sender_id {
+            stmt = stmt.filter(D2dRuleColumn::SenderId.eq(sender_id));
+        }
+        let paginator = stmt
+            .order_by_asc(D2dRuleColumn::Id)
+            .paginate(&self.conn, page_size);
+        let rules = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        Ok((rules, total))
+    }
+
+    async fn delete_d2d_rule(&self, account_id: &str, rule_id: &str) -> Result<()> {
+        let rule = self.get_d2d_rule(account_id, rule_id).await?;
+        let sender_id = rule.sender_id.clone();
+        let txn = self.conn.begin().await?;
+        rule.delete(&txn).await?;
+        rebuild_acl(&txn, &sender_id).await?;
+        txn.commit().await?;
+        Ok(())
+    }
+
+    async fn log_d2d_message(&self, topic: &str, payload: &str) -> Result<bool> {
+        let (account_id, sender_id, target, rule) = match topics::DeviceToDevice::parse(topic) {
+            Some(topics::DeviceToDevice::Device {
+                account_id,
+                receiver_id,
+                sender_id,
+            }) => {
+                let rule = D2dRuleEntity::find()
+                    .filter(D2dRuleColumn::AccountId.eq(account_id.as_str()))
+                    .filter(D2dRuleColumn::SenderId.eq(sender_id.as_str()))
+                    .filter(D2dRuleColumn::TargetDeviceId.eq(receiver_id.as_str()))
+                    .one(&self.conn)
+                    .await?;
+                (account_id, sender_id, receiver_id, rule)
+            }
+            Some(topics::DeviceToDevice::Label {
+                account_id,
+                label,
+                sender_id,
+            }) => {
+                let rule = D2dRuleEntity::find()
+                    .filter(D2dRuleColumn::AccountId.eq(account_id.as_str()))
+                    .filter(D2dRuleColumn::SenderId.eq(sender_id.as_str()))
+                    .find_also_related(LabelEntity)
+                    .all(&self.conn)
+                    .await?
+                    .into_iter()
+                    .find(|(_, l)| {
+                        l.as_ref().is_some_and(|l| {
+                            selector::format_label(&l.name, l.value.as_deref()) == label
+                        })
+                    })
+                    .map(|(rule, _)| rule);
+                (account_id, sender_id, label, rule)
+            }
+            None => return Ok(false),
+        };
+        let rule = match rule {
+            Some(rule) if rule.log_messages => rule,
+            _ => return Ok(false),
+        };
+        D2dMessageLogActiveModel {
+            id: Set(xid::new().to_string()),
+            account_id: Set(account_id),
+            rule_id: Set(Some(rule.id)),
+            sender_id: Set(sender_id),
+            target: Set(target),
+            topic: Set(topic.to_string()),
+            payload: Set(payload.to_string()),
+            ..Default::default()
+        }
+        .insert(&self.conn)
+        .await?;
+        Ok(true)
+    }
+
+    async fn list_d2d_messages(
+        &self,
+        account_id: &str,
+        device_id: Option<String>,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<D2dMessageLogModel>, usize)> {
+        let mut stmt =
+            D2dMessageLogEntity::find().filter(D2dMessageLogColumn::AccountId.eq(account_id));
+        if let Some(device_id) = device_id {
+            stmt = stmt.filter(
+                Condition::any()
+                    .add(D2dMessageLogColumn::SenderId.eq(device_id.as_str()))
+                    .add(D2dMessageLogColumn::Target.eq(device_id.as_str())),
+            );
+        }
+        let paginator = stmt
+            .order_by_desc(D2dMessageLogColumn::CreatedAt)
+            .paginate(&self.conn, page_size);
+        let messages = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        Ok((messages, total))
+    }
+
+    async fn create_rule(&self, account_id: &str, req: &CreateRule) -> Result<RuleModel> {
+        validate_rule_field(&req.field)?;
+        validate_rule_action(
+            req.selector.as_deref(),
+            req.action,
+            req.command.as_deref(),
+            req.webhook_url.as_deref(),
+        )?;
+        let rule = RuleActiveModel {
+            id: Set(xid::new().to_string()),
+            account_id: Set(account_id.to_string()),
+            name: Set(req.name.clone()),
+            selector: Set(req.selector.clone()),
+            field: Set(req.field.clone()),
+            operator: Set(req.operator),
+            threshold: Set(req.threshold),
+            duration_secs: Set(req.duration_secs),
+            action: Set(req.action),
+            command: Set(req.command.clone()),
+            command_payload: Set(req.command_payload.clone()),
+            webhook_url: Set(req.webhook_url.clone()),
+            alarm_severity: Set(req.alarm_severity),
+            is_active: Set(true),
+            ..Default::default()
+        }
+        .insert(&self.conn)
+        .await?;
+        Ok(rule)
+    }
+
+    async fn get_rule(&self, account_id: &str, rule_id: &str) -> Result<RuleModel> {
+        RuleEntity::find_by_id(rule_id.to_string())
+            .filter(RuleColumn::AccountId.eq(account_id))
+            .one(&self.conn)
+            .await?
+            .ok_or_else(|| NeoiotError::ObjectNotFound(format!("rule {}", rule_id)))
+    }
+
+    async fn list_rules(
+        &self,
+        account_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<RuleModel>, usize)> {
+        let paginator = RuleEntity::find()
+            .filter(RuleColumn::AccountId.eq(account_id))
+            .order_by_asc(RuleColumn::Id)
+            .paginate(&self.conn, page_size);
+        let rules = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        Ok((rules, total))
+    }
+
+    async fn update_rule(
+        &self,
+        account_id: &str,
+        rule_id: &str,
+        req: &UpdateRule,
+    ) -> Result<RuleModel> {
+        let current = self.get_rule(account_id, rule_id).await?;
+        let merge = |value: &MaybeUndefined<String>, current: &Option<String>| match value {
+            MaybeUndefined::Value(value) => Some(value.clone()),
+            MaybeUndefined::Null => None,
+            MaybeUndefined::Undefined => current.clone(),
+        };
+        let selector = merge(&req.selector, &current.selector);
+        let command = merge(&req.command, &current.command);
+        let command_payload = merge(&req.command_payload, &current.command_payload);
+        let webhook_url = merge(&req.webhook_url, &current.webhook_url);
+        let action = req.action.unwrap_or(current.action);
+        validate_rule_action(
+            selector.as_deref(),
+            action,
+            command.as_deref(),
+            webhook_url.as_deref(),
+        )?;
+        // 条件变化后重新计时
+        let reset_states = !req.selector.is_undefined()
+            || req.operator.is_some()
+            || req.threshold.is_some()
+            || req.duration_secs.is_some()
+            || req.is_active.is_some();
+
+        let mut rule: RuleActiveModel = current.into();
+        if let Some(name) = &req.name {
+            rule.name = Set(name.clone());
+        }
+        if let Some(operator) = req.operator {
+            rule.operator = Set(operator);
+        }
+        if let Some(threshold) = req.threshold {
+            rule.threshold = Set(threshold);
+        }
+        if let Some(duration_secs) = req.duration_secs {
+            rule.duration_secs = Set(duration_secs);
+        }
+        if let Some(alarm_severity) = req.alarm_severity {
+            rule.alarm_severity = Set(alarm_severity);
+        }
+        if let Some(is_active) = req.is_active {
+            rule.is_active = Set(is_active);
+        }
+        rule.selector = Set(selector);
+        rule.action = Set(action);
+        rule.command = Set(command);
+        rule.command_payload = Set(command_payload);
+        rule.webhook_url = Set(webhook_url);
+        rule.updated_at = Set(Some(Local::now().into()));
+        let txn = self.conn.begin().await?;
+        let rule = rule.update(&txn).await?;
+        if reset_states {
+            RuleStateEntity::delete_many()
+                .filter(RuleStateColumn::RuleId.eq(rule_id))
+                .exec(&txn)
+                .await?;
+        }
+        txn.commit().await?;
+        Ok(rule)
+    }
+
+    async fn delete_rule(&self, account_id: &str, rule_id: &str) -> Result<()> {
+        let rule = self.get_rule(account_id, rule_id).await?;
+        rule.delete(&self.conn).await?;
+        Ok(())
+    }
+
+    async fn evaluate_rules(
+        &self,
+        account_id: &str,
+        device_id: &str,
+        field: &str,
+        value: f64,
+    ) -> Result<Vec<FiredRule>> {
+        let device = DeviceEntity::find_by_id(device_id.to_string())
+            .filter(devices::Column::AccountId.eq(account_id))
+            .filter(devices::Column::IsActive.eq(true))
+            .one(&self.conn)
+            .await?;
+        if device.is_none() {
+            return Ok(vec![]);
+        }
+        let rules = RuleEntity::find()
+            .filter(RuleColumn::AccountId.eq(account_id))
+            .filter(RuleColumn::Field.eq(field))
+            .filter(RuleColumn::IsActive.eq(true))
+            .all(&self.conn)
+            .await?;
+
+        let now = Local::now();
+        let mut fired = vec![];
+        for rule in rules {
+            if let Some(selector) = &rule.selector {
+                let selector = match selector.parse::<Selector>() {
+                    Ok(selector) => selector,
+                    Err(_) => continue,
+                };
+                let matched = DeviceEntity::find()
+                    .filter(devices::Column::Id.eq(device_id))
+                    .filter(selector_condition(account_id, &selector))
+                    .count(&self.conn)
+                    .await?;
+                if matched == 0 {
+                    continue;
+                }
+            }
+            let state = RuleStateEntity::find_by_id((rule.id.clone(), device_id.to_string()))
+                .one(&self.conn)
+                .await?;
+            let matched = rules::compare(rule.operator, value, rule.threshold);
+            let was_fired = state.as_ref().is_some_and(|s| s.fired_at.is_some());
+            let step = rules::step(
+                matched,
+                state.as_ref().and_then(|s| s.matched_since).map(Into::into),
+                was_fired,
+                now,
+                rule.duration_secs,
+            );
+            let matched_since: Option<DateTimeWithTimeZone> = step.matched_since.map(Into::into);
+            let inserted = match state {
+                Some(_) => false,
+                None => RuleStateActiveModel {
+                    rule_id: Set(rule.id.clone()),
+                    device_id: Set(device_id.to_string()),
+                    last_value: Set(value),
+                    matched_since: Set(matched_since),
+                    fired_at: Set(None),
+                    updated_at: Set(now.into()),
+                }
+                .insert(&self.conn)
+                .await
+                // 并发上报时状态可能已被插入, 退回到更新
+                .is_ok(),
+            };
+            if !inserted {
+                let mut update = RuleStateEntity::update_many()
+                    .col_expr(RuleStateColumn::LastValue, Expr::value(value))
+                    .col_expr(RuleStateColumn::MatchedSince, Expr::value(matched_since))
+                    .col_expr(
+                        RuleStateColumn::UpdatedAt,
+                        Expr::value(DateTimeWithTimeZone::from(now)),
+                    );
+                if !matched {
+                    update = update.col_expr(
+                        RuleStateColumn::FiredAt,
+                        Expr::value(Option::<DateTimeWithTimeZone>::None),
+                    );
+                }
+                update
+                    .filter(RuleStateColumn::RuleId.eq(rule.id.as_str()))
+                    .filter(RuleStateColumn::DeviceId.eq(device_id))
+                    .exec(&self.conn)
+                    .await?;
+            }
+            // 条件不再满足时自动清除规则触发的告警
+            if !matched && was_fired && rule.action == RuleAction::Alarm {
+                self.clear_device_alarm(device_id, &alarms::rule_alarm_type(&rule.id), "system")
+                    .await?;
+            }
+            if step.fire && claim_rule_firing(&self.conn, &rule.id, device_id).await? {
+                fired.push(FiredRule {
+                    rule,
+                    device_id: device_id.to_string(),
+                    value,
+                });
+            }
+        }
+        Ok(fired)
+    }
+
+    async fn fire_pending_rules(&self) -> Result<Vec<FiredRule>> {
+        let active_devices = Query::select()
+            .column(devices::Column::Id)
+            .from(DeviceEntity)
+            .and_where(devices::Column::IsActive.eq(true))
+            .to_owned();
+        let states = RuleStateEntity::find()
+            .find_also_related(RuleEntity)
+            .filter(RuleStateColumn::MatchedSince.is_not_null())
+            .filter(RuleStateColumn::FiredAt.is_null())
+            .filter(RuleStateColumn::DeviceId.in_subquery(active_devices))
+            .filter(RuleColumn::IsActive.eq(true))
+            .all(&self.conn)
+            .await?;
+
+        let now = Local::now();
+        let mut fired = vec![];
+        for (state, rule) in states {
+            let (rule, matched_since) = match (rule, state.matched_since) {
+                (Some(rule), Some(matched_since)) => (rule, matched_since),
+                _ => continue,
+            };
+            if !rules::is_due(matched_since.into(), now, rule.duration_secs) {
+                continue;
+            }
+            if claim_rule_firing(&self.conn, &rule.id, &state.device_id).await? {
+                fired.push(FiredRule {
+                    rule,
+                    device_id: state.device_id,
+                    value: state.last_value,
+                });
+            }
+        }
+        Ok(fired)
+    }
+
+    async fn create_rule_execution(&self, fired: &FiredRule, error: Option<String>) -> Result<()> {
+        RuleExecutionActiveModel {
+            id: Set(xid::new().to_string()),
+            account_id: Set(fired.rule.account_id.clone()),
+            rule_id: Set(fired.rule.id.clone()),
+            device_id: Set(fired.device_id.clone()),
+            value: Set(fired.value),
+            action: Set(fired.rule.action),
+            error: Set(error),
+            ..Default::default()
+        }
+        .insert(&self.conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn list_rule_executions(
+        &self,
+        account_id: &str,
+        rule_id: &str,
+        device_id: Option<String>,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<RuleExecutionModel>, usize)> {
+        self.get_rule(account_id, rule_id).await?;
+        let mut stmt = RuleExecutionEntity::find().filter(RuleExecutionColumn::RuleId.eq(rule_id));
+        if let Some(device_id) = device_id {
+            stmt = stmt.filter(RuleExecutionColumn::DeviceId.eq(device_id));
+        }
+        let paginator = stmt
+            .order_by_desc(RuleExecutionColumn::CreatedAt)
+            .paginate(&self.conn, page_size);
+        let executions = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        Ok((executions, total))
+    }
+
+    async fn raise_alarm(&self, alarm: &NewAlarm) -> Result<Option<AlarmModel>> {
+        let device = DeviceEntity::find_by_id(alarm.device_id.clone())
+            .filter(devices::Column::AccountId.eq(alarm.account_id.as_str()))
+            .one(&self.conn)
+            .await?;
+        // 维护中或停用的设备不触发告警
+        match device {
+            Some(device)
+                if device.is_active && device.lifecycle_state != LifecycleState::Maintenance => {}
+            _ => return Ok(None),
+        }
+        if let Some(existing) = self.open_alarm(&alarm.device_id, &alarm.alarm_type).await? {
+            return self.repeat_alarm(existing, alarm).await.map(Some);
+        }
+        let inserted = AlarmActiveModel {
+            id: Set(xid::new().to_string()),
+            account_id: Set(alarm.account_id.clone()),
+            device_id: Set(alarm.device_id.clone()),
+            alarm_type: Set(alarm.alarm_type.clone()),
+            source: Set(alarm.source),
+            rule_id: Set(alarm.rule_id.clone()),
+            severity: Set(alarm.severity),
+            status: Set(AlarmStatus::Active),
+            message: Set(alarm.message.clone()),
+            count: Set(1),
+            ..Default::default()
+        }
+        .insert(&self.conn)
+        .await;
+        match inserted {
+            Ok(inserted) => Ok(Some(inserted)),
+            // 并发触发时违反唯一索引, 退回到累加次数
+            Err(err) => match self.open_alarm(&alarm.device_id, &alarm.alarm_type).await? {
+                Some(existing) => self.repeat_alarm(existing, alarm).await.map(Some),
+                None => Err(err.into()),
+            },
+        }
+    }
+
+    async fn get_alarm(&self, account_id: &str, alarm_id: &str) -> Result<AlarmModel> {
+        AlarmEntity::find_by_id(alarm_id.to_string())
+            .filter(AlarmColumn::AccountId.eq(account_id))
+            .one(&self.conn)
+            .await?
+            .ok_or_else(|| NeoiotError::ObjectNotFound(format!("alarm {}", alarm_id)))
+    }
+
+    async fn list_alarms(
+        &self,
+        account_id: &str,
+        device_id: Option<String>,
+        label_id: Option<String>,
+        severity: Option<AlarmSeverity>,
+        status: Option<AlarmStatus>,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<AlarmModel>, usize)> {
+        let mut stmt = AlarmEntity::find().filter(alarm_condition(account_id, device_id, label_id));
+        if let Some(severity) = severity {
+            stmt = stmt.filter(AlarmColumn::Severity.eq(severity));
+        }
+        if let Some(status) = status {
+            stmt = stmt.filter(AlarmColumn::Status.eq(status));
+        }
+        let paginator = stmt
+            .order_by_desc(AlarmColumn::LastRaisedAt)
+            .paginate(&self.conn, page_size);
+        let alarms = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        Ok((alarms, total))
+    }
+
+    async fn count_alarms(
+        &self,
+        account_id: &str,
+        device_id: Option<String>,
+        label_id: Option<String>,
+    ) -> Result<Vec<(AlarmSeverity, usize)>> {
+        let condition = alarm_condition(account_id, device_id, label_id);
+        let mut counts = vec![];
+        for severity in [
+            AlarmSeverity::Critical,
+            AlarmSeverity::Major,
+            AlarmSeverity::Minor,
+            AlarmSeverity::Warning,
+        ] {
+            let count = AlarmEntity::find()
+                .filter(condition.clone())
+                .filter(AlarmColumn::Status.ne(AlarmStatus::Cleared))
+                .filter(AlarmColumn::Severity.eq(severity))
+                .count(&self.conn)
+                .await?;
+            counts.push((severity, count));
+        }
+        Ok(counts)
+    }
+
+    async fn acknowledge_alarm(
+        &self,
+        account_id: &str,
+        alarm_id: &str,
+        actor: &str,
+        note: Option<String>,
+    ) -> Result<AlarmModel> {
+        let alarm = self.get_alarm(account_id, alarm_id).await?;
+        if alarm.status != AlarmStatus::Active {
+            return Err(NeoiotError::InvalidArgument(
+                "only active alarms can be acknowledged".to_string(),
+            ));
+        }
+        let now: DateTimeWithTimeZone = Local::now().into();
+        let mut alarm: AlarmActiveModel = alarm.into();
+        alarm.status = Set(AlarmStatus::Acknowledged);
+        alarm.acknowledged_at = Set(Some(now));
+        alarm.acknowledged_by = Set(Some(actor.to_string()));
+        alarm.ack_note = Set(note);
+        alarm.updated_at = Set(Some(now));
+        Ok(alarm.update(&self.conn).await?)
+    }
+
+    async fn clear_alarm(
+        &self,
+        account_id: &str,
+        alarm_id: &str,
+        actor: &str,
+    ) -> Result<AlarmModel> {
+        let alarm = self.get_alarm(account_id, alarm_id).await?;
+        if alarm.status == AlarmStatus::Cleared {
+            return Err(NeoiotError::InvalidArgument(
+                "alarm is already cleared".to_string(),
+            ));
+        }
+        clear(&self.conn, alarm, actor).await
+    }
+
+    async fn clear_device_alarm(
+        &self,
+        device_id: &str,
+        alarm_type: &str,
+        actor: &str,
+    ) -> Result<Option<AlarmModel>> {
+        match self.open_alarm(device_id, alarm_type).await? {
+            Some(alarm) => clear(&self.conn, alarm, actor).await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    async fn escalate_alarm(&self, account_id: &str, alarm_id: &str) -> Result<AlarmModel> {
+        let alarm = self.get_alarm(account_id, alarm_id).await?;
+        if alarm.status == AlarmStatus::Cleared {
+            return Err(NeoiotError::InvalidArgument(
+                "alarm is already cleared".to_string(),
+            ));
+        }
+        let severity = alarms::escalate(alarm.severity)
+            .ok_or_else(|| NeoiotError::InvalidArgument("alarm is already critical".to_string()))?;
+        let now: DateTimeWithTimeZone = Local::now().into();
+        let mut alarm: AlarmActiveModel = alarm.into();
+        alarm.severity = Set(severity);
+        alarm.escalated_at = Set(Some(now));
+        alarm.updated_at = Set(Some(now));
+        Ok(alarm.update(&self.conn).await?)
+    }
+
+    async fn escalate_stale_alarms(&self, before: DateTime<Local>) -> Result<usize> {
+        let before: DateTimeWithTimeZone = before.into();
+        let now: DateTimeWithTimeZone = Local::now().into();
+        let mut escalated = 0;
+        // 从高到低逐级升级, 升级后escalated_at被更新, 同一轮内不会再次升级
+        for (from, to) in [
+            (AlarmSeverity::Major, AlarmSeverity::Critical),
+            (AlarmSeverity::Minor, AlarmSeverity::Major),
+            (AlarmSeverity::Warning, AlarmSeverity::Minor),
+        ] {
+            let result = AlarmEntity::update_many()
+                .col_expr(AlarmColumn::Severity, Expr::value(to))
+                .col_expr(AlarmColumn::EscalatedAt, Expr::value(now))
+                .col_expr(AlarmColumn::UpdatedAt, Expr::value(now))
+                .filter(AlarmColumn::Status.eq(AlarmStatus::Active))
+                .filter(AlarmColumn::Severity.eq(from))
+                .filter(
+                    Condition::any()
+                        .add(AlarmColumn::EscalatedAt.lt(before))
+                        .add(
+                            Condition::all()
+                                .add(AlarmColumn::EscalatedAt.is_null())
+                                .add(AlarmColumn::RaisedAt.lt(before)),
+                        ),
+                )
+                .exec(&self.conn)
+                .await?;
+            escalated += result.rows_affected as usize;
+        }
+        Ok(escalated)
+    }
+
+    async fn create_webhook(&self, account_id: &str, req: &CreateWebhook) -> Result<WebhookModel> {
+        validate_webhook_url(&req.url)?;
+        let webhook = WebhookActiveModel {
+            id: Set(xid::new().to_string()),
+            account_id: Set(account_id.to_string()),
+            url: Set(req.url.clone()),
+            description: Set(req.description.clone()),
+            secret: Set(credential::generate_secret()),
+            event_types: Set(event_type_names(&req.event_types)),
+            is_active: Set(true),
+            ..Default::default()
+        }
+        .insert(&self.conn)
+        .await?;
+        Ok(webhook)
+    }
+
+    async fn get_webhook(&self, account_id: &str, webhook_id: &str) -> Result<WebhookModel> {
+        WebhookEntity::find_by_id(webhook_id.to_string())
+            .filter(WebhookColumn::AccountId.eq(account_id))
+            .one(&self.conn)
+            .await?
+            .ok_or_else(|| NeoiotError::ObjectNotFound(format!("webhook {}", webhook_id)))
+    }
+
+    async fn list_webhooks(
+        &self,
+        account_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<WebhookModel>, usize)> {
+        let paginator = WebhookEntity::find()
+            .filter(WebhookColumn::AccountId.eq(account_id))
+            .order_by_asc(WebhookColumn::Id)
+            .paginate(&self.conn, page_size);
+        let webhooks = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        Ok((webhooks, total))
+    }
+
+    async fn update_webhook(
+        &self,
+        account_id: &str,
+        webhook_id: &str,
+        req: &UpdateWebhook,
+    ) -> Result<WebhookModel> {
+        let current = self.get_webhook(account_id, webhook_id).await?;
+        let mut webhook: WebhookActiveModel = current.into();
+        if let Some(url) = &req.url {
+            validate_webhook_url(url)?;
+            webhook.url = Set(url.clone());
+        }
+        match &req.description {
+            MaybeUndefined::Value(description) => {
+                webhook.description = Set(Some(description.clone()))
+            }
+            MaybeUndefined::Null => webhook.description = Set(None),
+            MaybeUndefined::Undefined => {}
+        }
+        if let Some(event_types) = &req.event_types {
+            webhook.event_types = Set(event_type_names(event_types));
+        }
+        if let Some(is_active) = req.is_active {
+            webhook.is_active = Set(is_active);
+        }
+        webhook.updated_at = Set(Some(Local::now().into()));
+        Ok(webhook.update(&self.conn).await?)
+    }
+
+    async fn delete_webhook(&self, account_id: &str, webhook_id: &str) -> Result<()> {
+        let webhook = self.get_webhook(account_id, webhook_id).await?;
+        webhook.delete(&self.conn).await?;
+        Ok(())
+    }
+
+    async fn rotate_webhook_secret(
+        &self,
+        account_id: &str,
+        webhook_id: &str,
+    ) -> Result<WebhookModel> {
+        let mut webhook: WebhookActiveModel =
+            self.get_webhook(account_id, webhook_id).await?.into();
+        webhook.secret = Set(credential::generate_secret());
+        webhook.updated_at = Set(Some(Local::now().into()));
+        Ok(webhook.update(&self.conn).await?)
+    }
+
+    async fn enqueue_webhook_deliveries(&self, event: &Event) -> Result<usize> {
+        let webhooks: Vec<WebhookModel> = WebhookEntity::find()
+            .filter(WebhookColumn::AccountId.eq(event.account_id.as_str()))
+            .filter(WebhookColumn::IsActive.eq(true))
+            .all(&self.conn)
+            .await?
+            .into_iter()
+            .filter(|w| events::subscribed(&w.event_types, event.event_type))
+            .collect();
+        if webhooks.is_empty() {
+            return Ok(0);
+        }
+        let payload = event.payload();
+        let count = webhooks.len();
+        WebhookDeliveryEntity::insert_many(webhooks.into_iter().map(|webhook| {
+            WebhookDeliveryActiveModel {
+                id: Set(xid::new().to_string()),
+                account_id: Set(event.account_id.clone()),
+                webhook_id: Set(webhook.id),
+                event_type: Set(event.event_type),
+                payload: Set(payload.clone()),
+                status: Set(WebhookDeliveryStatus::Pending),
+                attempts: Set(0),
+                next_attempt_at: Set(Local::now().into()),
+                ..Default::default()
+            }
+        }))
+        .exec(&self.conn)
+        .await?;
+        Ok(count)
+    }
+
+    async fn claim_webhook_deliveries(
+        &self,
+        limit: u64,
+    ) -> Result<Vec<(WebhookDeliveryModel, WebhookModel)>> {
+        let now = Local::now();
+        let due = WebhookDeliveryEntity::find()
+            .find_also_related(WebhookEntity)
+            .filter(WebhookDeliveryColumn::Status.eq(WebhookDeliveryStatus::Pending))
+            .filter(WebhookDeliveryColumn::NextAttemptAt.lte(DateTimeWithTimeZone::from(now)))
+            // 停用的Webhook暂停投递, 重新启用后继续
+            .filter(WebhookColumn::IsActive.eq(true))
+            .order_by_asc(WebhookDeliveryColumn::NextAttemptAt)
+            .limit(limit)
+            .all(&self.conn)
+            .await?;
+        // 租约期内投递未完成(如进程退出)时, 到期后重新领取
+        let lease_until: DateTimeWithTimeZone = (now
+            + chrono::Duration::seconds(
+                SETTINGS.webhook.timeout_secs as i64 + WEBHOOK_LEASE_MARGIN_SECS,
+            ))
+        .into();
+        let mut claimed = vec![];
+        for (delivery, webhook) in due {
+            let webhook = match webhook {
+                Some(webhook) => webhook,
+                None => continue,
+            };
+            let result = WebhookDeliveryEntity::update_many()
+                .col_expr(
+                    WebhookDeliveryColumn::NextAttemptAt,
+                    Expr::value(lease_until),
+                )
+                .filter(WebhookDeliveryColumn::Id.eq(delivery.id.as_str()))
+                .filter(WebhookDeliveryColumn::Status.eq(WebhookDeliveryStatus::Pending))
+                .filter(WebhookDeliveryColumn::NextAttemptAt.eq(delivery.next_attempt_at))
+                .exec(&self.conn)
+                .await?;
+            if result.rows_affected == 1 {
+                claimed.push((delivery, webhook));
+            }
+        }
+        Ok(claimed)
+    }
+
+    async fn finish_webhook_delivery(
+        &self,
+        delivery: &WebhookDeliveryModel,
+        attempt: &Attempt,
+    ) -> Result<()> {
+        let config = &SETTINGS.webhook;
+        let now = Local::now();
+        let attempts = delivery.attempts + 1;
+        let mut model: WebhookDeliveryActiveModel = delivery.clone().into();
+        model.attempts = Set(attempts);
+        model.last_status_code = Set(attempt.status_code);
+        model.last_error = Set(attempt.error.clone());
+        model.updated_at = Set(Some(now.into()));
+        if attempt.is_success() {
+            model.status = Set(WebhookDeliveryStatus::Succeeded);
+            model.delivered_at = Set(Some(now.into()));
+        } else if attempts >= config.max_attempts {
+            model.status = Set(WebhookDeliveryStatus::Dead);
+        } else {
+            model.next_attempt_at = Set((now + webhook::backoff(config, attempts)).into());
+        }
+        model.update(&self.conn).await?;
+        Ok(())
+    }
+
+    async fn list_webhook_deliveries(
+        &self,
+        account_id: &str,
+        webhook_id: &str,
+        status: Option<WebhookDeliveryStatus>,
+        event_type: Option<EventType>,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<WebhookDeliveryModel>, usize)> {
+        self.get_webhook(account_id, webhook_id).await?;
+        let mut query =
+            WebhookDeliveryEntity::find().filter(WebhookDeliveryColumn::WebhookId.eq(webhook_id));
+        if let Some(status) = status {
+            query = query.filter(WebhookDeliveryColumn::Status.eq(status));
+        }
+        if let Some(event_type) = event_type {
+            query = query.filter(WebhookDeliveryColumn::EventType.eq(event_type));
+        }
+        let paginator = query
+            .order_by_desc(WebhookDeliveryColumn::CreatedAt)
+            .paginate(&self.conn, page_size);
+        let deliveries = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        Ok((deliveries, total))
+    }
+
+    async fn retry_webhook_delivery(
+        &self,
+        account_id: &str,
+        webhook_id: &str,
+        delivery_id: &str,
+    ) -> Result<WebhookDeliveryModel> {
+        self.get_webhook(account_id, webhook_id).await?;
+        let delivery = WebhookDeliveryEntity::find_by_id(delivery_id.to_string())
+            .filter(WebhookDeliveryColumn::WebhookId.eq(webhook_id))
+            .one(&self.conn)
+            .await?
+            .ok_or_else(|| {
+                NeoiotError::ObjectNotFound(format!("webhook delivery {}", delivery_id))
+            })?;
+        if delivery.status == WebhookDeliveryStatus::Pending {
+            return Err(NeoiotError::InvalidArgument(format!(
+                "webhook delivery {} is already pending",
+                delivery_id
+            )));
+        }
+        let now = Local::now();
+        let mut delivery: WebhookDeliveryActiveModel = delivery.into();
+        delivery.status = Set(WebhookDeliveryStatus::Pending);
+        delivery.attempts = Set(0);
+        delivery.next_attempt_at = Set(now.into());
+        delivery.updated_at = Set(Some(now.into()));
+        Ok(delivery.update(&self.conn).await?)
+    }
+
+    async fn create_data_route(
+        &self,
+        account_id: &str,
+        req: &CreateDataRoute,
+    ) -> Result<DataRouteModel> {
+        validate_data_route(
+            req.sink_type,
+            req.endpoint.as_deref(),
+            req.topic.as_deref(),
+            req.selector.as_deref(),
+        )?;
+        if let Some(schema_id) = &req.schema_id {
+            self.get_schema(account_id, schema_id).await?;
+        }
+        let route = DataRouteActiveModel {
+            id: Set(xid::new().to_string()),
+            account_id: Set(account_id.to_string()),
+            name: Set(req.name.clone()),
+            sink_type: Set(req.sink_type),
+            endpoint: Set(req.endpoint.clone()),
+            topic: Set(req.topic.clone()),
+            selector: Set(req.selector.clone()),
+            schema_id: Set(req.schema_id.clone()),
+            event_types: Set(event_type_names(&req.event_types)),
+            fields: Set(json!(req.fields)),
+            is_active: Set(true),
+            forwarded_count: Set(0),
+            error_count: Set(0),
+            ..Default::default()
+        }
+        .insert(&self.conn)
+        .await?;
+        Ok(route)
+    }
+
+    async fn get_data_route(&self, account_id: &str, route_id: &str) -> Result<DataRouteModel> {
+        DataRouteEntity::find_by_id(route_id.to_string())
+            .filter(DataRouteColumn::AccountId.eq(account_id))
+            .one(&self.conn)
+            .await?
+            .ok_or_else(|| NeoiotError::ObjectNotFound(format!("data route {}", route_id)))
+    }
+
+    async fn list_data_routes(
+        &self,
+        account_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<DataRouteModel>, usize)> {
+        let paginator = DataRouteEntity::find()
+            .filter(DataRouteColumn::AccountId.eq(account_id))
+            .order_by_asc(DataRouteColumn::Id)
+            .paginate(&self.conn, page_size);
+        let routes = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        Ok((routes, total))
+    }
+
+    async fn update_data_route(
+        &self,
+        account_id: &str,
+        route_id: &str,
+        req: &UpdateDataRoute,
+    ) -> Result<DataRouteModel> {
+        let current = self.get_data_route(account_id, route_id).await?;
+        let merge = |value: &MaybeUndefined<String>, current: &Option<String>| match value {
+            MaybeUndefined::Value(value) => Some(value.clone()),
+            MaybeUndefined::Null => None,
+            MaybeUndefined::Undefined => current.clone(),
+        };
+        let sink_type = req.sink_type.unwrap_or(current.sink_type);
+        let endpoint = merge(&req.endpoint, &current.endpoint);
+        let topic = merge(&req.topic, &current.topic);
+        let selector = merge(&req.selector, &current.selector);
+        let schema_id = merge(&req.schema_id, &current.schema_id);
+        validate_data_route(
+            sink_type,
+            endpoint.as_deref(),
+            topic.as_deref(),
+            selector.as_deref(),
+        )?;
+        if let MaybeUndefined::Value(schema_id) = &req.schema_id {
+            self.get_schema(account_id, schema_id).await?;
+        }
+
+        let mut route: DataRouteActiveModel = current.into();
+        if let Some(name) = &req.name {
+            route.name = Set(name.clone());
+        }
+        if let Some(event_types) = &req.event_types {
+            route.event_types = Set(event_type_names(event_types));
+        }
+        if let Some(fields) = &req.fields {
+            route.fields = Set(json!(fields));
+        }
+        if let Some(is_active) = req.is_active {
+            route.is_active = Set(is_active);
+        }
+        route.sink_type = Set(sink_type);
+        route.endpoint = Set(endpoint);
+        route.topic = Set(topic);
+        route.selector = Set(selector);
+        route.schema_id = Set(schema_id);
+        route.updated_at = Set(Some(Local::now().into()));
+        Ok(route.update(&self.conn).await?)
+    }
+
+    async fn delete_data_route(&self, account_id: &str, route_id: &str) -> Result<()> {
+        let route = self.get_data_route(account_id, route_id).await?;
+        route.delete(&self.conn).await?;
+        Ok(())
+    }
+
+    async fn matching_data_routes(&self, event: &Event) -> Result<Vec<DataRouteModel>> {
+        let routes = DataRouteEntity::find()
+            .filter(DataRouteColumn::AccountId.eq(event.account_id.as_str()))
+            .filter(DataRouteColumn::IsActive.eq(true))
+            .all(&self.conn)
+            .await?;
+        let mut matched = vec![];
+        for route in routes {
+            if !events::subscribed(&route.event_types, event.event_type)
+                || !forward::projected(&route.fields, event)
+            {
+                continue;
+            }
+            if route.selector.is_some() || route.schema_id.is_some() {
+                // 按设备过滤的路由不转发与设备无关的事件
+                let device_id = match &event.device_id {
+                    Some(device_id) => device_id,
+                    None => continue,
+                };
+                let mut query = DeviceEntity::find()
+                    .filter(devices::Column::Id.eq(device_id.as_str()))
+                    .filter(devices::Column::AccountId.eq(event.account_id.as_str()));
+                if let Some(selector) = &route.selector {
+                    let selector = match selector.parse::<Selector>() {
+                        Ok(selector) => selector,
+                        Err(_) => continue,
+                    };
+                    query = query.filter(selector_condition(&event.account_id, &selector));
+                }
+                if let Some(schema_id) = &route.schema_id {
+                    query = query.filter(devices::Column::SchemaId.eq(schema_id.as_str()));
+                }
+                if query.count(&self.conn).await? == 0 {
+                    continue;
+                }
+            }
+            matched.push(route);
+        }
+        Ok(matched)
+    }
+
+    async fn record_data_route_result(&self, route_id: &str, error: Option<String>) -> Result<()> {
+        let now: DateTimeWithTimeZone = Local::now().into();
+        let update = DataRouteEntity::update_many();
+        let update = match error {
+            None => update
+                .col_expr(
+                    DataRouteColumn::ForwardedCount,
+                    Expr::col(DataRouteColumn::ForwardedCount).add(1),
+                )
+                .col_expr(DataRouteColumn::LastForwardedAt, Expr::value(now)),
+            Some(error) => update
+                .col_expr(
+                    DataRouteColumn::ErrorCount,
+                    Expr::col(DataRouteColumn::ErrorCount).add(1),
+                )
+                .col_expr(DataRouteColumn::LastError, Expr::value(error))
+                .col_expr(DataRouteColumn::LastErrorAt, Expr::value(now)),
+        };
+        update
+            .filter(DataRouteColumn::Id.eq(route_id))
+            .exec(&self.conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn create_scheduled_command(
+        &self,
+        account_id: &str,
+        req: &CreateScheduledCommand,
+    ) -> Result<ScheduledCommandModel> {
+        match req.target_type {
+            ScheduleTarget::Device => {
+                self.get_device(account_id, &req.target_id).await?;
+            }
+            ScheduleTarget::Label => {
+                self.get_label(account_id, &req.target_id).await?;
+            }
+        }
+        let next_run_at = schedule::next_run(&req.cron, &req.timezone, Local::now())?;
+        let schedule = ScheduledCommandActiveModel {
+            id: Set(xid::new().to_string()),
+            account_id: Set(account_id.to_string()),
+            name: Set(req.name.clone()),
+            target_type: Set(req.target_type),
+            target_id: Set(req.target_id.clone()),
+            command: Set(req.command.clone()),
+            payload: Set(req.payload.clone()),
+            qos: Set(req.qos as i16),
+            ttl: Set(req.ttl.map(|ttl| ttl as i32)),
+            cron: Set(req.cron.trim().to_string()),
+            timezone: Set(req.timezone.clone()),
+            is_active: Set(true),
+            next_run_at: Set(Some(next_run_at.into())),
+            ..Default::default()
+        }
+        .insert(&self.conn)
+        .await?;
+        Ok(schedule)
+    }
+
+    async fn get_scheduled_command(
+        &self,
+        account_id: &str,
+        schedule_id: &str,
+    ) -> Result<ScheduledCommandModel> {
+        ScheduledCommandEntity::find_by_id(schedule_id.to_string())
+            .filter(ScheduledCommandColumn::AccountId.eq(account_id))
+            .one(&self.conn)
+            .await?
+            .ok_or_else(|| {
+                NeoiotError::ObjectNotFound(format!("scheduled command {}", schedule_id))
+            })
+    }
+
+    async fn list_scheduled_commands(
+        &self,
+        account_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<ScheduledCommandModel>, usize)> {
+        let paginator = ScheduledCommandEntity::find()
+            .filter(ScheduledCommandColumn::AccountId.eq(account_id))
+            .order_by_asc(ScheduledCommandColumn::Id)
+            .paginate(&self.conn, page_size);
+        let schedules = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        Ok((schedules, total))
+    }
+
+    async fn update_scheduled_command(
+        &self,
+        account_id: &str,
+        schedule_id: &str,
+        req: &UpdateScheduledCommand,
+    ) -> Result<ScheduledCommandModel> {
+        let current = self.get_scheduled_command(account_id, schedule_id).await?;
+        let cron = req
+            .cron
+            .as_deref()
+            .map(str::trim)
+            .unwrap_or(&current.cron)
+            .to_string();
+        let timezone = req
+            .timezone
+            .clone()
+            .unwrap_or_else(|| current.timezone.clone());
+        let is_active = req.is_active.unwrap_or(current.is_active);
+        let now = Local::now();
+        // 停用期间不计算执行时间, 重新启用后从当前时间开始计算, 不补发停用期间的执行
+        let next_run_at = schedule::next_run(&cron, &timezone, now)?;
+
+        let mut schedule: ScheduledCommandActiveModel = current.into();
+        if let Some(name) = &req.name {
+            schedule.name = Set(name.clone());
+        }
+        if let Some(command) = &req.command {
+            schedule.command = Set(command.clone());
+        }
+        if let Some(payload) = &req.payload {
+            schedule.payload = Set(payload.clone());
+        }
+        if let Some(qos) = req.qos {
+            schedule.qos = Set(qos as i16);
+        }
+        match req.ttl {
+            MaybeUndefined::Value(ttl) => schedule.ttl = Set(Some(ttl as i32)),
+            MaybeUndefined::Null => schedule.ttl = Set(None),
+            MaybeUndefined::Undefined => {}
+        }
+        schedule.cron = Set(cron);
+        schedule.timezone = Set(timezone);
+        schedule.is_active = Set(is_active);
+        schedule.next_run_at = Set(is_active.then(|| next_run_at.into()));
+        schedule.updated_at = Set(Some(now.into()));
+        Ok(schedule.update(&self.conn).await?)
+    }
+
+    async fn delete_scheduled_command(&self, account_id: &str, schedule_id: &str) -> Result<()> {
+        let schedule = self.get_scheduled_command(account_id, schedule_id).await?;
+        schedule.delete(&self.conn).await?;
+        Ok(())
+    }
+
+    async fn claim_due_scheduled_commands(
+        &self,
+        limit: u64,
+    ) -> Result<Vec<(ScheduledCommandModel, DateTime<Local>)>> {
+        let now = Local::now();
+        let due = ScheduledCommandEntity::find()
+            .filter(ScheduledCommandColumn::IsActive.eq(true))
+            .filter(ScheduledCommandColumn::NextRunAt.lte(DateTimeWithTimeZone::from(now)))
+            .order_by_asc(ScheduledCommandColumn::NextRunAt)
+            .limit(limit)
+            .all(&self.conn)
+            .await?;
+        let mut claimed = vec![];
+        for schedule in due {
+            let scheduled_at = match schedule.next_run_at {
+                Some(scheduled_at) => scheduled_at,
+                None => continue,
+            };
+            // 错过的执行不补发, 直接顺延到当前时间之后的下一个时间点
+            let next_run_at = match schedule::next_run(&schedule.cron, &schedule.timezone, now) {
+                Ok(next_run_at) => Some(DateTimeWithTimeZone::from(next_run_at)),
+                Err(err) => {
+                    tracing::warn!(schedule_id = %schedule.id, ?err, "scheduled command has no next run");
+                    None
+                }
+            };
+            // 以计划执行时间做乐观锁, 多个实例同时调度时只有一方能领取
+            let result = ScheduledCommandEntity::update_many()
+                .col_expr(ScheduledCommandColumn::NextRunAt, Expr::value(next_run_at))
+                .col_expr(
+                    ScheduledCommandColumn::LastRunAt,
+                    Expr::value(DateTimeWithTimeZone::from(now)),
+                )
+                .filter(ScheduledCommandColumn::Id.eq(schedule.id.as_str()))
+                .filter(ScheduledCommandColumn::NextRunAt.eq(scheduled_at))
+                .exec(&self.conn)
+                .await?;
+            if result.rows_affected == 1 {
+                claimed.push((schedule, scheduled_at.into()));
+            }
+        }
+        Ok(claimed)
+    }
+
+    async fn create_scheduled_command_run(
+        &self,
+        schedule: &ScheduledCommandModel,
+        scheduled_at: DateTime<Local>,
+        message_id: Option<String>,
+        error: Option<String>,
+    ) -> Result<()> {
+        let ttl = schedule.ttl.map(|ttl| ttl as usize);
+        // 下发失败时没有消息ID, 为指令记录单独生成一个
+        let log_id = message_id.clone().unwrap_or_else(|| xid::new().to_string());
+        let txn = self.conn.begin().await?;
+        let target = match schedule.target_type {
+            ScheduleTarget::Device => DeviceEntity::find()
+                .filter(devices::Column::AccountId.eq(schedule.account_id.as_str()))
+                .filter(devices::Column::Id.eq(schedule.target_id.as_str()))
+                .one(&txn)
+                .await?
+                .map(|device| {
+                    let command = topics::ServerToDevice {
+                        message_id: log_id.clone(),
+                        account_id: schedule.account_id.clone(),
+                        device_id: device.id.clone(),
+                        command: schedule.command.clone(),
+                        is_sync: false,
+                        ttl,
+                    };
+                    (command.topic(), Some(device.id), None)
+                }),
+            ScheduleTarget::Label => LabelEntity::find()
+                .filter(labels::Column::AccountId.eq(schedule.account_id.as_str()))
+                .filter(labels::Column::Id.eq(schedule.target_id.as_str()))
+                .one(&txn)
+                .await?
+                .map(|label| {
+                    let command = topics::ServerToDeviceBatch {
+                        message_id: log_id.clone(),
+                        account_id: schedule.account_id.clone(),
+                        label: selector::format_label(&label.name, label.value.as_deref()),
+                        command: schedule.command.clone(),
+                        ttl,
+                    };
+                    (command.topic(), None, Some(label.id))
+                }),
+        };
+        // 目标已被删除时指令记录无处关联, 只保留执行记录
+        if let Some((topic, device_id, label_id)) = target {
+            CommandRequestLogActiveModel {
+                message_id: Set(log_id),
+                topic: Set(topic),
+                command: Set(schedule.command.clone()),
+                mode: Set("async".to_string()),
+                body: Set(schedule.payload.clone()),
+                device_id: Set(device_id),
+                label_id: Set(label_id),
+                error: Set(error.clone()),
+                ..Default::default()
+            }
+            .insert(&txn)
+            .await?;
+        }
+        ScheduledCommandRunActiveModel {
+            id: Set(xid::new().to_string()),
+            schedule_id: Set(schedule.id.clone()),
+            account_id: Set(schedule.account_id.clone()),
+            scheduled_at: Set(scheduled_at.into()),
+            message_id: Set(message_id),
+            error: Set(error),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+        txn.commit().await?;
+        Ok(())
+    }
+
+    async fn list_scheduled_command_runs(
+        &self,
+        account_id: &str,
+        schedule_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<ScheduledCommandRunModel>, usize)> {
+        self.get_scheduled_command(account_id, schedule_id).await?;
+        let paginator = ScheduledCommandRunEntity::find()
+            .filter(ScheduledCommandRunColumn::ScheduleId.eq(schedule_id))
+            .order_by_desc(ScheduledCommandRunColumn::CreatedAt)
+            .paginate(&self.conn, page_size);
+        let runs = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        Ok((runs, total))
+    }
+
+    async fn create_command_job(
+        &self,
+        account_id: &str,
+        actor: &str,
+        req: &CreateCommandJob,
+    ) -> Result<CommandJobModel> {
+        let device_ids = match (&req.label_id, req.device_ids.is_empty()) {
+            (Some(label_id), true) => {
+                let label = self.get_label(account_id, label_id).await?;
+                label
+                    .find_related(DeviceEntity)
+                    .filter(devices::Column::AccountId.eq(account_id))
+                    .order_by_asc(devices::Column::Id)
+                    .limit(MAX_JOB_DEVICES as u64 + 1)
+                    .all(&self.conn)
+                    .await?
+                    .into_iter()
+                    .map(|d| d.id)
+                    .collect::<Vec<_>>()
+            }
+            (None, false) => {
+                let device_ids = req.device_ids.iter().cloned().collect::<HashSet<_>>();
+                let found = DeviceEntity::find()
+                    .filter(devices::Column::AccountId.eq(account_id))
+                    .filter(devices::Column::Id.is_in(device_ids.clone()))
+                    .count(&self.conn)
+                    .await?;
+                if found != device_ids.len() {
+                    return Err(NeoiotError::ObjectNotFound("device".to_string()));
+                }
+                let mut device_ids = device_ids.into_iter().collect::<Vec<_>>();
+                device_ids.sort();
+                device_ids
+            }
+            _ => {
+                return Err(NeoiotError::InvalidArgument(
+                    "exactly one of label_id and device_ids is required".to_string(),
+                ))
+            }
+        };
+        if device_ids.is_empty() {
+            return Err(NeoiotError::InvalidArgument(
+                "command job matches no devices".to_string(),
+            ));
+        }
+        if device_ids.len() > MAX_JOB_DEVICES {
+            return Err(NeoiotError::InvalidArgument(format!(
+                "command job matches more than {} devices",
+                MAX_JOB_DEVICES
+            )));
+        }
+
+        let job_id = xid::new().to_string();
+        let txn = self.conn.begin().await?;
+        let job = CommandJobActiveModel {
+            id: Set(job_id.clone()),
+            account_id: Set(account_id.to_string()),
+            label_id: Set(req.label_id.clone()),
+            command: Set(req.command.clone()),
+            payload: Set(req.payload.clone()),
+            qos: Set(req.qos as i16),
+            ttl: Set(req.ttl.map(|ttl| ttl as i32)),
+            rate_limit: Set(req.rate_limit),
+            timeout_secs: Set(req.timeout_secs),
+            status: Set(CommandJobStatus::Running),
+            total: Set(device_ids.len() as i32),
+            created_by: Set(actor.to_string()),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+        let targets = device_ids
+            .into_iter()
+            .map(|device_id| CommandJobDeviceActiveModel {
+                id: Set(xid::new().to_string()),
+                job_id: Set(job_id.clone()),
+                device_id: Set(device_id),
+                status: Set(CommandJobDeviceStatus::Pending),
+                attempts: Set(0),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        for chunk in targets.chunks(BULK_INSERT_CHUNK) {
+            CommandJobDeviceEntity::insert_many(chunk.to_vec())
+                .exec(&txn)
+                .await?;
+        }
+        txn.commit().await?;
+        Ok(job)
+    }
+
+    async fn get_command_job(&self, account_id: &str, job_id: &str) -> Result<CommandJobModel> {
+        CommandJobEntity::find_by_id(job_id.to_string())
+            .filter(CommandJobColumn::AccountId.eq(account_id))
+            .one(&self.conn)
+            .await?
+            .ok_or_else(|| NeoiotError::ObjectNotFound(format!("command job {}", job_id)))
+    }
+
+    async fn list_command_jobs(
+        &self,
+        account_id: &str,
+        status: Option<CommandJobStatus>,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<CommandJobModel>, usize)> {
+        let mut query = CommandJobEntity::find().filter(CommandJobColumn::AccountId.eq(account_id));
+        if let Some(status) = status {
+            query = query.filter(CommandJobColumn::Status.eq(status));
+        }
+        let paginator = query
+            .order_by_desc(CommandJobColumn::CreatedAt)
+            .paginate(&self.conn, page_size);
+        let jobs = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        Ok((jobs, total))
+    }
+
+    async fn count_command_job_devices(
+        &self,
+        job_id: &str,
+    ) -> Result<Vec<(CommandJobDeviceStatus, usize)>> {
+        let mut counts = vec![];
+        for status in [
+            CommandJobDeviceStatus::Pending,
+            CommandJobDeviceStatus::Sent,
+            CommandJobDeviceStatus::Responded,
+            CommandJobDeviceStatus::Failed,
+            CommandJobDeviceStatus::TimedOut,
+            CommandJobDeviceStatus::Cancelled,
+        ] {
+            let count = CommandJobDeviceEntity::find()
+                .filter(CommandJobDeviceColumn::JobId.eq(job_id))
+                .filter(CommandJobDeviceColumn::Status.eq(status))
+                .count(&self.conn)
+                .await?;
+            counts.push((status, count));
+        }
+        Ok(counts)
+    }
+
+    async fn list_command_job_devices(
+        &self,
+        account_id: &str,
+        job_id: &str,
+        status: Option<CommandJobDeviceStatus>,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<CommandJobDeviceModel>, usize)> {
+        self.get_command_job(account_id, job_id).await?;
+        let mut query =
+            CommandJobDeviceEntity::find().filter(CommandJobDeviceColumn::JobId.eq(job_id));
+        if let Some(status) = status {
+            query = query.filter(CommandJobDeviceColumn::Status.eq(status));
+        }
+        let paginator = query
+            .order_by_asc(CommandJobDeviceColumn::DeviceId)
+            .paginate(&self.conn, page_size);
+        let targets = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        Ok((targets, total))
+    }
+
+    async fn cancel_command_job(&self, account_id: &str, job_id: &str) -> Result<CommandJobModel> {
+        let job = self.get_command_job(account_id, job_id).await?;
+        if job.status != CommandJobStatus::Running {
+            return Err(NeoiotError::InvalidArgument(format!(
+                "command job {} is not running",
+                job_id
+            )));
+        }
+        let now: DateTimeWithTimeZone = Local::now().into();
+        let txn = self.conn.begin().await?;
+        // 已下发的设备继续等待回复
+        CommandJobDeviceEntity::update_many()
+            .col_expr(
+                CommandJobDeviceColumn::Status,
+                Expr::value(CommandJobDeviceStatus::Cancelled),
+            )
+            .col_expr(CommandJobDeviceColumn::UpdatedAt, Expr::value(now))
+            .filter(CommandJobDeviceColumn::JobId.eq(job_id))
+            .filter(CommandJobDeviceColumn::Status.eq(CommandJobDeviceStatus::Pending))
+            .exec(&txn)
+            .await?;
+        let mut job: CommandJobActiveModel = job.into();
+        job.status = Set(CommandJobStatus::Cancelled);
+        job.finished_at = Set(Some(now));
+        job.updated_at = Set(Some(now));
+        let job = job.update(&txn).await?;
+        txn.commit().await?;
+        Ok(job)
+    }
+
+    async fn retry_command_job(&self, account_id: &str, job_id: &str) -> Result<CommandJobModel> {
+        let job = self.get_command_job(account_id, job_id).await?;
+        let now: DateTimeWithTimeZone = Local::now().into();
+        let txn = self.conn.begin().await?;
+        let result = CommandJobDeviceEntity::update_many()
+            .col_expr(
+                CommandJobDeviceColumn::Status,
+                Expr::value(CommandJobDeviceStatus::Pending),
+            )
+            .col_expr(
+                CommandJobDeviceColumn::Error,
+                Expr::value(Option::<String>::None),
+            )
+            .col_expr(CommandJobDeviceColumn::UpdatedAt, Expr::value(now))
+            .filter(CommandJobDeviceColumn::JobId.eq(job_id))
+            .filter(CommandJobDeviceColumn::Status.is_in([
+                CommandJobDeviceStatus::Failed,
+                CommandJobDeviceStatus::TimedOut,
+                CommandJobDeviceStatus::Cancelled,
+            ]))
+            .exec(&txn)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(NeoiotError::InvalidArgument(format!(
+                "command job {} has no failed devices to retry",
+                job_id
+            )));
+        }
+        let mut job: CommandJobActiveModel = job.into();
+        job.status = Set(CommandJobStatus::Running);
+        job.finished_at = Set(None);
+        job.updated_at = Set(Some(now));
+        let job = job.update(&txn).await?;
+        txn.commit().await?;
+        Ok(job)
+    }
+
+    async fn claim_command_job_devices(
+        &self,
+    ) -> Result<Vec<(CommandJobModel, Vec<CommandJobDeviceModel>)>> {
+        let jobs = CommandJobEntity::find()
+            .filter(CommandJobColumn::Status.eq(CommandJobStatus::Running))
+            .order_by_asc(CommandJobColumn::CreatedAt)
+            .all(&self.conn)
+            .await?;
+        let now: DateTimeWithTimeZone = Local::now().into();
+        let mut claimed = vec![];
+        for job in jobs {
+            let pending = CommandJobDeviceEntity::find()
+                .filter(CommandJobDeviceColumn::JobId.eq(job.id.as_str()))
+                .filter(CommandJobDeviceColumn::Status.eq(CommandJobDeviceStatus::Pending))
+                .order_by_asc(CommandJobDeviceColumn::DeviceId)
+                .limit(job.rate_limit as u64)
+                .all(&self.conn)
+                .await?;
+            let mut targets = vec![];
+            for target in pending {
+                // 多个实例同时领取时只有一方能成功
+                let result = CommandJobDeviceEntity::update_many()
+                    .col_expr(
+                        CommandJobDeviceColumn::Status,
+                        Expr::value(CommandJobDeviceStatus::Sent),
+                    )
+                    .col_expr(
+                        CommandJobDeviceColumn::Attempts,
+                        Expr::col(CommandJobDeviceColumn::Attempts).add(1),
+                    )
+                    .col_expr(
+                        CommandJobDeviceColumn::MessageId,
+                        Expr::value(Option::<String>::None),
+                    )
+                    .col_expr(CommandJobDeviceColumn::SentAt, Expr::value(now))
+                    .col_expr(CommandJobDeviceColumn::UpdatedAt, Expr::value(now))
+                    .filter(CommandJobDeviceColumn::Id.eq(target.id.as_str()))
+                    .filter(CommandJobDeviceColumn::Status.eq(CommandJobDeviceStatus::Pending))
+                    .exec(&self.conn)
+                    .await?;
+                if result.rows_affected == 1 {
+                    targets.push(target);
+                }
+            }
+            if !targets.is_empty() {
+                claimed.push((job, targets));
+            }
+        }
+        Ok(claimed)
+    }
+
+    async fn send_command_to_job_device(
+        &self,
+        job: &CommandJobModel,
+        target: &CommandJobDeviceModel,
+    ) -> Result<String> {
+        let device = self.get_device(&job.account_id, &target.device_id).await?;
+        if !device.is_active {
+            return Err(NeoiotError::DeviceInactive(device.id));
+        }
+        let command = topics::ServerToDevice::new(
+            &job.account_id,
+            &target.device_id,
+            &job.command,
+            false,
+            job.ttl.map(|ttl| ttl as usize),
+        );
+        let message_id = command.message_id.clone();
+        CommandJobDeviceEntity::update_many()
+            .col_expr(
+                CommandJobDeviceColumn::MessageId,
+                Expr::value(message_id.as_str()),
+            )
+            .filter(CommandJobDeviceColumn::Id.eq(target.id.as_str()))
+            .exec(&self.conn)
+            .await?;
+        Message::new(Topics::S2D(command), job.payload.clone())
+            .publish(job.qos as u8)
+            .await?;
+        Ok(message_id)
+    }
+
+    async fn fail_command_job_device(
+        &self,
+        target: &CommandJobDeviceModel,
+        error: &str,
+    ) -> Result<()> {
+        CommandJobDeviceEntity::update_many()
+            .col_expr(
+                CommandJobDeviceColumn::Status,
+                Expr::value(CommandJobDeviceStatus::Failed),
+            )
+            .col_expr(CommandJobDeviceColumn::Error, Expr::value(error))
+            .col_expr(
+                CommandJobDeviceColumn::UpdatedAt,
+                Expr::value(DateTimeWithTimeZone::from(Local::now())),
+            )
+            .filter(CommandJobDeviceColumn::Id.eq(target.id.as_str()))
+            .filter(CommandJobDeviceColumn::Status.eq(CommandJobDeviceStatus::Sent))
+            .exec(&self.conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn record_command_job_response(&self, message_id: &str, payload: &str) -> Result<bool> {
+        let now: DateTimeWithTimeZone = Local::now().into();
+        // 超时后到达的回复同样记录
+        let result = CommandJobDeviceEntity::update_many()
+            .col_expr(
+                CommandJobDeviceColumn::Status,
+                Expr::value(CommandJobDeviceStatus::Responded),
+            )
+            .col_expr(CommandJobDeviceColumn::Response, Expr::value(payload))
+            .col_expr(CommandJobDeviceColumn::RespondedAt, Expr::value(now))
+            .col_expr(CommandJobDeviceColumn::UpdatedAt, Expr::value(now))
+            .filter(CommandJobDeviceColumn::MessageId.eq(message_id))
+            .filter(CommandJobDeviceColumn::Status.is_in([
+                CommandJobDeviceStatus::Sent,
+                CommandJobDeviceStatus::TimedOut,
+            ]))
+            .exec(&self.conn)
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    async fn sweep_command_jobs(&self) -> Result<usize> {
+        let now = Local::now();
+        let waiting = |statuses: Vec<CommandJobDeviceStatus>| {
+            Query::select()
+                .column(CommandJobDeviceColumn::JobId)
+                .from(CommandJobDeviceEntity)
+                .and_where(CommandJobDeviceColumn::Status.is_in(statuses))
+                .to_owned()
+        };
+        // 已取消的任务中已下发的设备同样需要超时
+        let jobs = CommandJobEntity::find()
+            .filter(CommandJobColumn::Id.in_subquery(waiting(vec![CommandJobDeviceStatus::Sent])))
+            .all(&self.conn)
+            .await?;
+        for job in jobs {
+            let deadline = now - chrono::Duration::seconds(job.timeout_secs as i64);
+            CommandJobDeviceEntity::update_many()
+                .col_expr(
+                    CommandJobDeviceColumn::Status,
+                    Expr::value(CommandJobDeviceStatus::TimedOut),
+                )
+                .col_expr(
+                    CommandJobDeviceColumn::UpdatedAt,
+                    Expr::value(DateTimeWithTimeZone::from(now)),
+                )
+                .filter(CommandJobDeviceColumn::JobId.eq(job.id.as_str()))
+                .filter(CommandJobDeviceColumn::Status.eq(CommandJobDeviceStatus::Sent))
+                .filter(CommandJobDeviceColumn::SentAt.lt(DateTimeWithTimeZone::from(deadline)))
+                .exec(&self.conn)
+                .await?;
+        }
+        let now: DateTimeWithTimeZone = now.into();
+        let result = CommandJobEntity::update_many()
+            .col_expr(
+                CommandJobColumn::Status,
+                Expr::value(CommandJobStatus::Completed),
+            )
+            .col_expr(CommandJobColumn::FinishedAt, Expr::value(now))
+            .col_expr(CommandJobColumn::UpdatedAt, Expr::value(now))
+            .filter(CommandJobColumn::Status.eq(CommandJobStatus::Running))
+            .filter(CommandJobColumn::Id.not_in_subquery(waiting(vec![
+                CommandJobDeviceStatus::Pending,
+                CommandJobDeviceStatus::Sent,
+            ])))
+            .exec(&self.conn)
+            .await?;
+        Ok(result.rows_affected as usize)
+    }
+
+    async fn create_firmware(&self, account_id: &str, req: &NewFirmware) -> Result<FirmwareModel> {
+        self.get_schema(account_id, &req.schema_id).await?;
+        let existed = FirmwareEntity::find()
+            .filter(FirmwareColumn::AccountId.eq(account_id))
+            .filter(FirmwareColumn::SchemaId.eq(req.schema_id.as_str()))
+            .filter(FirmwareColumn::Version.eq(req.version.as_str()))
+            .one(&self.conn)
+            .await?;
+        if existed.is_some() {
+            return Err(NeoiotError::AlreadyExists(format!(
+                "firmware version {}",
+                req.version
+            )));
+        }
+        let firmware = FirmwareActiveModel {
+            id: Set(req.id.clone()),
+            account_id: Set(account_id.to_string()),
+            schema_id: Set(req.schema_id.clone()),
+            version: Set(req.version.clone()),
+            checksum: Set(req.checksum.clone()),
+            size: Set(req.size),
+            blob_key: Set(req.blob_key.clone()),
+            filename: Set(req.filename.clone()),
+            description: Set(req.description.clone()),
+            ..Default::default()
+        }
+        .insert(&self.conn)
+        .await?;
+        Ok(firmware)
+    }
+
+    async fn get_firmware(&self, account_id: &str, firmware_id: &str) -> Result<FirmwareModel> {
+        FirmwareEntity::find_by_id(firmware_id.to_string())
+            .filter(FirmwareColumn::AccountId.eq(account_id))
+            .one(&self.conn)
+            .await?
+            .ok_or_else(|| NeoiotError::ObjectNotFound(format!("firmware {}", firmware_id)))
+    }
+
+    async fn find_firmware(&self, firmware_id: &str) -> Result<FirmwareModel> {
+        FirmwareEntity::find_by_id(firmware_id.to_string())
+            .one(&self.conn)
+            .await?
+            .ok_or_else(|| NeoiotError::ObjectNotFound(format!("firmware {}", firmware_id)))
+    }
+
+    async fn list_firmwares(
+        &self,
+        account_id: &str,
+        schema_id: Option<String>,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<FirmwareModel>, usize)> {
+        let mut query = FirmwareEntity::find().filter(FirmwareColumn::AccountId.eq(account_id));
+        if let Some(schema_id) = schema_id {
+            query = query.filter(FirmwareColumn::SchemaId.eq(schema_id));
+        }
+        let paginator = query
+            .order_by_desc(FirmwareColumn::CreatedAt)
+            .paginate(&self.conn, page_size);
+        let firmwares = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        Ok((firmwares, total))
+    }
+
+    async fn delete_firmware(&self, account_id: &str, firmware_id: &str) -> Result<FirmwareModel> {
+        let firmware = self.get_firmware(account_id, firmware_id).await?;
+        let running = OtaCampaignEntity::find()
+            .filter(OtaCampaignColumn::FirmwareId.eq(firmware_id))
+            .filter(OtaCampaignColumn::Status.eq(OtaCampaignStatus::Running))
+            .count(&self.conn)
+            .await?;
+        if running > 0 {
+            return Err(NeoiotError::InvalidArgument(format!(
+                "firmware {} is used by a running ota campaign",
+                firmware_id
+            )));
+        }
+        firmware.clone().delete(&self.conn).await?;
+        Ok(firmware)
+    }
+
+    async fn create_ota_campaign(
+        &self,
+        account_id: &str,
+        actor: &str,
+        req: &CreateOtaCampaign,
+    ) -> Result<OtaCampaignModel> {
+        let firmware = self.get_firmware(account_id, &req.firmware_id).await?;
+        ensure_labels(&self.conn, account_id, &req.label_ids).await?;
+        let mut label_ids = req.label_ids.clone();
+        label_ids.sort();
+        label_ids.dedup();
+        let labeled = Query::select()
+            .column(labels_device_relation::Column::DeviceId)
+            .from(labels_device_relation::Entity)
+            .and_where(labels_device_relation::Column::LabelId.is_in(label_ids.clone()))
+            .to_owned();
+        // 已是目标版本的设备不参与升级
+        let mut devices = DeviceEntity::find()
+            .filter(devices::Column::AccountId.eq(account_id))
+            .filter(devices::Column::SchemaId.eq(firmware.schema_id.as_str()))
+            .filter(devices::Column::LifecycleState.ne(LifecycleState::Decommissioned))
+            .filter(devices::Column::Id.in_subquery(labeled))
+            .filter(
+                Condition::any()
+                    .add(devices::Column::FirmwareVersion.is_null())
+                    .add(devices::Column::FirmwareVersion.ne(firmware.version.as_str())),
+            )
+            .order_by_asc(devices::Column::Id)
+            .limit(MAX_OTA_DEVICES as u64 + 1)
+            .all(&self.conn)
+            .await?;
+        if devices.is_empty() {
+            return Err(NeoiotError::InvalidArgument(
+                "ota campaign matches no devices".to_string(),
+            ));
+        }
+        if devices.len() > MAX_OTA_DEVICES {
+            return Err(NeoiotError::InvalidArgument(format!(
+                "ota campaign matches more than {} devices",
+                MAX_OTA_DEVICES
+            )));
+        }
+        // 同一设备同时只能参与一个进行中的活动
+        let busy = OtaCampaignDeviceEntity::find()
+            .filter(OtaCampaignDeviceColumn::DeviceId.is_in(devices.iter().map(|d| d.id.as_str())))
+            .filter(OtaCampaignDeviceColumn::Status.is_in(OTA_UNFINISHED))
+            .filter(
+                OtaCampaignDeviceColumn::CampaignId.in_subquery(
+                    Query::select()
+                        .column(OtaCampaignColumn::Id)
+                        .from(OtaCampaignEntity)
+                        .and_where(OtaCampaignColumn::Status.eq(OtaCampaignStatus::Running))
+                        .to_owned(),
+                ),
+            )
+            .one(&self.conn)
+            .await?;
+        if let Some(busy) = busy {
+            return Err(NeoiotError::InvalidArgument(format!(
+                "device {} is already in running ota campaign {}",
+                busy.device_id, busy.campaign_id
+            )));
+        }
+
+        devices.shuffle(&mut rand::thread_rng());
+        let canary = ota::canary_size(devices.len(), req.canary_percent);
+        let campaign_id = xid::new().to_string();
+        let txn = self.conn.begin().await?;
+        let campaign = OtaCampaignActiveModel {
+            id: Set(campaign_id.clone()),
+            account_id: Set(account_id.to_string()),
+            firmware_id: Set(firmware.id.clone()),
+            name: Set(req.name.clone()),
+            label_ids: Set(json!(label_ids)),
+            canary_percent: Set(req.canary_percent),
+            failure_threshold: Set(req.failure_threshold),
+            min_samples: Set(req.min_samples),
+            auto_promote: Set(req.auto_promote),
+            rate_limit: Set(req.rate_limit),
+            timeout_secs: Set(req.timeout_secs),
+            stage: Set(if canary == 0 {
+                OtaStage::Full
+            } else {
+                OtaStage::Canary
+            }),
+            status: Set(OtaCampaignStatus::Running),
+            total: Set(devices.len() as i32),
+            created_by: Set(actor.to_string()),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+        let targets = devices
+            .into_iter()
+            .enumerate()
+            .map(|(i, device)| OtaCampaignDeviceActiveModel {
+                id: Set(xid::new().to_string()),
+                campaign_id: Set(campaign_id.clone()),
+                device_id: Set(device.id),
+                stage: Set(if i < canary {
+                    OtaStage::Canary
+                } else {
+                    OtaStage::Full
+                }),
+                status: Set(OtaDeviceStatus::Pending),
+                progress: Set(0),
+                from_version: Set(device.firmware_version),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        for chunk in targets.chunks(BULK_INSERT_CHUNK) {
+            OtaCampaignDeviceEntity::insert_many(chunk.to_vec())
+                .exec(&txn)
+                .await?;
+        }
+        txn.commit().await?;
+        Ok(campaign)
+    }
+
+    async fn get_ota_campaign(
+        &self,
+        account_id: &str,
+        campaign_id: &str,
+    ) -> Result<OtaCampaignModel> {
+        OtaCampaignEntity::find_by_id(campaign_id.to_string())
+            .filter(OtaCampaignColumn::AccountId.eq(account_id))
+            .one(&self.conn)
+            .await?
+            .ok_or_else(|| NeoiotError::ObjectNotFound(format!("ota campaign {}", campaign_id)))
+    }
+
+    async fn list_ota_campaigns(
+        &self,
+        account_id: &str,
+        status: Option<OtaCampaignStatus>,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<OtaCampaignModel>, usize)> {
+        let mut query =
+            OtaCampaignEntity::find().filter(OtaCampaignColumn::AccountId.eq(account_id));
+        if let Some(status) = status {
+            query = query.filter(OtaCampaignColumn::Status.eq(status));
+        }
+        let paginator = query
+            .order_by_desc(OtaCampaignColumn::CreatedAt)
+            .paginate(&self.conn, page_size);
+        let campaigns = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        Ok((campaigns, total))
+    }
+
+    async fn count_ota_campaign_devices(
+        &self,
+        campaign_id: &str,
+    ) -> Result<Vec<(OtaStage, OtaDeviceStatus, usize)>> {
+        let mut counts = vec![];
+        for stage in [OtaStage::Canary, OtaStage::Full] {
+            for status in [
+                OtaDeviceStatus::Pending,
+                OtaDeviceStatus::Notified,
+                OtaDeviceStatus::Downloading,
+                OtaDeviceStatus::Installing,
+                OtaDeviceStatus::Succeeded,
+                OtaDeviceStatus::Failed,
+                OtaDeviceStatus::Skipped,
+            ] {
+                let count = OtaCampaignDeviceEntity::find()
+                    .filter(OtaCampaignDeviceColumn::CampaignId.eq(campaign_id))
+                    .filter(OtaCampaignDeviceColumn::Stage.eq(stage))
+                    .filter(OtaCampaignDeviceColumn::Status.eq(status))
+                    .count(&self.conn)
+                    .await?;
+                counts.push((stage, status, count));
+            }
+        }
+        Ok(counts)
+    }
+
+    async fn list_ota_campaign_devices(
+        &self,
+        account_id: &str,
+        campaign_id: &str,
+        status: Option<OtaDeviceStatus>,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<OtaCampaignDeviceModel>, usize)> {
+        self.get_ota_campaign(account_id, campaign_id).await?;
+        let mut query = OtaCampaignDeviceEntity::find()
+            .filter(OtaCampaignDeviceColumn::CampaignId.eq(campaign_id));
+        if let Some(status) = status {
+            query = query.filter(OtaCampaignDeviceColumn::Status.eq(status));
+        }
+        let paginator = query
+            .order_by_asc(OtaCampaignDeviceColumn::Stage)
+            .order_by_asc(OtaCampaignDeviceColumn::DeviceId)
+            .paginate(&self.conn, page_size);
+        let targets = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        Ok((targets, total))
+    }
+
+    async fn promote_ota_campaign(
+        &self,
+        account_id: &str,
+        campaign_id: &str,
+    ) -> Result<OtaCampaignModel> {
+        let campaign = self.get_ota_campaign(account_id, campaign_id).await?;
+        if campaign.status != OtaCampaignStatus::Running || campaign.stage != OtaStage::Canary {
+            return Err(NeoiotError::InvalidArgument(format!(
+                "ota campaign {} is not in canary stage",
+                campaign_id
+            )));
+        }
+        let mut campaign: OtaCampaignActiveModel = campaign.into();
+        campaign.stage = Set(OtaStage::Full);
+        campaign.updated_at = Set(Some(Local::now().into()));
+        Ok(campaign.update(&self.conn).await?)
+    }
+
+    async fn abort_ota_campaign(
+        &self,
+        account_id: &str,
+        campaign_id: &str,
+        reason: &str,
+    ) -> Result<OtaCampaignModel> {
+        let campaign = self.get_ota_campaign(account_id, campaign_id).await?;
+        if campaign.status != OtaCampaignStatus::Running {
+            return Err(NeoiotError::InvalidArgument(format!(
+                "ota campaign {} is not running",
+                campaign_id
+            )));
+        }
+        let now: DateTimeWithTimeZone = Local::now().into();
+        let txn = self.conn.begin().await?;
+        skip_pending_ota_devices(&txn, campaign_id, now).await?;
+        let mut campaign: OtaCampaignActiveModel = campaign.into();
+        campaign.status = Set(OtaCampaignStatus::Aborted);
+        campaign.abort_reason = Set(Some(reason.to_string()));
+        campaign.finished_at = Set(Some(now));
+        campaign.updated_at = Set(Some(now));
+        let campaign = campaign.update(&txn).await?;
+        txn.commit().await?;
+        Ok(campaign)
+    }
+
+    async fn claim_ota_campaign_devices(
+        &self,
+    ) -> Result<Vec<(OtaCampaignModel, FirmwareModel, Vec<OtaCampaignDeviceModel>)>> {
+        let campaigns = OtaCampaignEntity::find()
+            .filter(OtaCampaignColumn::Status.eq(OtaCampaignStatus::Running))
+            .order_by_asc(OtaCampaignColumn::CreatedAt)
+            .all(&self.conn)
+            .await?;
+        let now: DateTimeWithTimeZone = Local::now().into();
+        let mut claimed = vec![];
+        for campaign in campaigns {
+            let mut query = OtaCampaignDeviceEntity::find()
+                .filter(OtaCampaignDeviceColumn::CampaignId.eq(campaign.id.as_str()))
+                .filter(OtaCampaignDeviceColumn::Status.eq(OtaDeviceStatus::Pending));
+            if campaign.stage == OtaStage::Canary {
+                query = query.filter(OtaCampaignDeviceColumn::Stage.eq(OtaStage::Canary));
+            }
+            let pending = query
+                .order_by_asc(OtaCampaignDeviceColumn::Stage)
+                .order_by_asc(OtaCampaignDeviceColumn::DeviceId)
+                .limit(campaign.rate_limit as u64)
+                .all(&self.conn)
+                .await?;
+            let mut targets = vec![];
+            for target in pending {
+                // 多个实例同时领取时只有一方能成功
+                let result = OtaCampaignDeviceEntity::update_many()
+                    .col_expr(
+                        OtaCampaignDeviceColumn::Status,
+                        Expr::value(OtaDeviceStatus::Notified),
+                    )
+                    .col_expr(OtaCampaignDeviceColumn::NotifiedAt, Expr::value(now))
+                    .col_expr(OtaCampaignDeviceColumn::UpdatedAt, Expr::value(now))
+                    .filter(OtaCampaignDeviceColumn::Id.eq(target.id.as_str()))
+                    .filter(OtaCampaignDeviceColumn::Status.eq(OtaDeviceStatus::Pending))
+                    .exec(&self.conn)
+                    .await?;
+                if result.rows_affected == 1 {
+                    targets.push(target);
+                }
+            }
+            if targets.is_empty() {
+                continue;
+            }
+            match FirmwareEntity::find_by_id(campaign.firmware_id.clone())
+                .one(&self.conn)
+                .await?
+            {
+                Some(firmware) => claimed.push((campaign, firmware, targets)),
+                None => continue,
+            }
+        }
+        Ok(claimed)
+    }
+
+    async fn fail_ota_device(&self, target: &OtaCampaignDeviceModel, error: &str) -> Result<()> {
+        OtaCampaignDeviceEntity::update_many()
+            .col_expr(
+                OtaCampaignDeviceColumn::Status,
+                Expr::value(OtaDeviceStatus::Failed),
+            )
+            .col_expr(OtaCampaignDeviceColumn::Error, Expr::value(error))
+            .col_expr(
+                OtaCampaignDeviceColumn::UpdatedAt,
+                Expr::value(DateTimeWithTimeZone::from(Local::now())),
+            )
+            .filter(OtaCampaignDeviceColumn::Id.eq(target.id.as_str()))
+            .filter(OtaCampaignDeviceColumn::Status.eq(OtaDeviceStatus::Notified))
+            .exec(&self.conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn report_firmware_version(
+        &self,
+        account_id: &str,
+        device_id: &str,
+        version: &str,
+    ) -> Result<Option<String>> {
+        let now: DateTimeWithTimeZone = Local::now().into();
+        let result = DeviceEntity::update_many()
+            .col_expr(devices::Column::FirmwareVersion, Expr::value(version))
+            .col_expr(devices::Column::FirmwareReportedAt, Expr::value(now))
+            .filter(devices::Column::Id.eq(device_id))
+            .filter(devices::Column::AccountId.eq(account_id))
+            .exec(&self.conn)
+            .await?;
+        if result.rows_affected == 0 {
+            return Ok(None);
+        }
+        let in_flight = OtaCampaignDeviceEntity::find()
+            .filter(OtaCampaignDeviceColumn::DeviceId.eq(device_id))
+            .filter(OtaCampaignDeviceColumn::Status.is_in(OTA_IN_FLIGHT))
+            .find_also_related(OtaCampaignEntity)
+            .order_by_desc(OtaCampaignDeviceColumn::NotifiedAt)
+            .all(&self.conn)
+            .await?;
+        for (target, campaign) in in_flight {
+            let campaign = match campaign {
+                Some(campaign) => campaign,
+                None => continue,
+            };
+            let firmware = match FirmwareEntity::find_by_id(campaign.firmware_id.clone())
+                .one(&self.conn)
+                .await?
+            {
+                Some(firmware) => firmware,
+                None => continue,
+            };
+            // 设备在升级前后都会上报版本, 只有安装后仍是其他版本才视为失败
+            let (status, progress, error) = if firmware.version == version {
+                (OtaDeviceStatus::Succeeded, 100, None)
+            } else if target.status == OtaDeviceStatus::Installing {
+                (
+                    OtaDeviceStatus::Failed,
+                    target.progress,
+                    Some(format!(
+                        "device reported version {} after installing",
+                        version
+                    )),
+                )
+            } else {
+                continue;
+            };
+            let result = OtaCampaignDeviceEntity::update_many()
+                .col_expr(OtaCampaignDeviceColumn::Status, Expr::value(status))
+                .col_expr(OtaCampaignDeviceColumn::Progress, Expr::value(progress))
+                .col_expr(OtaCampaignDeviceColumn::Error, Expr::value(error))
+                .col_expr(OtaCampaignDeviceColumn::UpdatedAt, Expr::value(now))
+                .filter(OtaCampaignDeviceColumn::Id.eq(target.id.as_str()))
+                .filter(OtaCampaignDeviceColumn::Status.eq(target.status))
+                .exec(&self.conn)
+                .await?;
+            if result.rows_affected == 1 {
+                return Ok(Some(campaign.id));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn record_ota_progress(
+        &self,
+        account_id: &str,
+        device_id: &str,
+        report: &ProgressReport,
+    ) -> Result<Option<String>> {
+        let campaigns = Query::select()
+            .column(OtaCampaignColumn::Id)
+            .from(OtaCampaignEntity)
+            .and_where(OtaCampaignColumn::AccountId.eq(account_id))
+            .to_owned();
+        let mut query = OtaCampaignDeviceEntity::find()
+            .filter(OtaCampaignDeviceColumn::DeviceId.eq(device_id))
+            .filter(OtaCampaignDeviceColumn::Status.is_in(OTA_IN_FLIGHT))
+            .filter(OtaCampaignDeviceColumn::CampaignId.in_subquery(campaigns));
+        if let Some(campaign_id) = &report.campaign_id {
+            query = query.filter(OtaCampaignDeviceColumn::CampaignId.eq(campaign_id.as_str()));
+        }
+        let target = match query
+            .order_by_desc(OtaCampaignDeviceColumn::NotifiedAt)
+            .one(&self.conn)
+            .await?
+        {
+            Some(target) => target,
+            None => return Ok(None),
+        };
+        let status: OtaDeviceStatus = report.status.into();
+        let error = match status {
+            OtaDeviceStatus::Failed => Some(
+                report
+                    .error
+                    .clone()
+                    .unwrap_or_else(|| "device reported failure".to_string()),
+            ),
+            _ => None,
+        };
+        let result = OtaCampaignDeviceEntity::update_many()
+            .col_expr(OtaCampaignDeviceColumn::Status, Expr::value(status))
+            .col_expr(
+                OtaCampaignDeviceColumn::Progress,
+                Expr::value(report.progress),
+            )
+            .col_expr(OtaCampaignDeviceColumn::Error, Expr::value(error))
+            .col_expr(
+                OtaCampaignDeviceColumn::UpdatedAt,
+                Expr::value(DateTimeWithTimeZone::from(Local::now())),
+            )
+            .filter(OtaCampaignDeviceColumn::Id.eq(target.id.as_str()))
+            .filter(OtaCampaignDeviceColumn::Status.is_in(OTA_IN_FLIGHT))
+            .exec(&self.conn)
+            .await?;
+        if result.rows_affected == 1 && status == OtaDeviceStatus::Failed {
+            return Ok(Some(target.campaign_id));
+        }
+        Ok(None)
+    }
+
+    async fn evaluate_ota_campaign(&self, campaign_id: &str) -> Result<Option<OtaCampaignModel>> {
+        let campaign = match OtaCampaignEntity::find_by_id(campaign_id.to_string())
+            .one(&self.conn)
+            .await?
+        {
+            Some(campaign) if campaign.status == OtaCampaignStatus::Running => campaign,
+            _ => return Ok(None),
+        };
+        let counts = self.count_ota_campaign_devices(campaign_id).await?;
+        let transition = match ota::next_transition(&campaign, &counts.into()) {
+            Some(transition) => transition,
+            None => return Ok(None),
+        };
+        let now: DateTimeWithTimeZone = Local::now().into();
+        let txn = self.conn.begin().await?;
+        // 以评估时的状态和阶段为条件, 多个实例同时评估时只有一方生效
+        let update = OtaCampaignEntity::update_many()
+            .col_expr(OtaCampaignColumn::UpdatedAt, Expr::value(now))
+            .filter(OtaCampaignColumn::Id.eq(campaign_id))
+            .filter(OtaCampaignColumn::Status.eq(OtaCampaignStatus::Running))
+            .filter(OtaCampaignColumn::Stage.eq(campaign.stage));
+        let update = match &transition {
+            Transition::Abort(reason) => update
+                .col_expr(
+                    OtaCampaignColumn::Status,
+                    Expr::value(OtaCampaignStatus::Aborted),
+                )
+                .col_expr(OtaCampaignColumn::AbortReason, Expr::value(reason.as_str()))
+                .col_expr(OtaCampaignColumn::FinishedAt, Expr::value(now)),
+            Transition::Promote => {
+                update.col_expr(OtaCampaignColumn::Stage, Expr::value(OtaStage::Full))
+            }
+            Transition::Complete => update
+                .col_expr(
+                    OtaCampaignColumn::Status,
+                    Expr::value(OtaCampaignStatus::Completed),
+                )
+                .col_expr(OtaCampaignColumn::FinishedAt, Expr::value(now)),
+        };
+        if update.exec(&txn).await?.rows_affected == 0 {
+            return Ok(None);
+        }
+        if let Transition::Abort(_) = transition {
+            skip_pending_ota_devices(&txn, campaign_id, now).await?;
+        }
+        txn.commit().await?;
+        Ok(OtaCampaignEntity::find_by_id(campaign_id.to_string())
+            .one(&self.conn)
+            .await?)
+    }
+
+    async fn sweep_ota_campaigns(&self) -> Result<Vec<OtaCampaignModel>> {
+        let now = Local::now();
+        let in_flight = Query::select()
+            .column(OtaCampaignDeviceColumn::CampaignId)
+            .from(OtaCampaignDeviceEntity)
+            .and_where(OtaCampaignDeviceColumn::Status.is_in(OTA_IN_FLIGHT))
+            .to_owned();
+        // 已中止的活动中已推送的设备同样需要超时
+        let campaigns = OtaCampaignEntity::find()
+            .filter(OtaCampaignColumn::Id.in_subquery(in_flight))
+            .all(&self.conn)
+            .await?;
+        for campaign in campaigns {
+            let deadline = now - chrono::Duration::seconds(campaign.timeout_secs as i64);
+            OtaCampaignDeviceEntity::update_many()
+                .col_expr(
+                    OtaCampaignDeviceColumn::Status,
+                    Expr::value(OtaDeviceStatus::Failed),
+                )
+                .col_expr(OtaCampaignDeviceColumn::Error, Expr::value("timed out"))
+                .col_expr(
+                    OtaCampaignDeviceColumn::UpdatedAt,
+                    Expr::value(DateTimeWithTimeZone::from(now)),
+                )
+                .filter(OtaCampaignDeviceColumn::CampaignId.eq(campaign.id.as_str()))
+                .filter(OtaCampaignDeviceColumn::Status.is_in(OTA_IN_FLIGHT))
+                .filter(
+                    OtaCampaignDeviceColumn::NotifiedAt.lt(DateTimeWithTimeZone::from(deadline)),
+                )
+                .exec(&self.conn)
+                .await?;
+        }
+        let running = OtaCampaignEntity::find()
+            .filter(OtaCampaignColumn::Status.eq(OtaCampaignStatus::Running))
+            .all(&self.conn)
+            .await?;
+        let mut changed = vec![];
+        for campaign in running {
+            if let Some(campaign) = self.evaluate_ota_campaign(&campaign.id).await? {
+                changed.push(campaign);
+            }
+        }
+        Ok(changed)
+    }
+
+    async fn create_device_upload(
+        &self,
+        account_id: &str,
+        actor: &str,
+        req: &RequestDeviceUpload,
+    ) -> Result<DeviceUploadModel> {
+        let device = self.get_device(account_id, &req.device_id).await?;
+        if !device.is_active {
+            return Err(NeoiotError::DeviceInactive(device.id));
+        }
+        let expire_secs = SETTINGS.upload.expire_secs;
+        let command = topics::ServerToDevice::new(
+            account_id,
+            &device.id,
+            uploads::UPLOAD_COMMAND,
+            false,
+            Some(expire_secs as usize),
+        );
+        // 先保存上传请求再发送指令, 设备收到指令后立即上传的分片能找到对应的请求
+        let upload = DeviceUploadActiveModel {
+            id: Set(xid::new().to_string()),
+            account_id: Set(account_id.to_string()),
+            device_id: Set(device.id.clone()),
+            kind: Set(req.kind.clone()),
+            status: Set(DeviceUploadStatus::Requested),
+            message_id: Set(Some(command.message_id.clone())),
+            requested_by: Set(actor.to_string()),
+            expires_at: Set((Local::now() + chrono::Duration::seconds(expire_secs)).into()),
+            ..Default::default()
+        }
+        .insert(&self.conn)
+        .await?;
+        let payload = uploads::command_payload(&upload, req.params.as_ref());
+        if let Err(err) = Message::new(Topics::S2D(command), payload).publish(1).await {
+            upload.delete(&self.conn).await?;
+            return Err(err);
+        }
+        Ok(upload)
+    }
+
+    async fn get_device_upload(
+        &self,
+        account_id: &str,
+        upload_id: &str,
+    ) -> Result<DeviceUploadModel> {
+        DeviceUploadEntity::find_by_id(upload_id.to_string())
+            .filter(DeviceUploadColumn::AccountId.eq(account_id))
+            .one(&self.conn)
+            .await?
+            .ok_or_else(|| NeoiotError::ObjectNotFound(format!("upload {}", upload_id)))
+    }
+
+    async fn find_device_upload(&self, upload_id: &str) -> Result<DeviceUploadModel> {
+        DeviceUploadEntity::find_by_id(upload_id.to_string())
+            .one(&self.conn)
+            .await?
+            .ok_or_else(|| NeoiotError::ObjectNotFound(format!("upload {}", upload_id)))
+    }
+
+    async fn list_device_uploads(
+        &self,
+        account_id: &str,
+        device_id: Option<String>,
+        status: Option<DeviceUploadStatus>,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<DeviceUploadModel>, usize)> {
+        let mut query =
+            DeviceUploadEntity::find().filter(DeviceUploadColumn::AccountId.eq(account_id));
+        if let Some(device_id) = device_id {
+            query = query.filter(DeviceUploadColumn::DeviceId.eq(device_id));
+        }
+        if let Some(status) = status {
+            query = query.filter(DeviceUploadColumn::Status.eq(status));
+        }
+        let paginator = query
+            .order_by_desc(DeviceUploadColumn::CreatedAt)
+            .paginate(&self.conn, page_size);
+        let uploads = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        Ok((uploads, total))
+    }
+
+    async fn delete_device_upload(
+        &self,
+        account_id: &str,
+        upload_id: &str,
+    ) -> Result<(DeviceUploadModel, Vec<DeviceUploadChunkModel>)> {
+        let txn = self.conn.begin().await?;
+        // 锁住上传记录, 删除之后并发到达的分片记录失败, 由接收方删除写入的分片
+        let upload = DeviceUploadEntity::find_by_id(upload_id.to_string())
+            .filter(DeviceUploadColumn::AccountId.eq(account_id))
+            .lock_exclusive()
+            .one(&txn)
+            .await?
+            .ok_or_else(|| NeoiotError::ObjectNotFound(format!("upload {}", upload_id)))?;
+        if upload.status == DeviceUploadStatus::Assembling {
+            return Err(NeoiotError::InvalidArgument(format!(
+                "upload {} is being assembled",
+                upload_id
+            )));
+        }
+        let chunks = upload_chunks(&txn, upload_id).await?;
+        upload.clone().delete(&txn).await?;
+        txn.commit().await?;
+        Ok((upload, chunks))
+    }
+
+    async fn claim_upload_chunk(&self, upload_id: &str, total: i32) -> Result<()> {
+        let txn = self.conn.begin().await?;
+        lock_receiving_upload(&txn, upload_id, total).await?;
+        txn.commit().await?;
+        Ok(())
+    }
+
+    async fn record_upload_chunk(
+        &self,
+        upload_id: &str,
+        seq: i32,
+        total: i32,
+        size: i32,
+        blob_key: &str,
+    ) -> Result<(DeviceUploadModel, Option<String>)> {
+        let now: DateTimeWithTimeZone = Local::now().into();
+        let txn = self.conn.begin().await?;
+        let mut upload = lock_receiving_upload(&txn, upload_id, total).await?;
+        let replaced = match DeviceUploadChunkEntity::find_by_id((upload_id.to_string(), seq))
+            .one(&txn)
+            .await?
+        {
+            Some(chunk) => {
+                let replaced = chunk.blob_key.clone();
+                let mut chunk: DeviceUploadChunkActiveModel = chunk.into();
+                chunk.size = Set(size);
+                chunk.blob_key = Set(blob_key.to_string());
+                chunk.created_at = Set(now);
+                chunk.update(&txn).await?;
+                Some(replaced)
+            }
+            None => {
+                DeviceUploadChunkActiveModel {
+                    upload_id: Set(upload_id.to_string()),
+                    seq: Set(seq),
+                    size: Set(size),
+                    blob_key: Set(blob_key.to_string()),
+                    created_at: Set(now),
+                }
+                .insert(&txn)
+                .await?;
+                None
+            }
+        };
+        let received = DeviceUploadChunkEntity::find()
+            .filter(DeviceUploadChunkColumn::UploadId.eq(upload_id))
+            .count(&txn)
+            .await? as i32;
+        let status = if received == total {
+            DeviceUploadStatus::Assembling
+        } else {
+            DeviceUploadStatus::Receiving
+        };
+        DeviceUploadEntity::update_many()
+            .col_expr(DeviceUploadColumn::ReceivedChunks, Expr::value(received))
+            .col_expr(DeviceUploadColumn::Status, Expr::value(status))
+            .filter(DeviceUploadColumn::Id.eq(upload_id))
+            .exec(&txn)
+            .await?;
+        txn.commit().await?;
+        upload.received_chunks = received;
+        upload.status = status;
+        Ok((upload, replaced))
+    }
+
+    async fn list_upload_chunks(&self, upload_id: &str) -> Result<Vec<DeviceUploadChunkModel>> {
+        upload_chunks(&self.conn, upload_id).await
+    }
+
+    async fn complete_device_upload(
+        &self,
+        upload_id: &str,
+        blob_key: &str,
+        size: i64,
+        checksum: &str,
+    ) -> Result<()> {
+        let now: DateTimeWithTimeZone = Local::now().into();
+        DeviceUploadEntity::update_many()
+            .col_expr(
+                DeviceUploadColumn::Status,
+                Expr::value(DeviceUploadStatus::Completed),
+            )
+            .col_expr(DeviceUploadColumn::BlobKey, Expr::value(blob_key))
+            .col_expr(DeviceUploadColumn::Size, Expr::value(size))
+            .col_expr(DeviceUploadColumn::Checksum, Expr::value(checksum))
+            .col_expr(DeviceUploadColumn::UpdatedAt, Expr::value(now))
+            .col_expr(DeviceUploadColumn::CompletedAt, Expr::value(now))
+            .filter(DeviceUploadColumn::Id.eq(upload_id))
+            .filter(DeviceUploadColumn::Status.eq(DeviceUploadStatus::Assembling))
+            .exec(&self.conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn fail_device_upload(&self, upload_id: &str, error: &str) -> Result<()> {
+        DeviceUploadEntity::update_many()
+            .col_expr(
+                DeviceUploadColumn::Status,
+                Expr::value(DeviceUploadStatus::Failed),
+            )
+            .col_expr(DeviceUploadColumn::Error, Expr::value(error))
+            .col_expr(
+                DeviceUploadColumn::UpdatedAt,
+                Expr::value(DateTimeWithTimeZone::from(Local::now())),
+            )
+            .filter(DeviceUploadColumn::Id.eq(upload_id))
+            .filter(DeviceUploadColumn::Status.eq(DeviceUploadStatus::Assembling))
+            .exec(&self.conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn expire_device_uploads(&self) -> Result<Vec<DeviceUploadModel>> {
+        let now: DateTimeWithTimeZone = Local::now().into();
+        let candidates = DeviceUploadEntity::find()
+            .filter(DeviceUploadColumn::Status.is_in(UPLOAD_RECEIVING))
+            .filter(DeviceUploadColumn::ExpiresAt.lt(now))
+            .all(&self.conn)
+            .await?;
+        let mut expired = vec![];
+        for upload in candidates {
+            // 条件更新, 多实例时每个上传只由一个实例清理
+            let result = DeviceUploadEntity::update_many()
+                .col_expr(
+                    DeviceUploadColumn::Status,
+                    Expr::value(DeviceUploadStatus::Expired),
+                )
+                .col_expr(DeviceUploadColumn::UpdatedAt, Expr::value(now))
+                .filter(DeviceUploadColumn::Id.eq(upload.id.as_str()))
+                .filter(DeviceUploadColumn::Status.is_in(UPLOAD_RECEIVING))
+                .exec(&self.conn)
+                .await?;
+            if result.rows_affected == 1 {
+                expired.push(upload);
+            }
+        }
+        Ok(expired)
+    }
+
+    async fn create_config_profile(
+        &self,
+        account_id: &str,
+        actor: &str,
+        req: &CreateConfigProfile,
+    ) -> Result<(ConfigProfileModel, ConfigProfileVersionModel)> {
+        check_config_document(&req.schema, &req.document)?;
+        ensure_config_profile_name(&self.conn, account_id, &req.name).await?;
+        let txn = self.conn.begin().await?;
+        let profile = ConfigProfileActiveModel {
+            id: Set(xid::new().to_string()),
+            account_id: Set(account_id.to_string()),
+            name: Set(req.name.clone()),
+            description: Set(req.description.clone()),
+            schema: Set(req.schema.clone()),
+            version: Set(1),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+        let version = ConfigProfileVersionActiveModel {
+            profile_id: Set(profile.id.clone()),
+            version: Set(1),
+            document: Set(req.document.clone()),
+            created_by: Set(actor.to_string()),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+        txn.commit().await?;
+        Ok((profile, version))
+    }
+
+    async fn get_config_profile(
+        &self,
+        account_id: &str,
+        profile_id: &str,
+    ) -> Result<(ConfigProfileModel, ConfigProfileVersionModel)> {
+        let profile = ConfigProfileEntity::find_by_id(profile_id.to_string())
+            .filter(ConfigProfileColumn::AccountId.eq(account_id))
+            .one(&self.conn)
+            .await?
+            .ok_or_else(|| NeoiotError::ObjectNotFound(format!("config profile {}", profile_id)))?;
+        let version = latest_config_version(&self.conn, &profile).await?;
+        Ok((profile, version))
+    }
+
+    async fn list_config_profiles(
+        &self,
+        account_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<(ConfigProfileModel, ConfigProfileVersionModel)>, usize)> {
+        let paginator = ConfigProfileEntity::find()
+            .filter(ConfigProfileColumn::AccountId.eq(account_id))
+            .order_by_desc(ConfigProfileColumn::CreatedAt)
+            .paginate(&self.conn, page_size);
+        let profiles = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        let mut results = Vec::with_capacity(profiles.len());
+        for profile in profiles {
+            let version = latest_config_version(&self.conn, &profile).await?;
+            results.push((profile, version));
+        }
+        Ok((results, total))
+    }
+
+    async fn update_config_profile(
+        &self,
+        account_id: &str,
+        profile_id: &str,
+        req: &UpdateConfigProfile,
+    ) -> Result<(ConfigProfileModel, ConfigProfileVersionModel)> {
+        let (current, version) = self.get_config_profile(account_id, profile_id).await?;
+        let mut profile: ConfigProfileActiveModel = current.clone().into();
+        if let Some(name) = &req.name {
+            if name != &current.name {
+                ensure_config_profile_name(&self.conn, account_id, name).await?;
+                profile.name = Set(name.clone());
+            }
+        }
+        if let Some(description) = &req.description {
+            profile.description = Set(description.clone());
+        }
+        if let Some(schema) = &req.schema {
+            // 新的Schema须兼容当前版本的文档
+            check_config_document(schema, &version.document)?;
+            profile.schema = Set(schema.clone());
+        }
+        profile.updated_at = Set(Some(Local::now().into()));
+        let profile = profile.update(&self.conn).await?;
+        Ok((profile, version))
+    }
+
+    async fn create_config_profile_version(
+        &self,
+        account_id: &str,
+        actor: &str,
+        profile_id: &str,
+        document: &serde_json::Value,
+    ) -> Result<(ConfigProfileModel, ConfigProfileVersionModel)> {
+        let (current, _) = self.get_config_profile(account_id, profile_id).await?;
+        check_config_document(&current.schema, document)?;
+        let next = current.version + 1;
+        let txn = self.conn.begin().await?;
+        // 条件更新, 并发保存时只有一个请求能得到下一个版本号
+        let result = ConfigProfileEntity::update_many()
+            .col_expr(ConfigProfileColumn::Version, Expr::value(next))
+            .col_expr(
+                ConfigProfileColumn::UpdatedAt,
+                Expr::value(DateTimeWithTimeZone::from(Local::now())),
+            )
+            .filter(ConfigProfileColumn::Id.eq(profile_id))
+            .filter(ConfigProfileColumn::Version.eq(current.version))
+            .exec(&txn)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(NeoiotError::AlreadyExists(format!(
+                "version {} of config profile {}",
+                next, profile_id
+            )));
+        }
+        let version = ConfigProfileVersionActiveModel {
+            profile_id: Set(profile_id.to_string()),
+            version: Set(next),
+            document: Set(document.clone()),
+            created_by: Set(actor.to_string()),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+        let assignments = ConfigAssignmentEntity::find()
+            .filter(ConfigAssignmentColumn::ProfileId.eq(profile_id))
+            .all(&txn)
+            .await?;
+        mark_assignments_stale(&txn, &assignments).await?;
+        txn.commit().await?;
+        let (profile, _) = self.get_config_profile(account_id, profile_id).await?;
+        Ok((profile, version))
+    }
+
+    async fn get_config_profile_version(
+        &self,
+        account_id: &str,
+        profile_id: &str,
+        version: i32,
+    ) -> Result<ConfigProfileVersionModel> {
+        self.get_config_profile(account_id, profile_id).await?;
+        ConfigProfileVersionEntity::find_by_id((profile_id.to_string(), version))
+            .one(&self.conn)
+            .await?
+            .ok_or_else(|| {
+                NeoiotError::ObjectNotFound(format!(
+                    "version {} of config profile {}",
+                    version, profile_id
+                ))
+            })
+    }
+
+    async fn list_config_profile_versions(
+        &self,
+        account_id: &str,
+        profile_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<ConfigProfileVersionModel>, usize)> {
+        self.get_config_profile(account_id, profile_id).await?;
+        let paginator = ConfigProfileVersionEntity::find()
+            .filter(ConfigProfileVersionColumn::ProfileId.eq(profile_id))
+            .order_by_desc(ConfigProfileVersionColumn::Version)
+            .paginate(&self.conn, page_size);
+        let versions = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        Ok((versions, total))
+    }
+
+    async fn delete_config_profile(
+        &self,
+        account_id: &str,
+        profile_id: &str,
+    ) -> Result<ConfigProfileModel> {
+        let (profile, _) = self.get_config_profile(account_id, profile_id).await?;
+        let assignments = ConfigAssignmentEntity::find()
+            .filter(ConfigAssignmentColumn::ProfileId.eq(profile_id))
+            .all(&self.conn)
+            .await?;
+        let txn = self.conn.begin().await?;
+        profile.clone().delete(&txn).await?;
+        mark_assignments_stale(&txn, &assignments).await?;
+        txn.commit().await?;
+        Ok(profile)
+    }
+
+    async fn create_config_assignment(
+        &self,
+        account_id: &str,
+        req: &CreateConfigAssignment,
+    ) -> Result<ConfigAssignmentModel> {
+        self.get_config_profile(account_id, &req.profile_id).await?;
+        let target = match (&req.label_id, &req.device_id) {
+            (Some(label_id), None) => {
+                ensure_labels(&self.conn, account_id, std::slice::from_ref(label_id)).await?;
+                ConfigAssignmentColumn::LabelId.eq(label_id.as_str())
+            }
+            (None, Some(device_id)) => {
+                self.get_device(account_id, device_id).await?;
+                ConfigAssignmentColumn::DeviceId.eq(device_id.as_str())
+            }
+            _ => {
+                return Err(NeoiotError::InvalidArgument(
+                    "exactly one of label_id and device_id is required".to_string(),
+                ))
+            }
+        };
+        let existed = ConfigAssignmentEntity::find()
+            .filter(ConfigAssignmentColumn::ProfileId.eq(req.profile_id.as_str()))
+            .filter(target)
+            .count(&self.conn)
+            .await?;
+        if existed > 0 {
+            return Err(NeoiotError::AlreadyExists("config assignment".to_string()));
+        }
+        let txn = self.conn.begin().await?;
+        let assignment = ConfigAssignmentActiveModel {
+            id: Set(xid::new().to_string()),
+            account_id: Set(account_id.to_string()),
+            profile_id: Set(req.profile_id.clone()),
+            label_id: Set(req.label_id.clone()),
+            device_id: Set(req.device_id.clone()),
+            priority: Set(req.priority),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+        mark_assignments_stale(&txn, std::slice::from_ref(&assignment)).await?;
+        txn.commit().await?;
+        Ok(assignment)
+    }
+
+    async fn list_config_assignments(
+        &self,
+        account_id: &str,
+        profile_id: Option<String>,
+        label_id: Option<String>,
+        device_id: Option<String>,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<ConfigAssignmentModel>, usize)> {
+        let mut query =
+            ConfigAssignmentEntity::find().filter(ConfigAssignmentColumn::AccountId.eq(account_id));
+        if let Some(profile_id) = profile_id {
+            query = query.filter(ConfigAssignmentColumn::ProfileId.eq(profile_id));
+        }
+        if let Some(label_id) = label_id {
+            query = query.filter(ConfigAssignmentColumn::LabelId.eq(label_id));
+        }
+        if let Some(device_id) = device_id {
+            query = query.filter(ConfigAssignmentColumn::DeviceId.eq(device_id));
+        }
+        let paginator = query
+            .order_by_desc(ConfigAssignmentColumn::CreatedAt)
+            .paginate(&self.conn, page_size);
+        let assignments = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+        Ok((assignments, total))
+    }
+
+    async fn delete_config_assignment(
+        &self,
+        account_id: &str,
+        assignment_id: &str,
+    ) -> Result<ConfigAssignmentModel> {
+        let assignment = ConfigAssignmentEntity::find_by_id(assignment_id.to_string())
+            .filter(ConfigAssignmentColumn::AccountId.eq(account_id))
             .one(&self.conn)
             .await?
-            .ok_or_else(|| NeoiotError::ObjectNotFound("label".to_string()))?;
-        let command =
-            topics::ServerToDeviceBatch::new(account_id, &label.name, &req.command, req.ttl);
-        let message_id = command.message_id.clone();
-        Message::new(Topics::S2L(command), req.payload.clone())
-            .publish(req.qos)
+            .ok_or_else(|| {
+                NeoiotError::ObjectNotFound(format!("config assignment {}", assignment_id))
+            })?;
+        let txn = self.conn.begin().await?;
+        assignment.clone().delete(&txn).await?;
+        mark_assignments_stale(&txn, std::slice::from_ref(&assignment)).await?;
+        txn.commit().await?;
+        Ok(assignment)
+    }
+
+    async fn get_device_config(&self, device_id: &str) -> Result<Option<DeviceConfigModel>> {
+        Ok(DeviceConfigEntity::find_by_id(device_id.to_string())
+            .one(&self.conn)
+            .await?)
+    }
+
+    async fn sync_device_configs(
+        &self,
+        limit: u64,
+    ) -> Result<Vec<(DeviceModel, DeviceConfigModel)>> {
+        let candidates = DeviceEntity::find()
+            .filter(devices::Column::ConfigStale.eq(true))
+            .limit(limit)
+            .all(&self.conn)
             .await?;
-        Ok(message_id)
+        let mut changed = vec![];
+        for device in candidates {
+            // 先清除标记再计算, 计算期间发生的变化留到下一轮; 多实例部署时每台设备只由一个实例计算
+            let claimed = DeviceEntity::update_many()
+                .col_expr(devices::Column::ConfigStale, Expr::value(false))
+                .filter(devices::Column::Id.eq(device.id.as_str()))
+                .filter(devices::Column::ConfigStale.eq(true))
+                .exec(&self.conn)
+                .await?;
+            if claimed.rows_affected != 1 {
+                continue;
+            }
+            let document = effective_device_config(&self.conn, &device.id).await?;
+            let version = device_config::version(&document);
+            let now: DateTimeWithTimeZone = Local::now().into();
+            let config = match DeviceConfigEntity::find_by_id(device.id.clone())
+                .one(&self.conn)
+                .await?
+            {
+                Some(current) if current.version == version => current,
+                Some(current) => {
+                    let mut config: DeviceConfigActiveModel = current.into();
+                    config.version = Set(version);
+                    config.document = Set(document);
+                    config.updated_at = Set(now);
+                    config.update(&self.conn).await?
+                }
+                // 从未分配过配置的设备不保存空配置
+                None if document == json!({}) => continue,
+                None => {
+                    DeviceConfigActiveModel {
+                        device_id: Set(device.id.clone()),
+                        version: Set(version),
+                        document: Set(document),
+                        updated_at: Set(now),
+                        ..Default::default()
+                    }
+                    .insert(&self.conn)
+                    .await?
+                }
+            };
+            if config.pushed_version.as_deref() != Some(config.version.as_str()) {
+                changed.push((device, config));
+            }
+        }
+        Ok(changed)
+    }
+
+    async fn mark_device_config_pushed(&self, device_id: &str, version: &str) -> Result<()> {
+        DeviceConfigEntity::update_many()
+            .col_expr(DeviceConfigColumn::PushedVersion, Expr::value(version))
+            .col_expr(
+                DeviceConfigColumn::PushedAt,
+                Expr::value(DateTimeWithTimeZone::from(Local::now())),
+            )
+            .filter(DeviceConfigColumn::DeviceId.eq(device_id))
+            .exec(&self.conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn mark_device_configs_stale(&self, device_ids: Vec<String>) -> Result<()> {
+        if device_ids.is_empty() {
+            return Ok(());
+        }
+        DeviceEntity::update_many()
+            .col_expr(devices::Column::ConfigStale, Expr::value(true))
+            .filter(devices::Column::Id.is_in(device_ids))
+            .exec(&self.conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn ack_device_config(
+        &self,
+        account_id: &str,
+        device_id: &str,
+        ack: &ConfigAck,
+    ) -> Result<()> {
+        let device = Query::select()
+            .column(devices::Column::Id)
+            .from(DeviceEntity)
+            .and_where(devices::Column::Id.eq(device_id))
+            .and_where(devices::Column::AccountId.eq(account_id))
+            .to_owned();
+        let update = DeviceConfigEntity::update_many()
+            .col_expr(
+                DeviceConfigColumn::AppliedAt,
+                Expr::value(DateTimeWithTimeZone::from(Local::now())),
+            )
+            .filter(DeviceConfigColumn::DeviceId.in_subquery(device));
+        // 应用失败时保留上次成功应用的版本
+        let update = match &ack.error {
+            None => update
+                .col_expr(
+                    DeviceConfigColumn::AppliedVersion,
+                    Expr::value(ack.version.as_str()),
+                )
+                .col_expr(DeviceConfigColumn::ApplyError, Expr::value(None::<String>)),
+            Some(error) => update.col_expr(
+                DeviceConfigColumn::ApplyError,
+                Expr::value(format!("version {}: {}", ack.version, error)),
+            ),
+        };
+        update.exec(&self.conn).await?;
+        Ok(())
+    }
+
+    async fn create_provisioning_profile(
+        &self,
+        account_id: &str,
+        req: &CreateProvisioningProfile,
+    ) -> Result<(ProvisioningProfileModelWithRelated, String)> {
+        self.get_schema(account_id, &req.schema_id).await?;
+        ensure_labels(&self.conn, account_id, &req.label_ids).await?;
+        let profile_id = xid::new().to_string();
+        let claim_token = credential::generate_secret();
+        let txn = self.conn.begin().await?;
+        ProvisioningProfileActiveModel {
+            id: Set(profile_id.clone()),
+            account_id: Set(account_id.to_string()),
+            schema_id: Set(req.schema_id.clone()),
+            name: Set(req.name.clone()),
+            claim_token_hash: Set(credential::hash_secret(&claim_token)),
+            max_devices: Set(req.max_devices),
+            registered_count: Set(0),
+            is_active: Set(true),
+            expires_at: Set(req.expires_at.map(Into::into)),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+        set_profile_labels(&txn, &profile_id, &req.label_ids).await?;
+        txn.commit().await?;
+        let profile = self
+            .get_provisioning_profile(account_id, &profile_id)
+            .await?;
+        Ok((profile, claim_token))
+    }
+
+    async fn get_provisioning_profile(
+        &self,
+        account_id: &str,
+        profile_id: &str,
+    ) -> Result<ProvisioningProfileModelWithRelated> {
+        let profile = ProvisioningProfileEntity::find_by_id(profile_id.to_string())
+            .filter(ProvisioningProfileColumn::AccountId.eq(account_id))
+            .one(&self.conn)
+            .await?
+            .ok_or_else(|| NeoiotError::ObjectNotFound("provisioning profile".to_string()))?;
+        let labels = profile.find_related(LabelEntity).all(&self.conn).await?;
+        Ok(ProvisioningProfileModelWithRelated { profile, labels })
+    }
+
+    async fn list_provisioning_profiles(
+        &self,
+        account_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<ProvisioningProfileModelWithRelated>, usize)> {
+        let paginator = ProvisioningProfileEntity::find()
+            .filter(ProvisioningProfileColumn::AccountId.eq(account_id))
+            .order_by_asc(ProvisioningProfileColumn::Id)
+            .paginate(&self.conn, page_size);
+        let profiles = paginator.fetch_page(page - 1).await?;
+        let total = paginator.num_items().await?;
+
+        let mut labels: HashMap<String, Vec<LabelModel>> = HashMap::new();
+        for (relation, label) in ProvisioningProfileLabelEntity::find()
+            .filter(
+                ProvisioningProfileLabelColumn::ProfileId
+                    .is_in(profiles.iter().map(|p| p.id.as_str())),
+            )
+            .find_also_related(LabelEntity)
+            .all(&self.conn)
+            .await?
+        {
+            if let Some(label) = label {
+                labels.entry(relation.profile_id).or_default().push(label);
+            }
+        }
+        let profiles = profiles
+            .into_iter()
+            .map(|profile| ProvisioningProfileModelWithRelated {
+                labels: labels.remove(&profile.id).unwrap_or_default(),
+                profile,
+            })
+            .collect();
+        Ok((profiles, total))
+    }
+
+    async fn update_provisioning_profile(
+        &self,
+        account_id: &str,
+        profile_id: &str,
+        req: &UpdateProvisioningProfile,
+    ) -> Result<ProvisioningProfileModelWithRelated> {
+        let profile = self
+            .get_provisioning_profile(account_id, profile_id)
+            .await?
+            .profile;
+        if let Some(label_ids) = &req.label_ids {
+            ensure_labels(&self.conn, account_id, label_ids).await?;
+        }
+        let mut profile: ProvisioningProfileActiveModel = profile.into();
+        if let Some(name) = &req.name {
+            profile.name = Set(name.clone());
+        }
+        if let Some(is_active) = req.is_active {
+            profile.is_active = Set(is_active);
+        }
+        match req.max_devices {
+            MaybeUndefined::Value(max_devices) => profile.max_devices = Set(Some(max_devices)),
+            MaybeUndefined::Null => profile.max_devices = Set(None),
+            MaybeUndefined::Undefined => {}
+        }
+        match req.expires_at {
+            MaybeUndefined::Value(expires_at) => profile.expires_at = Set(Some(expires_at.into())),
+            MaybeUndefined::Null => profile.expires_at = Set(None),
+            MaybeUndefined::Undefined => {}
+        }
+        profile.updated_at = Set(Some(Local::now().into()));
+        let txn = self.conn.begin().await?;
+        profile.update(&txn).await?;
+        if let Some(label_ids) = &req.label_ids {
+            ProvisioningProfileLabelEntity::delete_many()
+                .filter(ProvisioningProfileLabelColumn::ProfileId.eq(profile_id))
+                .exec(&txn)
+                .await?;
+            set_profile_labels(&txn, profile_id, label_ids).await?;
+        }
+        txn.commit().await?;
+        self.get_provisioning_profile(account_id, profile_id).await
+    }
+
+    async fn reset_provisioning_claim_token(
+        &self,
+        account_id: &str,
+        profile_id: &str,
+    ) -> Result<(ProvisioningProfileModelWithRelated, String)> {
+        let profile = self
+            .get_provisioning_profile(account_id, profile_id)
+            .await?
+            .profile;
+        let claim_token = credential::generate_secret();
+        let mut profile: ProvisioningProfileActiveModel = profile.into();
+        profile.claim_token_hash = Set(credential::hash_secret(&claim_token));
+        profile.updated_at = Set(Some(Local::now().into()));
+        profile.update(&self.conn).await?;
+        let profile = self
+            .get_provisioning_profile(account_id, profile_id)
+            .await?;
+        Ok((profile, claim_token))
+    }
+
+    async fn delete_provisioning_profile(&self, account_id: &str, profile_id: &str) -> Result<()> {
+        let profile = self
+            .get_provisioning_profile(account_id, profile_id)
+            .await?
+            .profile;
+        profile.delete(&self.conn).await?;
+        Ok(())
+    }
+
+    async fn register_device(
+        &self,
+        req: &RegisterDevice,
+    ) -> Result<(DeviceModel, IssuedCredential)> {
+        let profile = ProvisioningProfileEntity::find()
+            .filter(
+                ProvisioningProfileColumn::ClaimTokenHash
+                    .eq(credential::hash_secret(&req.claim_token)),
+            )
+            .filter(ProvisioningProfileColumn::IsActive.eq(true))
+            .filter(
+                Condition::any()
+                    .add(ProvisioningProfileColumn::ExpiresAt.is_null())
+                    .add(ProvisioningProfileColumn::ExpiresAt.gt(Local::now())),
+            )
+            .one(&self.conn)
+            .await?
+            .ok_or(NeoiotError::AuthenticateError)?;
+        let account_id = profile.account_id.as_str();
+        if DeviceEntity::find()
+            .filter(devices::Column::AccountId.eq(account_id))
+            .filter(devices::Column::HardwareSerial.eq(req.hardware_serial.as_str()))
+            .one(&self.conn)
+            .await?
+            .is_some()
+        {
+            return Err(NeoiotError::AlreadyExists("device".to_string()));
+        }
+        let label_ids = ProvisioningProfileLabelEntity::find()
+            .filter(ProvisioningProfileLabelColumn::ProfileId.eq(profile.id.as_str()))
+            .all(&self.conn)
+            .await?
+            .into_iter()
+            .map(|r| r.label_id)
+            .collect::<Vec<_>>();
+        let create = CreateDevice {
+            name: req
+                .name
+                .clone()
+                .unwrap_or_else(|| req.hardware_serial.clone()),
+            schema_id: profile.schema_id.clone(),
+            label_ids,
+        };
+
+        let device_id = xid::new().to_string();
+        let secret = credential::generate_secret();
+        let txn = self.conn.begin().await?;
+        // 在同一条语句中检查并占用配额, 避免并发注册超出限制
+        let claimed = ProvisioningProfileEntity::update_many()
+            .col_expr(
+                ProvisioningProfileColumn::RegisteredCount,
+                Expr::col(ProvisioningProfileColumn::RegisteredCount).add(1),
+            )
+            .filter(ProvisioningProfileColumn::Id.eq(profile.id.as_str()))
+            .filter(
+                Condition::any()
+                    .add(ProvisioningProfileColumn::MaxDevices.is_null())
+                    .add(
+                        Expr::col(ProvisioningProfileColumn::RegisteredCount)
+                            .less_than(Expr::col(ProvisioningProfileColumn::MaxDevices)),
+                    ),
+            )
+            .exec(&txn)
+            .await?;
+        if claimed.rows_affected == 0 {
+            return Err(NeoiotError::QuotaExceeded(format!(
+                "provisioning profile {} can not register more devices",
+                profile.id
+            )));
+        }
+        let mut device = new_device(account_id, &device_id, &create);
+        device.hardware_serial = Set(Some(req.hardware_serial.clone()));
+        device.provisioning_profile_id = Set(Some(profile.id.clone()));
+        // 并发注册同一硬件序列号时, 前面的检查可能都通过, 由唯一索引兜底
+        let device = device.insert(&txn).await.map_err(|e| {
+            if e.to_string().contains("uniq_devices_hardware_serial") {
+                NeoiotError::AlreadyExists("device".to_string())
+            } else {
+                e.into()
+            }
+        })?;
+        let credential = new_credential(&device_id, None, &secret, None)
+            .insert(&txn)
+            .await?;
+        if !create.label_ids.is_empty() {
+            LabelDeviceRelationEntity::insert_many(create.label_ids.iter().map(|id| {
+                LabelDeviceRelationActiveModel {
+                    label_id: Set(id.to_string()),
+                    device_id: Set(device_id.to_string()),
+                    ..Default::default()
+                }
+            }))
+            .exec(&txn)
+            .await?;
+            rebuild_acl(&txn, &device_id).await?;
+        }
+        txn.commit().await?;
+        Ok((device, IssuedCredential { credential, secret }))
     }
 
     async fn create_schema(&self, account_id: &str, schema: &CreateSchema) -> Result<SchemaModel> {
@@ -567,3 +5200,633 @@ fn hash_password(password: &str) -> String {
         .unwrap()
         .to_string()
 }
+
+fn generate_password() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(20)
+        .map(char::from)
+        .collect()
+}
+
+/// 设备的标签版本号, 设备据此判断是否需要重新订阅标签主题
+fn label_version() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64
+}
+
+/// 标签关系变化后, 更新相关设备的标签版本号和按标签接收消息的ACL, 并重新计算生效配置
+async fn touch_label_version<C: ConnectionTrait>(conn: &C, device_ids: Vec<String>) -> Result<()> {
+    if device_ids.is_empty() {
+        return Ok(());
+    }
+    DeviceEntity::update_many()
+        .col_expr(devices::Column::LabelVersion, Expr::value(label_version()))
+        .col_expr(devices::Column::ConfigStale, Expr::value(true))
+        .filter(devices::Column::Id.is_in(device_ids.clone()))
+        .exec(conn)
+        .await?;
+    for device_id in device_ids {
+        rebuild_acl(conn, &device_id).await?;
+    }
+    Ok(())
+}
+
+/// 校验配置模板的Schema以及配置文档
+fn check_config_document(schema: &serde_json::Value, document: &serde_json::Value) -> Result<()> {
+    device_config::check_schema(schema)
+        .map_err(|err| NeoiotError::InvalidArgument(format!("invalid config schema: {}", err)))?;
+    if !document.is_object() {
+        return Err(NeoiotError::InvalidArgument(
+            "config document must be an object".to_string(),
+        ));
+    }
+    device_config::validate(schema, document).map_err(|err| {
+        NeoiotError::InvalidArgument(format!("config document does not match schema: {}", err))
+    })
+}
+
+async fn ensure_config_profile_name<C: ConnectionTrait>(
+    conn: &C,
+    account_id: &str,
+    name: &str,
+) -> Result<()> {
+    let existed = ConfigProfileEntity::find()
+        .filter(ConfigProfileColumn::AccountId.eq(account_id))
+        .filter(ConfigProfileColumn::Name.eq(name))
+        .count(conn)
+        .await?;
+    if existed > 0 {
+        return Err(NeoiotError::AlreadyExists(format!(
+            "config profile {}",
+            name
+        )));
+    }
+    Ok(())
+}
+
+async fn latest_config_version<C: ConnectionTrait>(
+    conn: &C,
+    profile: &ConfigProfileModel,
+) -> Result<ConfigProfileVersionModel> {
+    ConfigProfileVersionEntity::find_by_id((profile.id.clone(), profile.version))
+        .one(conn)
+        .await?
+        .ok_or_else(|| NeoiotError::ObjectNotFound(format!("config profile {}", profile.id)))
+}
+
+/// 将分配涉及的设备标记为需要重新计算生效配置
+async fn mark_assignments_stale<C: ConnectionTrait>(
+    conn: &C,
+    assignments: &[ConfigAssignmentModel],
+) -> Result<()> {
+    let label_ids: Vec<String> = assignments
+        .iter()
+        .filter_map(|a| a.label_id.clone())
+        .collect();
+    let device_ids: Vec<String> = assignments
+        .iter()
+        .filter_map(|a| a.device_id.clone())
+        .collect();
+    if label_ids.is_empty() && device_ids.is_empty() {
+        return Ok(());
+    }
+    let labeled = Query::select()
+        .column(LabelDeviceRelationColumn::DeviceId)
+        .from(LabelDeviceRelationEntity)
+        .and_where(LabelDeviceRelationColumn::LabelId.is_in(label_ids))
+        .to_owned();
+    DeviceEntity::update_many()
+        .col_expr(devices::Column::ConfigStale, Expr::value(true))
+        .filter(
+            Condition::any()
+                .add(devices::Column::Id.is_in(device_ids))
+                .add(devices::Column::Id.in_subquery(labeled)),
+        )
+        .exec(conn)
+        .await?;
+    Ok(())
+}
+
+/// 计算设备的生效配置, 合并设备所在标签和设备本身分配的模板的最新文档
+async fn effective_device_config<C: ConnectionTrait>(
+    conn: &C,
+    device_id: &str,
+) -> Result<serde_json::Value> {
+    let labels = Query::select()
+        .column(LabelDeviceRelationColumn::LabelId)
+        .from(LabelDeviceRelationEntity)
+        .and_where(LabelDeviceRelationColumn::DeviceId.eq(device_id))
+        .to_owned();
+    let assignments = ConfigAssignmentEntity::find()
+        .filter(
+            Condition::any()
+                .add(ConfigAssignmentColumn::DeviceId.eq(device_id))
+                .add(ConfigAssignmentColumn::LabelId.in_subquery(labels)),
+        )
+        .find_also_related(ConfigProfileEntity)
+        .all(conn)
+        .await?;
+    let mut layers = Vec::with_capacity(assignments.len());
+    for (assignment, profile) in assignments {
+        if let Some(profile) = profile {
+            let version = latest_config_version(conn, &profile).await?;
+            layers.push((assignment, version.document));
+        }
+    }
+    Ok(device_config::effective(layers))
+}
+
+fn new_device(account_id: &str, device_id: &str, req: &CreateDevice) -> devices::ActiveModel {
+    let acl = ACLRules::new(account_id.to_string(), device_id.to_string());
+    devices::ActiveModel {
+        id: Set(device_id.to_string()),
+        account_id: Set(account_id.to_string()),
+        schema_id: Set(req.schema_id.clone()),
+        name: Set(req.name.clone()),
+        label_version: Set(0),
+        is_active: Set(true),
+        is_online: Set(false),
+        mqtt_username: Set(format!("{}/{}", device_id, account_id)),
+        acl_pubs: Set(json!(acl.pubs())),
+        acl_subs: Set(json!(acl.subs())),
+        is_super_device: Set(false),
+        lifecycle_state: Set(LifecycleState::Provisioned),
+        ..Default::default()
+    }
+}
+
+/// 重新生成设备的ACL: 设备自身的权限, 设备间通信白名单, 按所带标签接收消息的权限,
+/// 以及网关代子设备收发消息的权限
+async fn rebuild_acl<C: ConnectionTrait>(conn: &C, device_id: &str) -> Result<()> {
+    let device = match DeviceEntity::find_by_id(device_id.to_string())
+        .one(conn)
+        .await?
+    {
+        Some(device) => device,
+        None => return Ok(()),
+    };
+    let acl = ACLRules::new(device.account_id.clone(), device.id.clone());
+    let (mut pubs, mut subs) = (acl.pubs(), acl.subs());
+    let rules = D2dRuleEntity::find()
+        .filter(D2dRuleColumn::SenderId.eq(device_id))
+        .find_also_related(LabelEntity)
+        .order_by_asc(D2dRuleColumn::Id)
+        .all(conn)
+        .await?;
+    for (rule, label) in rules {
+        match (rule.target_device_id, label) {
+            (Some(receiver_id), _) => pubs.push(acl.pub_d2d(&receiver_id)),
+            (None, Some(label)) => {
+                pubs.push(acl.pub_d2l(&selector::format_label(&label.name, label.value.as_deref())))
+            }
+            (None, None) => {}
+        }
+    }
+    let labels = LabelEntity::find()
+        .filter(
+            labels::Column::Id.in_subquery(
+                LabelDeviceRelationEntity::find()
+                    .select_only()
+                    .column(LabelDeviceRelationColumn::LabelId)
+                    .filter(LabelDeviceRelationColumn::DeviceId.eq(device_id))
+                    .into_query(),
+            ),
+        )
+        .order_by_asc(labels::Column::Id)
+        .all(conn)
+        .await?;
+    for label in labels {
+        subs.push(acl.sub_d2l(&selector::format_label(&label.name, label.value.as_deref())));
+    }
+    if device.is_super_device {
+        let children = DeviceEntity::find()
+            .filter(devices::Column::ParentId.eq(device_id))
+            .filter(devices::Column::IsActive.eq(true))
+            .order_by_asc(devices::Column::Id)
+            .all(conn)
+            .await?;
+        for child in children {
+            let acl = ACLRules::new(child.account_id, child.id);
+            pubs.extend(acl.pubs());
+            subs.extend(acl.proxy_subs());
+        }
+    }
+    let mut device: DeviceActiveModel = device.into();
+    device.acl_pubs = Set(json!(pubs));
+    device.acl_subs = Set(json!(subs));
+    device.update(conn).await?;
+    Ok(())
+}
+
+/// 以该标签或设备为目标的白名单的发送方
+async fn d2d_senders<C: ConnectionTrait>(conn: &C, target: Condition) -> Result<Vec<String>> {
+    let senders = D2dRuleEntity::find()
+        .filter(target)
+        .all(conn)
+        .await?
+        .into_iter()
+        .map(|r| r.sender_id)
+        .collect::<HashSet<_>>();
+    Ok(senders.into_iter().collect())
+}
+
+/// 告警列表和统计的公共筛选条件
+fn alarm_condition(
+    account_id: &str,
+    device_id: Option<String>,
+    label_id: Option<String>,
+) -> Condition {
+    let mut condition = Condition::all().add(AlarmColumn::AccountId.eq(account_id));
+    if let Some(device_id) = device_id {
+        condition = condition.add(AlarmColumn::DeviceId.eq(device_id));
+    }
+    if let Some(label_id) = label_id {
+        condition = condition.add(
+            AlarmColumn::DeviceId.in_subquery(
+                Query::select()
+                    .column(LabelDeviceRelationColumn::DeviceId)
+                    .from(LabelDeviceRelationEntity)
+                    .and_where(LabelDeviceRelationColumn::LabelId.eq(label_id))
+                    .to_owned(),
+            ),
+        );
+    }
+    condition
+}
+
+async fn clear<C: ConnectionTrait>(conn: &C, alarm: AlarmModel, actor: &str) -> Result<AlarmModel> {
+    let now: DateTimeWithTimeZone = Local::now().into();
+    let mut alarm: AlarmActiveModel = alarm.into();
+    alarm.status = Set(AlarmStatus::Cleared);
+    alarm.cleared_at = Set(Some(now));
+    alarm.cleared_by = Set(Some(actor.to_string()));
+    alarm.updated_at = Set(Some(now));
+    Ok(alarm.update(conn).await?)
+}
+
+/// 规则字段即`metrics`主题的最后一级
+fn validate_rule_field(field: &str) -> Result<()> {
+    if field.is_empty() || field.contains(['/', '+', '#']) {
+        return Err(NeoiotError::InvalidArgument(format!(
+            "invalid rule field `{}`",
+            field
+        )));
+    }
+    Ok(())
+}
+
+/// 校验规则的选择器以及动作所需的参数
+fn validate_rule_action(
+    selector: Option<&str>,
+    action: RuleAction,
+    command: Option<&str>,
+    webhook_url: Option<&str>,
+) -> Result<()> {
+    if let Some(selector) = selector {
+        selector.parse::<Selector>()?;
+    }
+    match action {
+        RuleAction::Command if command.is_none_or(str::is_empty) => Err(
+            NeoiotError::InvalidArgument("command is required for command action".to_string()),
+        ),
+        RuleAction::Webhook => {
+            let url = webhook_url.ok_or_else(|| {
+                NeoiotError::InvalidArgument(
+                    "webhook_url is required for webhook action".to_string(),
+                )
+            })?;
+            validate_webhook_url(url)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// 校验Webhook地址, 只支持http和https
+fn validate_webhook_url(url: &str) -> Result<()> {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(()),
+        _ => Err(NeoiotError::InvalidArgument(format!(
+            "invalid webhook url `{}`",
+            url
+        ))),
+    }
+}
+
+/// 校验数据转发路由的目标
+fn validate_data_route(
+    sink_type: SinkType,
+    endpoint: Option<&str>,
+    topic: Option<&str>,
+    selector: Option<&str>,
+) -> Result<()> {
+    if let Some(selector) = selector {
+        selector.parse::<Selector>()?;
+    }
+    fn required<'a>(value: Option<&'a str>, name: &str, sink_type: SinkType) -> Result<&'a str> {
+        value.filter(|v| !v.is_empty()).ok_or_else(|| {
+            NeoiotError::InvalidArgument(format!(
+                "{} is required for {} sink",
+                name,
+                sink_type.to_value()
+            ))
+        })
+    }
+    match sink_type {
+        SinkType::Pulsar => {
+            let endpoint = required(endpoint, "endpoint", sink_type)?;
+            required(topic, "topic", sink_type)?;
+            match reqwest::Url::parse(endpoint) {
+                Ok(url) if matches!(url.scheme(), "pulsar" | "pulsar+ssl") => Ok(()),
+                _ => Err(NeoiotError::InvalidArgument(format!(
+                    "invalid pulsar endpoint `{}`",
+                    endpoint
+                ))),
+            }
+        }
+        SinkType::Kafka => {
+            required(topic, "topic", sink_type)?;
+            validate_webhook_url(required(endpoint, "endpoint", sink_type)?)
+        }
+        SinkType::Http => validate_webhook_url(required(endpoint, "endpoint", sink_type)?),
+        SinkType::File => {
+            // 文件只能写在配置的目录下, 主题作为文件名前缀
+            let prefix = required(topic, "topic", sink_type)?;
+            if prefix.len() > 64
+                || !prefix
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(NeoiotError::InvalidArgument(format!(
+                    "invalid file prefix `{}`",
+                    prefix
+                )));
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Webhook订阅的事件类型以名称数组保存
+fn event_type_names(event_types: &[EventType]) -> serde_json::Value {
+    event_types.iter().map(|t| t.to_value()).collect()
+}
+
+/// 标记规则在设备上已触发, 并发求值时只有一方能成功
+async fn claim_rule_firing<C: ConnectionTrait>(
+    conn: &C,
+    rule_id: &str,
+    device_id: &str,
+) -> Result<bool> {
+    let result = RuleStateEntity::update_many()
+        .col_expr(
+            RuleStateColumn::FiredAt,
+            Expr::value(DateTimeWithTimeZone::from(Local::now())),
+        )
+        .filter(RuleStateColumn::RuleId.eq(rule_id))
+        .filter(RuleStateColumn::DeviceId.eq(device_id))
+        .filter(RuleStateColumn::FiredAt.is_null())
+        .exec(conn)
+        .await?;
+    Ok(result.rows_affected == 1)
+}
+
+/// 吊销设备所有未吊销的证书
+async fn revoke_device_certificates<C: ConnectionTrait>(
+    conn: &C,
+    account_id: &str,
+    device_id: &str,
+) -> Result<()> {
+    let revoked = DeviceCertificateEntity::update_many()
+        .col_expr(
+            DeviceCertificateColumn::RevokedAt,
+            Expr::value(DateTimeWithTimeZone::from(Local::now())),
+        )
+        .filter(DeviceCertificateColumn::AccountId.eq(account_id))
+        .filter(DeviceCertificateColumn::DeviceId.eq(device_id))
+        .filter(DeviceCertificateColumn::RevokedAt.is_null())
+        .exec(conn)
+        .await?;
+    if revoked.rows_affected > 0 {
+        bump_crl_number(conn, account_id).await?;
+    }
+    Ok(())
+}
+
+/// 吊销证书后递增CRL编号
+async fn bump_crl_number<C: ConnectionTrait>(conn: &C, account_id: &str) -> Result<()> {
+    DeviceCaEntity::update_many()
+        .col_expr(
+            DeviceCaColumn::CrlNumber,
+            Expr::col(DeviceCaColumn::CrlNumber).add(1),
+        )
+        .filter(DeviceCaColumn::AccountId.eq(account_id))
+        .exec(conn)
+        .await?;
+    Ok(())
+}
+
+/// 活动中止时尚未推送的设备不再推送
+async fn skip_pending_ota_devices<C: ConnectionTrait>(
+    conn: &C,
+    campaign_id: &str,
+    now: DateTimeWithTimeZone,
+) -> Result<()> {
+    OtaCampaignDeviceEntity::update_many()
+        .col_expr(
+            OtaCampaignDeviceColumn::Status,
+            Expr::value(OtaDeviceStatus::Skipped),
+        )
+        .col_expr(OtaCampaignDeviceColumn::UpdatedAt, Expr::value(now))
+        .filter(OtaCampaignDeviceColumn::CampaignId.eq(campaign_id))
+        .filter(OtaCampaignDeviceColumn::Status.eq(OtaDeviceStatus::Pending))
+        .exec(conn)
+        .await?;
+    Ok(())
+}
+
+/// 锁住仍在接收分片的上传记录, 并发到达的分片依次处理, 只有一个分片会触发拼接;
+/// 第一个分片到达时确定分片总数
+async fn lock_receiving_upload<C: ConnectionTrait>(
+    conn: &C,
+    upload_id: &str,
+    total: i32,
+) -> Result<DeviceUploadModel> {
+    let now: DateTimeWithTimeZone = Local::now().into();
+    let locked = DeviceUploadEntity::update_many()
+        .col_expr(DeviceUploadColumn::UpdatedAt, Expr::value(now))
+        .filter(DeviceUploadColumn::Id.eq(upload_id))
+        .filter(DeviceUploadColumn::Status.is_in(UPLOAD_RECEIVING))
+        .exec(conn)
+        .await?;
+    if locked.rows_affected == 0 {
+        return Err(NeoiotError::InvalidArgument(format!(
+            "upload {} is no longer accepting chunks",
+            upload_id
+        )));
+    }
+    DeviceUploadEntity::update_many()
+        .col_expr(DeviceUploadColumn::TotalChunks, Expr::value(total))
+        .filter(DeviceUploadColumn::Id.eq(upload_id))
+        .filter(DeviceUploadColumn::TotalChunks.is_null())
+        .exec(conn)
+        .await?;
+    let upload = DeviceUploadEntity::find_by_id(upload_id.to_string())
+        .one(conn)
+        .await?
+        .ok_or_else(|| NeoiotError::ObjectNotFound(format!("upload {}", upload_id)))?;
+    if upload.total_chunks != Some(total) {
+        return Err(NeoiotError::InvalidArgument(format!(
+            "total chunks of upload {} is {}",
+            upload_id,
+            upload.total_chunks.unwrap_or_default()
+        )));
+    }
+    Ok(upload)
+}
+
+/// 上传已收到的分片, 按序号排列
+async fn upload_chunks<C: ConnectionTrait>(
+    conn: &C,
+    upload_id: &str,
+) -> Result<Vec<DeviceUploadChunkModel>> {
+    Ok(DeviceUploadChunkEntity::find()
+        .filter(DeviceUploadChunkColumn::UploadId.eq(upload_id))
+        .order_by_asc(DeviceUploadChunkColumn::Seq)
+        .all(conn)
+        .await?)
+}
+
+/// 校验标签均属于该账号
+async fn ensure_labels<C: ConnectionTrait>(
+    conn: &C,
+    account_id: &str,
+    label_ids: &[String],
+) -> Result<()> {
+    if label_ids.is_empty() {
+        return Ok(());
+    }
+    let owned = LabelEntity::find()
+        .filter(labels::Column::AccountId.eq(account_id))
+        .filter(labels::Column::Id.is_in(label_ids.iter().map(|id| id.as_str())))
+        .all(conn)
+        .await?
+        .into_iter()
+        .map(|l| l.id)
+        .collect::<HashSet<_>>();
+    match foreign_label(label_ids, &owned) {
+        Some(label_id) => Err(NeoiotError::ObjectNotFound(format!("label {}", label_id))),
+        None => Ok(()),
+    }
+}
+
+/// 第一个不在账号标签中的标签ID
+fn foreign_label<'a>(label_ids: &'a [String], owned: &HashSet<String>) -> Option<&'a str> {
+    label_ids
+        .iter()
+        .find(|id| !owned.contains(*id))
+        .map(String::as_str)
+}
+
+async fn set_profile_labels<C: ConnectionTrait>(
+    conn: &C,
+    profile_id: &str,
+    label_ids: &[String],
+) -> Result<()> {
+    let label_ids = label_ids.iter().collect::<HashSet<_>>();
+    if label_ids.is_empty() {
+        return Ok(());
+    }
+    ProvisioningProfileLabelEntity::insert_many(label_ids.into_iter().map(|id| {
+        ProvisioningProfileLabelActiveModel {
+            profile_id: Set(profile_id.to_string()),
+            label_id: Set(id.to_string()),
+        }
+    }))
+    .exec(conn)
+    .await?;
+    Ok(())
+}
+
+fn new_credential(
+    device_id: &str,
+    name: Option<String>,
+    secret: &str,
+    expires_at: Option<DateTimeWithTimeZone>,
+) -> DeviceCredentialActiveModel {
+    DeviceCredentialActiveModel {
+        id: Set(xid::new().to_string()),
+        device_id: Set(device_id.to_string()),
+        name: Set(name),
+        secret_hash: Set(credential::hash_secret(secret)),
+        expires_at: Set(expires_at),
+        ..Default::default()
+    }
+}
+
+/// 带有指定标签(及取值)的设备ID子查询
+fn labeled_devices(account_id: &str, key: &str, values: Option<&[String]>) -> SelectStatement {
+    let mut stmt = LabelDeviceRelationEntity::find()
+        .select_only()
+        .column(LabelDeviceRelationColumn::DeviceId)
+        .inner_join(LabelEntity)
+        .filter(labels::Column::AccountId.eq(account_id))
+        .filter(labels::Column::Name.eq(key));
+    if let Some(values) = values {
+        stmt = stmt.filter(labels::Column::Value.is_in(values.to_vec()));
+    }
+    stmt.into_query()
+}
+
+/// 将标签选择器转换为设备的查询条件, 使用子查询避免联表带来的重复行
+fn selector_condition(account_id: &str, selector: &Selector) -> Condition {
+    let id = devices::Column::Id;
+    selector
+        .0
+        .iter()
+        .fold(Condition::all(), |cond, requirement| {
+            let expr = match requirement {
+                Requirement::Exists(k) => id.in_subquery(labeled_devices(account_id, k, None)),
+                Requirement::NotExists(k) => {
+                    id.not_in_subquery(labeled_devices(account_id, k, None))
+                }
+                Requirement::Equals(k, v) => id.in_subquery(labeled_devices(
+                    account_id,
+                    k,
+                    Some(std::slice::from_ref(v)),
+                )),
+                Requirement::NotEquals(k, v) => id.not_in_subquery(labeled_devices(
+                    account_id,
+                    k,
+                    Some(std::slice::from_ref(v)),
+                )),
+                Requirement::In(k, vs) => id.in_subquery(labeled_devices(account_id, k, Some(vs))),
+                Requirement::NotIn(k, vs) => {
+                    id.not_in_subquery(labeled_devices(account_id, k, Some(vs)))
+                }
+            };
+            cond.add(expr)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_foreign_label() {
+        let owned = HashSet::from(["l1".to_string(), "l2".to_string()]);
+        assert_eq!(
+            foreign_label(&["l1".to_string(), "l2".to_string()], &owned),
+            None
+        );
+        // 其他账号的标签ID不能关联到本账号的设备
+        assert_eq!(
+            foreign_label(&["l1".to_string(), "other".to_string()], &owned),
+            Some("other")
+        );
+        assert_eq!(foreign_label(&[], &owned), None);
+    }
+}
@@ -0,0 +1,208 @@
+//! 遥测规则: 设备通过`metrics/{account_id}/{device_id}/{field}`上报字段值,
+//! 条件满足并持续`duration_secs`后执行一次动作, 条件不再满足后重新计时
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use entity::alarms::AlarmSource;
+use entity::prelude::*;
+use entity::rules::{RuleAction, RuleOperator};
+use serde_json::json;
+
+use crate::{
+    alarms::{self, NewAlarm},
+    errors::{NeoiotError, Result},
+    oai_schema::{PayloadCodec, SendCommandToDevice},
+    repository::Repository,
+};
+
+/// Webhook请求超时
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 触发的规则
+#[derive(Debug, Clone)]
+pub struct FiredRule {
+    pub rule: RuleModel,
+    pub device_id: String,
+    pub value: f64,
+}
+
+/// 一次求值的结果
+#[derive(Debug, PartialEq)]
+pub struct Step {
+    /// 条件开始满足的时间, 条件不满足时为None
+    pub matched_since: Option<DateTime<Local>>,
+    /// 是否需要执行动作
+    pub fire: bool,
+}
+
+fn symbol(operator: RuleOperator) -> &'static str {
+    match operator {
+        RuleOperator::Gt => ">",
+        RuleOperator::Gte => ">=",
+        RuleOperator::Lt => "<",
+        RuleOperator::Lte => "<=",
+        RuleOperator::Eq => "==",
+        RuleOperator::Ne => "!=",
+    }
+}
+
+pub fn compare(operator: RuleOperator, value: f64, threshold: f64) -> bool {
+    match operator {
+        RuleOperator::Gt => value > threshold,
+        RuleOperator::Gte => value >= threshold,
+        RuleOperator::Lt => value < threshold,
+        RuleOperator::Lte => value <= threshold,
+        RuleOperator::Eq => (value - threshold).abs() < f64::EPSILON,
+        RuleOperator::Ne => (value - threshold).abs() >= f64::EPSILON,
+    }
+}
+
+/// 条件是否已持续足够长的时间
+pub fn is_due(matched_since: DateTime<Local>, now: DateTime<Local>, duration_secs: i32) -> bool {
+    now - matched_since >= chrono::Duration::seconds(duration_secs as i64)
+}
+
+/// 根据本次条件是否满足推进规则状态, `fired`表示本轮已经执行过动作
+pub fn step(
+    matched: bool,
+    matched_since: Option<DateTime<Local>>,
+    fired: bool,
+    now: DateTime<Local>,
+    duration_secs: i32,
+) -> Step {
+    if !matched {
+        return Step {
+            matched_since: None,
+            fire: false,
+        };
+    }
+    let since = matched_since.unwrap_or(now);
+    Step {
+        matched_since: Some(since),
+        fire: !fired && is_due(since, now, duration_secs),
+    }
+}
+
+/// 解析上报的字段值, 支持数字和布尔值
+pub fn parse_value(payload: &str) -> Option<f64> {
+    match payload.trim() {
+        "true" => Some(1.0),
+        "false" => Some(0.0),
+        value => value.parse::<f64>().ok().filter(|v| v.is_finite()),
+    }
+}
+
+/// 执行规则的动作并记录执行结果
+pub async fn execute<R: Repository>(repo: &R, fired: FiredRule) {
+    let result = match fired.rule.action {
+        RuleAction::Command => send_command(repo, &fired).await,
+        RuleAction::Webhook => call_webhook(&fired).await,
+        RuleAction::Alarm => raise_alarm(repo, &fired).await,
+    };
+    let error = result.err().map(|err| err.to_string());
+    if let Some(error) = &error {
+        tracing::warn!(rule_id = %fired.rule.id, device_id = %fired.device_id, %error, "rule action failed");
+    }
+    if let Err(err) = repo.create_rule_execution(&fired, error).await {
+        tracing::error!(?err, "failed to record rule execution");
+    }
+}
+
+async fn send_command<R: Repository>(repo: &R, fired: &FiredRule) -> Result<()> {
+    let req = SendCommandToDevice {
+        command: fired.rule.command.clone().unwrap_or_default(),
+        codec: PayloadCodec::Plain,
+        payload: fired.rule.command_payload.clone().unwrap_or_default(),
+        is_sync: false,
+        sync_timeout: 10,
+        ttl: None,
+        qos: 1,
+    };
+    repo.send_command_to_device(&fired.rule.account_id, &fired.device_id, &req)
+        .await?;
+    Ok(())
+}
+
+async fn raise_alarm<R: Repository>(repo: &R, fired: &FiredRule) -> Result<()> {
+    let rule = &fired.rule;
+    let message = format!(
+        "{}: {} {} {}, current value {}",
+        rule.name,
+        rule.field,
+        symbol(rule.operator),
+        rule.threshold,
+        fired.value
+    );
+    let alarm = NewAlarm::new(
+        &rule.account_id,
+        &fired.device_id,
+        &alarms::rule_alarm_type(&rule.id),
+        AlarmSource::Rule,
+        rule.alarm_severity,
+        &message,
+    )
+    .rule(&rule.id);
+    repo.raise_alarm(&alarm).await?;
+    Ok(())
+}
+
+async fn call_webhook(fired: &FiredRule) -> Result<()> {
+    let url = fired.rule.webhook_url.as_deref().unwrap_or_default();
+    let body = json!({
+        "rule_id": fired.rule.id,
+        "rule_name": fired.rule.name,
+        "account_id": fired.rule.account_id,
+        "device_id": fired.device_id,
+        "field": fired.rule.field,
+        "value": fired.value,
+        "fired_at": Local::now().to_rfc3339(),
+    });
+    reqwest::Client::new()
+        .post(url)
+        .timeout(WEBHOOK_TIMEOUT)
+        .json(&body)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| NeoiotError::WebhookError(e.to_string()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_step() {
+        let now = Local::now();
+        let earlier = now - chrono::Duration::seconds(300);
+
+        assert!(compare(RuleOperator::Gt, 8.5, 8.0));
+        assert!(!compare(RuleOperator::Gt, 8.0, 8.0));
+        assert!(compare(RuleOperator::Lte, 8.0, 8.0));
+        assert!(compare(RuleOperator::Ne, 1.0, 0.0));
+
+        // 条件首次满足, 开始计时
+        let s = step(true, None, false, now, 300);
+        assert_eq!(s.matched_since, Some(now));
+        assert!(!s.fire);
+        // 持续满5分钟后触发, 且只触发一次
+        assert!(step(true, Some(earlier), false, now, 300).fire);
+        assert!(!step(true, Some(earlier), true, now, 300).fire);
+        // 无持续时间要求时立即触发
+        assert!(step(true, None, false, now, 0).fire);
+        // 条件不满足时重置
+        assert_eq!(
+            step(false, Some(earlier), true, now, 300),
+            Step {
+                matched_since: None,
+                fire: false
+            }
+        );
+
+        assert_eq!(parse_value(" 8.5\n"), Some(8.5));
+        assert_eq!(parse_value("true"), Some(1.0));
+        assert_eq!(parse_value("NaN"), None);
+        assert_eq!(parse_value("{}"), None);
+    }
+}
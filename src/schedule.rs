@@ -0,0 +1,186 @@
+//! 定时指令: 按cron表达式在指定时区计算执行时间, 到期后由调度任务向设备或标签下发指令,
+//! 错过的执行(如服务停机期间)不补发, 只执行一次并顺延到下一个未来的时间点
+use std::str::FromStr;
+
+use chrono::{DateTime, Local};
+use chrono_tz::Tz;
+use entity::prelude::*;
+use entity::scheduled_commands::ScheduleTarget;
+
+use crate::{
+    errors::{NeoiotError, Result},
+    oai_schema::{PayloadCodec, SendCommandToDevice, SendCommandToDeviceBatch},
+    repository::Repository,
+};
+
+/// 解析cron表达式, 支持`分 时 日 月 周`五段格式以及带秒、年的六段或七段格式;
+/// 五段格式的周字段按标准cron计数(0和7为周日, 1为周一), 六段、七段格式按cron库计数(1为周日)
+pub fn parse_cron(expr: &str) -> Result<cron::Schedule> {
+    let expr = expr.trim();
+    let fields = expr.split_whitespace().collect::<Vec<_>>();
+    let expr = if let [minute, hour, day, month, weekday] = fields[..] {
+        format!(
+            "0 {} {} {} {} {}",
+            minute,
+            hour,
+            day,
+            month,
+            weekdays(weekday)
+        )
+    } else {
+        expr.to_string()
+    };
+    cron::Schedule::from_str(&expr)
+        .map_err(|err| NeoiotError::InvalidArgument(format!("invalid cron `{}`: {}", expr, err)))
+}
+
+/// 将标准cron周字段中的数字换成英文缩写, 名称、`*`和无法识别的写法原样保留
+fn weekdays(field: &str) -> String {
+    field
+        .split(',')
+        .map(|item| expand_weekdays(item).unwrap_or_else(|| item.to_string()))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// 展开`3`、`1-5`、`1-5/2`、`1/2`形式的数字周
+fn expand_weekdays(item: &str) -> Option<String> {
+    const NAMES: [&str; 8] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+    let (range, step) = match item.split_once('/') {
+        Some((range, step)) => (range, step.parse::<usize>().ok().filter(|s| *s > 0)?),
+        None => (item, 1),
+    };
+    let (start, end) = match range.split_once('-') {
+        Some((start, end)) => (start.parse::<usize>().ok()?, end.parse::<usize>().ok()?),
+        None => {
+            let start = range.parse::<usize>().ok()?;
+            (start, if item.contains('/') { 7 } else { start })
+        }
+    };
+    if start > end || end > 7 {
+        return None;
+    }
+    let days = (start..=end)
+        .step_by(step)
+        .map(|d| NAMES[d])
+        .collect::<Vec<_>>();
+    Some(days.join(","))
+}
+
+/// 解析IANA时区名称, 如`Asia/Shanghai`
+pub fn parse_timezone(timezone: &str) -> Result<Tz> {
+    timezone
+        .parse::<Tz>()
+        .map_err(|_| NeoiotError::InvalidArgument(format!("invalid timezone `{}`", timezone)))
+}
+
+/// `after`之后的下一次执行时间
+pub fn next_run(cron: &str, timezone: &str, after: DateTime<Local>) -> Result<DateTime<Local>> {
+    let tz = parse_timezone(timezone)?;
+    parse_cron(cron)?
+        .after(&after.with_timezone(&tz))
+        .next()
+        .map(|next| next.with_timezone(&Local))
+        .ok_or_else(|| NeoiotError::InvalidArgument(format!("cron `{}` never fires again", cron)))
+}
+
+/// 下发定时指令并记录执行结果
+pub async fn execute<R: Repository>(
+    repo: &R,
+    schedule: ScheduledCommandModel,
+    scheduled_at: DateTime<Local>,
+) {
+    let result = dispatch(repo, &schedule).await;
+    let (message_id, error) = match result {
+        Ok(message_id) => (Some(message_id), None),
+        Err(err) => (None, Some(err.to_string())),
+    };
+    if let Some(error) = &error {
+        tracing::warn!(schedule_id = %schedule.id, %error, "scheduled command failed");
+    }
+    if let Err(err) = repo
+        .create_scheduled_command_run(&schedule, scheduled_at, message_id, error)
+        .await
+    {
+        tracing::error!(?err, "failed to record scheduled command run");
+    }
+}
+
+async fn dispatch<R: Repository>(repo: &R, schedule: &ScheduledCommandModel) -> Result<String> {
+    let ttl = schedule.ttl.map(|ttl| ttl as usize);
+    let qos = schedule.qos as u8;
+    match schedule.target_type {
+        ScheduleTarget::Device => {
+            let req = SendCommandToDevice {
+                command: schedule.command.clone(),
+                codec: PayloadCodec::Plain,
+                payload: schedule.payload.clone(),
+                is_sync: false,
+                sync_timeout: 10,
+                ttl,
+                qos,
+            };
+            repo.send_command_to_device(&schedule.account_id, &schedule.target_id, &req)
+                .await
+        }
+        ScheduleTarget::Label => {
+            let req = SendCommandToDeviceBatch {
+                command: schedule.command.clone(),
+                codec: PayloadCodec::Plain,
+                payload: schedule.payload.clone(),
+                ttl,
+                qos,
+            };
+            repo.send_command_to_label(&schedule.account_id, &schedule.target_id, &req)
+                .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_next_run() {
+        let tz: Tz = "Asia/Shanghai".parse().unwrap();
+        let after = tz.ymd(2022, 8, 29).and_hms(10, 30, 0).with_timezone(&Local);
+
+        // 每天03:00, 按指定时区计算, 上海10:30即UTC 02:30
+        let next = next_run("0 3 * * *", "Asia/Shanghai", after).unwrap();
+        assert_eq!(next, tz.ymd(2022, 8, 30).and_hms(3, 0, 0));
+        let next = next_run("0 3 * * *", "UTC", after).unwrap();
+        assert_eq!(next, chrono::Utc.ymd(2022, 8, 29).and_hms(3, 0, 0));
+
+        // 每小时整点, 带秒的六段格式
+        let next = next_run("0 0 * * * *", "Asia/Shanghai", after).unwrap();
+        assert_eq!(next, tz.ymd(2022, 8, 29).and_hms(11, 0, 0));
+
+        // 工作日
+        let next = next_run("0 9 * * Mon-Fri", "Asia/Shanghai", after).unwrap();
+        assert_eq!(next, tz.ymd(2022, 8, 30).and_hms(9, 0, 0));
+
+        // 五段格式的数字周按标准cron计数, 2022-08-29为周一
+        let next = next_run("0 9 * * 1-5", "Asia/Shanghai", after).unwrap();
+        assert_eq!(next, tz.ymd(2022, 8, 30).and_hms(9, 0, 0));
+        let next = next_run("0 9 * * 1", "Asia/Shanghai", after).unwrap();
+        assert_eq!(next, tz.ymd(2022, 9, 5).and_hms(9, 0, 0));
+        let next = next_run("0 9 * * 0", "Asia/Shanghai", after).unwrap();
+        assert_eq!(next, tz.ymd(2022, 9, 4).and_hms(9, 0, 0));
+        let next = next_run("0 9 * * 7", "Asia/Shanghai", after).unwrap();
+        assert_eq!(next, tz.ymd(2022, 9, 4).and_hms(9, 0, 0));
+        let next = next_run("0 9 * * 6,0", "Asia/Shanghai", after).unwrap();
+        assert_eq!(next, tz.ymd(2022, 9, 3).and_hms(9, 0, 0));
+        let next = next_run("0 9 * * 5-7", "Asia/Shanghai", after).unwrap();
+        assert_eq!(next, tz.ymd(2022, 9, 2).and_hms(9, 0, 0));
+        let next = next_run("0 9 * * 2/2", "Asia/Shanghai", after).unwrap();
+        assert_eq!(next, tz.ymd(2022, 8, 30).and_hms(9, 0, 0));
+        let next = next_run("0 9 * * 3-6/2", "Asia/Shanghai", after).unwrap();
+        assert_eq!(next, tz.ymd(2022, 8, 31).and_hms(9, 0, 0));
+
+        assert!(next_run("0 3 * *", "UTC", after).is_err());
+        assert!(next_run("0 3 * * *", "Mars/Olympus", after).is_err());
+        assert!(next_run("0 0 3 1 1 * 2000", "UTC", after).is_err());
+    }
+}
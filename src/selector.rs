@@ -0,0 +1,207 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::errors::NeoiotError;
+
+/// 标签选择器中的单个条件
+#[derive(Debug, Clone, PartialEq)]
+pub enum Requirement {
+    /// `key`: 带有该标签
+    Exists(String),
+    /// `!key`: 不带该标签
+    NotExists(String),
+    /// `key=value`
+    Equals(String, String),
+    /// `key!=value`, 不带该标签的设备同样满足
+    NotEquals(String, String),
+    /// `key in (v1,v2)`
+    In(String, Vec<String>),
+    /// `key notin (v1,v2)`, 不带该标签的设备同样满足
+    NotIn(String, Vec<String>),
+}
+
+/// 标签选择器, 多个条件之间为且的关系
+///
+/// 例如: `site=sh01,floor in (2,3),!decommissioned`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Selector(pub Vec<Requirement>);
+
+impl Selector {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl FromStr for Selector {
+    type Err = NeoiotError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut requirements = vec![];
+        for term in split_terms(s)? {
+            let term = term.trim();
+            if term.is_empty() {
+                if s.trim().is_empty() {
+                    continue;
+                }
+                return Err(invalid(s, "empty requirement"));
+            }
+            requirements.push(parse_requirement(term)?);
+        }
+        Ok(Selector(requirements))
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let terms = self
+            .0
+            .iter()
+            .map(|r| match r {
+                Requirement::Exists(k) => k.clone(),
+                Requirement::NotExists(k) => format!("!{}", k),
+                Requirement::Equals(k, v) => format!("{}={}", k, v),
+                Requirement::NotEquals(k, v) => format!("{}!={}", k, v),
+                Requirement::In(k, vs) => format!("{} in ({})", k, vs.join(",")),
+                Requirement::NotIn(k, vs) => format!("{} notin ({})", k, vs.join(",")),
+            })
+            .collect::<Vec<_>>();
+        write!(f, "{}", terms.join(","))
+    }
+}
+
+/// 标签的键和值不能包含选择器的保留字符
+pub fn validate_token(token: &str) -> Result<(), NeoiotError> {
+    if token.is_empty() {
+        return Err(NeoiotError::InvalidArgument(
+            "label key/value can not be empty".to_string(),
+        ));
+    }
+    if token
+        .chars()
+        .any(|c| c.is_whitespace() || matches!(c, ',' | '=' | '!' | '(' | ')' | '/' | '+' | '#'))
+    {
+        return Err(NeoiotError::InvalidArgument(format!(
+            "label key/value `{}` contains reserved characters",
+            token
+        )));
+    }
+    Ok(())
+}
+
+/// 标签的文本形式, 如`site=sh01`, 同时也是s2l主题中的标签名
+pub fn format_label(name: &str, value: Option<&str>) -> String {
+    match value {
+        Some(value) => format!("{}={}", name, value),
+        None => name.to_string(),
+    }
+}
+
+fn invalid(selector: &str, reason: &str) -> NeoiotError {
+    NeoiotError::InvalidArgument(format!("invalid selector `{}`: {}", selector, reason))
+}
+
+/// 按照括号外的逗号切分条件
+fn split_terms(s: &str) -> Result<Vec<&str>, NeoiotError> {
+    let mut terms = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' if depth == 0 => depth += 1,
+            '(' => return Err(invalid(s, "nested parentheses")),
+            ')' if depth == 1 => depth -= 1,
+            ')' => return Err(invalid(s, "unbalanced parentheses")),
+            ',' if depth == 0 => {
+                terms.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err(invalid(s, "unbalanced parentheses"));
+    }
+    terms.push(&s[start..]);
+    Ok(terms)
+}
+
+fn parse_requirement(term: &str) -> Result<Requirement, NeoiotError> {
+    let token = |t: &str| -> Result<String, NeoiotError> {
+        let t = t.trim();
+        validate_token(t).map_err(|_| invalid(term, "invalid key or value"))?;
+        Ok(t.to_string())
+    };
+
+    if let Some(key) = term.strip_prefix('!') {
+        return Ok(Requirement::NotExists(token(key)?));
+    }
+    if let Some((key, value)) = term.split_once("!=") {
+        return Ok(Requirement::NotEquals(token(key)?, token(value)?));
+    }
+    if let Some((key, value)) = term.split_once("==").or_else(|| term.split_once('=')) {
+        return Ok(Requirement::Equals(token(key)?, token(value)?));
+    }
+    if let Some(open) = term.find('(') {
+        let mut head = term[..open].split_whitespace();
+        let (key, op) = match (head.next(), head.next(), head.next()) {
+            (Some(key), Some(op), None) => (key, op),
+            _ => return Err(invalid(term, "expect `key in (...)`")),
+        };
+        let values = term[open + 1..]
+            .trim_end()
+            .strip_suffix(')')
+            .ok_or_else(|| invalid(term, "expect `)` at the end"))?
+            .split(',')
+            .map(token)
+            .collect::<Result<Vec<_>, _>>()?;
+        return match op {
+            "in" => Ok(Requirement::In(token(key)?, values)),
+            "notin" => Ok(Requirement::NotIn(token(key)?, values)),
+            _ => Err(invalid(term, "unknown operator")),
+        };
+    }
+    Ok(Requirement::Exists(token(term)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_selector() {
+        let selector: Selector =
+            "site=sh01, floor in (2, 3),!decommissioned,env!=dev,gateway,zone notin (a)"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            selector.0,
+            vec![
+                Requirement::Equals("site".into(), "sh01".into()),
+                Requirement::In("floor".into(), vec!["2".into(), "3".into()]),
+                Requirement::NotExists("decommissioned".into()),
+                Requirement::NotEquals("env".into(), "dev".into()),
+                Requirement::Exists("gateway".into()),
+                Requirement::NotIn("zone".into(), vec!["a".into()]),
+            ]
+        );
+        assert_eq!(
+            selector.to_string(),
+            "site=sh01,floor in (2,3),!decommissioned,env!=dev,gateway,zone notin (a)"
+        );
+        assert!("".parse::<Selector>().unwrap().is_empty());
+
+        for bad in [
+            "site=",
+            "=sh01",
+            "site=sh01,",
+            "floor in (2,3",
+            "floor in 2,3)",
+            "floor between (2,3)",
+            "floor in ()",
+            "a b",
+            "!",
+        ] {
+            assert!(bad.parse::<Selector>().is_err(), "{}", bad);
+        }
+    }
+}
@@ -1,6 +1,8 @@
-use super::{default_page, default_page_size, ApiTags, AppState};
-use crate::oai_schema;
+use super::password::revoke_reset_token;
+use super::{default_page, default_page_size, ApiTags, AppState, ClientIp};
+use crate::{audit::AuditLog, oai_schema};
 use crate::{auth::JWTAuthorization, errors::NeoiotError, repository::Repository};
+use entity::audit_logs::{AuditAction, AuditResource};
 use poem::web::Data;
 use poem::Result;
 use poem_openapi::param::{Path, Query};
@@ -16,14 +18,25 @@ impl AccountService {
         &self,
         account: JWTAuthorization,
         state: Data<&AppState>,
+        ip: ClientIp,
         body: Json<oai_schema::CreateAccount>,
     ) -> Result<Json<oai_schema::Account>> {
         let account = state.repo.get_account(&account.0).await?;
         if !account.is_superuser {
             return Err(NeoiotError::PermissionDenied.into());
         }
-        let new_account = state.repo.create_account(&body).await?;
-        Ok(Json(new_account.into()))
+        let new_account: oai_schema::Account = state.repo.create_account(&body).await?.into();
+        let log = AuditLog::new(
+            &account.id,
+            &ip.0,
+            AuditAction::Create,
+            AuditResource::Account,
+            &new_account.id,
+        )
+        .account(&new_account.id)
+        .after(&new_account);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(new_account))
     }
 
     /// 查询账号列表
@@ -80,6 +93,7 @@ impl AccountService {
         &self,
         account: JWTAuthorization,
         state: Data<&AppState>,
+        ip: ClientIp,
         /// 要更新的账户ID
         account_id: Path<String>,
         body: Json<oai_schema::UpdateAccount>,
@@ -88,8 +102,24 @@ impl AccountService {
         if !account.is_superuser {
             return Err(NeoiotError::PermissionDenied.into());
         }
-        let account = state.repo.update_account(&account_id, &body).await?;
-        Ok(Json(account.into()))
+        let before: oai_schema::Account = state.repo.get_account(&account_id).await?.into();
+        let after: oai_schema::Account =
+            state.repo.update_account(&account_id, &body).await?.into();
+        if body.password.is_some() {
+            revoke_reset_token(&state.cache, &account_id).await?;
+        }
+        let log = AuditLog::new(
+            &account.id,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::Account,
+            &account_id,
+        )
+        .account(&account_id)
+        .before(&before)
+        .after(&after);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(after))
     }
 
     /// 删除账号
@@ -98,6 +128,7 @@ impl AccountService {
         &self,
         state: Data<&AppState>,
         account: JWTAuthorization,
+        ip: ClientIp,
         /// 要删除的账户ID
         account_id: Path<String>,
     ) -> Result<()> {
@@ -105,7 +136,77 @@ impl AccountService {
         if !account.is_superuser {
             return Err(NeoiotError::PermissionDenied.into());
         }
+        let before: oai_schema::Account = state.repo.get_account(&account_id).await?.into();
         state.repo.delete_account(&account_id).await?;
+        let log = AuditLog::new(
+            &account.id,
+            &ip.0,
+            AuditAction::Delete,
+            AuditResource::Account,
+            &account_id,
+        )
+        .account(&account_id)
+        .before(&before);
+        state.repo.create_audit_log(log).await?;
+        Ok(())
+    }
+
+    /// 查询账号登录记录
+    #[oai(path = "/:account_id/login_attempts", method = "get")]
+    async fn list_login_attempts(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        /// 要查询的账户ID
+        account_id: Path<String>,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::LoginAttempts>> {
+        let account = state.repo.get_account(&account.0).await?;
+        if !account.is_superuser {
+            return Err(NeoiotError::PermissionDenied.into());
+        }
+        let (attempts, total) = state
+            .repo
+            .list_login_attempts(&account_id, page.0, page_size.0)
+            .await?;
+        Ok(Json(oai_schema::LoginAttempts {
+            results: attempts.into_iter().map(Into::into).collect(),
+            total,
+        }))
+    }
+
+    /// 重置账号的两步验证
+    #[oai(path = "/:account_id/totp", method = "delete")]
+    async fn reset_account_totp(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        /// 要重置的账户ID
+        account_id: Path<String>,
+    ) -> Result<()> {
+        let account = state.repo.get_account(&account.0).await?;
+        if !account.is_superuser {
+            return Err(NeoiotError::PermissionDenied.into());
+        }
+        let before: oai_schema::Account = state.repo.get_account(&account_id).await?.into();
+        let after: oai_schema::Account = state.repo.disable_totp(&account_id).await?.into();
+        let log = AuditLog::new(
+            &account.id,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::Account,
+            &account_id,
+        )
+        .account(&account_id)
+        .before(&before)
+        .after(&after);
+        state.repo.create_audit_log(log).await?;
         Ok(())
     }
 }
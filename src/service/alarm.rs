@@ -0,0 +1,172 @@
+use super::{default_page, default_page_size, ApiTags, AppState, ClientIp};
+use crate::{audit::AuditLog, auth::JWTAuthorization, oai_schema, repository::Repository};
+use entity::alarms::{AlarmSeverity, AlarmStatus};
+use entity::audit_logs::{AuditAction, AuditResource};
+use poem::web::Data;
+use poem::Result;
+use poem_openapi::param::{Path, Query};
+use poem_openapi::{payload::Json, OpenApi};
+use serde_json::json;
+
+pub struct AlarmService;
+
+/// 设备告警
+///
+/// 告警由遥测规则、设备离线以及上报数据不符合数据模型触发, 同一设备同一类型的告警在清除前只保留一条;
+/// 未确认的告警超过配置的时长后自动升级一级
+#[OpenApi(prefix_path = "/alarm", tag = "ApiTags::Alarm")]
+impl AlarmService {
+    /// 查询告警列表
+    #[oai(path = "/", method = "get")]
+    #[allow(clippy::too_many_arguments)]
+    async fn list_alarms(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        /// 设备ID
+        device_id: Query<Option<String>>,
+        /// 标签ID, 筛选带有该标签的设备的告警
+        label_id: Query<Option<String>>,
+        /// 告警级别
+        severity: Query<Option<AlarmSeverity>>,
+        /// 告警状态
+        status: Query<Option<AlarmStatus>>,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::Alarms>> {
+        let (alarms, total) = state
+            .repo
+            .list_alarms(
+                &account.0,
+                device_id.0,
+                label_id.0,
+                severity.0,
+                status.0,
+                page.0,
+                page_size.0,
+            )
+            .await?;
+        Ok(Json(oai_schema::Alarms {
+            results: alarms.into_iter().map(Into::into).collect(),
+            total,
+        }))
+    }
+
+    /// 按级别统计未清除的告警数量
+    #[oai(path = "/count", method = "get")]
+    async fn count_alarms(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        /// 设备ID
+        device_id: Query<Option<String>>,
+        /// 标签ID, 统计带有该标签的设备的告警
+        label_id: Query<Option<String>>,
+    ) -> Result<Json<oai_schema::AlarmCounts>> {
+        let counts = state
+            .repo
+            .count_alarms(&account.0, device_id.0, label_id.0)
+            .await?;
+        Ok(Json(counts.into()))
+    }
+
+    /// 获取告警详情
+    #[oai(path = "/:alarm_id", method = "get")]
+    async fn get_alarm(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        alarm_id: Path<String>,
+    ) -> Result<Json<oai_schema::Alarm>> {
+        let alarm = state.repo.get_alarm(&account.0, &alarm_id).await?;
+        Ok(Json(alarm.into()))
+    }
+
+    /// 确认告警
+    #[oai(path = "/:alarm_id/acknowledge", method = "post")]
+    async fn acknowledge_alarm(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        alarm_id: Path<String>,
+        body: Json<oai_schema::AcknowledgeAlarm>,
+    ) -> Result<Json<oai_schema::Alarm>> {
+        let alarm: oai_schema::Alarm = state
+            .repo
+            .acknowledge_alarm(&account.0, &alarm_id, &account.0, body.0.note)
+            .await?
+            .into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::Alarm,
+            &alarm_id,
+        )
+        .detail(json!({
+            "operation": "acknowledge",
+            "note": alarm.ack_note,
+        }));
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(alarm))
+    }
+
+    /// 清除告警
+    #[oai(path = "/:alarm_id/clear", method = "post")]
+    async fn clear_alarm(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        alarm_id: Path<String>,
+    ) -> Result<Json<oai_schema::Alarm>> {
+        let alarm: oai_schema::Alarm = state
+            .repo
+            .clear_alarm(&account.0, &alarm_id, &account.0)
+            .await?
+            .into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::Alarm,
+            &alarm_id,
+        )
+        .detail(json!({ "operation": "clear" }));
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(alarm))
+    }
+
+    /// 告警升级一级
+    #[oai(path = "/:alarm_id/escalate", method = "post")]
+    async fn escalate_alarm(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        alarm_id: Path<String>,
+    ) -> Result<Json<oai_schema::Alarm>> {
+        let before: oai_schema::Alarm = state.repo.get_alarm(&account.0, &alarm_id).await?.into();
+        let after: oai_schema::Alarm = state
+            .repo
+            .escalate_alarm(&account.0, &alarm_id)
+            .await?
+            .into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::Alarm,
+            &alarm_id,
+        )
+        .before(&before)
+        .after(&after);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(after))
+    }
+}
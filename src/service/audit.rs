@@ -0,0 +1,75 @@
+use super::{default_page, default_page_size, ApiTags, AppState};
+use crate::audit::AuditLogQuery;
+use crate::oai_schema;
+use crate::{auth::JWTAuthorization, errors::NeoiotError, repository::Repository};
+use chrono::{DateTime, Local};
+use entity::audit_logs::{AuditAction, AuditResource};
+use poem::web::Data;
+use poem::Result;
+use poem_openapi::param::Query;
+use poem_openapi::{payload::Json, OpenApi};
+
+pub struct AuditService;
+
+#[OpenApi(prefix_path = "/audit", tag = "ApiTags::Audit")]
+impl AuditService {
+    /// 查询审计日志
+    ///
+    /// 超级用户可以查询所有账号的记录，普通用户只能查询自己账号的记录
+    #[oai(path = "/", method = "get")]
+    #[allow(clippy::too_many_arguments)]
+    async fn list_audit_logs(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        /// 资源所属账号(仅超级用户可用)
+        account_id: Query<Option<String>>,
+        /// 操作人
+        actor_id: Query<Option<String>>,
+        /// 操作类型
+        action: Query<Option<AuditAction>>,
+        /// 资源类型
+        resource_type: Query<Option<AuditResource>>,
+        /// 资源ID
+        resource_id: Query<Option<String>>,
+        /// 起始时间(包含)
+        created_after: Query<Option<DateTime<Local>>>,
+        /// 截止时间(不包含)
+        created_before: Query<Option<DateTime<Local>>>,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::AuditLogs>> {
+        let account = state.repo.get_account(&account.0).await?;
+        let account_id = if account.is_superuser {
+            account_id.0
+        } else {
+            match account_id.0 {
+                Some(account_id) if account_id != account.id => {
+                    return Err(NeoiotError::PermissionDenied.into())
+                }
+                _ => Some(account.id),
+            }
+        };
+        let query = AuditLogQuery {
+            account_id,
+            actor_id: actor_id.0,
+            action: action.0,
+            resource_type: resource_type.0,
+            resource_id: resource_id.0,
+            created_after: created_after.0,
+            created_before: created_before.0,
+        };
+        let (logs, total) = state
+            .repo
+            .list_audit_logs(&query, page.0, page_size.0)
+            .await?;
+        Ok(Json(oai_schema::AuditLogs {
+            results: logs.into_iter().map(Into::into).collect(),
+            total,
+        }))
+    }
+}
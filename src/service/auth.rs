@@ -1,41 +1,477 @@
-use super::{ApiTags, AppState};
-use crate::{config::SETTINGS, oai_schema};
+use std::time::SystemTime;
+
+use super::password::revoke_reset_token;
+use super::{ApiTags, AppState, ClientIp};
+use crate::cache::Cache;
+use crate::config::{LoginConfig, SETTINGS};
+use crate::{auth, oai_schema, totp};
 use crate::{errors::NeoiotError, repository::Repository};
 use argon2::{Argon2, PasswordHash, PasswordVerifier};
-use jwt_simple::prelude::*;
+use entity::prelude::AccountModel;
 use poem::web::Data;
 use poem::Result;
 use poem_openapi::{payload::Json, OpenApi};
+use rand::{distributions::Alphanumeric, Rng};
 
 pub struct AuthService;
 
+/// 登录验证步骤的有效期(秒)
+const CHALLENGE_TTL: usize = 300;
+/// 单个验证步骤允许的最大尝试次数
+const CHALLENGE_MAX_ATTEMPTS: i64 = 5;
+
 #[OpenApi(prefix_path = "/auth/token", tag = "ApiTags::Auth")]
 impl AuthService {
     /// 获取Token
+    ///
+    /// 账号被要求修改密码或启用了两步验证时返回202和`challenge_token`，
+    /// 需要继续调用`/auth/token/password`或`/auth/token/totp`完成登录
     #[oai(path = "/obtain", method = "post")]
     async fn obtain_token(
         &self,
         state: Data<&AppState>,
+        ip: ClientIp,
         data: Json<oai_schema::Login>,
-    ) -> Result<Json<oai_schema::TokenResponse>> {
-        let account = state
-            .repo
-            .get_account_by_email(&data.email)
-            .await
-            .map_err(|_| NeoiotError::AuthenticateError)?;
-        if !verify_password(&data.password, &account.password) {
+    ) -> Result<oai_schema::ObtainTokenResponse> {
+        let config = &SETTINGS.login;
+        // 限流、锁定、查找账号和登录记录都使用同一个规范化后的邮箱
+        let email = data.email.to_lowercase();
+        if let Err(err) = check_login_limit(&state.cache, config, &email, &ip.0).await {
+            let reason = match err {
+                NeoiotError::AccountLocked(_) => "locked",
+                _ => "rate_limited",
+            };
+            state
+                .repo
+                .create_login_attempt(&email, &ip.0, None, false, Some(reason))
+                .await?;
+            return Err(err.into());
+        }
+        let account = match state.repo.get_account_by_email(&email).await {
+            Ok(account) if verify_password(&data.password, &account.password) => account,
+            Ok(account) => {
+                record_login_failure(&state.cache, config, &email).await?;
+                state
+                    .repo
+                    .create_login_attempt(
+                        &email,
+                        &ip.0,
+                        Some(&account.id),
+                        false,
+                        Some("invalid_password"),
+                    )
+                    .await?;
+                return Err(NeoiotError::AuthenticateError.into());
+            }
+            Err(_) => {
+                record_login_failure(&state.cache, config, &email).await?;
+                state
+                    .repo
+                    .create_login_attempt(&email, &ip.0, None, false, Some("unknown_account"))
+                    .await?;
+                return Err(NeoiotError::AuthenticateError.into());
+            }
+        };
+        state.cache.del(&failures_key(&email)).await?;
+        Ok(next_login_step(&state, &account, &ip.0, false).await?)
+    }
+
+    /// 登录时修改密码
+    ///
+    /// 用于被要求修改密码的账号，已启用两步验证的账号须先通过`/auth/token/totp`，
+    /// 修改成功后继续下一步验证或直接返回Token
+    #[oai(path = "/password", method = "post")]
+    async fn change_password(
+        &self,
+        state: Data<&AppState>,
+        ip: ClientIp,
+        data: Json<oai_schema::PasswordChangeLogin>,
+    ) -> Result<oai_schema::ObtainTokenResponse> {
+        let key = challenge_key(PASSWORD_CHALLENGE, &data.challenge_token);
+        let account = get_challenge_account(&state, &key).await?;
+        if verify_password(&data.new_password, &account.password) {
+            return Err(NeoiotError::InvalidArgument(
+                "new password must differ from the current one".into(),
+            )
+            .into());
+        }
+        let req = oai_schema::UpdateAccount {
+            email: None,
+            name: None,
+            password: Some(data.new_password.clone()),
+            must_change_password: Some(false),
+        };
+        let account = state.repo.update_account(&account.id, &req).await?;
+        state.cache.del(&key).await?;
+        revoke_reset_token(&state.cache, &account.id).await?;
+        // 修改密码的验证步骤只在两步验证通过后签发
+        Ok(next_login_step(&state, &account, &ip.0, true).await?)
+    }
+
+    /// 登录时绑定TOTP
+    ///
+    /// 仅用于被要求启用两步验证但尚未绑定的账号，绑定后调用`/auth/token/totp`完成登录
+    #[oai(path = "/totp/enroll", method = "post")]
+    async fn enroll_totp(
+        &self,
+        state: Data<&AppState>,
+        data: Json<oai_schema::ChallengeRequest>,
+    ) -> Result<Json<oai_schema::TotpEnrollment>> {
+        let key = challenge_key(TOTP_CHALLENGE, &data.challenge_token);
+        let account = get_challenge_account(&state, &key).await?;
+        if account.totp_enabled {
+            return Err(NeoiotError::InvalidArgument("totp already enabled".into()).into());
+        }
+        let secret = totp::generate_secret();
+        let account = state.repo.set_totp_secret(&account.id, &secret).await?;
+        Ok(Json(oai_schema::TotpEnrollment {
+            provisioning_uri: totp::provisioning_uri(&secret, &account.email),
+            secret,
+        }))
+    }
+
+    /// 提交两步验证码完成登录
+    ///
+    /// 账号还被要求修改密码时返回202和`challenge_token`，需要继续调用`/auth/token/password`
+    #[oai(path = "/totp", method = "post")]
+    async fn verify_totp(
+        &self,
+        state: Data<&AppState>,
+        ip: ClientIp,
+        data: Json<oai_schema::TotpLogin>,
+    ) -> Result<oai_schema::ObtainTokenResponse> {
+        let key = challenge_key(TOTP_CHALLENGE, &data.challenge_token);
+        let account = get_challenge_account(&state, &key).await?;
+        let attempts = state
+            .cache
+            .incr(&format!("{}:attempts", key), CHALLENGE_TTL)
+            .await?;
+        if attempts > CHALLENGE_MAX_ATTEMPTS {
+            state.cache.del(&key).await?;
             return Err(NeoiotError::AuthenticateError.into());
         }
-        let claims = Claims::create(Duration::from_days(1)).with_subject(account.id);
-        let key = HS256Key::from_bytes(SETTINGS.core.secret.as_bytes());
-        let token = key.authenticate(claims)?;
-        state.repo.after_account_logined(&data.email).await?;
-        Ok(Json(oai_schema::TokenResponse { token }))
+        let mut recovery_codes = None;
+        let verified = if account.totp_enabled {
+            verify_second_factor(&state, &account, &data.code).await?
+        } else {
+            // 首次绑定，只接受动态码
+            let verified = match &account.totp_secret {
+                Some(secret) => {
+                    verify_totp_code(&state.cache, &account.id, secret, &data.code).await?
+                }
+                None => false,
+            };
+            if verified {
+                let (codes, hashes) = new_recovery_codes();
+                state.repo.enable_totp(&account.id, &hashes).await?;
+                recovery_codes = Some(codes);
+            }
+            verified
+        };
+        if !verified {
+            state
+                .repo
+                .create_login_attempt(
+                    &account.email,
+                    &ip.0,
+                    Some(&account.id),
+                    false,
+                    Some("invalid_totp"),
+                )
+                .await?;
+            return Err(NeoiotError::AuthenticateError.into());
+        }
+        state.cache.del(&key).await?;
+        if account.must_change_password {
+            return Ok(next_login_step(&state, &account, &ip.0, true).await?);
+        }
+        let token = complete_login(&state, &account, &ip.0).await?;
+        Ok(oai_schema::ObtainTokenResponse::Token(Json(
+            oai_schema::TokenResponse {
+                token,
+                recovery_codes,
+            },
+        )))
     }
 }
 
-fn verify_password(password: &str, hash: &str) -> bool {
-    let argon2 = Argon2::default();
-    let hashed = PasswordHash::new(hash).unwrap();
-    argon2.verify_password(password.as_bytes(), &hashed).is_ok()
+/// 每次生成的恢复码数量
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// 生成一组恢复码，返回(明文, 摘要)
+pub(super) fn new_recovery_codes() -> (Vec<String>, Vec<String>) {
+    let codes = totp::generate_recovery_codes(RECOVERY_CODE_COUNT);
+    let hashes = codes.iter().map(|c| totp::hash_recovery_code(c)).collect();
+    (codes, hashes)
+}
+
+const TOTP_CHALLENGE: &str = "totp";
+const PASSWORD_CHALLENGE: &str = "password";
+
+fn challenge_key(kind: &str, token: &str) -> String {
+    format!("login:challenge:{}:{}", kind, token)
+}
+
+// 密码校验通过后，依次要求两步验证、修改密码、绑定TOTP，全部满足后签发Token
+async fn next_login_step(
+    state: &AppState,
+    account: &AccountModel,
+    ip: &str,
+    totp_passed: bool,
+) -> std::result::Result<oai_schema::ObtainTokenResponse, NeoiotError> {
+    let policy = state.repo.get_security_policy().await?;
+    let challenge = next_challenge(account, totp_passed, policy.require_superuser_totp);
+    if let Some((kind, challenge)) = challenge {
+        let challenge_token: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .map(char::from)
+            .collect();
+        state
+            .cache
+            .set_ex(
+                &challenge_key(kind, &challenge_token),
+                &account.id,
+                CHALLENGE_TTL,
+            )
+            .await?;
+        return Ok(oai_schema::ObtainTokenResponse::Challenge(Json(
+            oai_schema::LoginChallenge {
+                challenge_token,
+                challenge,
+            },
+        )));
+    }
+    let token = complete_login(state, account, ip).await?;
+    Ok(oai_schema::ObtainTokenResponse::Token(Json(
+        oai_schema::TokenResponse {
+            token,
+            recovery_codes: None,
+        },
+    )))
+}
+
+// 已启用两步验证的账号必须先通过动态码校验才能修改密码，否则只知道密码的人可以抢先改掉密码
+fn next_challenge(
+    account: &AccountModel,
+    totp_passed: bool,
+    require_superuser_totp: bool,
+) -> Option<(&'static str, oai_schema::ChallengeKind)> {
+    if account.totp_enabled && !totp_passed {
+        Some((TOTP_CHALLENGE, oai_schema::ChallengeKind::Totp))
+    } else if account.must_change_password {
+        Some((
+            PASSWORD_CHALLENGE,
+            oai_schema::ChallengeKind::PasswordChange,
+        ))
+    } else if !account.totp_enabled && account.is_superuser && require_superuser_totp {
+        Some((TOTP_CHALLENGE, oai_schema::ChallengeKind::TotpEnrollment))
+    } else {
+        None
+    }
+}
+
+async fn get_challenge_account(
+    state: &AppState,
+    key: &str,
+) -> std::result::Result<AccountModel, NeoiotError> {
+    let account_id = state
+        .cache
+        .get(key)
+        .await?
+        .ok_or(NeoiotError::AuthenticateError)?;
+    state.repo.get_account(&account_id).await
+}
+
+// 签发Token并记录登录成功
+async fn complete_login(
+    state: &AppState,
+    account: &AccountModel,
+    ip: &str,
+) -> std::result::Result<String, NeoiotError> {
+    let token = auth::issue_token(account)?;
+    state.repo.after_account_logined(&account.email).await?;
+    state
+        .repo
+        .create_login_attempt(&account.email, ip, Some(&account.id), true, None)
+        .await?;
+    Ok(token)
+}
+
+/// 校验TOTP动态码，同一时间步的动态码只能使用一次
+pub(super) async fn verify_totp_code<C: Cache>(
+    cache: &C,
+    account_id: &str,
+    secret: &str,
+    code: &str,
+) -> std::result::Result<bool, NeoiotError> {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let step = match totp::verify(secret, code, now) {
+        Some(step) => step,
+        None => return Ok(false),
+    };
+    let key = format!("totp:used:{}:{}", account_id, step);
+    if cache.incr(&key, 120).await? > 1 {
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// 校验已启用两步验证账号的动态码或恢复码，恢复码使用后作废
+pub(super) async fn verify_second_factor(
+    state: &AppState,
+    account: &AccountModel,
+    code: &str,
+) -> std::result::Result<bool, NeoiotError> {
+    if let Some(secret) = &account.totp_secret {
+        if verify_totp_code(&state.cache, &account.id, secret, code).await? {
+            return Ok(true);
+        }
+    }
+    let hash = totp::hash_recovery_code(code);
+    state.repo.consume_recovery_code(&account.id, &hash).await
+}
+
+pub(super) fn lock_key(email: &str) -> String {
+    format!("login:lock:{}", email)
+}
+
+fn failures_key(email: &str) -> String {
+    format!("login:failures:{}", email)
+}
+
+// 检查账号是否被锁定以及邮箱/IP维度的请求频率
+async fn check_login_limit<C: Cache>(
+    cache: &C,
+    config: &LoginConfig,
+    email: &str,
+    ip: &str,
+) -> std::result::Result<(), NeoiotError> {
+    if let Some(ttl) = cache.ttl(&lock_key(email)).await? {
+        return Err(NeoiotError::AccountLocked(ttl));
+    }
+    let limits = [
+        (
+            format!("login:attempts:email:{}", email),
+            config.max_attempts_per_email,
+        ),
+        (
+            format!("login:attempts:ip:{}", ip),
+            config.max_attempts_per_ip,
+        ),
+    ];
+    for (key, limit) in limits {
+        if cache.incr(&key, config.window_secs).await? > limit {
+            let ttl = cache.ttl(&key).await?.unwrap_or(config.window_secs as i64);
+            return Err(NeoiotError::TooManyRequests(ttl));
+        }
+    }
+    Ok(())
+}
+
+// 累计失败次数，达到阈值后锁定账号
+async fn record_login_failure<C: Cache>(
+    cache: &C,
+    config: &LoginConfig,
+    email: &str,
+) -> std::result::Result<(), NeoiotError> {
+    let failures = cache
+        .incr(&failures_key(email), config.lockout_secs)
+        .await?;
+    if failures >= config.max_failures {
+        cache
+            .set_ex(&lock_key(email), "1", config.lockout_secs)
+            .await?;
+        cache.del(&failures_key(email)).await?;
+    }
+    Ok(())
+}
+
+/// 校验已登录账号的当前密码, 与登录共用失败计数和锁定, 防止持有Token的人暴力猜测密码
+pub(super) async fn verify_current_password(
+    state: &AppState,
+    account: &AccountModel,
+    password: &str,
+) -> std::result::Result<(), NeoiotError> {
+    let config = &SETTINGS.login;
+    let email = account.email.to_lowercase();
+    if let Some(ttl) = state.cache.ttl(&lock_key(&email)).await? {
+        return Err(NeoiotError::AccountLocked(ttl));
+    }
+    if !verify_password(password, &account.password) {
+        record_login_failure(&state.cache, config, &email).await?;
+        return Err(NeoiotError::AuthenticateError);
+    }
+    state.cache.del(&failures_key(&email)).await?;
+    Ok(())
+}
+
+pub(super) fn verify_password(password: &str, hash: &str) -> bool {
+    let hashed = match PasswordHash::new(hash) {
+        Ok(hashed) => hashed,
+        Err(err) => {
+            tracing::error!("malformed password hash: {}", err);
+            return false;
+        }
+    };
+    Argon2::default()
+        .verify_password(password.as_bytes(), &hashed)
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_password() {
+        assert!(!verify_password("123123", "not-a-phc-string"));
+        assert!(!verify_password("123123", ""));
+    }
+
+    #[test]
+    fn test_next_challenge() {
+        let kind = |account: &AccountModel, totp_passed: bool| {
+            next_challenge(account, totp_passed, true).map(|(_, kind)| kind)
+        };
+        let mut account = AccountModel {
+            id: "acc".to_string(),
+            email: "a@example.com".to_string(),
+            password: String::new(),
+            name: "a".to_string(),
+            is_superuser: false,
+            last_login_at: None,
+            created_at: chrono::Local::now().into(),
+            updated_at: None,
+            totp_secret: Some("secret".to_string()),
+            totp_enabled: true,
+            recovery_codes: serde_json::json!([]),
+            must_change_password: true,
+            password_changed_at: None,
+        };
+        // 两步验证先于修改密码
+        assert_eq!(kind(&account, false), Some(oai_schema::ChallengeKind::Totp));
+        assert_eq!(
+            kind(&account, true),
+            Some(oai_schema::ChallengeKind::PasswordChange)
+        );
+        account.must_change_password = false;
+        assert_eq!(kind(&account, true), None);
+
+        account.totp_enabled = false;
+        account.must_change_password = true;
+        assert_eq!(
+            kind(&account, false),
+            Some(oai_schema::ChallengeKind::PasswordChange)
+        );
+        account.must_change_password = false;
+        account.is_superuser = true;
+        assert_eq!(
+            kind(&account, true),
+            Some(oai_schema::ChallengeKind::TotpEnrollment)
+        );
+    }
 }
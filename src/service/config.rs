@@ -0,0 +1,353 @@
+use super::{default_page, default_page_size, ApiTags, AppState, ClientIp};
+use crate::{
+    audit::AuditLog,
+    auth::{DeviceAuthorization, JWTAuthorization},
+    errors::NeoiotError,
+    oai_schema,
+    repository::Repository,
+};
+use entity::audit_logs::{AuditAction, AuditResource};
+use poem::web::Data;
+use poem::Result;
+use poem_openapi::param::{Path, Query};
+use poem_openapi::payload::Json;
+use poem_openapi::OpenApi;
+
+pub struct ConfigService;
+
+/// 远程配置
+///
+/// 配置模板包含JSON Schema和带版本的配置文档, 模板可以分配到标签或单个设备;
+/// 设备的生效配置先合并标签级再合并设备级分配的最新文档, 生效配置变化时平台向
+/// `cfg/{account_id}/{device_id}/push`推送`{"version", "label_version", "config"}`,
+/// 设备也可以在标签版本号变化后经HTTP `GET /config/current`重新拉取;
+/// 设备应用后向`cfgr/{account_id}/{device_id}/applied`发布`{"version", "error"}`
+#[OpenApi(prefix_path = "/config", tag = "ApiTags::Config")]
+impl ConfigService {
+    /// 创建配置模板
+    #[oai(path = "/profile", method = "post")]
+    async fn create_profile(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        body: Json<oai_schema::CreateConfigProfile>,
+    ) -> Result<Json<oai_schema::ConfigProfile>> {
+        let profile: oai_schema::ConfigProfile = state
+            .repo
+            .create_config_profile(&account.0, &account.0, &body)
+            .await?
+            .into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Create,
+            AuditResource::ConfigProfile,
+            &profile.id,
+        )
+        .after(&profile);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(profile))
+    }
+
+    /// 查询配置模板列表
+    #[oai(path = "/profile", method = "get")]
+    async fn list_profiles(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::ConfigProfiles>> {
+        let (profiles, total) = state
+            .repo
+            .list_config_profiles(&account.0, page.0, page_size.0)
+            .await?;
+        Ok(Json(oai_schema::ConfigProfiles {
+            results: profiles.into_iter().map(Into::into).collect(),
+            total,
+        }))
+    }
+
+    /// 获取配置模板及最新版本的文档
+    #[oai(path = "/profile/:profile_id", method = "get")]
+    async fn get_profile(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        profile_id: Path<String>,
+    ) -> Result<Json<oai_schema::ConfigProfile>> {
+        let profile = state
+            .repo
+            .get_config_profile(&account.0, &profile_id)
+            .await?;
+        Ok(Json(profile.into()))
+    }
+
+    /// 更新配置模板的名称、描述或Schema
+    #[oai(path = "/profile/:profile_id", method = "patch")]
+    async fn update_profile(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        profile_id: Path<String>,
+        body: Json<oai_schema::UpdateConfigProfile>,
+    ) -> Result<Json<oai_schema::ConfigProfile>> {
+        let before: oai_schema::ConfigProfile = state
+            .repo
+            .get_config_profile(&account.0, &profile_id)
+            .await?
+            .into();
+        let after: oai_schema::ConfigProfile = state
+            .repo
+            .update_config_profile(&account.0, &profile_id, &body)
+            .await?
+            .into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::ConfigProfile,
+            &profile_id,
+        )
+        .before(&before)
+        .after(&after);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(after))
+    }
+
+    /// 保存新版本的配置文档, 分配了该模板的设备会收到新的生效配置
+    #[oai(path = "/profile/:profile_id/document", method = "put")]
+    async fn update_document(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        profile_id: Path<String>,
+        body: Json<oai_schema::UpdateConfigDocument>,
+    ) -> Result<Json<oai_schema::ConfigProfile>> {
+        let before: oai_schema::ConfigProfile = state
+            .repo
+            .get_config_profile(&account.0, &profile_id)
+            .await?
+            .into();
+        let after: oai_schema::ConfigProfile = state
+            .repo
+            .create_config_profile_version(&account.0, &account.0, &profile_id, &body.document)
+            .await?
+            .into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::ConfigProfile,
+            &profile_id,
+        )
+        .before(&before)
+        .after(&after);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(after))
+    }
+
+    /// 删除配置模板及其分配
+    #[oai(path = "/profile/:profile_id", method = "delete")]
+    async fn delete_profile(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        profile_id: Path<String>,
+    ) -> Result<()> {
+        let before: oai_schema::ConfigProfile = state
+            .repo
+            .get_config_profile(&account.0, &profile_id)
+            .await?
+            .into();
+        state
+            .repo
+            .delete_config_profile(&account.0, &profile_id)
+            .await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Delete,
+            AuditResource::ConfigProfile,
+            &profile_id,
+        )
+        .before(&before);
+        state.repo.create_audit_log(log).await?;
+        Ok(())
+    }
+
+    /// 查询配置模板的历史版本
+    #[oai(path = "/profile/:profile_id/versions", method = "get")]
+    async fn list_versions(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        profile_id: Path<String>,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::ConfigProfileVersions>> {
+        let (versions, total) = state
+            .repo
+            .list_config_profile_versions(&account.0, &profile_id, page.0, page_size.0)
+            .await?;
+        Ok(Json(oai_schema::ConfigProfileVersions {
+            results: versions.into_iter().map(Into::into).collect(),
+            total,
+        }))
+    }
+
+    /// 获取配置模板的指定版本
+    #[oai(path = "/profile/:profile_id/versions/:version", method = "get")]
+    async fn get_version(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        profile_id: Path<String>,
+        version: Path<i32>,
+    ) -> Result<Json<oai_schema::ConfigProfileVersion>> {
+        let version = state
+            .repo
+            .get_config_profile_version(&account.0, &profile_id, version.0)
+            .await?;
+        Ok(Json(version.into()))
+    }
+
+    /// 将配置模板分配到标签或设备
+    #[oai(path = "/assignment", method = "post")]
+    async fn create_assignment(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        body: Json<oai_schema::CreateConfigAssignment>,
+    ) -> Result<Json<oai_schema::ConfigAssignment>> {
+        let assignment: oai_schema::ConfigAssignment = state
+            .repo
+            .create_config_assignment(&account.0, &body)
+            .await?
+            .into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Create,
+            AuditResource::ConfigAssignment,
+            &assignment.id,
+        )
+        .after(&assignment);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(assignment))
+    }
+
+    /// 查询配置分配列表
+    #[oai(path = "/assignment", method = "get")]
+    #[allow(clippy::too_many_arguments)]
+    async fn list_assignments(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        /// 模板ID
+        profile_id: Query<Option<String>>,
+        /// 标签ID
+        label_id: Query<Option<String>>,
+        /// 设备ID
+        device_id: Query<Option<String>>,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::ConfigAssignments>> {
+        let (assignments, total) = state
+            .repo
+            .list_config_assignments(
+                &account.0,
+                profile_id.0,
+                label_id.0,
+                device_id.0,
+                page.0,
+                page_size.0,
+            )
+            .await?;
+        Ok(Json(oai_schema::ConfigAssignments {
+            results: assignments.into_iter().map(Into::into).collect(),
+            total,
+        }))
+    }
+
+    /// 取消配置分配
+    #[oai(path = "/assignment/:assignment_id", method = "delete")]
+    async fn delete_assignment(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        assignment_id: Path<String>,
+    ) -> Result<()> {
+        let assignment = state
+            .repo
+            .delete_config_assignment(&account.0, &assignment_id)
+            .await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Delete,
+            AuditResource::ConfigAssignment,
+            &assignment_id,
+        )
+        .before(&oai_schema::ConfigAssignment::from(assignment));
+        state.repo.create_audit_log(log).await?;
+        Ok(())
+    }
+
+    /// 获取设备的生效配置及推送、应用状态
+    #[oai(path = "/device/:device_id", method = "get")]
+    async fn get_device_config(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        device_id: Path<String>,
+    ) -> Result<Json<oai_schema::DeviceConfig>> {
+        let device = state.repo.get_device(&account.0, &device_id).await?;
+        let config = state.repo.get_device_config(&device.id).await?;
+        Ok(Json(oai_schema::DeviceConfig::new(&device, config)))
+    }
+
+    /// 设备拉取生效配置, 使用设备凭据认证, 网关可以代子设备拉取
+    #[oai(path = "/current", method = "get")]
+    async fn current_config(
+        &self,
+        state: Data<&AppState>,
+        device: DeviceAuthorization,
+        /// 子设备ID, 默认为认证的设备本身
+        device_id: Query<Option<String>>,
+    ) -> Result<Json<oai_schema::DeviceConfig>> {
+        let target = match device_id.0 {
+            Some(device_id) if device_id != device.0.id => {
+                let target = state
+                    .repo
+                    .get_device(&device.0.account_id, &device_id)
+                    .await?;
+                if target.parent_id.as_deref() != Some(device.0.id.as_str()) {
+                    return Err(NeoiotError::PermissionDenied.into());
+                }
+                target
+            }
+            _ => device.0,
+        };
+        let config = state.repo.get_device_config(&target.id).await?;
+        Ok(Json(oai_schema::DeviceConfig::new(&target, config)))
+    }
+}
@@ -0,0 +1,113 @@
+use super::{default_page, default_page_size, ApiTags, AppState, ClientIp};
+use crate::{audit::AuditLog, auth::JWTAuthorization, oai_schema, repository::Repository};
+use entity::audit_logs::{AuditAction, AuditResource};
+use poem::web::Data;
+use poem::Result;
+use poem_openapi::param::{Path, Query};
+use poem_openapi::{payload::Json, OpenApi};
+
+pub struct D2dService;
+
+/// 设备间通信白名单
+///
+/// 设备A向设备B发送消息使用主题`d2d/{account_id}/{B}/{A}/{message_id}`,
+/// 向带有标签的设备广播使用主题`d2l/{account_id}/{label}/{A}/{message_id}`,
+/// 其中`label`为`name=value`或`name`
+#[OpenApi(prefix_path = "/d2d", tag = "ApiTags::D2d")]
+impl D2dService {
+    /// 创建白名单
+    #[oai(path = "/rule", method = "post")]
+    async fn create_rule(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        body: Json<oai_schema::CreateD2dRule>,
+    ) -> Result<Json<oai_schema::D2dRule>> {
+        let rule: oai_schema::D2dRule = state.repo.create_d2d_rule(&account.0, &body).await?.into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Create,
+            AuditResource::D2dRule,
+            &rule.id,
+        )
+        .after(&rule);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(rule))
+    }
+
+    /// 查询白名单列表
+    #[oai(path = "/rule", method = "get")]
+    async fn list_rules(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        /// 发送方设备ID
+        sender_id: Query<Option<String>>,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::D2dRules>> {
+        let (rules, total) = state
+            .repo
+            .list_d2d_rules(&account.0, sender_id.0, page.0, page_size.0)
+            .await?;
+        Ok(Json(oai_schema::D2dRules {
+            results: rules.into_iter().map(Into::into).collect(),
+            total,
+        }))
+    }
+
+    /// 删除白名单
+    #[oai(path = "/rule/:rule_id", method = "delete")]
+    async fn delete_rule(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        rule_id: Path<String>,
+    ) -> Result<()> {
+        let before: oai_schema::D2dRule =
+            state.repo.get_d2d_rule(&account.0, &rule_id).await?.into();
+        state.repo.delete_d2d_rule(&account.0, &rule_id).await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Delete,
+            AuditResource::D2dRule,
+            &rule_id,
+        )
+        .before(&before);
+        state.repo.create_audit_log(log).await?;
+        Ok(())
+    }
+
+    /// 查询设备间消息记录
+    #[oai(path = "/message", method = "get")]
+    async fn list_messages(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        /// 发送方或目标设备ID
+        device_id: Query<Option<String>>,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::D2dMessages>> {
+        let (messages, total) = state
+            .repo
+            .list_d2d_messages(&account.0, device_id.0, page.0, page_size.0)
+            .await?;
+        Ok(Json(oai_schema::D2dMessages {
+            results: messages.into_iter().map(Into::into).collect(),
+            total,
+        }))
+    }
+}
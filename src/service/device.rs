@@ -1,10 +1,20 @@
-use super::{default_page, default_page_size, ApiTags, AppState};
-use crate::{auth::JWTAuthorization, repository::Repository};
-use crate::{cache::Cache, oai_schema};
+use super::{default_page, default_page_size, ApiTags, AppState, ClientIp};
+use crate::events::{self, Event};
+use crate::{audit::AuditLog, auth::JWTAuthorization, lifecycle, repository::Repository};
+use crate::{cache::Cache, errors::NeoiotError, mqtt_client, oai_schema, selector::Selector};
+use entity::audit_logs::{AuditAction, AuditResource};
+use entity::devices::LifecycleState;
+use entity::webhook_deliveries::EventType;
+use poem::error::InternalServerError;
 use poem::web::Data;
 use poem::Result;
 use poem_openapi::param::{Path, Query};
-use poem_openapi::{payload::Json, OpenApi};
+use poem_openapi::payload::{Attachment, Json};
+use poem_openapi::OpenApi;
+use serde_json::json;
+
+/// 单次批量创建的最大设备数
+const MAX_BULK_DEVICES: usize = 5000;
 
 pub struct DeviceService;
 
@@ -16,10 +26,124 @@ impl DeviceService {
         &self,
         state: Data<&AppState>,
         account: JWTAuthorization,
+        ip: ClientIp,
         body: Json<oai_schema::CreateDevice>,
     ) -> Result<Json<oai_schema::DeviceWithLables>> {
-        let device = state.repo.create_device(&account.0, &body).await?;
-        Ok(Json(device.into()))
+        let (device, issued) = state.repo.create_device(&account.0, &body).await?;
+        let username = device.device.mqtt_username.clone();
+        let mut device: oai_schema::DeviceWithLables = device.into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Create,
+            AuditResource::Device,
+            &device.id,
+        )
+        .after(&device);
+        state.repo.create_audit_log(log).await?;
+        events::emit(
+            &state.repo,
+            Event::new(&account.0, EventType::DeviceCreated)
+                .device(&device.id)
+                .object(&device),
+        )
+        .await;
+        device.credential = Some(oai_schema::DeviceCredentialSecret::new(&username, issued));
+        Ok(Json(device))
+    }
+
+    /// 批量创建设备
+    ///
+    /// 所有数据校验通过后在一个事务中创建, 任意一行校验失败则不创建任何设备
+    #[oai(path = "/bulk", method = "post")]
+    async fn bulk_create_devices(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        /// 只校验数据, 不创建设备
+        dry_run: Query<Option<bool>>,
+        body: oai_schema::BulkCreateDevices,
+    ) -> Result<oai_schema::BulkCreateDevicesResponse> {
+        let (rows, mut errors) = match body {
+            oai_schema::BulkCreateDevices::Json(rows) => {
+                (rows.0.into_iter().enumerate().collect(), vec![])
+            }
+            oai_schema::BulkCreateDevices::Csv(text) => parse_csv(&text),
+        };
+        let total = rows.len() + errors.len();
+        if total > MAX_BULK_DEVICES {
+            return Err(NeoiotError::InvalidArgument(format!(
+                "at most {} devices can be created at once",
+                MAX_BULK_DEVICES
+            ))
+            .into());
+        }
+
+        let (indexes, rows): (Vec<usize>, Vec<oai_schema::BulkDeviceRow>) =
+            rows.into_iter().unzip();
+        let mut reqs = vec![];
+        for (index, resolved) in indexes
+            .into_iter()
+            .zip(state.repo.resolve_device_rows(&account.0, &rows).await?)
+        {
+            match resolved {
+                Ok(req) => reqs.push(req),
+                Err(message) => errors.push(oai_schema::BulkDeviceError {
+                    row: index + 1,
+                    message,
+                }),
+            }
+        }
+        errors.sort_by_key(|e| e.row);
+        let report = oai_schema::BulkDeviceReport {
+            total,
+            valid: reqs.len(),
+            errors,
+        };
+        if dry_run.0.unwrap_or(false) {
+            return Ok(oai_schema::BulkCreateDevicesResponse::DryRun(Json(report)));
+        }
+        if !report.errors.is_empty() || reqs.is_empty() {
+            return Ok(oai_schema::BulkCreateDevicesResponse::Invalid(Json(report)));
+        }
+
+        let devices = state.repo.bulk_create_devices(&account.0, &reqs).await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Create,
+            AuditResource::Device,
+            "bulk",
+        )
+        .detail(json!({
+            "device_ids": devices.iter().map(|d| &d.id).collect::<Vec<_>>(),
+        }));
+        state.repo.create_audit_log(log).await?;
+        for device in &devices {
+            let device = oai_schema::Device::from(device.clone());
+            events::emit(
+                &state.repo,
+                Event::new(&account.0, EventType::DeviceCreated)
+                    .device(&device.id)
+                    .object(&device),
+            )
+            .await;
+        }
+
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer
+            .write_record(["device_id", "name", "mqtt_username", "mqtt_password"])
+            .map_err(InternalServerError)?;
+        for (device, (_, password)) in devices.iter().zip(&reqs) {
+            writer
+                .write_record([&device.id, &device.name, &device.mqtt_username, password])
+                .map_err(InternalServerError)?;
+        }
+        let content = writer.into_inner().map_err(InternalServerError)?;
+        Ok(oai_schema::BulkCreateDevicesResponse::Created(
+            Attachment::new(content).filename("device_credentials.csv"),
+        ))
     }
 
     /// 查询设备列表
@@ -35,6 +159,10 @@ impl DeviceService {
         q: Query<Option<String>>,
         /// 筛选包含标签的设备
         labels_in: Query<Option<Vec<String>>>,
+        /// 标签选择器, 例如: `site=sh01,floor in (2,3),!decommissioned`
+        selector: Query<Option<String>>,
+        /// 按生命周期状态筛选, 默认不包含已退役的设备
+        lifecycle_state: Query<Option<LifecycleState>>,
         /// 第几页
         #[oai(default = "default_page")]
         page: Query<usize>,
@@ -42,6 +170,11 @@ impl DeviceService {
         #[oai(default = "default_page_size")]
         page_size: Query<usize>,
     ) -> Result<Json<oai_schema::Devices>> {
+        let selector = selector
+            .0
+            .as_deref()
+            .map(str::parse::<Selector>)
+            .transpose()?;
         let (devices, total) = state
             .repo
             .list_device(
@@ -50,6 +183,8 @@ impl DeviceService {
                 page_size.0,
                 id_in.clone(),
                 labels_in.clone(),
+                selector.as_ref(),
+                lifecycle_state.0,
                 q.clone(),
             )
             .await?;
@@ -59,6 +194,289 @@ impl DeviceService {
         }))
     }
 
+    /// 查询网关的子设备
+    #[oai(path = "/:device_id/children", method = "get")]
+    async fn list_device_children(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        device_id: Path<String>,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::Devices>> {
+        let (devices, total) = state
+            .repo
+            .list_device_children(&account.0, &device_id, page.0, page_size.0)
+            .await?;
+        Ok(Json(oai_schema::Devices {
+            results: devices.into_iter().map(Into::into).collect(),
+            total,
+        }))
+    }
+
+    /// 将设备挂到网关下
+    ///
+    /// 网关获得代子设备上报数据、接收并回复指令的权限, 已属于其他网关的设备会被转移
+    #[oai(path = "/:device_id/children", method = "post")]
+    async fn add_device_children(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        device_id: Path<String>,
+        body: Json<oai_schema::DeviceChildren>,
+    ) -> Result<Json<oai_schema::DeviceChildren>> {
+        let device_ids = state
+            .repo
+            .add_device_children(&account.0, &device_id, &body.device_ids)
+            .await?;
+        if !device_ids.is_empty() {
+            let log = AuditLog::new(
+                &account.0,
+                &ip.0,
+                AuditAction::Update,
+                AuditResource::Device,
+                &device_id,
+            )
+            .detail(json!({ "added_child_ids": device_ids }));
+            state.repo.create_audit_log(log).await?;
+        }
+        Ok(Json(oai_schema::DeviceChildren { device_ids }))
+    }
+
+    /// 将设备从网关下移除
+    #[oai(path = "/:device_id/children", method = "delete")]
+    async fn remove_device_children(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        device_id: Path<String>,
+        /// 子设备ID列表
+        device_id_in: Query<Vec<String>>,
+    ) -> Result<Json<oai_schema::DeviceChildren>> {
+        let device_ids = state
+            .repo
+            .remove_device_children(&account.0, &device_id, &device_id_in)
+            .await?;
+        if !device_ids.is_empty() {
+            let log = AuditLog::new(
+                &account.0,
+                &ip.0,
+                AuditAction::Update,
+                AuditResource::Device,
+                &device_id,
+            )
+            .detail(json!({ "removed_child_ids": device_ids }));
+            state.repo.create_audit_log(log).await?;
+        }
+        Ok(Json(oai_schema::DeviceChildren { device_ids }))
+    }
+
+    /// 查询设备的连接凭据
+    #[oai(path = "/:device_id/credentials", method = "get")]
+    async fn list_device_credentials(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        device_id: Path<String>,
+    ) -> Result<Json<oai_schema::DeviceCredentials>> {
+        let credentials = state
+            .repo
+            .list_device_credentials(&account.0, &device_id)
+            .await?;
+        Ok(Json(oai_schema::DeviceCredentials {
+            total: credentials.len(),
+            results: credentials.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    /// 为设备签发一个新的连接凭据, 与已有凭据同时有效
+    #[oai(path = "/:device_id/credentials", method = "post")]
+    async fn create_device_credential(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        device_id: Path<String>,
+        body: Json<oai_schema::CreateDeviceCredential>,
+    ) -> Result<Json<oai_schema::DeviceCredentialSecret>> {
+        let device = state.repo.get_device(&account.0, &device_id).await?;
+        let issued = state
+            .repo
+            .create_device_credential(&account.0, &device_id, &body)
+            .await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Create,
+            AuditResource::DeviceCredential,
+            &issued.credential.id,
+        )
+        .after(&oai_schema::DeviceCredential::from(
+            issued.credential.clone(),
+        ))
+        .detail(json!({ "device_id": device.id }));
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(oai_schema::DeviceCredentialSecret::new(
+            &device.mqtt_username,
+            issued,
+        )))
+    }
+
+    /// 轮换设备凭据
+    ///
+    /// 签发新凭据, 已有凭据在重叠期内仍然有效, 之后自动失效
+    #[oai(path = "/:device_id/credentials/rotate", method = "post")]
+    async fn rotate_device_credential(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        device_id: Path<String>,
+        body: Json<oai_schema::RotateDeviceCredential>,
+    ) -> Result<Json<oai_schema::DeviceCredentialSecret>> {
+        let device = state.repo.get_device(&account.0, &device_id).await?;
+        let issued = state
+            .repo
+            .rotate_device_credential(&account.0, &device_id, &body)
+            .await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Create,
+            AuditResource::DeviceCredential,
+            &issued.credential.id,
+        )
+        .after(&oai_schema::DeviceCredential::from(
+            issued.credential.clone(),
+        ))
+        .detail(json!({
+            "device_id": device.id,
+            "rotated": true,
+            "overlap_secs": body.overlap_secs,
+        }));
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(oai_schema::DeviceCredentialSecret::new(
+            &device.mqtt_username,
+            issued,
+        )))
+    }
+
+    /// 吊销设备凭据
+    #[oai(path = "/:device_id/credentials/:credential_id", method = "delete")]
+    async fn revoke_device_credential(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        device_id: Path<String>,
+        credential_id: Path<String>,
+    ) -> Result<Json<oai_schema::DeviceCredential>> {
+        let credential: oai_schema::DeviceCredential = state
+            .repo
+            .revoke_device_credential(&account.0, &device_id, &credential_id)
+            .await?
+            .into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Delete,
+            AuditResource::DeviceCredential,
+            &credential_id,
+        )
+        .after(&credential)
+        .detail(json!({ "device_id": device_id.0 }));
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(credential))
+    }
+
+    /// 查询设备的客户端证书
+    #[oai(path = "/:device_id/certificates", method = "get")]
+    async fn list_device_certificates(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        device_id: Path<String>,
+    ) -> Result<Json<oai_schema::DeviceCertificates>> {
+        let certificates = state
+            .repo
+            .list_device_certificates(&account.0, &device_id)
+            .await?;
+        Ok(Json(oai_schema::DeviceCertificates {
+            total: certificates.len(),
+            results: certificates.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    /// 根据CSR为设备签发客户端证书
+    ///
+    /// 证书由账号的设备CA签发, CN与URI SAN均为设备ID, CA证书可通过`/pki/ca`获取
+    #[oai(path = "/:device_id/certificates", method = "post")]
+    async fn issue_device_certificate(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        device_id: Path<String>,
+        body: Json<oai_schema::IssueDeviceCertificate>,
+    ) -> Result<Json<oai_schema::DeviceCertificate>> {
+        let certificate: oai_schema::DeviceCertificate = state
+            .repo
+            .issue_device_certificate(&account.0, &device_id, &body)
+            .await?
+            .into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Create,
+            AuditResource::DeviceCertificate,
+            &certificate.serial,
+        )
+        .detail(json!({
+            "device_id": device_id.0,
+            "fingerprint": certificate.fingerprint,
+            "not_after": certificate.not_after,
+        }));
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(certificate))
+    }
+
+    /// 吊销设备证书, 吊销后证书出现在账号的CRL中
+    #[oai(path = "/:device_id/certificates/:serial", method = "delete")]
+    async fn revoke_device_certificate(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        device_id: Path<String>,
+        serial: Path<String>,
+    ) -> Result<Json<oai_schema::DeviceCertificate>> {
+        let certificate: oai_schema::DeviceCertificate = state
+            .repo
+            .revoke_device_certificate(&account.0, &device_id, &serial)
+            .await?
+            .into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Delete,
+            AuditResource::DeviceCertificate,
+            &certificate.serial,
+        )
+        .detail(json!({
+            "device_id": device_id.0,
+            "fingerprint": certificate.fingerprint,
+            "revoked_at": certificate.revoked_at,
+        }));
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(certificate))
+    }
+
     /// 获取设备详情
     #[oai(path = "/:device_id", method = "get")]
     async fn get_device(
@@ -79,33 +497,167 @@ impl DeviceService {
         &self,
         state: Data<&AppState>,
         account: JWTAuthorization,
+        ip: ClientIp,
         device_id: Path<String>,
         body: Json<oai_schema::UpdateDevice>,
     ) -> Result<Json<oai_schema::DeviceWithLables>> {
-        let device = state
+        let before: oai_schema::DeviceWithLables = state
+            .repo
+            .get_device_with_labels(&account.0, &device_id)
+            .await?
+            .into();
+        let after: oai_schema::DeviceWithLables = state
             .repo
             .update_device(&account.0, &device_id, &body)
-            .await?;
-        Ok(Json(device.into()))
+            .await?
+            .into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::Device,
+            &device_id,
+        )
+        .before(&before)
+        .after(&after);
+        state.repo.create_audit_log(log).await?;
+        if body.is_active == Some(false) {
+            let device = state.repo.get_device(&account.0, &device_id).await?;
+            kick_sessions(&[&device.mqtt_username, &device.id]).await?;
+        }
+        Ok(Json(after))
     }
 
     /// 删除设备
+    ///
+    /// 设备被退役: 凭证和证书被吊销并断开连接, 历史数据保留到保留期结束后才会被清理
     #[oai(path = "/:device_id", method = "delete")]
     async fn delete_device(
         &self,
         state: Data<&AppState>,
         account: JWTAuthorization,
+        ip: ClientIp,
         device_id: Path<String>,
     ) -> Result<()> {
-        state.repo.delete_device(&account.0, &device_id).await?;
+        let before: oai_schema::DeviceWithLables = state
+            .repo
+            .get_device_with_labels(&account.0, &device_id)
+            .await?
+            .into();
+        let device = state
+            .repo
+            .transition_device(
+                &account.0,
+                &device_id,
+                LifecycleState::Decommissioned,
+                Some("deleted".to_string()),
+                &account.0,
+            )
+            .await?;
+        let username = device.device.mqtt_username.clone();
+        let after: oai_schema::DeviceWithLables = device.into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Delete,
+            AuditResource::Device,
+            &device_id,
+        )
+        .before(&before)
+        .after(&after);
+        state.repo.create_audit_log(log).await?;
+        events::emit(
+            &state.repo,
+            Event::new(&account.0, EventType::DeviceDeleted)
+                .device(&device_id)
+                .object(&after),
+        )
+        .await;
+        kick_sessions(&[&username, &device_id]).await?;
         Ok(())
     }
+
+    /// 变更设备的生命周期状态
+    ///
+    /// 可选状态: provisioned -> active/suspended/decommissioned,
+    /// active -> suspended/maintenance/decommissioned, suspended -> active/decommissioned,
+    /// maintenance -> active/suspended/decommissioned
+    #[oai(path = "/:device_id/lifecycle", method = "post")]
+    async fn transition_device(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        device_id: Path<String>,
+        body: Json<oai_schema::TransitionDevice>,
+    ) -> Result<Json<oai_schema::DeviceWithLables>> {
+        let before: oai_schema::DeviceWithLables = state
+            .repo
+            .get_device_with_labels(&account.0, &device_id)
+            .await?
+            .into();
+        let device = state
+            .repo
+            .transition_device(
+                &account.0,
+                &device_id,
+                body.state,
+                body.reason.clone(),
+                &account.0,
+            )
+            .await?;
+        let username = device.device.mqtt_username.clone();
+        let after: oai_schema::DeviceWithLables = device.into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::Device,
+            &device_id,
+        )
+        .before(&before)
+        .detail(json!({
+            "from": lifecycle::name(before.lifecycle_state),
+            "to": lifecycle::name(after.lifecycle_state),
+            "reason": body.reason,
+        }));
+        state.repo.create_audit_log(log).await?;
+        if !after.is_active {
+            kick_sessions(&[&username, &device_id]).await?;
+        }
+        Ok(Json(after))
+    }
+
+    /// 查询设备的生命周期变更记录
+    #[oai(path = "/:device_id/lifecycle", method = "get")]
+    async fn list_device_lifecycle_events(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        device_id: Path<String>,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::DeviceLifecycleEvents>> {
+        let (events, total) = state
+            .repo
+            .list_device_lifecycle_events(&account.0, &device_id, page.0, page_size.0)
+            .await?;
+        Ok(Json(oai_schema::DeviceLifecycleEvents {
+            results: events.into_iter().map(Into::into).collect(),
+            total,
+        }))
+    }
     /// 向设备发送指令
     #[oai(path = "/:device_id/command", method = "post")]
     async fn send_command_to_deivce(
         &self,
         state: Data<&AppState>,
         account: JWTAuthorization,
+        ip: ClientIp,
         device_id: Path<String>,
         req: Json<oai_schema::SendCommandToDevice>,
     ) -> Result<oai_schema::CommandResponse> {
@@ -113,14 +665,32 @@ impl DeviceService {
             .repo
             .send_command_to_device(&account.0, &device_id, &req)
             .await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Command,
+            AuditResource::Device,
+            &device_id,
+        )
+        .detail(json!({
+            "message_id": message_id,
+            "command": req.command,
+            "payload": req.payload,
+            "is_sync": req.is_sync,
+            "ttl": req.ttl,
+            "qos": req.qos,
+        }));
+        state.repo.create_audit_log(log).await?;
         if req.is_sync {
-            Ok(oai_schema::CommandResponse::new_async(message_id))
-        } else {
+            // 设备的回复经由`/hook/mqtt/message`写入
             let response = state
                 .cache
-                .block_pop(&message_id, req.ttl.unwrap_or(10))
-                .await?;
+                .block_pop(&message_id, req.sync_timeout)
+                .await?
+                .ok_or(NeoiotError::CommandTimeout(message_id))?;
             Ok(oai_schema::CommandResponse::new_sync(response))
+        } else {
+            Ok(oai_schema::CommandResponse::new_async(message_id))
         }
     }
 
@@ -145,3 +715,86 @@ impl DeviceService {
         Ok(Json(result.into()))
     }
 }
+
+/// 断开设备的所有MQTT会话, 包括以设备ID作为用户名的证书连接
+async fn kick_sessions(usernames: &[&str]) -> Result<()> {
+    for username in usernames {
+        let client_ids = mqtt_client::Client::kick_username(username).await?;
+        if !client_ids.is_empty() {
+            tracing::info!(%username, ?client_ids, "kicked device sessions");
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct CsvDeviceRow {
+    name: String,
+    schema: String,
+    #[serde(default)]
+    labels: Option<String>,
+    #[serde(default)]
+    mqtt_password: Option<String>,
+}
+
+/// 解析CSV格式的批量设备数据, 返回(行下标, 数据)以及无法解析的行
+fn parse_csv(
+    text: &str,
+) -> (
+    Vec<(usize, oai_schema::BulkDeviceRow)>,
+    Vec<oai_schema::BulkDeviceError>,
+) {
+    let mut rows = vec![];
+    let mut errors = vec![];
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(text.as_bytes());
+    for (index, record) in reader.deserialize::<CsvDeviceRow>().enumerate() {
+        match record {
+            Ok(record) => rows.push((
+                index,
+                oai_schema::BulkDeviceRow {
+                    name: record.name,
+                    schema: record.schema,
+                    labels: record
+                        .labels
+                        .unwrap_or_default()
+                        .split(';')
+                        .map(str::trim)
+                        .filter(|l| !l.is_empty())
+                        .map(ToString::to_string)
+                        .collect(),
+                    mqtt_password: record.mqtt_password.filter(|p| !p.is_empty()),
+                },
+            )),
+            Err(err) => errors.push(oai_schema::BulkDeviceError {
+                row: index + 1,
+                message: err.to_string(),
+            }),
+        }
+    }
+    (rows, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv() {
+        let (rows, errors) = parse_csv(
+            "name,schema,labels,mqtt_password\n\
+             dev-1,sensor,site=sh01; floor=3,\n\
+             dev-2,sensor\n\
+             dev-3,sensor,,secret123\n",
+        );
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].0, 0);
+        assert_eq!(rows[0].1.labels, vec!["site=sh01", "floor=3"]);
+        assert_eq!(rows[0].1.mqtt_password, None);
+        assert_eq!(rows[1].0, 2);
+        assert_eq!(rows[1].1.mqtt_password.as_deref(), Some("secret123"));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].row, 2);
+    }
+}
@@ -0,0 +1,137 @@
+use super::{default_page, default_page_size, ApiTags, AppState, ClientIp};
+use crate::{audit::AuditLog, auth::JWTAuthorization, oai_schema, repository::Repository};
+use entity::audit_logs::{AuditAction, AuditResource};
+use poem::web::Data;
+use poem::Result;
+use poem_openapi::param::{Path, Query};
+use poem_openapi::{payload::Json, OpenApi};
+
+pub struct ForwardService;
+
+/// 数据转发
+///
+/// 将校验通过的遥测数据和设备事件转发到Pulsar主题、Kafka REST Proxy、HTTP地址或本地按小时滚动的
+/// NDJSON文件; 每条记录与Webhook推送的消息体一致, 转发失败不重试, 计入失败次数
+#[OpenApi(prefix_path = "/forward", tag = "ApiTags::Forward")]
+impl ForwardService {
+    /// 创建数据转发路由
+    #[oai(path = "/", method = "post")]
+    async fn create_route(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        body: Json<oai_schema::CreateDataRoute>,
+    ) -> Result<Json<oai_schema::DataRoute>> {
+        let route: oai_schema::DataRoute = state
+            .repo
+            .create_data_route(&account.0, &body)
+            .await?
+            .into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Create,
+            AuditResource::DataRoute,
+            &route.id,
+        )
+        .after(&route);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(route))
+    }
+
+    /// 查询数据转发路由列表
+    #[oai(path = "/", method = "get")]
+    async fn list_routes(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::DataRoutes>> {
+        let (routes, total) = state
+            .repo
+            .list_data_routes(&account.0, page.0, page_size.0)
+            .await?;
+        Ok(Json(oai_schema::DataRoutes {
+            results: routes.into_iter().map(Into::into).collect(),
+            total,
+        }))
+    }
+
+    /// 获取数据转发路由详情, 包含转发和失败次数
+    #[oai(path = "/:route_id", method = "get")]
+    async fn get_route(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        route_id: Path<String>,
+    ) -> Result<Json<oai_schema::DataRoute>> {
+        let route = state.repo.get_data_route(&account.0, &route_id).await?;
+        Ok(Json(route.into()))
+    }
+
+    /// 更新数据转发路由
+    #[oai(path = "/:route_id", method = "patch")]
+    async fn update_route(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        route_id: Path<String>,
+        body: Json<oai_schema::UpdateDataRoute>,
+    ) -> Result<Json<oai_schema::DataRoute>> {
+        let before: oai_schema::DataRoute = state
+            .repo
+            .get_data_route(&account.0, &route_id)
+            .await?
+            .into();
+        let after: oai_schema::DataRoute = state
+            .repo
+            .update_data_route(&account.0, &route_id, &body)
+            .await?
+            .into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::DataRoute,
+            &route_id,
+        )
+        .before(&before)
+        .after(&after);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(after))
+    }
+
+    /// 删除数据转发路由
+    #[oai(path = "/:route_id", method = "delete")]
+    async fn delete_route(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        route_id: Path<String>,
+    ) -> Result<()> {
+        let before: oai_schema::DataRoute = state
+            .repo
+            .get_data_route(&account.0, &route_id)
+            .await?
+            .into();
+        state.repo.delete_data_route(&account.0, &route_id).await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Delete,
+            AuditResource::DataRoute,
+            &route_id,
+        )
+        .before(&before);
+        state.repo.create_audit_log(log).await?;
+        Ok(())
+    }
+}
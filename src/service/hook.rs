@@ -0,0 +1,382 @@
+use super::{ApiTags, AppState};
+use crate::{
+    alarms::{self, NewAlarm},
+    auth::HookAuthorization,
+    cache::Cache,
+    device_config::ConfigAck,
+    errors::NeoiotError,
+    events::{self, Event},
+    oai_schema::{self, HookResponse, MqttAction},
+    ota::ProgressReport,
+    pki,
+    repository::Repository,
+    rules, telemetry,
+    topics::{
+        topic_matches, CommandResponse, ConfigApplied, Metric, OtaReport, OtaReportKind,
+        UploadChunk,
+    },
+    uploads,
+};
+use entity::alarms::{AlarmSeverity, AlarmSource};
+use entity::webhook_deliveries::EventType;
+use poem::web::Data;
+use poem::Result;
+use poem_openapi::{payload::Json, OpenApi};
+use serde_json::json;
+
+/// 同步指令回复的保留时长(秒), 不短于同步指令的最大等待时长,
+/// 等待方超时后才到达的回复由过期清理
+const SYNC_RESPONSE_EXPIRE: usize = 120;
+
+pub struct HookService;
+
+/// 供EMQX调用的认证与ACL Hook, 需要在请求头携带`X-Hook-Token`
+#[OpenApi(prefix_path = "/hook/mqtt", tag = "ApiTags::Hook")]
+impl HookService {
+    /// 设备连接认证
+    #[oai(path = "/auth", method = "post")]
+    async fn auth(
+        &self,
+        state: Data<&AppState>,
+        _token: HookAuthorization,
+        req: Json<oai_schema::MqttAuthRequest>,
+    ) -> Result<HookResponse> {
+        let device = match (&req.cert_common_name, &req.password) {
+            // 双向TLS连接, 证书CN即设备ID, 用户名须为设备ID或设备的MQTT用户名;
+            // EMQX还须传入证书序列号或指纹, 用来匹配出示的那一张证书
+            (Some(common_name), _) => {
+                let serial = req.cert_serial.as_deref().map(pki::normalize_hex);
+                let fingerprint = req.cert_fingerprint.as_deref().map(pki::normalize_hex);
+                state
+                    .repo
+                    .authenticate_device_certificate(
+                        common_name,
+                        serial.as_deref(),
+                        fingerprint.as_deref(),
+                    )
+                    .await?
+                    .filter(|d| req.username == d.id || req.username == d.mqtt_username)
+            }
+            (None, Some(password)) => {
+                state
+                    .repo
+                    .authenticate_device(&req.username, password)
+                    .await?
+            }
+            (None, None) => None,
+        };
+        match device {
+            Some(_) => Ok(HookResponse::Allow),
+            None => {
+                tracing::info!(username = %req.username, "mqtt authentication rejected");
+                Ok(HookResponse::Deny)
+            }
+        }
+    }
+
+    /// 设备发布/订阅权限校验
+    #[oai(path = "/acl", method = "post")]
+    async fn acl(
+        &self,
+        state: Data<&AppState>,
+        _token: HookAuthorization,
+        req: Json<oai_schema::MqttAclRequest>,
+    ) -> Result<HookResponse> {
+        let device = match state.repo.get_device_by_username(&req.username).await {
+            Ok(device) => device,
+            Err(NeoiotError::ObjectNotFound(_)) => return Ok(HookResponse::Deny),
+            Err(err) => return Err(err.into()),
+        };
+        if !device.is_active {
+            return Ok(HookResponse::Deny);
+        }
+        let rules = match req.action {
+            MqttAction::Publish => &device.acl_pubs,
+            MqttAction::Subscribe => &device.acl_subs,
+        };
+        let allowed = rules
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|rule| rule.as_str())
+            .any(|rule| topic_matches(rule, &req.topic));
+        if allowed {
+            Ok(HookResponse::Allow)
+        } else {
+            Ok(HookResponse::Deny)
+        }
+    }
+
+    /// 设备上线事件, 需要在EMQX WebHook中配置`client.connected`
+    #[oai(path = "/connected", method = "post")]
+    async fn connected(
+        &self,
+        state: Data<&AppState>,
+        _token: HookAuthorization,
+        req: Json<oai_schema::MqttConnectedEvent>,
+    ) -> Result<()> {
+        if let Some(device) = state.repo.device_connected(&req).await? {
+            state
+                .repo
+                .clear_device_alarm(&device.id, alarms::DEVICE_OFFLINE, "system")
+                .await?;
+            let event = Event::new(&device.account_id, EventType::DeviceConnected)
+                .device(&device.id)
+                .data(json!({
+                    "device_id": device.id,
+                    "clientid": req.clientid,
+                    "ipaddress": req.ipaddress,
+                }));
+            events::emit(&state.repo, event).await;
+        }
+        Ok(())
+    }
+
+    /// 设备下线事件, 需要在EMQX WebHook中配置`client.disconnected`
+    #[oai(path = "/disconnected", method = "post")]
+    async fn disconnected(
+        &self,
+        state: Data<&AppState>,
+        _token: HookAuthorization,
+        req: Json<oai_schema::MqttDisconnectedEvent>,
+    ) -> Result<()> {
+        let device = match state.repo.device_disconnected(&req).await? {
+            Some(device) => device,
+            None => return Ok(()),
+        };
+        let event = Event::new(&device.account_id, EventType::DeviceDisconnected)
+            .device(&device.id)
+            .data(json!({
+                "device_id": device.id,
+                "clientid": req.clientid,
+                "reason": req.reason,
+            }));
+        events::emit(&state.repo, event).await;
+        // 还有其他会话在线时不触发离线告警
+        if device.is_online {
+            return Ok(());
+        }
+        let alarm = NewAlarm::new(
+            &device.account_id,
+            &device.id,
+            alarms::DEVICE_OFFLINE,
+            AlarmSource::Offline,
+            AlarmSeverity::Major,
+            &format!("device disconnected: {}", req.reason),
+        );
+        state.repo.raise_alarm(&alarm).await?;
+        Ok(())
+    }
+
+    /// 消息发布事件, 用于记录设备间消息、接收指令回复、校验上报的字段值并执行遥测规则、记录升级进度、接收文件分片以及记录配置应用结果
+    ///
+    /// 需要在EMQX WebHook中为`d2d/#`、`d2l/#`、`s2dr/#`、`metrics/#`、`otar/#`、`upload/#`和`cfgr/#`配置`message.publish`
+    #[oai(path = "/message", method = "post")]
+    async fn message(
+        &self,
+        state: Data<&AppState>,
+        _token: HookAuthorization,
+        req: Json<oai_schema::MqttMessageEvent>,
+    ) -> Result<()> {
+        if let Some(metric) = Metric::parse(&req.topic) {
+            return ingest_metric(&state, metric, &req.payload).await;
+        }
+        if let Some(response) = CommandResponse::parse(&req.topic) {
+            return ingest_command_response(&state, response, &req.payload).await;
+        }
+        if let Some(report) = OtaReport::parse(&req.topic) {
+            return ingest_ota_report(&state, report, &req.payload).await;
+        }
+        if let Some(chunk) = UploadChunk::parse(&req.topic) {
+            return ingest_upload_chunk(&state, chunk, &req.payload).await;
+        }
+        if let Some(applied) = ConfigApplied::parse(&req.topic) {
+            return ingest_config_ack(&state, applied, &req.payload).await;
+        }
+        state.repo.log_d2d_message(&req.topic, &req.payload).await?;
+        Ok(())
+    }
+}
+
+/// 按数据模型校验上报的字段值, 校验失败时触发告警, 否则执行遥测规则
+async fn ingest_metric(state: &AppState, metric: Metric, payload: &str) -> Result<()> {
+    let device = match state
+        .repo
+        .get_device(&metric.account_id, &metric.device_id)
+        .await
+    {
+        Ok(device) => device,
+        Err(NeoiotError::ObjectNotFound(_)) => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    let invalid = match state
+        .repo
+        .get_field(&metric.account_id, &device.schema_id, &metric.field)
+        .await
+    {
+        Ok(field) => telemetry::validate(&field.data_type, payload).err(),
+        Err(NeoiotError::ObjectNotFound(_)) => {
+            Some(format!("field `{}` is not defined in schema", metric.field))
+        }
+        Err(err) => return Err(err.into()),
+    };
+    if let Some(reason) = invalid {
+        let alarm = NewAlarm::new(
+            &metric.account_id,
+            &metric.device_id,
+            &alarms::schema_alarm_type(&metric.field),
+            AlarmSource::SchemaValidation,
+            AlarmSeverity::Warning,
+            &reason,
+        );
+        state.repo.raise_alarm(&alarm).await?;
+        return Ok(());
+    }
+
+    let event = Event::new(&metric.account_id, EventType::TelemetryReceived)
+        .device(&metric.device_id)
+        .data(json!({
+            "device_id": metric.device_id,
+            "field": metric.field,
+            "value": payload,
+        }));
+    events::emit(&state.repo, event).await;
+
+    let value = match rules::parse_value(payload) {
+        Some(value) => value,
+        None => return Ok(()),
+    };
+    let fired = state
+        .repo
+        .evaluate_rules(&metric.account_id, &metric.device_id, &metric.field, value)
+        .await?;
+    if !fired.is_empty() {
+        // 动作可能较慢, 不阻塞EMQX的WebHook
+        let repo = state.repo.clone();
+        tokio::spawn(async move {
+            for fired in fired {
+                rules::execute(&repo, fired).await;
+            }
+        });
+    }
+    Ok(())
+}
+
+/// 设备回复指令, 同步指令的回复交给等待中的请求, 异步指令的回复更新所属批量任务的进度
+async fn ingest_command_response(
+    state: &AppState,
+    response: CommandResponse,
+    payload: &str,
+) -> Result<()> {
+    if response.is_sync {
+        state
+            .cache
+            .lpush(&response.message_id, payload, SYNC_RESPONSE_EXPIRE)
+            .await?;
+    } else {
+        state
+            .repo
+            .record_command_job_response(&response.message_id, payload)
+            .await?;
+    }
+    let event = Event::new(&response.account_id, EventType::CommandResponded)
+        .device(&response.device_id)
+        .data(json!({
+            "device_id": response.device_id,
+            "command": response.command,
+            "message_id": response.message_id,
+            "payload": payload,
+        }));
+    events::emit(&state.repo, event).await;
+    Ok(())
+}
+
+/// 设备上报固件版本或升级进度, 设备升级结束后立即评估所属的升级活动, 失败率超过阈值时中止
+async fn ingest_ota_report(state: &AppState, report: OtaReport, payload: &str) -> Result<()> {
+    let finished = match report.kind {
+        OtaReportKind::Version => {
+            let version = payload.trim();
+            if version.is_empty() {
+                return Ok(());
+            }
+            state
+                .repo
+                .report_firmware_version(&report.account_id, &report.device_id, version)
+                .await?
+        }
+        OtaReportKind::Progress => {
+            let progress = match ProgressReport::parse(payload) {
+                Ok(progress) => progress,
+                Err(err) => {
+                    tracing::info!(device_id = %report.device_id, %err, "invalid ota progress");
+                    return Ok(());
+                }
+            };
+            state
+                .repo
+                .record_ota_progress(&report.account_id, &report.device_id, &progress)
+                .await?
+        }
+    };
+    if let Some(campaign_id) = finished {
+        if let Some(campaign) = state.repo.evaluate_ota_campaign(&campaign_id).await? {
+            tracing::info!(
+                campaign_id = %campaign.id,
+                status = ?campaign.status,
+                stage = ?campaign.stage,
+                reason = ?campaign.abort_reason,
+                "ota campaign changed"
+            );
+        }
+    }
+    Ok(())
+}
+
+/// 设备经MQTT上传的文件分片, 分片无效时只记录日志, 不影响EMQX的WebHook
+async fn ingest_upload_chunk(state: &AppState, chunk: UploadChunk, payload: &str) -> Result<()> {
+    let upload = match state.repo.find_device_upload(&chunk.upload_id).await {
+        Ok(upload)
+            if upload.account_id == chunk.account_id && upload.device_id == chunk.device_id =>
+        {
+            upload
+        }
+        Ok(_) | Err(NeoiotError::ObjectNotFound(_)) => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    let result = async {
+        let data = uploads::decode_chunk(payload)?;
+        uploads::receive_chunk(
+            &state.repo,
+            &state.blob,
+            &upload,
+            chunk.seq,
+            chunk.total,
+            data,
+        )
+        .await
+    }
+    .await;
+    match result {
+        Err(NeoiotError::InvalidArgument(reason)) => {
+            tracing::info!(upload_id = %upload.id, seq = chunk.seq, %reason, "invalid upload chunk");
+            Ok(())
+        }
+        result => Ok(result?),
+    }
+}
+
+/// 设备回复的配置应用结果, 负载无效时只记录日志
+async fn ingest_config_ack(state: &AppState, applied: ConfigApplied, payload: &str) -> Result<()> {
+    let ack = match ConfigAck::parse(payload) {
+        Ok(ack) => ack,
+        Err(err) => {
+            tracing::info!(device_id = %applied.device_id, %err, "invalid config ack");
+            return Ok(());
+        }
+    };
+    state
+        .repo
+        .ack_device_config(&applied.account_id, &applied.device_id, &ack)
+        .await?;
+    Ok(())
+}
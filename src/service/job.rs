@@ -0,0 +1,161 @@
+use super::{default_page, default_page_size, ApiTags, AppState, ClientIp};
+use crate::{audit::AuditLog, auth::JWTAuthorization, oai_schema, repository::Repository};
+use entity::audit_logs::{AuditAction, AuditResource};
+use entity::command_job_devices::CommandJobDeviceStatus;
+use entity::command_jobs::CommandJobStatus;
+use entity::prelude::*;
+use poem::web::Data;
+use poem::Result;
+use poem_openapi::param::{Path, Query};
+use poem_openapi::{payload::Json, OpenApi};
+
+pub struct JobService;
+
+async fn with_progress(state: &AppState, job: CommandJobModel) -> Result<oai_schema::CommandJob> {
+    let progress = state.repo.count_command_job_devices(&job.id).await?;
+    Ok(oai_schema::CommandJob::new(job, progress))
+}
+
+/// 批量指令任务
+///
+/// 与向标签广播的`s2l`指令不同, 任务在创建时将标签或设备列表展开为设备,
+/// 按速率逐个下发异步`s2d`指令并跟踪每台设备的进度: pending、sent、responded、failed、timed_out;
+/// 任务可以取消, 失败、超时和已取消的设备可以重新下发
+#[OpenApi(prefix_path = "/job", tag = "ApiTags::Job")]
+impl JobService {
+    /// 创建批量指令任务
+    #[oai(path = "/", method = "post")]
+    async fn create_job(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        body: Json<oai_schema::CreateCommandJob>,
+    ) -> Result<Json<oai_schema::CommandJob>> {
+        let job = state
+            .repo
+            .create_command_job(&account.0, &account.0, &body)
+            .await?;
+        let job = with_progress(&state, job).await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Command,
+            AuditResource::CommandJob,
+            &job.id,
+        )
+        .after(&job);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(job))
+    }
+
+    /// 查询批量指令任务列表
+    #[oai(path = "/", method = "get")]
+    async fn list_jobs(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        /// 任务状态
+        status: Query<Option<CommandJobStatus>>,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::CommandJobs>> {
+        let (jobs, total) = state
+            .repo
+            .list_command_jobs(&account.0, status.0, page.0, page_size.0)
+            .await?;
+        let mut results = Vec::with_capacity(jobs.len());
+        for job in jobs {
+            results.push(with_progress(&state, job).await?);
+        }
+        Ok(Json(oai_schema::CommandJobs { results, total }))
+    }
+
+    /// 获取批量指令任务详情及进度
+    #[oai(path = "/:job_id", method = "get")]
+    async fn get_job(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        job_id: Path<String>,
+    ) -> Result<Json<oai_schema::CommandJob>> {
+        let job = state.repo.get_command_job(&account.0, &job_id).await?;
+        Ok(Json(with_progress(&state, job).await?))
+    }
+
+    /// 查询任务中每台设备的进度
+    #[oai(path = "/:job_id/devices", method = "get")]
+    async fn list_job_devices(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        job_id: Path<String>,
+        /// 设备状态
+        status: Query<Option<CommandJobDeviceStatus>>,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::CommandJobDevices>> {
+        let (targets, total) = state
+            .repo
+            .list_command_job_devices(&account.0, &job_id, status.0, page.0, page_size.0)
+            .await?;
+        Ok(Json(oai_schema::CommandJobDevices {
+            results: targets.into_iter().map(Into::into).collect(),
+            total,
+        }))
+    }
+
+    /// 取消任务, 尚未下发的设备不再下发, 已下发的设备继续等待回复
+    #[oai(path = "/:job_id/cancel", method = "post")]
+    async fn cancel_job(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        job_id: Path<String>,
+    ) -> Result<Json<oai_schema::CommandJob>> {
+        let job = state.repo.cancel_command_job(&account.0, &job_id).await?;
+        let job = with_progress(&state, job).await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::CommandJob,
+            &job_id,
+        )
+        .detail(serde_json::json!({ "cancelled": true }));
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(job))
+    }
+
+    /// 重新下发失败、超时和已取消的设备
+    #[oai(path = "/:job_id/retry", method = "post")]
+    async fn retry_job(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        job_id: Path<String>,
+    ) -> Result<Json<oai_schema::CommandJob>> {
+        let job = state.repo.retry_command_job(&account.0, &job_id).await?;
+        let job = with_progress(&state, job).await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Command,
+            AuditResource::CommandJob,
+            &job_id,
+        )
+        .detail(serde_json::json!({ "retried": job.progress.pending }));
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(job))
+    }
+}
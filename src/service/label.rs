@@ -1,45 +1,103 @@
-use super::{ApiTags, AppState};
-use crate::oai_schema;
+use super::{default_page, default_page_size, ApiTags, AppState, ClientIp};
+use crate::{audit::AuditLog, oai_schema, selector::Selector};
 use crate::{auth::JWTAuthorization, repository::Repository};
+use entity::audit_logs::{AuditAction, AuditResource};
 use poem::web::Data;
 use poem::Result;
 use poem_openapi::param::{Path, Query};
 use poem_openapi::{payload::Json, OpenApi};
+use serde_json::json;
 
 pub struct LabelService;
 
 #[OpenApi(prefix_path = "/label", tag = "ApiTags::Label")]
 impl LabelService {
+    /// 创建标签
+    #[oai(path = "/", method = "post")]
+    async fn create_label(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        body: Json<oai_schema::CreateLabel>,
+    ) -> Result<Json<oai_schema::Label>> {
+        let label: oai_schema::Label = state.repo.create_label(&account.0, &body).await?.into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Create,
+            AuditResource::Label,
+            &label.id,
+        )
+        .after(&label);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(label))
+    }
+
     /// 查询标签列表
     #[oai(path = "/", method = "get")]
-    #[allow(clippy::too_many_arguments)]
     async fn list_label(
         &self,
         state: Data<&AppState>,
         account: JWTAuthorization,
         /// 模糊查询标签名称
         q: Query<Option<String>>,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
     ) -> Result<Json<oai_schema::Labels>> {
-        let labels = state.repo.list_labels(&account.0, q.0).await?;
+        let (labels, total) = state
+            .repo
+            .list_labels(&account.0, page.0, page_size.0, q.0)
+            .await?;
         Ok(Json(oai_schema::Labels {
             results: labels.into_iter().map(|label| label.into()).collect(),
+            total,
         }))
     }
 
+    /// 获取标签详情
+    #[oai(path = "/:label_id", method = "get")]
+    async fn get_label(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        label_id: Path<String>,
+    ) -> Result<Json<oai_schema::Label>> {
+        let label = state.repo.get_label(&account.0, &label_id).await?;
+        Ok(Json(label.into()))
+    }
+
     /// 更新标签信息
     #[oai(path = "/:label_id", method = "patch")]
     async fn update_label(
         &self,
         state: Data<&AppState>,
         account: JWTAuthorization,
+        ip: ClientIp,
         label_id: Path<String>,
         body: Json<oai_schema::UpdateLabel>,
     ) -> Result<Json<oai_schema::Label>> {
-        let label = state
+        let before: oai_schema::Label = state.repo.get_label(&account.0, &label_id).await?.into();
+        let after: oai_schema::Label = state
             .repo
             .update_label(&account.0, &label_id, &body)
-            .await?;
-        Ok(Json(label.into()))
+            .await?
+            .into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::Label,
+            &label_id,
+        )
+        .before(&before)
+        .after(&after);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(after))
     }
 
     /// 删除标签
@@ -48,18 +106,111 @@ impl LabelService {
         &self,
         state: Data<&AppState>,
         account: JWTAuthorization,
+        ip: ClientIp,
         label_id: Path<String>,
     ) -> Result<()> {
+        let before: oai_schema::Label = state.repo.get_label(&account.0, &label_id).await?.into();
         state.repo.delete_label(&account.0, &label_id).await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Delete,
+            AuditResource::Label,
+            &label_id,
+        )
+        .before(&before);
+        state.repo.create_audit_log(log).await?;
         Ok(())
     }
 
+    /// 查询带有标签的设备列表
+    #[oai(path = "/:label_id/devices", method = "get")]
+    async fn list_label_devices(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        label_id: Path<String>,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::Devices>> {
+        let (devices, total) = state
+            .repo
+            .list_label_devices(&account.0, &label_id, page.0, page_size.0)
+            .await?;
+        Ok(Json(oai_schema::Devices {
+            results: devices.into_iter().map(|device| device.into()).collect(),
+            total,
+        }))
+    }
+
+    /// 批量给设备添加标签, 返回新添加的设备ID
+    #[oai(path = "/:label_id/devices", method = "post")]
+    async fn add_label_devices(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        label_id: Path<String>,
+        body: Json<oai_schema::LabelDevices>,
+    ) -> Result<Json<oai_schema::LabelDevices>> {
+        let device_ids = state
+            .repo
+            .add_label_devices(&account.0, &label_id, &body.device_ids)
+            .await?;
+        if !device_ids.is_empty() {
+            let log = AuditLog::new(
+                &account.0,
+                &ip.0,
+                AuditAction::Update,
+                AuditResource::Label,
+                &label_id,
+            )
+            .detail(json!({ "added_device_ids": device_ids }));
+            state.repo.create_audit_log(log).await?;
+        }
+        Ok(Json(oai_schema::LabelDevices { device_ids }))
+    }
+
+    /// 批量移除设备的标签, 返回实际移除的设备ID
+    #[oai(path = "/:label_id/devices", method = "delete")]
+    async fn remove_label_devices(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        label_id: Path<String>,
+        /// 需要移除的设备ID
+        device_id_in: Query<Vec<String>>,
+    ) -> Result<Json<oai_schema::LabelDevices>> {
+        let device_ids = state
+            .repo
+            .remove_label_devices(&account.0, &label_id, &device_id_in)
+            .await?;
+        if !device_ids.is_empty() {
+            let log = AuditLog::new(
+                &account.0,
+                &ip.0,
+                AuditAction::Update,
+                AuditResource::Label,
+                &label_id,
+            )
+            .detail(json!({ "removed_device_ids": device_ids }));
+            state.repo.create_audit_log(log).await?;
+        }
+        Ok(Json(oai_schema::LabelDevices { device_ids }))
+    }
+
     /// 向包含标签的设备批量发送指令
     #[oai(path = "/:label_id/command", method = "post")]
     async fn send_command_to_deivce(
         &self,
         state: Data<&AppState>,
         account: JWTAuthorization,
+        ip: ClientIp,
         label_id: Path<String>,
         req: Json<oai_schema::SendCommandToDeviceBatch>,
     ) -> Result<oai_schema::CommandResponse> {
@@ -67,6 +218,64 @@ impl LabelService {
             .repo
             .send_command_to_label(&account.0, &label_id, &req)
             .await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Command,
+            AuditResource::Label,
+            &label_id,
+        )
+        .detail(json!({
+            "message_id": message_id,
+            "command": req.command,
+            "payload": req.payload,
+            "ttl": req.ttl,
+            "qos": req.qos,
+        }));
+        state.repo.create_audit_log(log).await?;
         Ok(oai_schema::CommandResponse::new_async(message_id))
     }
+
+    /// 向标签选择器匹配的设备逐个发送异步指令
+    #[oai(path = "/command", method = "post")]
+    async fn send_command_by_selector(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        req: Json<oai_schema::SendCommandToSelector>,
+    ) -> Result<Json<oai_schema::SelectorCommandResponse>> {
+        let selector: Selector = req.selector.parse()?;
+        let results = state
+            .repo
+            .send_command_to_selector(&account.0, &selector, &req)
+            .await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Command,
+            AuditResource::Label,
+            &selector.to_string(),
+        )
+        .detail(json!({
+            "selector": selector.to_string(),
+            "command": req.command,
+            "payload": req.payload,
+            "ttl": req.ttl,
+            "qos": req.qos,
+            "message_ids": results.iter().map(|(_, id)| id).collect::<Vec<_>>(),
+        }));
+        state.repo.create_audit_log(log).await?;
+        let results = results
+            .into_iter()
+            .map(|(device_id, message_id)| oai_schema::DeviceCommand {
+                device_id,
+                message_id,
+            })
+            .collect::<Vec<_>>();
+        Ok(Json(oai_schema::SelectorCommandResponse {
+            total: results.len(),
+            results,
+        }))
+    }
 }
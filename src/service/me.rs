@@ -0,0 +1,95 @@
+use super::auth::verify_current_password;
+use super::password::revoke_reset_token;
+use super::{ApiTags, AppState, ClientIp};
+use crate::{audit::AuditLog, oai_schema};
+use crate::{auth::JWTAuthorization, errors::NeoiotError, repository::Repository};
+use entity::audit_logs::{AuditAction, AuditResource};
+use poem::web::Data;
+use poem::Result;
+use poem_openapi::{payload::Json, OpenApi};
+use serde_json::json;
+
+pub struct MeService;
+
+#[OpenApi(prefix_path = "/me", tag = "ApiTags::Me")]
+impl MeService {
+    /// 查询当前账号信息
+    #[oai(path = "/", method = "get")]
+    async fn get_profile(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+    ) -> Result<Json<oai_schema::Account>> {
+        let account = state.repo.get_account(&account.0).await?;
+        Ok(Json(account.into()))
+    }
+
+    /// 更新当前账号信息
+    #[oai(path = "/", method = "patch")]
+    async fn update_profile(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        body: Json<oai_schema::UpdateProfile>,
+    ) -> Result<Json<oai_schema::Account>> {
+        let account = state.repo.get_account(&account.0).await?;
+        if body.email.is_some() {
+            let password = body
+                .current_password
+                .as_deref()
+                .ok_or(NeoiotError::AuthenticateError)?;
+            verify_current_password(&state, &account, password).await?;
+        }
+        let req = oai_schema::UpdateAccount {
+            email: body.email.clone(),
+            name: body.name.clone(),
+            password: None,
+            must_change_password: None,
+        };
+        let before: oai_schema::Account = account.into();
+        let after: oai_schema::Account = state.repo.update_account(&before.id, &req).await?.into();
+        let log = AuditLog::new(
+            &after.id,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::Account,
+            &after.id,
+        )
+        .before(&before)
+        .after(&after);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(after))
+    }
+
+    /// 修改当前账号密码
+    #[oai(path = "/password", method = "post")]
+    async fn change_password(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        body: Json<oai_schema::ChangePassword>,
+    ) -> Result<()> {
+        let account = state.repo.get_account(&account.0).await?;
+        verify_current_password(&state, &account, &body.current_password).await?;
+        let req = oai_schema::UpdateAccount {
+            email: None,
+            name: None,
+            password: Some(body.new_password.clone()),
+            must_change_password: Some(false),
+        };
+        state.repo.update_account(&account.id, &req).await?;
+        revoke_reset_token(&state.cache, &account.id).await?;
+        let log = AuditLog::new(
+            &account.id,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::Account,
+            &account.id,
+        )
+        .detail(json!({ "password": "changed" }));
+        state.repo.create_audit_log(log).await?;
+        Ok(())
+    }
+}
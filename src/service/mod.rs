@@ -1,35 +1,122 @@
 mod account;
+mod alarm;
+mod audit;
 mod auth;
+mod config;
+mod d2d;
 mod device;
+mod forward;
+mod hook;
+mod job;
 mod label;
+mod me;
+mod ota;
+mod password;
+mod pki;
+mod provisioning;
+mod rule;
+mod schedule;
 mod schema;
+mod security;
+mod stream;
+mod totp;
+mod upload;
+mod webhook;
 
-use poem::{listener::TcpListener, middleware, EndpointExt, Route, Server};
+use std::net::IpAddr;
+
+use chrono::Local;
+use poem::{
+    get, listener::TcpListener, middleware, EndpointExt, FromRequest, Request, RequestBody, Route,
+    Server,
+};
 use poem_openapi::{OpenApiService, Tags};
 
 use crate::{
+    blob::{BlobStore, LocalBlobStore},
     cache::{Cache, RedisCache},
     config::SETTINGS,
+    notifier::{LogNotifier, Notifier},
     repository::{PostgresRepository, Repository},
+    rules,
 };
 
 use self::{
-    account::AccountService, auth::AuthService, device::DeviceService, label::LabelService,
-    schema::SchemaService,
+    account::AccountService, alarm::AlarmService, audit::AuditService, auth::AuthService,
+    config::ConfigService, d2d::D2dService, device::DeviceService, forward::ForwardService,
+    hook::HookService, job::JobService, label::LabelService, me::MeService, ota::OtaService,
+    password::PasswordService, pki::PkiService, provisioning::ProvisioningService,
+    rule::RuleService, schedule::ScheduleService, schema::SchemaService, security::SecurityService,
+    stream::StreamService, totp::TotpService, upload::UploadService, webhook::WebhookService,
 };
 
+/// 检查待触发规则的间隔
+const RULE_SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+/// 检查待升级告警的间隔
+const ALARM_SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+/// 检查待投递Webhook的间隔
+const WEBHOOK_SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+/// 每轮最多领取的Webhook投递数
+const WEBHOOK_BATCH_SIZE: u64 = 100;
+/// 检查到期定时指令的间隔
+const SCHEDULE_SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+/// 每轮最多领取的定时指令数
+const SCHEDULE_BATCH_SIZE: u64 = 100;
+/// 批量指令任务的调度间隔, 每轮每个任务最多下发`rate_limit`台设备
+const JOB_SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+/// 升级活动的调度间隔, 每轮每个活动最多推送`rate_limit`台设备
+const OTA_SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+/// 检查过期文件上传的间隔
+const UPLOAD_SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+/// 重新计算设备生效配置的间隔
+const CONFIG_SYNC_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+/// 每轮最多重新计算的设备数
+const CONFIG_SYNC_BATCH_SIZE: u64 = 100;
+
 #[derive(Tags)]
 enum ApiTags {
     /// Auth相关API
     Auth,
+    /// 当前账号相关API
+    Me,
     /// 账号相关API(需要管理员权限)
     Account,
     /// 标签相关API
     Label,
     /// 设备相关API
     Device,
+    /// 设备间通信相关API
+    D2d,
     /// 数据模型相关API
     Schema,
+    /// 审计日志相关API
+    Audit,
+    /// EMQX Hook
+    Hook,
+    /// 设备证书相关API
+    Pki,
+    /// 设备自注册相关API
+    Provisioning,
+    /// 遥测规则相关API
+    Rule,
+    /// 告警相关API
+    Alarm,
+    /// 平台事件订阅相关API
+    Webhook,
+    /// 数据转发相关API
+    Forward,
+    /// 实时推送相关API
+    Stream,
+    /// 定时指令相关API
+    Schedule,
+    /// 批量指令任务相关API
+    Job,
+    /// 固件升级相关API
+    Ota,
+    /// 设备文件上传相关API
+    Upload,
+    /// 远程配置相关API
+    Config,
 }
 const fn default_page() -> usize {
     1
@@ -38,24 +125,111 @@ const fn default_page_size() -> usize {
     10
 }
 
+/// 客户端IP地址，请求来自受信任的反向代理时读取`X-Forwarded-For`
+pub struct ClientIp(pub String);
+
+#[poem::async_trait]
+impl<'a> FromRequest<'a> for ClientIp {
+    async fn from_request(req: &'a Request, _body: &mut RequestBody) -> poem::Result<Self> {
+        let ip = match req.remote_addr().as_socket_addr() {
+            Some(addr) => client_ip(
+                addr.ip(),
+                req.header("X-Forwarded-For"),
+                &SETTINGS.login.trusted_proxies,
+            ),
+            None => req.remote_addr().to_string(),
+        };
+        Ok(Self(ip))
+    }
+}
+
+/// 对端不是受信任的代理时直接使用对端地址, 否则从右向左跳过受信任的代理,
+/// 取`X-Forwarded-For`中第一个不受信任的地址; 客户端可以伪造该头部左侧的任意值
+fn client_ip(peer: IpAddr, forwarded: Option<&str>, trusted_proxies: &[IpAddr]) -> String {
+    if !trusted_proxies.contains(&peer) {
+        return peer.to_string();
+    }
+    forwarded
+        .into_iter()
+        .flat_map(|value| value.rsplit(','))
+        .map(str::trim)
+        .filter(|ip| !ip.is_empty())
+        .find(|ip| {
+            !ip.parse::<IpAddr>()
+                .is_ok_and(|ip| trusted_proxies.contains(&ip))
+        })
+        .map(ToString::to_string)
+        .unwrap_or_else(|| peer.to_string())
+}
+
 #[derive(Clone)]
-pub struct AppState<R: Repository = PostgresRepository, C: Cache = RedisCache> {
+pub struct AppState<
+    R: Repository = PostgresRepository,
+    C: Cache = RedisCache,
+    N: Notifier = LogNotifier,
+    B: BlobStore = LocalBlobStore,
+> {
     pub repo: R,
     pub cache: C,
+    pub notifier: N,
+    pub blob: B,
 }
 
 pub async fn run() {
+    if SETTINGS.emqx.hook_token().is_none() {
+        tracing::error!(
+            "emqx.hook_token is not set, all EMQX auth, ACL and webhook requests will be rejected; \
+             set it and configure EMQX to send it in the X-Hook-Token header"
+        );
+    }
     let repo = PostgresRepository::new(SETTINGS.core.postgres_dsn.clone()).await;
     let cache = RedisCache::new(SETTINGS.core.redis_dsn.clone()).await;
     repo.initial_admin().await;
-    let state = AppState { repo, cache };
+    tokio::spawn(purge_decommissioned_devices(repo.clone()));
+    tokio::spawn(fire_pending_rules(repo.clone()));
+    tokio::spawn(escalate_stale_alarms(repo.clone()));
+    tokio::spawn(deliver_webhooks(repo.clone()));
+    tokio::spawn(run_scheduled_commands(repo.clone()));
+    tokio::spawn(run_command_jobs(repo.clone()));
+    tokio::spawn(run_ota_campaigns(repo.clone()));
+    tokio::spawn(sync_device_configs(repo.clone()));
+    let blob = LocalBlobStore::new(&SETTINGS.ota.blob_dir);
+    tokio::spawn(expire_device_uploads(repo.clone(), blob.clone()));
+    let state = AppState {
+        repo,
+        cache,
+        notifier: LogNotifier,
+        blob,
+    };
     let api_service = OpenApiService::new(
         (
             AuthService,
+            TotpService,
+            PasswordService,
+            MeService,
             AccountService,
+            SecurityService,
             LabelService,
             DeviceService,
+            D2dService,
             SchemaService,
+            AuditService,
+            HookService,
+            PkiService,
+            ProvisioningService,
+            // 元组最多支持16个元素, 之后的服务嵌套在内层元组中
+            (
+                RuleService,
+                AlarmService,
+                WebhookService,
+                ForwardService,
+                StreamService,
+                ScheduleService,
+                JobService,
+                OtaService,
+                UploadService,
+                ConfigService,
+            ),
         ),
         "NEOIOT Core",
         "v1.0",
@@ -65,15 +239,16 @@ pub async fn run() {
     let rapidoc = api_service.rapidoc();
 
     let api_service = api_service
-        .with(middleware::Tracing::default())
+        .with(middleware::Tracing)
         .with(middleware::Cors::new())
         .with(middleware::NormalizePath::new(
             middleware::TrailingSlash::Trim,
         ))
-        .with(middleware::Compression::default());
+        .with(middleware::Compression);
     Server::new(TcpListener::bind(SETTINGS.core.endpoint.as_str()))
         .run(
             Route::new()
+                .at("/api/stream/ws", get(stream::websocket))
                 .nest("/api", api_service)
                 .nest("/swagger", swagger)
                 .nest("/redoc", redoc)
@@ -83,3 +258,231 @@ pub async fn run() {
         .await
         .unwrap();
 }
+
+/// 定期彻底删除超过保留期的退役设备
+async fn purge_decommissioned_devices<R: Repository>(repo: R) {
+    let config = &SETTINGS.lifecycle;
+    let mut interval =
+        tokio::time::interval(std::time::Duration::from_secs(config.purge_interval_secs));
+    loop {
+        interval.tick().await;
+        let before = Local::now() - chrono::Duration::days(config.retention_days);
+        match repo.purge_decommissioned_devices(before).await {
+            Ok(0) => {}
+            Ok(count) => tracing::info!(count, "purged decommissioned devices"),
+            Err(err) => tracing::error!(?err, "failed to purge decommissioned devices"),
+        }
+    }
+}
+
+/// 定期触发条件已持续足够时长但设备之后没有再上报的规则
+async fn fire_pending_rules<R: Repository>(repo: R) {
+    let mut interval = tokio::time::interval(RULE_SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        match repo.fire_pending_rules().await {
+            Ok(fired) => {
+                for fired in fired {
+                    rules::execute(&repo, fired).await;
+                }
+            }
+            Err(err) => tracing::error!(?err, "failed to fire pending rules"),
+        }
+    }
+}
+
+/// 定期升级长时间未确认的告警
+async fn escalate_stale_alarms<R: Repository>(repo: R) {
+    let escalate_after_secs = SETTINGS.alarm.escalate_after_secs;
+    if escalate_after_secs <= 0 {
+        return;
+    }
+    let mut interval = tokio::time::interval(ALARM_SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        let before = Local::now() - chrono::Duration::seconds(escalate_after_secs);
+        match repo.escalate_stale_alarms(before).await {
+            Ok(0) => {}
+            Ok(count) => tracing::info!(count, "escalated stale alarms"),
+            Err(err) => tracing::error!(?err, "failed to escalate stale alarms"),
+        }
+    }
+}
+
+/// 定期推送到期的Webhook投递, 每条投递在独立的任务中执行, 慢速的订阅方不阻塞其他投递
+async fn deliver_webhooks<R: Repository + Clone>(repo: R) {
+    let timeout = std::time::Duration::from_secs(SETTINGS.webhook.timeout_secs);
+    let mut interval = tokio::time::interval(WEBHOOK_SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        match repo.claim_webhook_deliveries(WEBHOOK_BATCH_SIZE).await {
+            Ok(claimed) => {
+                for (delivery, hook) in claimed {
+                    let repo = repo.clone();
+                    tokio::spawn(async move {
+                        crate::webhook::process(&repo, hook, delivery, timeout).await;
+                    });
+                }
+            }
+            Err(err) => tracing::error!(?err, "failed to claim webhook deliveries"),
+        }
+    }
+}
+
+/// 定期下发到期的定时指令, 每条指令在独立的任务中下发, 不阻塞下一轮调度
+async fn run_scheduled_commands<R: Repository + Clone>(repo: R) {
+    let mut interval = tokio::time::interval(SCHEDULE_SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        match repo.claim_due_scheduled_commands(SCHEDULE_BATCH_SIZE).await {
+            Ok(claimed) => {
+                for (command, scheduled_at) in claimed {
+                    let repo = repo.clone();
+                    tokio::spawn(async move {
+                        crate::schedule::execute(&repo, command, scheduled_at).await;
+                    });
+                }
+            }
+            Err(err) => tracing::error!(?err, "failed to claim scheduled commands"),
+        }
+    }
+}
+
+/// 按速率下发批量指令任务, 并将超时未回复的设备标记为超时
+async fn run_command_jobs<R: Repository + Clone>(repo: R) {
+    let mut interval = tokio::time::interval(JOB_SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        match repo.claim_command_job_devices().await {
+            Ok(claimed) => {
+                for (job, targets) in claimed {
+                    let repo = repo.clone();
+                    tokio::spawn(async move {
+                        crate::jobs::process(&repo, job, targets).await;
+                    });
+                }
+            }
+            Err(err) => tracing::error!(?err, "failed to claim command job devices"),
+        }
+        match repo.sweep_command_jobs().await {
+            Ok(0) => {}
+            Ok(count) => tracing::info!(count, "completed command jobs"),
+            Err(err) => tracing::error!(?err, "failed to sweep command jobs"),
+        }
+    }
+}
+
+/// 按速率推送升级通知, 将超时未完成的设备标记为失败, 并按失败率和进度中止、推进或结束活动
+async fn run_ota_campaigns<R: Repository + Clone>(repo: R) {
+    let mut interval = tokio::time::interval(OTA_SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        match repo.claim_ota_campaign_devices().await {
+            Ok(claimed) => {
+                for (campaign, firmware, targets) in claimed {
+                    let repo = repo.clone();
+                    tokio::spawn(async move {
+                        crate::ota::process(&repo, campaign, firmware, targets).await;
+                    });
+                }
+            }
+            Err(err) => tracing::error!(?err, "failed to claim ota campaign devices"),
+        }
+        match repo.sweep_ota_campaigns().await {
+            Ok(changed) => {
+                for campaign in changed {
+                    tracing::info!(
+                        campaign_id = %campaign.id,
+                        status = ?campaign.status,
+                        stage = ?campaign.stage,
+                        reason = ?campaign.abort_reason,
+                        "ota campaign changed"
+                    );
+                }
+            }
+            Err(err) => tracing::error!(?err, "failed to sweep ota campaigns"),
+        }
+    }
+}
+
+/// 定期将超时未收到全部分片的文件上传标记为过期, 并删除已收到的分片
+async fn expire_device_uploads<R: Repository, B: BlobStore>(repo: R, blob: B) {
+    let mut interval = tokio::time::interval(UPLOAD_SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        match repo.expire_device_uploads().await {
+            Ok(expired) => {
+                for upload in expired {
+                    tracing::info!(upload_id = %upload.id, "device upload expired");
+                    // 过期后不再记录新的分片, 已记录的分片就是需要清理的全部分片
+                    match repo.list_upload_chunks(&upload.id).await {
+                        Ok(chunks) => crate::uploads::delete_chunks(&blob, &chunks).await,
+                        Err(err) => tracing::error!(?err, "failed to list device upload chunks"),
+                    }
+                }
+            }
+            Err(err) => tracing::error!(?err, "failed to expire device uploads"),
+        }
+    }
+}
+
+/// 重新计算标签或分配变化的设备的生效配置, 配置变化时推送到设备; 推送失败的设备留到下一轮重试
+async fn sync_device_configs<R: Repository>(repo: R) {
+    let mut interval = tokio::time::interval(CONFIG_SYNC_INTERVAL);
+    loop {
+        interval.tick().await;
+        let changed = match repo.sync_device_configs(CONFIG_SYNC_BATCH_SIZE).await {
+            Ok(changed) => changed,
+            Err(err) => {
+                tracing::error!(?err, "failed to sync device configs");
+                continue;
+            }
+        };
+        for (device, config) in changed {
+            let result = match crate::device_config::push(&device, &config).await {
+                Ok(()) => {
+                    repo.mark_device_config_pushed(&device.id, &config.version)
+                        .await
+                }
+                Err(err) => {
+                    tracing::warn!(device_id = %device.id, ?err, "failed to push device config");
+                    repo.mark_device_configs_stale(vec![device.id.clone()])
+                        .await
+                }
+            };
+            if let Err(err) = result {
+                tracing::error!(device_id = %device.id, ?err, "failed to record device config push");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_ip() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let client: IpAddr = "203.0.113.7".parse().unwrap();
+        // 不受信任的对端伪造的头部被忽略
+        assert_eq!(
+            client_ip(client, Some("198.51.100.1"), &[proxy]),
+            "203.0.113.7"
+        );
+        assert_eq!(client_ip(client, Some("198.51.100.1"), &[]), "203.0.113.7");
+        // 受信任的代理追加了真实的客户端地址, 客户端伪造的左侧值被忽略
+        assert_eq!(
+            client_ip(proxy, Some("198.51.100.1, 203.0.113.7"), &[proxy]),
+            "203.0.113.7"
+        );
+        // 多级代理
+        let edge: IpAddr = "10.0.0.2".parse().unwrap();
+        assert_eq!(
+            client_ip(proxy, Some("203.0.113.7, 10.0.0.2"), &[proxy, edge]),
+            "203.0.113.7"
+        );
+        assert_eq!(client_ip(proxy, None, &[proxy]), "10.0.0.1");
+        assert_eq!(client_ip(proxy, Some(" "), &[proxy]), "10.0.0.1");
+    }
+}
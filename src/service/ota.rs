@@ -0,0 +1,339 @@
+use super::{default_page, default_page_size, ApiTags, AppState, ClientIp};
+use crate::{
+    audit::AuditLog,
+    auth::JWTAuthorization,
+    blob::BlobStore,
+    config::SETTINGS,
+    errors::NeoiotError,
+    oai_schema,
+    ota::{self, NewFirmware},
+    repository::Repository,
+};
+use chrono::Local;
+use entity::audit_logs::{AuditAction, AuditResource};
+use entity::ota_campaign_devices::OtaDeviceStatus;
+use entity::ota_campaigns::OtaCampaignStatus;
+use entity::prelude::*;
+use poem::error::InternalServerError;
+use poem::web::Data;
+use poem::Result;
+use poem_openapi::param::{Path, Query};
+use poem_openapi::payload::{Attachment, Json};
+use poem_openapi::OpenApi;
+
+pub struct OtaService;
+
+async fn with_progress(
+    state: &AppState,
+    campaign: OtaCampaignModel,
+) -> Result<oai_schema::OtaCampaign> {
+    let progress = state.repo.count_ota_campaign_devices(&campaign.id).await?;
+    Ok(oai_schema::OtaCampaign::new(campaign, progress))
+}
+
+/// 固件升级
+///
+/// 固件按数据模型和版本上传到blob存储; 升级活动在创建时将标签展开为同一数据模型的设备,
+/// 向`ota/{account_id}/{device_id}/notify`推送包含签名下载链接的升级通知, 先推送金丝雀设备,
+/// 金丝雀设备全部结束后自动或手动推送到其余设备。设备向`otar/{account_id}/{device_id}/progress`
+/// 上报升级进度, 重启后向`otar/{account_id}/{device_id}/version`上报固件版本,
+/// 版本与活动的固件一致即升级成功; 已结束设备的失败率超过阈值时活动自动中止
+#[OpenApi(prefix_path = "/ota", tag = "ApiTags::Ota")]
+impl OtaService {
+    /// 上传固件
+    #[oai(path = "/firmware", method = "post")]
+    async fn upload_firmware(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        body: oai_schema::UploadFirmware,
+    ) -> Result<Json<oai_schema::Firmware>> {
+        let filename = body.file.file_name().unwrap_or("firmware.bin").to_string();
+        let data = body.file.into_vec().await.map_err(InternalServerError)?;
+        if data.is_empty() || data.len() > SETTINGS.ota.max_firmware_size {
+            return Err(NeoiotError::InvalidArgument(format!(
+                "firmware size must be between 1 and {} bytes",
+                SETTINGS.ota.max_firmware_size
+            ))
+            .into());
+        }
+        let checksum = ota::checksum(&data);
+        if let Some(expected) = &body.checksum {
+            if !expected.eq_ignore_ascii_case(&checksum) {
+                return Err(NeoiotError::InvalidArgument(format!(
+                    "checksum mismatch, uploaded file is {}",
+                    checksum
+                ))
+                .into());
+            }
+        }
+        let firmware_id = xid::new().to_string();
+        let req = NewFirmware {
+            blob_key: ota::blob_key(&account.0, &firmware_id),
+            id: firmware_id,
+            schema_id: body.schema_id,
+            version: body.version,
+            checksum,
+            size: data.len() as i64,
+            filename,
+            description: body.description,
+        };
+        state.blob.put(&req.blob_key, data).await?;
+        let firmware: oai_schema::Firmware =
+            match state.repo.create_firmware(&account.0, &req).await {
+                Ok(firmware) => firmware.into(),
+                Err(err) => {
+                    state.blob.delete(&req.blob_key).await?;
+                    return Err(err.into());
+                }
+            };
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Create,
+            AuditResource::Firmware,
+            &firmware.id,
+        )
+        .after(&firmware);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(firmware))
+    }
+
+    /// 查询固件列表
+    #[oai(path = "/firmware", method = "get")]
+    async fn list_firmwares(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        /// 数据模型ID
+        schema_id: Query<Option<String>>,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::Firmwares>> {
+        let (firmwares, total) = state
+            .repo
+            .list_firmwares(&account.0, schema_id.0, page.0, page_size.0)
+            .await?;
+        Ok(Json(oai_schema::Firmwares {
+            results: firmwares.into_iter().map(Into::into).collect(),
+            total,
+        }))
+    }
+
+    /// 获取固件详情
+    #[oai(path = "/firmware/:firmware_id", method = "get")]
+    async fn get_firmware(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        firmware_id: Path<String>,
+    ) -> Result<Json<oai_schema::Firmware>> {
+        let firmware = state.repo.get_firmware(&account.0, &firmware_id).await?;
+        Ok(Json(firmware.into()))
+    }
+
+    /// 删除固件, 有进行中的升级活动时不能删除
+    #[oai(path = "/firmware/:firmware_id", method = "delete")]
+    async fn delete_firmware(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        firmware_id: Path<String>,
+    ) -> Result<()> {
+        let firmware = state.repo.delete_firmware(&account.0, &firmware_id).await?;
+        state.blob.delete(&firmware.blob_key).await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Delete,
+            AuditResource::Firmware,
+            &firmware_id,
+        )
+        .before(&oai_schema::Firmware::from(firmware));
+        state.repo.create_audit_log(log).await?;
+        Ok(())
+    }
+
+    /// 设备下载固件, 使用升级通知中的签名链接, 不需要登录
+    #[oai(path = "/firmware/:firmware_id/download", method = "get")]
+    async fn download_firmware(
+        &self,
+        state: Data<&AppState>,
+        firmware_id: Path<String>,
+        /// 设备ID
+        device_id: Query<String>,
+        /// 链接过期时间(Unix时间戳)
+        expires: Query<i64>,
+        /// 链接签名
+        signature: Query<String>,
+    ) -> Result<Attachment<Vec<u8>>> {
+        let valid = ota::verify_download(
+            &SETTINGS.core.secret,
+            &firmware_id,
+            &device_id,
+            expires.0,
+            &signature,
+            Local::now(),
+        );
+        if !valid {
+            return Err(NeoiotError::AuthenticateError.into());
+        }
+        let firmware = state.repo.find_firmware(&firmware_id).await?;
+        let data =
+            state.blob.get(&firmware.blob_key).await?.ok_or_else(|| {
+                NeoiotError::ObjectNotFound(format!("firmware {}", firmware_id.0))
+            })?;
+        Ok(Attachment::new(data).filename(firmware.filename))
+    }
+
+    /// 创建升级活动
+    #[oai(path = "/campaign", method = "post")]
+    async fn create_campaign(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        body: Json<oai_schema::CreateOtaCampaign>,
+    ) -> Result<Json<oai_schema::OtaCampaign>> {
+        let campaign = state
+            .repo
+            .create_ota_campaign(&account.0, &account.0, &body)
+            .await?;
+        let campaign = with_progress(&state, campaign).await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Create,
+            AuditResource::OtaCampaign,
+            &campaign.id,
+        )
+        .after(&campaign);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(campaign))
+    }
+
+    /// 查询升级活动列表
+    #[oai(path = "/campaign", method = "get")]
+    async fn list_campaigns(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        /// 活动状态
+        status: Query<Option<OtaCampaignStatus>>,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::OtaCampaigns>> {
+        let (campaigns, total) = state
+            .repo
+            .list_ota_campaigns(&account.0, status.0, page.0, page_size.0)
+            .await?;
+        let mut results = Vec::with_capacity(campaigns.len());
+        for campaign in campaigns {
+            results.push(with_progress(&state, campaign).await?);
+        }
+        Ok(Json(oai_schema::OtaCampaigns { results, total }))
+    }
+
+    /// 获取升级活动详情及进度
+    #[oai(path = "/campaign/:campaign_id", method = "get")]
+    async fn get_campaign(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        campaign_id: Path<String>,
+    ) -> Result<Json<oai_schema::OtaCampaign>> {
+        let campaign = state
+            .repo
+            .get_ota_campaign(&account.0, &campaign_id)
+            .await?;
+        Ok(Json(with_progress(&state, campaign).await?))
+    }
+
+    /// 查询活动中每台设备的升级进度
+    #[oai(path = "/campaign/:campaign_id/devices", method = "get")]
+    async fn list_campaign_devices(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        campaign_id: Path<String>,
+        /// 设备状态
+        status: Query<Option<OtaDeviceStatus>>,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::OtaCampaignDevices>> {
+        let (targets, total) = state
+            .repo
+            .list_ota_campaign_devices(&account.0, &campaign_id, status.0, page.0, page_size.0)
+            .await?;
+        Ok(Json(oai_schema::OtaCampaignDevices {
+            results: targets.into_iter().map(Into::into).collect(),
+            total,
+        }))
+    }
+
+    /// 结束金丝雀阶段, 推送到其余设备
+    #[oai(path = "/campaign/:campaign_id/promote", method = "post")]
+    async fn promote_campaign(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        campaign_id: Path<String>,
+    ) -> Result<Json<oai_schema::OtaCampaign>> {
+        let campaign = state
+            .repo
+            .promote_ota_campaign(&account.0, &campaign_id)
+            .await?;
+        let campaign = with_progress(&state, campaign).await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::OtaCampaign,
+            &campaign_id,
+        )
+        .detail(serde_json::json!({ "promoted": true }));
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(campaign))
+    }
+
+    /// 中止升级活动, 尚未推送的设备不再推送, 已推送的设备继续上报进度
+    #[oai(path = "/campaign/:campaign_id/abort", method = "post")]
+    async fn abort_campaign(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        campaign_id: Path<String>,
+    ) -> Result<Json<oai_schema::OtaCampaign>> {
+        let campaign = state
+            .repo
+            .abort_ota_campaign(&account.0, &campaign_id, "aborted manually")
+            .await?;
+        let campaign = with_progress(&state, campaign).await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::OtaCampaign,
+            &campaign_id,
+        )
+        .detail(serde_json::json!({ "aborted": true }));
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(campaign))
+    }
+}
@@ -0,0 +1,138 @@
+use super::auth::lock_key;
+use super::{ApiTags, AppState, ClientIp};
+use crate::config::SETTINGS;
+use crate::notifier::Notifier;
+use crate::oai_schema;
+use crate::{cache::Cache, errors::NeoiotError, repository::Repository};
+use poem::web::Data;
+use poem::Result;
+use poem_openapi::{payload::Json, OpenApi};
+use rand::{distributions::Alphanumeric, Rng};
+use sha2::{Digest, Sha256};
+
+pub struct PasswordService;
+
+/// 每个邮箱每小时最多发送的重置邮件数
+const MAX_RESET_REQUESTS: i64 = 5;
+
+#[OpenApi(prefix_path = "/auth/password", tag = "ApiTags::Auth")]
+impl PasswordService {
+    /// 申请重置密码
+    ///
+    /// 无论邮箱是否存在都返回成功，避免泄露账号信息
+    #[oai(path = "/forgot", method = "post")]
+    async fn forgot_password(
+        &self,
+        state: Data<&AppState>,
+        ip: ClientIp,
+        body: Json<oai_schema::ForgotPassword>,
+    ) -> Result<()> {
+        let email = body.email.to_lowercase();
+        let limits = [
+            format!("password_reset:email:{}", email),
+            format!("password_reset:ip:{}", ip.0),
+        ];
+        for key in limits {
+            if state.cache.incr(&key, 3600).await? > MAX_RESET_REQUESTS {
+                let ttl = state.cache.ttl(&key).await?.unwrap_or(3600);
+                return Err(NeoiotError::TooManyRequests(ttl).into());
+            }
+        }
+        let account = match state.repo.get_account_by_email(&body.email).await {
+            Ok(account) => account,
+            Err(_) => return Ok(()),
+        };
+        let token: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(48)
+            .map(char::from)
+            .collect();
+        let config = &SETTINGS.login;
+        // 每个账号只保留最新签发的凭证
+        revoke_reset_token(&state.cache, &account.id).await?;
+        let digest = token_digest(&token);
+        state
+            .cache
+            .set_ex(
+                &reset_token_key(&digest),
+                &account.id,
+                config.password_reset_ttl_secs,
+            )
+            .await?;
+        state
+            .cache
+            .set_ex(
+                &reset_account_key(&account.id),
+                &digest,
+                config.password_reset_ttl_secs,
+            )
+            .await?;
+        let link = match &config.password_reset_url {
+            Some(url) => format!("{}?token={}", url, token),
+            None => token,
+        };
+        let body = format!(
+            "Hi {},\n\nUse the following link or token to reset your password, it expires in {} minutes:\n\n{}\n\nIf you did not request a password reset, please ignore this email.",
+            account.name,
+            config.password_reset_ttl_secs / 60,
+            link
+        );
+        state
+            .notifier
+            .send_email(&account.email, "Reset your NEOIOT password", &body)
+            .await?;
+        Ok(())
+    }
+
+    /// 使用重置凭证设置新密码
+    #[oai(path = "/reset", method = "post")]
+    async fn reset_password(
+        &self,
+        state: Data<&AppState>,
+        body: Json<oai_schema::ResetPassword>,
+    ) -> Result<()> {
+        let account_id = state
+            .cache
+            .take(&reset_token_key(&token_digest(&body.token)))
+            .await?
+            .ok_or(NeoiotError::AuthenticateError)?;
+        let req = oai_schema::UpdateAccount {
+            email: None,
+            name: None,
+            password: Some(body.new_password.clone()),
+            must_change_password: Some(false),
+        };
+        let account = state.repo.update_account(&account_id, &req).await?;
+        revoke_reset_token(&state.cache, &account.id).await?;
+        state
+            .cache
+            .del(&lock_key(&account.email.to_lowercase()))
+            .await?;
+        Ok(())
+    }
+}
+
+// 缓存中只保存凭证的摘要
+fn token_digest(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn reset_token_key(digest: &str) -> String {
+    format!("password_reset:token:{}", digest)
+}
+
+// 账号当前有效的凭证摘要
+fn reset_account_key(account_id: &str) -> String {
+    format!("password_reset:account:{}", account_id)
+}
+
+/// 作废账号尚未使用的重置凭证, 签发新凭证以及任何方式修改密码后调用
+pub async fn revoke_reset_token<C: Cache>(
+    cache: &C,
+    account_id: &str,
+) -> crate::errors::Result<()> {
+    if let Some(digest) = cache.take(&reset_account_key(account_id)).await? {
+        cache.del(&reset_token_key(&digest)).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,34 @@
+use super::{ApiTags, AppState};
+use crate::{auth::JWTAuthorization, repository::Repository};
+use poem::web::Data;
+use poem::Result;
+use poem_openapi::param::Path;
+use poem_openapi::payload::PlainText;
+use poem_openapi::OpenApi;
+
+pub struct PkiService;
+
+#[OpenApi(prefix_path = "/pki", tag = "ApiTags::Pki")]
+impl PkiService {
+    /// 获取当前账号的设备CA证书(PEM), 用于配置EMQX的双向TLS
+    #[oai(path = "/ca", method = "get")]
+    async fn get_ca(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+    ) -> Result<PlainText<String>> {
+        let ca = state.repo.get_or_create_device_ca(&account.0).await?;
+        Ok(PlainText(ca.cert_pem))
+    }
+
+    /// 获取账号设备CA的证书吊销列表(PEM), 无需认证
+    #[oai(path = "/:account_id/crl", method = "get")]
+    async fn get_crl(
+        &self,
+        state: Data<&AppState>,
+        account_id: Path<String>,
+    ) -> Result<PlainText<String>> {
+        let crl = state.repo.generate_device_crl(&account_id).await?;
+        Ok(PlainText(crl))
+    }
+}
@@ -0,0 +1,218 @@
+use super::{default_page, default_page_size, ApiTags, AppState, ClientIp};
+use crate::events::{self, Event};
+use crate::{audit::AuditLog, auth::JWTAuthorization, repository::Repository};
+use crate::{cache::Cache, errors::NeoiotError, oai_schema};
+use entity::audit_logs::{AuditAction, AuditResource};
+use entity::webhook_deliveries::EventType;
+use poem::web::Data;
+use poem::Result;
+use poem_openapi::param::{Path, Query};
+use poem_openapi::{payload::Json, OpenApi};
+use serde_json::json;
+
+/// 单个IP每分钟最多的注册请求数
+const MAX_REGISTER_REQUESTS: i64 = 30;
+
+pub struct ProvisioningService;
+
+#[OpenApi(prefix_path = "/provisioning", tag = "ApiTags::Provisioning")]
+impl ProvisioningService {
+    /// 创建注册模板
+    ///
+    /// 认领令牌只在此时返回一次, 需要烧录到设备中
+    #[oai(path = "/profile", method = "post")]
+    async fn create_profile(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        body: Json<oai_schema::CreateProvisioningProfile>,
+    ) -> Result<Json<oai_schema::ProvisioningProfile>> {
+        let (profile, claim_token) = state
+            .repo
+            .create_provisioning_profile(&account.0, &body)
+            .await?;
+        let mut profile: oai_schema::ProvisioningProfile = profile.into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Create,
+            AuditResource::ProvisioningProfile,
+            &profile.id,
+        )
+        .after(&profile);
+        state.repo.create_audit_log(log).await?;
+        profile.claim_token = Some(claim_token);
+        Ok(Json(profile))
+    }
+
+    /// 查询注册模板列表
+    #[oai(path = "/profile", method = "get")]
+    async fn list_profiles(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::ProvisioningProfiles>> {
+        let (profiles, total) = state
+            .repo
+            .list_provisioning_profiles(&account.0, page.0, page_size.0)
+            .await?;
+        Ok(Json(oai_schema::ProvisioningProfiles {
+            results: profiles.into_iter().map(Into::into).collect(),
+            total,
+        }))
+    }
+
+    /// 获取注册模板详情
+    #[oai(path = "/profile/:profile_id", method = "get")]
+    async fn get_profile(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        profile_id: Path<String>,
+    ) -> Result<Json<oai_schema::ProvisioningProfile>> {
+        let profile = state
+            .repo
+            .get_provisioning_profile(&account.0, &profile_id)
+            .await?;
+        Ok(Json(profile.into()))
+    }
+
+    /// 更新注册模板
+    #[oai(path = "/profile/:profile_id", method = "patch")]
+    async fn update_profile(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        profile_id: Path<String>,
+        body: Json<oai_schema::UpdateProvisioningProfile>,
+    ) -> Result<Json<oai_schema::ProvisioningProfile>> {
+        let before: oai_schema::ProvisioningProfile = state
+            .repo
+            .get_provisioning_profile(&account.0, &profile_id)
+            .await?
+            .into();
+        let after: oai_schema::ProvisioningProfile = state
+            .repo
+            .update_provisioning_profile(&account.0, &profile_id, &body)
+            .await?
+            .into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::ProvisioningProfile,
+            &profile_id,
+        )
+        .before(&before)
+        .after(&after);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(after))
+    }
+
+    /// 重置认领令牌, 旧令牌立即失效
+    #[oai(path = "/profile/:profile_id/claim_token", method = "post")]
+    async fn reset_claim_token(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        profile_id: Path<String>,
+    ) -> Result<Json<oai_schema::ProvisioningProfile>> {
+        let (profile, claim_token) = state
+            .repo
+            .reset_provisioning_claim_token(&account.0, &profile_id)
+            .await?;
+        let mut profile: oai_schema::ProvisioningProfile = profile.into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::ProvisioningProfile,
+            &profile_id,
+        )
+        .detail(json!({ "claim_token_reset": true }));
+        state.repo.create_audit_log(log).await?;
+        profile.claim_token = Some(claim_token);
+        Ok(Json(profile))
+    }
+
+    /// 删除注册模板, 已注册的设备不受影响
+    #[oai(path = "/profile/:profile_id", method = "delete")]
+    async fn delete_profile(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        profile_id: Path<String>,
+    ) -> Result<()> {
+        let before: oai_schema::ProvisioningProfile = state
+            .repo
+            .get_provisioning_profile(&account.0, &profile_id)
+            .await?
+            .into();
+        state
+            .repo
+            .delete_provisioning_profile(&account.0, &profile_id)
+            .await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Delete,
+            AuditResource::ProvisioningProfile,
+            &profile_id,
+        )
+        .before(&before);
+        state.repo.create_audit_log(log).await?;
+        Ok(())
+    }
+
+    /// 设备自注册
+    ///
+    /// 设备使用注册模板的认领令牌和自身的硬件序列号注册, 无需登录, 返回设备ID和连接凭据
+    #[oai(path = "/register", method = "post")]
+    async fn register(
+        &self,
+        state: Data<&AppState>,
+        ip: ClientIp,
+        body: Json<oai_schema::RegisterDevice>,
+    ) -> Result<Json<oai_schema::RegisteredDevice>> {
+        let key = format!("provisioning:register:ip:{}", ip.0);
+        if state.cache.incr(&key, 60).await? > MAX_REGISTER_REQUESTS {
+            let ttl = state.cache.ttl(&key).await?.unwrap_or(60);
+            return Err(NeoiotError::TooManyRequests(ttl).into());
+        }
+        let (device, issued) = state.repo.register_device(&body).await?;
+        let log = AuditLog::new(
+            &device.account_id,
+            &ip.0,
+            AuditAction::Create,
+            AuditResource::Device,
+            &device.id,
+        )
+        .after(&oai_schema::Device::from(device.clone()))
+        .detail(json!({
+            "provisioning_profile_id": device.provisioning_profile_id,
+            "hardware_serial": device.hardware_serial,
+        }));
+        state.repo.create_audit_log(log).await?;
+        events::emit(
+            &state.repo,
+            Event::new(&device.account_id, EventType::DeviceCreated)
+                .device(&device.id)
+                .object(&oai_schema::Device::from(device.clone())),
+        )
+        .await;
+        Ok(Json(oai_schema::RegisteredDevice {
+            credential: oai_schema::DeviceCredentialSecret::new(&device.mqtt_username, issued),
+            device_id: device.id,
+        }))
+    }
+}
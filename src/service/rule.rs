@@ -0,0 +1,152 @@
+use super::{default_page, default_page_size, ApiTags, AppState, ClientIp};
+use crate::{audit::AuditLog, auth::JWTAuthorization, oai_schema, repository::Repository};
+use entity::audit_logs::{AuditAction, AuditResource};
+use poem::web::Data;
+use poem::Result;
+use poem_openapi::param::{Path, Query};
+use poem_openapi::{payload::Json, OpenApi};
+
+pub struct RuleService;
+
+/// 遥测规则
+///
+/// 设备向`metrics/{account_id}/{device_id}/{field}`上报字段值, 需要在EMQX WebHook中为
+/// `metrics/#`配置`message.publish`; 条件满足并持续`duration_secs`后执行一次动作,
+/// 条件不再满足后重新计时
+#[OpenApi(prefix_path = "/rule", tag = "ApiTags::Rule")]
+impl RuleService {
+    /// 创建规则
+    #[oai(path = "/", method = "post")]
+    async fn create_rule(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        body: Json<oai_schema::CreateRule>,
+    ) -> Result<Json<oai_schema::Rule>> {
+        let rule: oai_schema::Rule = state.repo.create_rule(&account.0, &body).await?.into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Create,
+            AuditResource::Rule,
+            &rule.id,
+        )
+        .after(&rule);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(rule))
+    }
+
+    /// 查询规则列表
+    #[oai(path = "/", method = "get")]
+    async fn list_rules(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::Rules>> {
+        let (rules, total) = state
+            .repo
+            .list_rules(&account.0, page.0, page_size.0)
+            .await?;
+        Ok(Json(oai_schema::Rules {
+            results: rules.into_iter().map(Into::into).collect(),
+            total,
+        }))
+    }
+
+    /// 获取规则详情
+    #[oai(path = "/:rule_id", method = "get")]
+    async fn get_rule(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        rule_id: Path<String>,
+    ) -> Result<Json<oai_schema::Rule>> {
+        let rule = state.repo.get_rule(&account.0, &rule_id).await?;
+        Ok(Json(rule.into()))
+    }
+
+    /// 更新规则
+    #[oai(path = "/:rule_id", method = "patch")]
+    async fn update_rule(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        rule_id: Path<String>,
+        body: Json<oai_schema::UpdateRule>,
+    ) -> Result<Json<oai_schema::Rule>> {
+        let before: oai_schema::Rule = state.repo.get_rule(&account.0, &rule_id).await?.into();
+        let after: oai_schema::Rule = state
+            .repo
+            .update_rule(&account.0, &rule_id, &body)
+            .await?
+            .into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::Rule,
+            &rule_id,
+        )
+        .before(&before)
+        .after(&after);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(after))
+    }
+
+    /// 删除规则
+    #[oai(path = "/:rule_id", method = "delete")]
+    async fn delete_rule(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        rule_id: Path<String>,
+    ) -> Result<()> {
+        let before: oai_schema::Rule = state.repo.get_rule(&account.0, &rule_id).await?.into();
+        state.repo.delete_rule(&account.0, &rule_id).await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Delete,
+            AuditResource::Rule,
+            &rule_id,
+        )
+        .before(&before);
+        state.repo.create_audit_log(log).await?;
+        Ok(())
+    }
+
+    /// 查询规则的执行记录
+    #[oai(path = "/:rule_id/executions", method = "get")]
+    async fn list_executions(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        rule_id: Path<String>,
+        /// 设备ID
+        device_id: Query<Option<String>>,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::RuleExecutions>> {
+        let (executions, total) = state
+            .repo
+            .list_rule_executions(&account.0, &rule_id, device_id.0, page.0, page_size.0)
+            .await?;
+        Ok(Json(oai_schema::RuleExecutions {
+            results: executions.into_iter().map(Into::into).collect(),
+            total,
+        }))
+    }
+}
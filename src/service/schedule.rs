@@ -0,0 +1,167 @@
+use super::{default_page, default_page_size, ApiTags, AppState, ClientIp};
+use crate::{audit::AuditLog, auth::JWTAuthorization, oai_schema, repository::Repository};
+use entity::audit_logs::{AuditAction, AuditResource};
+use poem::web::Data;
+use poem::Result;
+use poem_openapi::param::{Path, Query};
+use poem_openapi::{payload::Json, OpenApi};
+
+pub struct ScheduleService;
+
+/// 定时指令
+///
+/// 按cron表达式和时区定期向设备或标签异步下发指令, 每次执行的结果记录在执行记录中,
+/// 下发到设备的指令同时写入指令日志; 服务停机期间错过的执行不补发
+#[OpenApi(prefix_path = "/schedule", tag = "ApiTags::Schedule")]
+impl ScheduleService {
+    /// 创建定时指令
+    #[oai(path = "/", method = "post")]
+    async fn create_schedule(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        body: Json<oai_schema::CreateScheduledCommand>,
+    ) -> Result<Json<oai_schema::ScheduledCommand>> {
+        let schedule: oai_schema::ScheduledCommand = state
+            .repo
+            .create_scheduled_command(&account.0, &body)
+            .await?
+            .into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Create,
+            AuditResource::ScheduledCommand,
+            &schedule.id,
+        )
+        .after(&schedule);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(schedule))
+    }
+
+    /// 查询定时指令列表
+    #[oai(path = "/", method = "get")]
+    async fn list_schedules(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::ScheduledCommands>> {
+        let (schedules, total) = state
+            .repo
+            .list_scheduled_commands(&account.0, page.0, page_size.0)
+            .await?;
+        Ok(Json(oai_schema::ScheduledCommands {
+            results: schedules.into_iter().map(Into::into).collect(),
+            total,
+        }))
+    }
+
+    /// 获取定时指令详情
+    #[oai(path = "/:schedule_id", method = "get")]
+    async fn get_schedule(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        schedule_id: Path<String>,
+    ) -> Result<Json<oai_schema::ScheduledCommand>> {
+        let schedule = state
+            .repo
+            .get_scheduled_command(&account.0, &schedule_id)
+            .await?;
+        Ok(Json(schedule.into()))
+    }
+
+    /// 更新定时指令
+    #[oai(path = "/:schedule_id", method = "patch")]
+    async fn update_schedule(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        schedule_id: Path<String>,
+        body: Json<oai_schema::UpdateScheduledCommand>,
+    ) -> Result<Json<oai_schema::ScheduledCommand>> {
+        let before: oai_schema::ScheduledCommand = state
+            .repo
+            .get_scheduled_command(&account.0, &schedule_id)
+            .await?
+            .into();
+        let after: oai_schema::ScheduledCommand = state
+            .repo
+            .update_scheduled_command(&account.0, &schedule_id, &body)
+            .await?
+            .into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::ScheduledCommand,
+            &schedule_id,
+        )
+        .before(&before)
+        .after(&after);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(after))
+    }
+
+    /// 删除定时指令
+    #[oai(path = "/:schedule_id", method = "delete")]
+    async fn delete_schedule(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        schedule_id: Path<String>,
+    ) -> Result<()> {
+        let before: oai_schema::ScheduledCommand = state
+            .repo
+            .get_scheduled_command(&account.0, &schedule_id)
+            .await?
+            .into();
+        state
+            .repo
+            .delete_scheduled_command(&account.0, &schedule_id)
+            .await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Delete,
+            AuditResource::ScheduledCommand,
+            &schedule_id,
+        )
+        .before(&before);
+        state.repo.create_audit_log(log).await?;
+        Ok(())
+    }
+
+    /// 查询定时指令的执行记录
+    #[oai(path = "/:schedule_id/runs", method = "get")]
+    async fn list_runs(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        schedule_id: Path<String>,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::ScheduledCommandRuns>> {
+        let (runs, total) = state
+            .repo
+            .list_scheduled_command_runs(&account.0, &schedule_id, page.0, page_size.0)
+            .await?;
+        Ok(Json(oai_schema::ScheduledCommandRuns {
+            results: runs.into_iter().map(Into::into).collect(),
+            total,
+        }))
+    }
+}
@@ -1,6 +1,9 @@
-use super::{default_page, default_page_size, ApiTags, AppState};
-use crate::oai_schema;
+use super::{default_page, default_page_size, ApiTags, AppState, ClientIp};
+use crate::events::{self, Event};
+use crate::{audit::AuditLog, oai_schema};
 use crate::{auth::JWTAuthorization, repository::Repository};
+use entity::audit_logs::{AuditAction, AuditResource};
+use entity::webhook_deliveries::EventType;
 use poem::web::Data;
 use poem::Result;
 use poem_openapi::param::{Path, Query};
@@ -16,10 +19,25 @@ impl SchemaService {
         &self,
         state: Data<&AppState>,
         account: JWTAuthorization,
+        ip: ClientIp,
         body: Json<oai_schema::CreateSchema>,
     ) -> Result<Json<oai_schema::Schema>> {
-        let schema = state.repo.create_schema(&account.0, &body).await?;
-        Ok(Json(schema.into()))
+        let schema: oai_schema::Schema = state.repo.create_schema(&account.0, &body).await?.into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Create,
+            AuditResource::Schema,
+            &schema.id,
+        )
+        .after(&schema);
+        state.repo.create_audit_log(log).await?;
+        events::emit(
+            &state.repo,
+            Event::new(&account.0, EventType::SchemaCreated).object(&schema),
+        )
+        .await;
+        Ok(Json(schema))
     }
 
     /// 查询数据模型列表
@@ -70,14 +88,28 @@ impl SchemaService {
         &self,
         state: Data<&AppState>,
         account: JWTAuthorization,
+        ip: ClientIp,
         schema_id: Path<String>,
         body: Json<oai_schema::UpdateSchema>,
     ) -> Result<Json<oai_schema::Schema>> {
-        let schema = state
+        let before: oai_schema::Schema =
+            state.repo.get_schema(&account.0, &schema_id).await?.into();
+        let after: oai_schema::Schema = state
             .repo
             .update_schema(&account.0, &schema_id, &body)
-            .await?;
-        Ok(Json(schema.into()))
+            .await?
+            .into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::Schema,
+            &schema_id,
+        )
+        .before(&before)
+        .after(&after);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(after))
     }
 
     /// 删除数据模型
@@ -86,9 +118,29 @@ impl SchemaService {
         &self,
         state: Data<&AppState>,
         account: JWTAuthorization,
+        ip: ClientIp,
         schema_id: Path<String>,
     ) -> Result<()> {
+        let before: oai_schema::SchemaWithFields = state
+            .repo
+            .get_schema_with_related(&account.0, &schema_id)
+            .await?
+            .into();
         state.repo.delete_schema(&account.0, &schema_id).await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Delete,
+            AuditResource::Schema,
+            &schema_id,
+        )
+        .before(&before);
+        state.repo.create_audit_log(log).await?;
+        events::emit(
+            &state.repo,
+            Event::new(&account.0, EventType::SchemaDeleted).object(&before),
+        )
+        .await;
         Ok(())
     }
 
@@ -98,14 +150,25 @@ impl SchemaService {
         &self,
         state: Data<&AppState>,
         account: JWTAuthorization,
+        ip: ClientIp,
         schema_id: Path<String>,
         body: Json<oai_schema::CreateField>,
     ) -> Result<Json<oai_schema::Field>> {
-        let field = state
+        let field: oai_schema::Field = state
             .repo
             .create_field(&account.0, &schema_id, &body)
-            .await?;
-        Ok(Json(field.into()))
+            .await?
+            .into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Create,
+            AuditResource::Field,
+            &field.id,
+        )
+        .after(&field);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(field))
     }
 
     /// 数据模型更新字段
@@ -114,15 +177,32 @@ impl SchemaService {
         &self,
         state: Data<&AppState>,
         account: JWTAuthorization,
+        ip: ClientIp,
         schema_id: Path<String>,
         identifier: Path<String>,
         body: Json<oai_schema::UpdateField>,
     ) -> Result<Json<oai_schema::Field>> {
-        let field = state
+        let before: oai_schema::Field = state
+            .repo
+            .get_field(&account.0, &schema_id, &identifier)
+            .await?
+            .into();
+        let after: oai_schema::Field = state
             .repo
             .update_field(&account.0, &schema_id, &identifier, &body)
-            .await?;
-        Ok(Json(field.into()))
+            .await?
+            .into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::Field,
+            &after.id,
+        )
+        .before(&before)
+        .after(&after);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(after))
     }
     /// 数据模型删除字段
     #[oai(path = "/:schema_id/field/:identifier", method = "delete")]
@@ -130,13 +210,28 @@ impl SchemaService {
         &self,
         state: Data<&AppState>,
         account: JWTAuthorization,
+        ip: ClientIp,
         schema_id: Path<String>,
         identifier: Path<String>,
     ) -> Result<()> {
+        let before: oai_schema::Field = state
+            .repo
+            .get_field(&account.0, &schema_id, &identifier)
+            .await?
+            .into();
         state
             .repo
             .delete_field(&account.0, &schema_id, &identifier)
             .await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Delete,
+            AuditResource::Field,
+            &before.id,
+        )
+        .before(&before);
+        state.repo.create_audit_log(log).await?;
         Ok(())
     }
 }
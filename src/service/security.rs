@@ -0,0 +1,54 @@
+use super::{ApiTags, AppState, ClientIp};
+use crate::{audit::AuditLog, oai_schema};
+use crate::{auth::JWTAuthorization, errors::NeoiotError, repository::Repository};
+use entity::audit_logs::{AuditAction, AuditResource};
+use poem::web::Data;
+use poem::Result;
+use poem_openapi::{payload::Json, OpenApi};
+
+pub struct SecurityService;
+
+#[OpenApi(prefix_path = "/admin/security", tag = "ApiTags::Account")]
+impl SecurityService {
+    /// 查询安全策略
+    #[oai(path = "/", method = "get")]
+    async fn get_security_policy(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+    ) -> Result<Json<oai_schema::SecurityPolicy>> {
+        let account = state.repo.get_account(&account.0).await?;
+        if !account.is_superuser {
+            return Err(NeoiotError::PermissionDenied.into());
+        }
+        Ok(Json(state.repo.get_security_policy().await?))
+    }
+
+    /// 更新安全策略
+    #[oai(path = "/", method = "patch")]
+    async fn update_security_policy(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        body: Json<oai_schema::UpdateSecurityPolicy>,
+    ) -> Result<Json<oai_schema::SecurityPolicy>> {
+        let account = state.repo.get_account(&account.0).await?;
+        if !account.is_superuser {
+            return Err(NeoiotError::PermissionDenied.into());
+        }
+        let before = state.repo.get_security_policy().await?;
+        let after = state.repo.update_security_policy(&body).await?;
+        let log = AuditLog::new(
+            &account.id,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::SecurityPolicy,
+            "security_policy",
+        )
+        .before(&before)
+        .after(&after);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(after))
+    }
+}
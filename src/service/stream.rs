@@ -0,0 +1,162 @@
+use std::{collections::HashSet, time::Duration};
+
+use super::{ApiTags, AppState};
+use crate::{
+    auth::{self, JWTAuthorization},
+    errors::NeoiotError,
+    repository::Repository,
+    selector::Selector,
+    stream::{self, Subscription},
+};
+use futures_util::{stream::BoxStream, SinkExt, StreamExt};
+use poem::web::websocket::{Message, WebSocket};
+use poem::web::{Data, Query};
+use poem::{handler, IntoResponse, Result};
+use poem_openapi::{param, payload::EventStream, OpenApi};
+use serde_json::{json, Value};
+
+/// SSE保活间隔
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+/// 单个连接最多直接订阅的设备数, 更多设备请使用标签选择器
+const MAX_STREAM_DEVICES: usize = 1000;
+
+/// 订阅条件, 各项均为空时推送账号下的所有事件
+#[derive(Debug, Default, Deserialize)]
+struct StreamFilter {
+    /// 逗号分隔的设备ID
+    device_ids: Option<String>,
+    /// 设备的标签选择器, 在订阅时解析为设备列表
+    selector: Option<String>,
+    /// 逗号分隔的事件类型
+    event_types: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WebSocketParams {
+    /// 登录获取的JWT, 浏览器无法为WebSocket设置请求头
+    token: String,
+    #[serde(flatten)]
+    filter: StreamFilter,
+}
+
+pub struct StreamService;
+
+/// 实时推送
+///
+/// 推送遥测数据、设备上下线、指令回复等平台事件, 消息体与Webhook推送的一致;
+/// 消费过慢时丢弃最早的消息并推送`{"type": "lagged", "dropped": n}`
+#[OpenApi(prefix_path = "/stream", tag = "ApiTags::Stream")]
+impl StreamService {
+    /// 通过SSE订阅事件
+    ///
+    /// 也可以通过WebSocket订阅: `/api/stream/ws?token={jwt}`, 查询参数与本接口相同,
+    /// 连接后发送`{"device_ids": "...", "selector": "...", "event_types": "..."}`可以替换订阅条件
+    #[oai(path = "/events", method = "get")]
+    async fn events(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        /// 逗号分隔的设备ID
+        device_ids: param::Query<Option<String>>,
+        /// 设备的标签选择器, 例如: `site=sh01`
+        selector: param::Query<Option<String>>,
+        /// 逗号分隔的事件类型, 例如: `telemetry_received,device_connected`
+        event_types: param::Query<Option<String>>,
+    ) -> Result<EventStream<BoxStream<'static, Value>>> {
+        let filter = StreamFilter {
+            device_ids: device_ids.0,
+            selector: selector.0,
+            event_types: event_types.0,
+        };
+        let subscription = build_subscription(&state, &account.0, &filter).await?;
+        Ok(EventStream::new(stream::subscribe(subscription)).keep_alive(KEEP_ALIVE))
+    }
+}
+
+/// 通过WebSocket订阅事件
+#[handler]
+pub async fn websocket(
+    ws: WebSocket,
+    Query(params): Query<WebSocketParams>,
+    state: Data<&AppState>,
+) -> Result<impl IntoResponse> {
+    let account_id = auth::verify_token(&state.repo, &params.token)
+        .await
+        .ok_or(NeoiotError::AuthenticateError)?;
+    let subscription = build_subscription(&state, &account_id, &params.filter).await?;
+    let state = state.clone();
+    Ok(ws.on_upgrade(move |socket| async move {
+        let (mut sink, mut incoming) = socket.split();
+        let mut events = stream::subscribe(subscription);
+        loop {
+            tokio::select! {
+                message = events.next() => {
+                    let message = match message {
+                        Some(message) => message,
+                        None => break,
+                    };
+                    // 发送阻塞期间的事件积压在连接的接收端
+                    if sink.send(Message::Text(message.to_string())).await.is_err() {
+                        break;
+                    }
+                }
+                received = incoming.next() => match received {
+                    Some(Ok(Message::Text(text))) => {
+                        let resubscribed = match serde_json::from_str::<StreamFilter>(&text) {
+                            Ok(filter) => build_subscription(&state, &account_id, &filter).await,
+                            Err(err) => Err(NeoiotError::InvalidArgument(err.to_string())),
+                        };
+                        match resubscribed {
+                            Ok(subscription) => events = stream::subscribe(subscription),
+                            Err(err) => {
+                                let error = json!({ "type": "error", "message": err.to_string() });
+                                if sink.send(Message::Text(error.to_string())).await.is_err() {
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+    }))
+}
+
+/// 按订阅条件生成订阅, 标签选择器解析为设备列表
+async fn build_subscription(
+    state: &AppState,
+    account_id: &str,
+    filter: &StreamFilter,
+) -> crate::errors::Result<Subscription> {
+    let mut subscription = Subscription::new(account_id);
+    if let Some(event_types) = &filter.event_types {
+        subscription.event_types = stream::parse_event_types(event_types)?;
+    }
+    let mut device_ids: Option<HashSet<String>> = None;
+    if let Some(ids) = &filter.device_ids {
+        let ids = ids
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(ToString::to_string);
+        device_ids.get_or_insert_with(HashSet::new).extend(ids);
+    }
+    if let Some(selector) = &filter.selector {
+        let selector = selector.parse::<Selector>()?;
+        let ids = state.repo.select_device_ids(account_id, &selector).await?;
+        device_ids.get_or_insert_with(HashSet::new).extend(ids);
+    }
+    if device_ids
+        .as_ref()
+        .is_some_and(|ids| ids.len() > MAX_STREAM_DEVICES)
+    {
+        return Err(NeoiotError::InvalidArgument(format!(
+            "at most {} devices can be subscribed",
+            MAX_STREAM_DEVICES
+        )));
+    }
+    subscription.device_ids = device_ids;
+    Ok(subscription)
+}
@@ -0,0 +1,103 @@
+use super::auth::{new_recovery_codes, verify_second_factor, verify_totp_code};
+use super::{ApiTags, AppState};
+use crate::{auth::JWTAuthorization, errors::NeoiotError, repository::Repository};
+use crate::{oai_schema, totp};
+use poem::web::Data;
+use poem::Result;
+use poem_openapi::{payload::Json, OpenApi};
+
+pub struct TotpService;
+
+#[OpenApi(prefix_path = "/auth/totp", tag = "ApiTags::Auth")]
+impl TotpService {
+    /// 生成TOTP密钥
+    ///
+    /// 使用认证器App扫描`provisioning_uri`后，调用`/auth/totp/activate`启用
+    #[oai(path = "/enroll", method = "post")]
+    async fn enroll(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+    ) -> Result<Json<oai_schema::TotpEnrollment>> {
+        let account = state.repo.get_account(&account.0).await?;
+        if account.totp_enabled {
+            return Err(NeoiotError::InvalidArgument("totp already enabled".into()).into());
+        }
+        let secret = totp::generate_secret();
+        state.repo.set_totp_secret(&account.id, &secret).await?;
+        Ok(Json(oai_schema::TotpEnrollment {
+            provisioning_uri: totp::provisioning_uri(&secret, &account.email),
+            secret,
+        }))
+    }
+
+    /// 启用两步验证
+    #[oai(path = "/activate", method = "post")]
+    async fn activate(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        body: Json<oai_schema::TotpCode>,
+    ) -> Result<Json<oai_schema::RecoveryCodes>> {
+        let account = state.repo.get_account(&account.0).await?;
+        if account.totp_enabled {
+            return Err(NeoiotError::InvalidArgument("totp already enabled".into()).into());
+        }
+        let secret = account
+            .totp_secret
+            .as_ref()
+            .ok_or_else(|| NeoiotError::InvalidArgument("totp not enrolled".into()))?;
+        if !verify_totp_code(&state.cache, &account.id, secret, &body.code).await? {
+            return Err(NeoiotError::AuthenticateError.into());
+        }
+        let (codes, hashes) = new_recovery_codes();
+        state.repo.enable_totp(&account.id, &hashes).await?;
+        Ok(Json(oai_schema::RecoveryCodes { codes }))
+    }
+
+    /// 关闭两步验证
+    #[oai(path = "/disable", method = "post")]
+    async fn disable(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        body: Json<oai_schema::TotpCode>,
+    ) -> Result<()> {
+        let account = state.repo.get_account(&account.0).await?;
+        if !account.totp_enabled {
+            return Err(NeoiotError::InvalidArgument("totp not enabled".into()).into());
+        }
+        let policy = state.repo.get_security_policy().await?;
+        if account.is_superuser && policy.require_superuser_totp {
+            return Err(NeoiotError::PermissionDenied.into());
+        }
+        if !verify_second_factor(&state, &account, &body.code).await? {
+            return Err(NeoiotError::AuthenticateError.into());
+        }
+        state.repo.disable_totp(&account.id).await?;
+        Ok(())
+    }
+
+    /// 重新生成恢复码，旧的恢复码全部作废
+    #[oai(path = "/recovery_codes", method = "post")]
+    async fn regenerate_recovery_codes(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        body: Json<oai_schema::TotpCode>,
+    ) -> Result<Json<oai_schema::RecoveryCodes>> {
+        let account = state.repo.get_account(&account.0).await?;
+        if !account.totp_enabled {
+            return Err(NeoiotError::InvalidArgument("totp not enabled".into()).into());
+        }
+        if !verify_second_factor(&state, &account, &body.code).await? {
+            return Err(NeoiotError::AuthenticateError.into());
+        }
+        let (codes, hashes) = new_recovery_codes();
+        state
+            .repo
+            .update_recovery_codes(&account.id, &hashes)
+            .await?;
+        Ok(Json(oai_schema::RecoveryCodes { codes }))
+    }
+}
@@ -0,0 +1,177 @@
+use super::{default_page, default_page_size, ApiTags, AppState, ClientIp};
+use crate::{
+    audit::AuditLog,
+    auth::{DeviceAuthorization, JWTAuthorization},
+    blob::BlobStore,
+    errors::NeoiotError,
+    oai_schema,
+    repository::Repository,
+    uploads,
+};
+use entity::audit_logs::{AuditAction, AuditResource};
+use entity::device_uploads::DeviceUploadStatus;
+use poem::web::Data;
+use poem::Result;
+use poem_openapi::param::{Path, Query};
+use poem_openapi::payload::{Attachment, Binary, Json};
+use poem_openapi::OpenApi;
+
+pub struct UploadService;
+
+/// 设备文件上传
+///
+/// 请求上传时平台向设备发送异步指令`upload`, 负载包含上传ID、分片大小和上传地址;
+/// 设备将文件按序号从0开始切分, 每个分片可以经HTTP `PUT /upload/{upload_id}/chunks/{seq}?total={total}`
+/// 使用设备凭据上传, 也可以base64编码后发布到`upload/{account_id}/{device_id}/{upload_id}/{seq}/{total}`,
+/// 重复上传的分片覆盖之前的内容; 分片全部到达后平台拼接为完整文件供下载
+#[OpenApi(prefix_path = "/upload", tag = "ApiTags::Upload")]
+impl UploadService {
+    /// 请求设备上传文件
+    #[oai(path = "/", method = "post")]
+    async fn request_upload(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        body: Json<oai_schema::RequestDeviceUpload>,
+    ) -> Result<Json<oai_schema::DeviceUpload>> {
+        let upload: oai_schema::DeviceUpload = state
+            .repo
+            .create_device_upload(&account.0, &account.0, &body)
+            .await?
+            .into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Create,
+            AuditResource::DeviceUpload,
+            &upload.id,
+        )
+        .after(&upload);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(upload))
+    }
+
+    /// 查询上传列表
+    #[oai(path = "/", method = "get")]
+    async fn list_uploads(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        /// 设备ID
+        device_id: Query<Option<String>>,
+        /// 上传状态
+        status: Query<Option<DeviceUploadStatus>>,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::DeviceUploads>> {
+        let (uploads, total) = state
+            .repo
+            .list_device_uploads(&account.0, device_id.0, status.0, page.0, page_size.0)
+            .await?;
+        Ok(Json(oai_schema::DeviceUploads {
+            results: uploads.into_iter().map(Into::into).collect(),
+            total,
+        }))
+    }
+
+    /// 获取上传详情
+    #[oai(path = "/:upload_id", method = "get")]
+    async fn get_upload(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        upload_id: Path<String>,
+    ) -> Result<Json<oai_schema::DeviceUpload>> {
+        let upload = state.repo.get_device_upload(&account.0, &upload_id).await?;
+        Ok(Json(upload.into()))
+    }
+
+    /// 下载已完成的文件
+    #[oai(path = "/:upload_id/download", method = "get")]
+    async fn download_upload(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        upload_id: Path<String>,
+    ) -> Result<Attachment<Vec<u8>>> {
+        let upload = state.repo.get_device_upload(&account.0, &upload_id).await?;
+        let blob_key = match (upload.status, &upload.blob_key) {
+            (DeviceUploadStatus::Completed, Some(blob_key)) => blob_key,
+            _ => {
+                return Err(NeoiotError::InvalidArgument(format!(
+                    "upload {} is not completed",
+                    upload.id
+                ))
+                .into())
+            }
+        };
+        let data = state
+            .blob
+            .get(blob_key)
+            .await?
+            .ok_or_else(|| NeoiotError::ObjectNotFound(format!("upload {}", upload.id)))?;
+        let filename = format!("{}-{}-{}", upload.device_id, upload.kind, upload.id);
+        Ok(Attachment::new(data).filename(filename))
+    }
+
+    /// 删除上传及已收到的文件
+    #[oai(path = "/:upload_id", method = "delete")]
+    async fn delete_upload(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        upload_id: Path<String>,
+    ) -> Result<()> {
+        let (upload, chunks) = state
+            .repo
+            .delete_device_upload(&account.0, &upload_id)
+            .await?;
+        if let Some(blob_key) = &upload.blob_key {
+            state.blob.delete(blob_key).await?;
+        }
+        uploads::delete_chunks(&state.blob, &chunks).await;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Delete,
+            AuditResource::DeviceUpload,
+            &upload_id,
+        )
+        .before(&oai_schema::DeviceUpload::from(upload));
+        state.repo.create_audit_log(log).await?;
+        Ok(())
+    }
+
+    /// 设备上传分片, 使用设备凭据认证, 网关可以代子设备上传
+    #[oai(path = "/:upload_id/chunks/:seq", method = "put")]
+    async fn upload_chunk(
+        &self,
+        state: Data<&AppState>,
+        device: DeviceAuthorization,
+        upload_id: Path<String>,
+        /// 分片序号, 从0开始
+        seq: Path<i32>,
+        /// 分片总数
+        total: Query<i32>,
+        body: Binary<Vec<u8>>,
+    ) -> Result<()> {
+        let upload = state.repo.find_device_upload(&upload_id).await?;
+        if upload.device_id != device.0.id {
+            let target = state
+                .repo
+                .get_device(&upload.account_id, &upload.device_id)
+                .await?;
+            if target.parent_id.as_deref() != Some(device.0.id.as_str()) {
+                return Err(NeoiotError::PermissionDenied.into());
+            }
+        }
+        uploads::receive_chunk(&state.repo, &state.blob, &upload, seq.0, total.0, body.0).await?;
+        Ok(())
+    }
+}
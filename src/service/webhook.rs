@@ -0,0 +1,212 @@
+use super::{default_page, default_page_size, ApiTags, AppState, ClientIp};
+use crate::{audit::AuditLog, auth::JWTAuthorization, oai_schema, repository::Repository};
+use entity::audit_logs::{AuditAction, AuditResource};
+use entity::webhook_deliveries::{EventType, WebhookDeliveryStatus};
+use poem::web::Data;
+use poem::Result;
+use poem_openapi::param::{Path, Query};
+use poem_openapi::{payload::Json, OpenApi};
+
+pub struct WebhookService;
+
+/// 平台事件订阅
+///
+/// 事件以POST请求推送到订阅地址, 请求头`X-Neoiot-Signature`为
+/// `sha256=hex(HMAC-SHA256(secret, "{X-Neoiot-Timestamp}.{body}"))`;
+/// 非2xx响应或超时按指数退避重试, 超过最大次数后进入死信, 可手动重新投递
+#[OpenApi(prefix_path = "/webhook", tag = "ApiTags::Webhook")]
+impl WebhookService {
+    /// 创建Webhook, 签名密钥只在此时返回一次
+    #[oai(path = "/", method = "post")]
+    async fn create_webhook(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        body: Json<oai_schema::CreateWebhook>,
+    ) -> Result<Json<oai_schema::Webhook>> {
+        let webhook = state.repo.create_webhook(&account.0, &body).await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Create,
+            AuditResource::Webhook,
+            &webhook.id,
+        )
+        .after(&oai_schema::Webhook::from(webhook.clone()));
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(oai_schema::Webhook::with_secret(webhook)))
+    }
+
+    /// 查询Webhook列表
+    #[oai(path = "/", method = "get")]
+    async fn list_webhooks(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::Webhooks>> {
+        let (webhooks, total) = state
+            .repo
+            .list_webhooks(&account.0, page.0, page_size.0)
+            .await?;
+        Ok(Json(oai_schema::Webhooks {
+            results: webhooks.into_iter().map(Into::into).collect(),
+            total,
+        }))
+    }
+
+    /// 获取Webhook详情
+    #[oai(path = "/:webhook_id", method = "get")]
+    async fn get_webhook(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        webhook_id: Path<String>,
+    ) -> Result<Json<oai_schema::Webhook>> {
+        let webhook = state.repo.get_webhook(&account.0, &webhook_id).await?;
+        Ok(Json(webhook.into()))
+    }
+
+    /// 更新Webhook
+    #[oai(path = "/:webhook_id", method = "patch")]
+    async fn update_webhook(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        webhook_id: Path<String>,
+        body: Json<oai_schema::UpdateWebhook>,
+    ) -> Result<Json<oai_schema::Webhook>> {
+        let before: oai_schema::Webhook = state
+            .repo
+            .get_webhook(&account.0, &webhook_id)
+            .await?
+            .into();
+        let after: oai_schema::Webhook = state
+            .repo
+            .update_webhook(&account.0, &webhook_id, &body)
+            .await?
+            .into();
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::Webhook,
+            &webhook_id,
+        )
+        .before(&before)
+        .after(&after);
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(after))
+    }
+
+    /// 删除Webhook
+    #[oai(path = "/:webhook_id", method = "delete")]
+    async fn delete_webhook(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        webhook_id: Path<String>,
+    ) -> Result<()> {
+        let before: oai_schema::Webhook = state
+            .repo
+            .get_webhook(&account.0, &webhook_id)
+            .await?
+            .into();
+        state.repo.delete_webhook(&account.0, &webhook_id).await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Delete,
+            AuditResource::Webhook,
+            &webhook_id,
+        )
+        .before(&before);
+        state.repo.create_audit_log(log).await?;
+        Ok(())
+    }
+
+    /// 重置签名密钥, 旧密钥立即失效
+    #[oai(path = "/:webhook_id/secret", method = "post")]
+    async fn rotate_secret(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        ip: ClientIp,
+        webhook_id: Path<String>,
+    ) -> Result<Json<oai_schema::Webhook>> {
+        let webhook = state
+            .repo
+            .rotate_webhook_secret(&account.0, &webhook_id)
+            .await?;
+        let log = AuditLog::new(
+            &account.0,
+            &ip.0,
+            AuditAction::Update,
+            AuditResource::Webhook,
+            &webhook_id,
+        )
+        .detail(serde_json::json!({ "secret_rotated": true }));
+        state.repo.create_audit_log(log).await?;
+        Ok(Json(oai_schema::Webhook::with_secret(webhook)))
+    }
+
+    /// 查询投递记录
+    #[oai(path = "/:webhook_id/deliveries", method = "get")]
+    #[allow(clippy::too_many_arguments)]
+    async fn list_deliveries(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        webhook_id: Path<String>,
+        /// 投递状态
+        status: Query<Option<WebhookDeliveryStatus>>,
+        /// 事件类型
+        event_type: Query<Option<EventType>>,
+        /// 第几页
+        #[oai(default = "default_page")]
+        page: Query<usize>,
+        /// 每页条目数
+        #[oai(default = "default_page_size")]
+        page_size: Query<usize>,
+    ) -> Result<Json<oai_schema::WebhookDeliveries>> {
+        let (deliveries, total) = state
+            .repo
+            .list_webhook_deliveries(
+                &account.0,
+                &webhook_id,
+                status.0,
+                event_type.0,
+                page.0,
+                page_size.0,
+            )
+            .await?;
+        Ok(Json(oai_schema::WebhookDeliveries {
+            results: deliveries.into_iter().map(Into::into).collect(),
+            total,
+        }))
+    }
+
+    /// 重新投递, 用于处理进入死信的记录
+    #[oai(path = "/:webhook_id/deliveries/:delivery_id/retry", method = "post")]
+    async fn retry_delivery(
+        &self,
+        state: Data<&AppState>,
+        account: JWTAuthorization,
+        webhook_id: Path<String>,
+        delivery_id: Path<String>,
+    ) -> Result<Json<oai_schema::WebhookDelivery>> {
+        let delivery = state
+            .repo
+            .retry_webhook_delivery(&account.0, &webhook_id, &delivery_id)
+            .await?;
+        Ok(Json(delivery.into()))
+    }
+}
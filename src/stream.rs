@@ -0,0 +1,153 @@
+//! 实时推送: 平台事件经进程内广播分发给WebSocket/SSE连接
+//!
+//! 每个账号一个广播通道, 每个连接持有独立的接收端, 消费过慢时丢弃最早的消息并推送一条
+//! `lagged`通知, 不会阻塞事件的发布方和其他连接, 其他账号的事件量也不会挤掉本账号的消息;
+//! 多实例部署时每个实例只推送本实例收到的事件
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use entity::sea_orm::ActiveEnum;
+use entity::webhook_deliveries::EventType;
+use futures_util::{stream::BoxStream, StreamExt};
+use serde_json::{json, Value};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+
+use crate::{errors::NeoiotError, events::Event};
+
+/// 每个连接最多积压的消息数
+const STREAM_BUFFER: usize = 256;
+
+lazy_static! {
+    /// 按账号划分的广播通道, 账号的最后一个连接断开后在下次推送时移除
+    static ref BUSES: Mutex<HashMap<String, broadcast::Sender<Arc<Event>>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// 推送事件, 账号没有连接时直接丢弃
+pub fn publish(event: &Event) {
+    let mut buses = BUSES.lock().unwrap();
+    if let Some(bus) = buses.get(&event.account_id) {
+        if bus.send(Arc::new(event.clone())).is_err() {
+            buses.remove(&event.account_id);
+        }
+    }
+}
+
+/// 一个连接的订阅条件, 只能收到JWT中账号的事件
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subscription {
+    pub account_id: String,
+    /// 为None时不按设备过滤, 否则只推送这些设备的事件
+    pub device_ids: Option<HashSet<String>>,
+    /// 为空时推送所有类型的事件
+    pub event_types: Vec<EventType>,
+}
+
+impl Subscription {
+    pub fn new(account_id: &str) -> Self {
+        Self {
+            account_id: account_id.to_string(),
+            device_ids: None,
+            event_types: vec![],
+        }
+    }
+
+    pub fn matches(&self, event: &Event) -> bool {
+        if event.account_id != self.account_id {
+            return false;
+        }
+        if !self.event_types.is_empty() && !self.event_types.contains(&event.event_type) {
+            return false;
+        }
+        match &self.device_ids {
+            Some(device_ids) => event
+                .device_id
+                .as_ref()
+                .is_some_and(|id| device_ids.contains(id)),
+            None => true,
+        }
+    }
+}
+
+/// 解析逗号分隔的事件类型
+pub fn parse_event_types(value: &str) -> Result<Vec<EventType>, NeoiotError> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(|t| {
+            EventType::try_from_value(&t.to_string())
+                .map_err(|_| NeoiotError::InvalidArgument(format!("unknown event type `{}`", t)))
+        })
+        .collect()
+}
+
+/// 订阅事件流, 每条消息为事件的JSON消息体
+pub fn subscribe(subscription: Subscription) -> BoxStream<'static, Value> {
+    let receiver = BUSES
+        .lock()
+        .unwrap()
+        .entry(subscription.account_id.clone())
+        .or_insert_with(|| broadcast::channel(STREAM_BUFFER).0)
+        .subscribe();
+    BroadcastStream::new(receiver)
+        .filter_map(move |received| {
+            let message = match received {
+                Ok(event) if subscription.matches(&event) => Some(event.payload()),
+                Ok(_) => None,
+                Err(BroadcastStreamRecvError::Lagged(dropped)) => {
+                    Some(json!({ "type": "lagged", "dropped": dropped }))
+                }
+            };
+            futures_util::future::ready(message)
+        })
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let mut subscription = Subscription::new("acc");
+        subscription.device_ids = Some(HashSet::from(["dev".to_string()]));
+        subscription.event_types =
+            parse_event_types("telemetry_received, device_connected").unwrap();
+        assert!(parse_event_types("unknown").is_err());
+
+        let mut stream = subscribe(subscription);
+        let telemetry = Event::new("acc", EventType::TelemetryReceived).device("dev");
+        // 其他账号、其他设备和未订阅类型的事件不推送
+        publish(&Event::new("other", EventType::TelemetryReceived).device("dev"));
+        publish(&Event::new("acc", EventType::TelemetryReceived).device("peer"));
+        publish(&Event::new("acc", EventType::DeviceDeleted).device("dev"));
+        publish(&telemetry);
+        assert_eq!(stream.next().await, Some(telemetry.payload()));
+
+        // 其他账号的大量事件不会挤掉本账号的消息
+        let mut other = subscribe(Subscription::new("other"));
+        for _ in 0..STREAM_BUFFER + 10 {
+            publish(&Event::new("other", EventType::TelemetryReceived).device("dev"));
+        }
+        publish(&telemetry);
+        assert_eq!(stream.next().await, Some(telemetry.payload()));
+        assert_eq!(
+            other.next().await,
+            Some(json!({ "type": "lagged", "dropped": 10 }))
+        );
+
+        // 消费过慢时收到丢弃通知
+        for _ in 0..STREAM_BUFFER + 10 {
+            publish(&telemetry);
+        }
+        assert_eq!(
+            stream.next().await,
+            Some(json!({ "type": "lagged", "dropped": 10 }))
+        );
+        assert_eq!(stream.next().await, Some(telemetry.payload()));
+    }
+}
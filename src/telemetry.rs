@@ -0,0 +1,58 @@
+//! 设备上报数据的校验
+use chrono::DateTime;
+use entity::fields::DataType;
+
+/// 按字段的数据类型校验上报的值, 返回不合法的原因
+pub fn validate(data_type: &DataType, payload: &str) -> Result<(), String> {
+    let value = payload.trim();
+    let valid = match data_type {
+        DataType::String => true,
+        DataType::Number => value.parse::<f64>().is_ok_and(|v| v.is_finite()),
+        DataType::Integer => value.parse::<i64>().is_ok(),
+        DataType::Boolean => matches!(value, "true" | "false"),
+        DataType::Time => {
+            value.parse::<i64>().is_ok() || DateTime::parse_from_rfc3339(value).is_ok()
+        }
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "value `{}` is not a valid {}",
+            value,
+            type_name(data_type)
+        ))
+    }
+}
+
+fn type_name(data_type: &DataType) -> &'static str {
+    match data_type {
+        DataType::String => "string",
+        DataType::Number => "number",
+        DataType::Integer => "integer",
+        DataType::Boolean => "boolean",
+        DataType::Time => "time",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&DataType::Number, "8.5").is_ok());
+        assert!(validate(&DataType::Number, "NaN").is_err());
+        assert!(validate(&DataType::Integer, "42").is_ok());
+        assert!(validate(&DataType::Integer, "4.2").is_err());
+        assert!(validate(&DataType::Boolean, "true").is_ok());
+        assert!(validate(&DataType::Boolean, "1").is_err());
+        assert!(validate(&DataType::Time, "2022-08-08T10:00:00+08:00").is_ok());
+        assert!(validate(&DataType::Time, "1659924000").is_ok());
+        assert!(validate(&DataType::Time, "yesterday").is_err());
+        assert_eq!(
+            validate(&DataType::Integer, "abc").unwrap_err(),
+            "value `abc` is not a valid integer"
+        );
+    }
+}
@@ -69,7 +69,7 @@ impl ServerToDeviceBatch {
 
     pub fn topic(&self) -> String {
         let topic = format!(
-            "s2l/{account_id}/{label}/{command}/{message_id}/{}",
+            "s2l/{account_id}/{label}/{command}/{message_id}/{account_id}",
             account_id = self.account_id,
             label = self.label,
             command = self.command,
@@ -82,6 +82,46 @@ impl ServerToDeviceBatch {
     }
 }
 
+/// 推送给设备的升级通知`ota/{account_id}/{device_id}/notify`
+#[derive(Debug, Clone, PartialEq)]
+pub struct OtaNotify {
+    pub account_id: String,
+    pub device_id: String,
+}
+
+impl OtaNotify {
+    pub fn new(account_id: &str, device_id: &str) -> Self {
+        Self {
+            account_id: account_id.to_string(),
+            device_id: device_id.to_string(),
+        }
+    }
+
+    pub fn topic(&self) -> String {
+        format!("ota/{}/{}/notify", self.account_id, self.device_id)
+    }
+}
+
+/// 推送给设备的生效配置`cfg/{account_id}/{device_id}/push`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigPush {
+    pub account_id: String,
+    pub device_id: String,
+}
+
+impl ConfigPush {
+    pub fn new(account_id: &str, device_id: &str) -> Self {
+        Self {
+            account_id: account_id.to_string(),
+            device_id: device_id.to_string(),
+        }
+    }
+
+    pub fn topic(&self) -> String {
+        format!("cfg/{}/{}/push", self.account_id, self.device_id)
+    }
+}
+
 pub struct ACLRules {
     account_id: String,
     device_id: String,
@@ -94,6 +134,31 @@ impl ACLRules {
             device_id,
         }
     }
+    /// 设备自身的发布权限, 设备间通信的发布权限由白名单另行生成; 网关代子设备发布时使用子设备的同一组权限
+    pub fn pubs(&self) -> Vec<String> {
+        vec![
+            self.pub_d2s(),
+            self.pub_s2dr(),
+            self.pub_metrics(),
+            self.pub_otar(),
+            self.pub_upload(),
+            self.pub_cfgr(),
+        ]
+    }
+    /// 设备自身的订阅权限, 按标签接收消息的订阅权限由设备的标签另行生成
+    pub fn subs(&self) -> Vec<String> {
+        vec![
+            self.sub_s2d(),
+            self.sub_s2l(),
+            self.sub_d2d(),
+            self.sub_ota(),
+            self.sub_cfg(),
+        ]
+    }
+    /// 网关代子设备接收指令、升级通知和配置推送所需的订阅权限
+    pub fn proxy_subs(&self) -> Vec<String> {
+        vec![self.sub_s2d(), self.sub_ota(), self.sub_cfg()]
+    }
     pub fn sub_s2d(&self) -> String {
         // server to device
         format!(
@@ -122,11 +187,29 @@ impl ACLRules {
             device_id = self.device_id
         )
     }
-    pub fn pub_d2d(&self) -> String {
+    pub fn sub_d2l(&self, label: &str) -> String {
+        // device to label
+        format!(
+            "d2l/{account_id}/{label}/+/+",
+            account_id = self.account_id,
+            label = label
+        )
+    }
+    pub fn pub_d2d(&self, receiver_id: &str) -> String {
         // device to device
         format!(
-            "d2d/{account_id}/+/{sender_id}/+",
+            "d2d/{account_id}/{receiver_id}/{sender_id}/+",
             account_id = self.account_id,
+            receiver_id = receiver_id,
+            sender_id = self.device_id
+        )
+    }
+    pub fn pub_d2l(&self, label: &str) -> String {
+        // device to label
+        format!(
+            "d2l/{account_id}/{label}/{sender_id}/+",
+            account_id = self.account_id,
+            label = label,
             sender_id = self.device_id
         )
     }
@@ -146,6 +229,51 @@ impl ACLRules {
             device_id = self.device_id
         )
     }
+
+    pub fn sub_ota(&self) -> String {
+        // ota notify
+        format!(
+            "ota/{account_id}/{device_id}/notify",
+            account_id = self.account_id,
+            device_id = self.device_id
+        )
+    }
+
+    pub fn pub_otar(&self) -> String {
+        // ota report
+        format!(
+            "otar/{account_id}/{device_id}/+",
+            account_id = self.account_id,
+            device_id = self.device_id
+        )
+    }
+
+    pub fn pub_upload(&self) -> String {
+        // file upload chunk
+        format!(
+            "upload/{account_id}/{device_id}/+/+/+",
+            account_id = self.account_id,
+            device_id = self.device_id
+        )
+    }
+
+    pub fn sub_cfg(&self) -> String {
+        // config push
+        format!(
+            "cfg/{account_id}/{device_id}/push",
+            account_id = self.account_id,
+            device_id = self.device_id
+        )
+    }
+
+    pub fn pub_cfgr(&self) -> String {
+        // config applied report
+        format!(
+            "cfgr/{account_id}/{device_id}/applied",
+            account_id = self.account_id,
+            device_id = self.device_id
+        )
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -167,6 +295,8 @@ impl Message {
 pub enum Topics {
     S2D(ServerToDevice),
     S2L(ServerToDeviceBatch),
+    Ota(OtaNotify),
+    Config(ConfigPush),
 }
 
 impl Topics {
@@ -174,6 +304,8 @@ impl Topics {
         match self {
             Topics::S2D(cmd) => cmd.topic(),
             Topics::S2L(cmd) => cmd.topic(),
+            Topics::Ota(notify) => notify.topic(),
+            Topics::Config(push) => push.topic(),
         }
     }
 }
@@ -209,6 +341,192 @@ impl FromStr for Topics {
     }
 }
 
+/// 设备间通信的主题
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeviceToDevice {
+    /// `d2d/{account_id}/{receiver_id}/{sender_id}/{message_id}`
+    Device {
+        account_id: String,
+        receiver_id: String,
+        sender_id: String,
+    },
+    /// `d2l/{account_id}/{label}/{sender_id}/{message_id}`
+    Label {
+        account_id: String,
+        label: String,
+        sender_id: String,
+    },
+}
+
+impl DeviceToDevice {
+    pub fn parse(topic: &str) -> Option<Self> {
+        let parts: Vec<&str> = topic.split('/').collect();
+        match &parts[..] {
+            ["d2d", account_id, receiver_id, sender_id, _] => Some(Self::Device {
+                account_id: account_id.to_string(),
+                receiver_id: receiver_id.to_string(),
+                sender_id: sender_id.to_string(),
+            }),
+            ["d2l", account_id, label, sender_id, _] => Some(Self::Label {
+                account_id: account_id.to_string(),
+                label: label.to_string(),
+                sender_id: sender_id.to_string(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// 设备上报字段值的主题`metrics/{account_id}/{device_id}/{field}`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metric {
+    pub account_id: String,
+    pub device_id: String,
+    pub field: String,
+}
+
+impl Metric {
+    pub fn parse(topic: &str) -> Option<Self> {
+        let parts: Vec<&str> = topic.split('/').collect();
+        match &parts[..] {
+            ["metrics", account_id, device_id, field] => Some(Self {
+                account_id: account_id.to_string(),
+                device_id: device_id.to_string(),
+                field: field.to_string(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// 设备回复指令的主题`s2dr/{account_id}/{device_id}/{command}/{mode}/{message_id}/#`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandResponse {
+    pub account_id: String,
+    pub device_id: String,
+    pub command: String,
+    pub message_id: String,
+    pub is_sync: bool,
+}
+
+impl CommandResponse {
+    pub fn parse(topic: &str) -> Option<Self> {
+        let parts: Vec<&str> = topic.split('/').collect();
+        match &parts[..] {
+            ["s2dr", account_id, device_id, command, mode, message_id, ..] => Some(Self {
+                account_id: account_id.to_string(),
+                device_id: device_id.to_string(),
+                command: command.to_string(),
+                message_id: message_id.to_string(),
+                is_sync: *mode == "sync",
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// 设备上报的升级信息
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OtaReportKind {
+    /// 当前运行的固件版本
+    Version,
+    /// 升级进度
+    Progress,
+}
+
+/// 设备上报升级信息的主题`otar/{account_id}/{device_id}/{version|progress}`
+#[derive(Debug, Clone, PartialEq)]
+pub struct OtaReport {
+    pub account_id: String,
+    pub device_id: String,
+    pub kind: OtaReportKind,
+}
+
+impl OtaReport {
+    pub fn parse(topic: &str) -> Option<Self> {
+        let parts: Vec<&str> = topic.split('/').collect();
+        let (account_id, device_id, kind) = match &parts[..] {
+            ["otar", account_id, device_id, "version"] => {
+                (account_id, device_id, OtaReportKind::Version)
+            }
+            ["otar", account_id, device_id, "progress"] => {
+                (account_id, device_id, OtaReportKind::Progress)
+            }
+            _ => return None,
+        };
+        Some(Self {
+            account_id: account_id.to_string(),
+            device_id: device_id.to_string(),
+            kind,
+        })
+    }
+}
+
+/// 设备经MQTT上传文件分片的主题`upload/{account_id}/{device_id}/{upload_id}/{seq}/{total}`,
+/// 分片序号从0开始, 载荷为base64编码的分片内容
+#[derive(Debug, Clone, PartialEq)]
+pub struct UploadChunk {
+    pub account_id: String,
+    pub device_id: String,
+    pub upload_id: String,
+    pub seq: i32,
+    pub total: i32,
+}
+
+impl UploadChunk {
+    pub fn parse(topic: &str) -> Option<Self> {
+        let parts: Vec<&str> = topic.split('/').collect();
+        match &parts[..] {
+            ["upload", account_id, device_id, upload_id, seq, total] => Some(Self {
+                account_id: account_id.to_string(),
+                device_id: device_id.to_string(),
+                upload_id: upload_id.to_string(),
+                seq: seq.parse().ok()?,
+                total: total.parse().ok()?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// 设备回复配置应用结果的主题`cfgr/{account_id}/{device_id}/applied`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigApplied {
+    pub account_id: String,
+    pub device_id: String,
+}
+
+impl ConfigApplied {
+    pub fn parse(topic: &str) -> Option<Self> {
+        let parts: Vec<&str> = topic.split('/').collect();
+        match &parts[..] {
+            ["cfgr", account_id, device_id, "applied"] => Some(Self {
+                account_id: account_id.to_string(),
+                device_id: device_id.to_string(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// 判断主题(或订阅时的主题过滤器)是否被ACL中的主题过滤器覆盖
+///
+/// ACL中的`+`匹配任意一级, `#`匹配剩余所有层级; 订阅请求中的通配符
+/// 只能被ACL中相同位置或更宽的通配符覆盖
+pub fn topic_matches(filter: &str, topic: &str) -> bool {
+    let mut filter = filter.split('/');
+    let mut topic = topic.split('/');
+    loop {
+        match (filter.next(), topic.next()) {
+            (Some("#"), _) => return true,
+            (Some("+"), Some(t)) if t != "#" => {}
+            (Some(f), Some(t)) if f == t => {}
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -244,4 +562,153 @@ mod tests {
         let topic = "blablabla/test_account/test_device/async/test_command/test_message_id/3600";
         assert!(topic.parse::<Topics>().is_err(),);
     }
+
+    #[test]
+    fn test_topic_matches() {
+        let acl = ACLRules::new("acc".to_string(), "dev".to_string());
+        assert!(topic_matches(&acl.sub_s2d(), "s2d/acc/dev/reboot/sync/mid"));
+        assert!(topic_matches(&acl.sub_s2d(), "s2d/acc/dev/+/+/+/#"));
+        assert!(topic_matches(
+            &acl.sub_s2d(),
+            "s2d/acc/dev/reboot/sync/mid/60"
+        ));
+        assert!(!topic_matches(
+            &acl.sub_s2d(),
+            "s2d/acc/other/reboot/sync/mid"
+        ));
+        assert!(!topic_matches(&acl.sub_s2d(), "s2d/acc/+/+/+/+/#"));
+        assert!(!topic_matches(&acl.sub_s2d(), "s2d/acc/#"));
+        assert!(topic_matches(
+            &acl.pub_metrics(),
+            "metrics/acc/dev/temperature"
+        ));
+        assert!(!topic_matches(&acl.pub_metrics(), "metrics/acc/dev/a/b"));
+        assert!(!topic_matches(&acl.pub_metrics(), "metrics/acc/dev"));
+
+        let child = ACLRules::new("acc".to_string(), "child".to_string());
+        let proxied = |filters: Vec<String>, topic: &str| {
+            filters.iter().any(|filter| topic_matches(filter, topic))
+        };
+        assert!(proxied(child.pubs(), "d2s/acc/child/temperature/mid"));
+        assert!(proxied(child.pubs(), "s2dr/acc/child/reboot/sync/mid"));
+        assert!(proxied(child.proxy_subs(), "s2d/acc/child/reboot/sync/mid"));
+        assert!(!proxied(child.pubs(), "d2d/acc/other/child/mid"));
+        assert!(!proxied(child.proxy_subs(), "d2d/acc/child/other/mid"));
+        assert!(proxied(child.pubs(), "otar/acc/child/progress"));
+        assert!(proxied(child.proxy_subs(), "ota/acc/child/notify"));
+        assert!(proxied(child.pubs(), "cfgr/acc/child/applied"));
+        assert!(proxied(child.proxy_subs(), "cfg/acc/child/push"));
+
+        assert!(topic_matches(
+            &acl.sub_ota(),
+            &OtaNotify::new("acc", "dev").topic()
+        ));
+        assert!(!topic_matches(&acl.sub_ota(), "ota/acc/other/notify"));
+        assert!(topic_matches(&acl.pub_otar(), "otar/acc/dev/version"));
+        assert!(!acl
+            .pubs()
+            .iter()
+            .any(|f| topic_matches(f, "ota/acc/dev/notify")));
+
+        assert!(topic_matches(&acl.pub_d2d("peer"), "d2d/acc/peer/dev/mid"));
+        assert!(!topic_matches(
+            &acl.pub_d2d("peer"),
+            "d2d/acc/other/dev/mid"
+        ));
+        assert!(topic_matches(
+            &acl.pub_d2l("site=sh01"),
+            "d2l/acc/site=sh01/dev/mid"
+        ));
+        assert!(topic_matches(
+            &acl.sub_d2l("site=sh01"),
+            "d2l/acc/site=sh01/peer/mid"
+        ));
+        assert!(!topic_matches(
+            &acl.sub_d2l("site=sh01"),
+            "d2l/acc/site=sh02/peer/mid"
+        ));
+        assert!(!acl
+            .subs()
+            .iter()
+            .any(|f| topic_matches(f, "d2l/acc/site=sh01/peer/mid")));
+        assert!(!acl
+            .pubs()
+            .iter()
+            .any(|f| topic_matches(f, "d2d/acc/peer/dev/mid")));
+    }
+
+    #[test]
+    fn test_d2d_topic() {
+        assert_eq!(
+            DeviceToDevice::parse("d2d/acc/peer/dev/mid"),
+            Some(DeviceToDevice::Device {
+                account_id: "acc".to_string(),
+                receiver_id: "peer".to_string(),
+                sender_id: "dev".to_string(),
+            })
+        );
+        assert_eq!(
+            DeviceToDevice::parse("d2l/acc/site=sh01/dev/mid"),
+            Some(DeviceToDevice::Label {
+                account_id: "acc".to_string(),
+                label: "site=sh01".to_string(),
+                sender_id: "dev".to_string(),
+            })
+        );
+        assert_eq!(DeviceToDevice::parse("d2s/acc/dev/temperature/mid"), None);
+        assert_eq!(
+            Metric::parse("metrics/acc/dev/temperature"),
+            Some(Metric {
+                account_id: "acc".to_string(),
+                device_id: "dev".to_string(),
+                field: "temperature".to_string(),
+            })
+        );
+        assert_eq!(Metric::parse("metrics/acc/dev/a/b"), None);
+        assert_eq!(
+            CommandResponse::parse("s2dr/acc/dev/reboot/sync/mid/3600"),
+            Some(CommandResponse {
+                account_id: "acc".to_string(),
+                device_id: "dev".to_string(),
+                command: "reboot".to_string(),
+                message_id: "mid".to_string(),
+                is_sync: true,
+            })
+        );
+        assert_eq!(CommandResponse::parse("s2dr/acc/dev/reboot/sync"), None);
+        assert_eq!(
+            OtaReport::parse("otar/acc/dev/progress"),
+            Some(OtaReport {
+                account_id: "acc".to_string(),
+                device_id: "dev".to_string(),
+                kind: OtaReportKind::Progress,
+            })
+        );
+        assert_eq!(
+            OtaReport::parse("otar/acc/dev/version").map(|r| r.kind),
+            Some(OtaReportKind::Version)
+        );
+        assert_eq!(OtaReport::parse("otar/acc/dev/reboot"), None);
+        assert_eq!(OtaReport::parse("ota/acc/dev/notify"), None);
+        assert_eq!(
+            UploadChunk::parse("upload/acc/dev/up1/2/5"),
+            Some(UploadChunk {
+                account_id: "acc".to_string(),
+                device_id: "dev".to_string(),
+                upload_id: "up1".to_string(),
+                seq: 2,
+                total: 5,
+            })
+        );
+        assert_eq!(UploadChunk::parse("upload/acc/dev/up1/x/5"), None);
+        assert_eq!(UploadChunk::parse("upload/acc/dev/up1/2"), None);
+        assert_eq!(
+            ConfigApplied::parse("cfgr/acc/dev/applied"),
+            Some(ConfigApplied {
+                account_id: "acc".to_string(),
+                device_id: "dev".to_string(),
+            })
+        );
+        assert_eq!(ConfigApplied::parse("cfg/acc/dev/push"), None);
+    }
 }
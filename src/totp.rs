@@ -0,0 +1,145 @@
+//! 基于时间的一次性密码(RFC 6238)
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, Rng};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+const ISSUER: &str = "NEOIOT";
+const DIGITS: u32 = 6;
+const PERIOD: u64 = 30;
+/// 允许前后各偏移一个周期，兼容客户端时钟误差
+const SKEW: u64 = 1;
+const ALPHABET: base32::Alphabet = base32::Alphabet::RFC4648 { padding: false };
+
+/// 生成一个新的160位随机密钥(Base32编码)
+pub fn generate_secret() -> String {
+    let secret: [u8; 20] = rand::random();
+    base32::encode(ALPHABET, &secret)
+}
+
+/// 生成认证器App扫码使用的`otpauth://`地址
+pub fn provisioning_uri(secret: &str, account_name: &str) -> String {
+    let mut uri = reqwest::Url::parse("otpauth://totp/").unwrap();
+    uri.set_path(&format!("{}:{}", ISSUER, account_name));
+    uri.query_pairs_mut()
+        .append_pair("secret", secret)
+        .append_pair("issuer", ISSUER)
+        .append_pair("algorithm", "SHA1")
+        .append_pair("digits", &DIGITS.to_string())
+        .append_pair("period", &PERIOD.to_string());
+    uri.to_string()
+}
+
+/// 校验动态码，成功时返回匹配的时间步，用于防止重放
+pub fn verify(secret: &str, code: &str, unix_time: u64) -> Option<u64> {
+    let key = base32::decode(ALPHABET, secret)?;
+    let code: u32 = code.trim().parse().ok()?;
+    let current = unix_time / PERIOD;
+    (current.saturating_sub(SKEW)..=current + SKEW).find(|step| hotp(&key, *step) == code)
+}
+
+fn hotp(key: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("hmac accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    binary % 10u32.pow(DIGITS)
+}
+
+/// 生成一组恢复码，格式为`xxxxx-xxxxx`
+pub fn generate_recovery_codes(count: usize) -> Vec<String> {
+    let mut rng = rand::thread_rng();
+    (0..count)
+        .map(|_| {
+            let code: String = (&mut rng)
+                .sample_iter(&Alphanumeric)
+                .take(10)
+                .map(|c| (c as char).to_ascii_lowercase())
+                .collect();
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+/// 恢复码只保存摘要
+pub fn hash_recovery_code(code: &str) -> String {
+    hex::encode(Sha256::digest(code.trim().to_ascii_lowercase().as_bytes()))
+}
+
+/// 从保存的恢复码摘要中去掉使用的恢复码, 恢复码无效或已使用时返回None
+pub fn consume_recovery_code(hashes: &serde_json::Value, hash: &str) -> Option<Vec<String>> {
+    let mut hashes = hashes
+        .as_array()?
+        .iter()
+        .filter_map(|h| h.as_str().map(ToString::to_string))
+        .collect::<Vec<_>>();
+    let index = hashes.iter().position(|h| h == hash)?;
+    hashes.remove(index);
+    Some(hashes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hotp() {
+        // RFC 4226 Appendix D
+        let key = b"12345678901234567890";
+        let expected = [755224, 287082, 359152, 969429, 338314];
+        for (counter, code) in expected.iter().enumerate() {
+            assert_eq!(hotp(key, counter as u64), *code);
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let secret = base32::encode(ALPHABET, b"12345678901234567890");
+        // RFC 6238 Appendix B, 截取后6位
+        assert_eq!(verify(&secret, "287082", 59), Some(1));
+        assert_eq!(verify(&secret, "081804", 1111111109), Some(37037036));
+        assert_eq!(verify(&secret, "081804", 1111111109 + 30), Some(37037036));
+        assert_eq!(verify(&secret, "081804", 1111111109 + 90), None);
+        assert_eq!(verify(&secret, "abcdef", 59), None);
+        assert_eq!(verify("!!invalid!!", "287082", 59), None);
+    }
+
+    #[test]
+    fn test_provisioning_uri() {
+        let uri = provisioning_uri("JBSWY3DPEHPK3PXP", "admin@neoiot.com");
+        assert!(uri.starts_with("otpauth://totp/NEOIOT:admin@neoiot.com?"));
+        assert!(uri.contains("secret=JBSWY3DPEHPK3PXP"));
+        assert!(uri.contains("issuer=NEOIOT"));
+    }
+
+    #[test]
+    fn test_recovery_codes() {
+        let codes = generate_recovery_codes(10);
+        assert_eq!(codes.len(), 10);
+        assert!(codes.iter().all(|c| c.len() == 11 && &c[5..6] == "-"));
+        assert_eq!(
+            hash_recovery_code(&codes[0]),
+            hash_recovery_code(&codes[0].to_uppercase())
+        );
+
+        // 每个恢复码只能使用一次
+        let hashes = serde_json::json!(codes
+            .iter()
+            .map(|c| hash_recovery_code(c))
+            .collect::<Vec<_>>());
+        let hash = hash_recovery_code(&codes[3]);
+        let remaining = consume_recovery_code(&hashes, &hash).unwrap();
+        assert_eq!(remaining.len(), 9);
+        assert_eq!(
+            consume_recovery_code(&serde_json::json!(remaining), &hash),
+            None
+        );
+        assert_eq!(consume_recovery_code(&hashes, "unknown"), None);
+    }
+}
@@ -0,0 +1,310 @@
+//! 设备文件上传: 平台通过异步指令`upload`请求设备上传日志等文件, 设备按分片经MQTT或HTTP上传,
+//! 分片先写入blob存储, 全部到达后按序号拼接为完整文件并删除分片
+use chrono::{DateTime, Local};
+use entity::device_uploads::DeviceUploadStatus;
+use entity::prelude::*;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    blob::BlobStore,
+    config::{UploadConfig, SETTINGS},
+    errors::{NeoiotError, Result},
+    repository::Repository,
+};
+
+/// 请求设备上传文件的指令名称
+pub const UPLOAD_COMMAND: &str = "upload";
+
+/// 分片在blob存储中的键, 同一分片每次写入使用不同的`attempt`
+pub fn chunk_key(account_id: &str, upload_id: &str, seq: i32, attempt: &str) -> String {
+    format!(
+        "upload/{}/{}/chunk/{}-{}",
+        account_id, upload_id, seq, attempt
+    )
+}
+
+/// 拼接后的文件在blob存储中的键
+pub fn file_key(account_id: &str, upload_id: &str) -> String {
+    format!("upload/{}/{}/file", account_id, upload_id)
+}
+
+/// 上传指令的负载, 告知设备分片大小以及两种上传方式的地址,
+/// 地址中的`{seq}`和`{total}`由设备替换为分片序号(从0开始)和分片总数
+pub fn command_payload(upload: &DeviceUploadModel, params: Option<&serde_json::Value>) -> String {
+    let config = &SETTINGS.upload;
+    json!({
+        "upload_id": upload.id,
+        "kind": upload.kind,
+        "params": params,
+        "chunk_size": config.chunk_size,
+        "max_chunk_size": config.max_chunk_size,
+        "max_chunks": config.max_chunks,
+        "expires_at": upload.expires_at.timestamp(),
+        "http_url": format!(
+            "{}/api/upload/{}/chunks/{{seq}}?total={{total}}",
+            SETTINGS.ota.public_url.trim_end_matches('/'),
+            upload.id
+        ),
+        "mqtt_topic": format!(
+            "upload/{}/{}/{}/{{seq}}/{{total}}",
+            upload.account_id, upload.device_id, upload.id
+        ),
+    })
+    .to_string()
+}
+
+/// 校验分片是否可以接收
+pub fn validate_chunk(
+    upload: &DeviceUploadModel,
+    seq: i32,
+    total: i32,
+    size: usize,
+    config: &UploadConfig,
+    now: DateTime<Local>,
+) -> Result<()> {
+    if !matches!(
+        upload.status,
+        DeviceUploadStatus::Requested | DeviceUploadStatus::Receiving
+    ) || upload.expires_at.timestamp() < now.timestamp()
+    {
+        return Err(NeoiotError::InvalidArgument(format!(
+            "upload {} is no longer accepting chunks",
+            upload.id
+        )));
+    }
+    if total < 1 || total > config.max_chunks {
+        return Err(NeoiotError::InvalidArgument(format!(
+            "total chunks must be between 1 and {}",
+            config.max_chunks
+        )));
+    }
+    if matches!(upload.total_chunks, Some(t) if t != total) {
+        return Err(NeoiotError::InvalidArgument(format!(
+            "total chunks of upload {} is {}",
+            upload.id,
+            upload.total_chunks.unwrap_or_default()
+        )));
+    }
+    if seq < 0 || seq >= total {
+        return Err(NeoiotError::InvalidArgument(format!(
+            "chunk seq must be between 0 and {}",
+            total - 1
+        )));
+    }
+    if size == 0 || size > config.max_chunk_size {
+        return Err(NeoiotError::InvalidArgument(format!(
+            "chunk size must be between 1 and {} bytes",
+            config.max_chunk_size
+        )));
+    }
+    Ok(())
+}
+
+/// 解码经MQTT上传的base64分片
+pub fn decode_chunk(payload: &str) -> Result<Vec<u8>> {
+    base64::decode(payload.trim())
+        .map_err(|err| NeoiotError::InvalidArgument(format!("invalid chunk encoding: {}", err)))
+}
+
+/// 保存一个分片, 分片全部到达后在后台拼接
+pub async fn receive_chunk<R, B>(
+    repo: &R,
+    blob: &B,
+    upload: &DeviceUploadModel,
+    seq: i32,
+    total: i32,
+    data: Vec<u8>,
+) -> Result<()>
+where
+    R: Repository + Clone,
+    B: BlobStore + Clone,
+{
+    validate_chunk(
+        upload,
+        seq,
+        total,
+        data.len(),
+        &SETTINGS.upload,
+        Local::now(),
+    )?;
+    let size = data.len() as i32;
+    // 先锁住上传记录确认仍在接收分片, 再写入分片
+    repo.claim_upload_chunk(&upload.id, total).await?;
+    // 每次写入使用新的键, 迟到或重复的分片不会覆盖正在拼接的分片
+    let key = chunk_key(&upload.account_id, &upload.id, seq, &xid::new().to_string());
+    blob.put(&key, data).await?;
+    // 第一个分片到达前上传记录中还没有分片总数, 拼接必须使用记录分片后的上传记录
+    let (upload, replaced) = match repo
+        .record_upload_chunk(&upload.id, seq, total, size, &key)
+        .await
+    {
+        Ok(recorded) => recorded,
+        Err(err) => {
+            // 写入期间上传已结束或被删除, 分片不会被使用
+            delete_blob(blob, &upload.id, &key).await;
+            return Err(err);
+        }
+    };
+    if let Some(replaced) = replaced {
+        delete_blob(blob, &upload.id, &replaced).await;
+    }
+    if upload.status == DeviceUploadStatus::Assembling {
+        let (repo, blob) = (repo.clone(), blob.clone());
+        tokio::spawn(async move { assemble(&repo, &blob, upload).await });
+    }
+    Ok(())
+}
+
+/// 按序号逐个分片追加写入文件, 返回文件的键、大小和摘要
+pub async fn concat_chunks<B: BlobStore>(
+    blob: &B,
+    upload: &DeviceUploadModel,
+    chunks: &[DeviceUploadChunkModel],
+) -> Result<(String, i64, String)> {
+    let total = upload.total_chunks.ok_or_else(|| {
+        NeoiotError::BlobError(format!("total chunks of upload {} is unknown", upload.id))
+    })?;
+    if let Some(seq) = (0..total).find(|seq| chunks.get(*seq as usize).map(|c| c.seq) != Some(*seq))
+    {
+        return Err(NeoiotError::BlobError(format!("chunk {} is missing", seq)));
+    }
+    let key = file_key(&upload.account_id, &upload.id);
+    // 清掉之前拼接失败留下的文件
+    blob.delete(&key).await?;
+    let (mut size, mut hasher) = (0i64, Sha256::new());
+    for chunk in chunks {
+        let data = blob
+            .get(&chunk.blob_key)
+            .await?
+            .ok_or_else(|| NeoiotError::BlobError(format!("chunk {} is missing", chunk.seq)))?;
+        size += data.len() as i64;
+        hasher.update(&data);
+        blob.append(&key, data).await?;
+    }
+    Ok((key, size, hex::encode(hasher.finalize())))
+}
+
+/// 拼接分片并记录文件大小和摘要, 之后删除分片
+pub async fn assemble<R: Repository, B: BlobStore>(repo: &R, blob: &B, upload: DeviceUploadModel) {
+    let mut chunks = vec![];
+    let result = async {
+        chunks = repo.list_upload_chunks(&upload.id).await?;
+        let (key, size, checksum) = concat_chunks(blob, &upload, &chunks).await?;
+        repo.complete_device_upload(&upload.id, &key, size, &checksum)
+            .await
+    }
+    .await;
+    if let Err(err) = result {
+        tracing::warn!(upload_id = %upload.id, ?err, "failed to assemble device upload");
+        if let Err(err) = repo.fail_device_upload(&upload.id, &err.to_string()).await {
+            tracing::error!(?err, "failed to record device upload failure");
+        }
+    }
+    delete_chunks(blob, &chunks).await;
+}
+
+/// 删除上传的分片
+pub async fn delete_chunks<B: BlobStore>(blob: &B, chunks: &[DeviceUploadChunkModel]) {
+    for chunk in chunks {
+        delete_blob(blob, &chunk.upload_id, &chunk.blob_key).await;
+    }
+}
+
+async fn delete_blob<B: BlobStore>(blob: &B, upload_id: &str, key: &str) {
+    if let Err(err) = blob.delete(key).await {
+        tracing::warn!(upload_id, key, ?err, "failed to delete upload chunk");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blob::LocalBlobStore;
+    use chrono::Duration;
+
+    /// 收到第一个分片之前的上传记录, 分片总数未知
+    fn new_upload(now: DateTime<Local>) -> DeviceUploadModel {
+        DeviceUploadModel {
+            id: "up".to_string(),
+            account_id: "acc".to_string(),
+            device_id: "dev".to_string(),
+            kind: "log".to_string(),
+            status: DeviceUploadStatus::Requested,
+            message_id: None,
+            total_chunks: None,
+            received_chunks: 0,
+            size: None,
+            checksum: None,
+            blob_key: None,
+            error: None,
+            requested_by: "acc".to_string(),
+            created_at: now.into(),
+            updated_at: None,
+            completed_at: None,
+            expires_at: (now + Duration::hours(1)).into(),
+        }
+    }
+
+    #[test]
+    fn test_validate_chunk() {
+        let now = Local::now();
+        let config = UploadConfig::default();
+        let mut upload = new_upload(now);
+        assert!(validate_chunk(&upload, 0, 3, 10, &config, now).is_ok());
+        assert!(validate_chunk(&upload, 3, 3, 10, &config, now).is_err());
+        assert!(validate_chunk(&upload, -1, 3, 10, &config, now).is_err());
+        assert!(validate_chunk(&upload, 0, 0, 10, &config, now).is_err());
+        assert!(validate_chunk(&upload, 0, 3, 0, &config, now).is_err());
+        assert!(validate_chunk(&upload, 0, 3, config.max_chunk_size + 1, &config, now).is_err());
+
+        upload.status = DeviceUploadStatus::Receiving;
+        upload.total_chunks = Some(3);
+        assert!(validate_chunk(&upload, 2, 3, 10, &config, now).is_ok());
+        assert!(validate_chunk(&upload, 2, 4, 10, &config, now).is_err());
+        assert!(validate_chunk(&upload, 2, 3, 10, &config, now + Duration::hours(2)).is_err());
+
+        upload.status = DeviceUploadStatus::Assembling;
+        assert!(validate_chunk(&upload, 2, 3, 10, &config, now).is_err());
+
+        assert_eq!(decode_chunk("aGVsbG8=\n").unwrap(), b"hello".to_vec());
+        assert!(decode_chunk("not base64!").is_err());
+        assert_eq!(chunk_key("acc", "up", 2, "x"), "upload/acc/up/chunk/2-x");
+    }
+
+    #[tokio::test]
+    async fn test_concat_chunks() {
+        let root = std::env::temp_dir().join(format!("neoiot-upload-{}", xid::new().to_string()));
+        let blob = LocalBlobStore::new(&root);
+        let now = Local::now();
+        let mut upload = new_upload(now);
+        let mut chunks = vec![];
+        for (seq, data) in [b"hel".to_vec(), b"lo".to_vec()].into_iter().enumerate() {
+            let key = chunk_key("acc", "up", seq as i32, "a");
+            chunks.push(DeviceUploadChunkModel {
+                upload_id: "up".to_string(),
+                seq: seq as i32,
+                size: data.len() as i32,
+                blob_key: key.clone(),
+                created_at: now.into(),
+            });
+            blob.put(&key, data).await.unwrap();
+        }
+        assert!(concat_chunks(&blob, &upload, &chunks).await.is_err());
+
+        upload.status = DeviceUploadStatus::Assembling;
+        upload.total_chunks = Some(2);
+        upload.received_chunks = 2;
+        assert!(concat_chunks(&blob, &upload, &chunks[1..]).await.is_err());
+
+        // 重新拼接时覆盖之前的文件而不是继续追加
+        for _ in 0..2 {
+            let (key, size, checksum) = concat_chunks(&blob, &upload, &chunks).await.unwrap();
+            assert_eq!(key, "upload/acc/up/file");
+            assert_eq!(size, 5);
+            assert_eq!(checksum, crate::ota::checksum(b"hello"));
+            assert_eq!(blob.get(&key).await.unwrap(), Some(b"hello".to_vec()));
+        }
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+}
@@ -0,0 +1,225 @@
+//! 出站Webhook: 事件以HMAC-SHA256签名后推送到订阅地址, 失败后按指数退避重试,
+//! 超过最大次数后进入死信
+//!
+//! 签名为`sha256=hex(HMAC(secret, "{timestamp}.{body}"))`, 订阅方应使用相同的方式
+//! 计算并比较`X-Neoiot-Signature`, 同时检查`X-Neoiot-Timestamp`防止重放
+use std::time::Duration;
+
+use chrono::Local;
+use entity::prelude::*;
+use entity::sea_orm::ActiveEnum;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{config::WebhookConfig, repository::Repository};
+
+pub const SIGNATURE_HEADER: &str = "X-Neoiot-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Neoiot-Timestamp";
+pub const EVENT_HEADER: &str = "X-Neoiot-Event";
+pub const DELIVERY_HEADER: &str = "X-Neoiot-Delivery";
+
+/// 一次投递的结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attempt {
+    /// 订阅方返回的状态码, 连接失败时为None
+    pub status_code: Option<i32>,
+    /// 失败原因, 成功时为None
+    pub error: Option<String>,
+}
+
+impl Attempt {
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// 计算消息签名
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any length");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// 第`attempts`次失败后到下次重试的间隔
+pub fn backoff(config: &WebhookConfig, attempts: i32) -> chrono::Duration {
+    let exp = attempts.saturating_sub(1).clamp(0, 30) as u32;
+    let secs = config
+        .backoff_base_secs
+        .saturating_mul(2i64.saturating_pow(exp))
+        .min(config.backoff_max_secs);
+    chrono::Duration::seconds(secs)
+}
+
+/// 推送一条投递记录
+pub async fn deliver(
+    webhook: &WebhookModel,
+    delivery: &WebhookDeliveryModel,
+    timeout: Duration,
+) -> Attempt {
+    let body = delivery.payload.to_string();
+    let timestamp = Local::now().timestamp();
+    let result = reqwest::Client::new()
+        .post(&webhook.url)
+        .timeout(timeout)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(SIGNATURE_HEADER, sign(&webhook.secret, timestamp, &body))
+        .header(TIMESTAMP_HEADER, timestamp.to_string())
+        .header(EVENT_HEADER, delivery.event_type.to_value())
+        .header(DELIVERY_HEADER, &delivery.id)
+        .body(body)
+        .send()
+        .await;
+    match result {
+        Ok(resp) if resp.status().is_success() => Attempt {
+            status_code: Some(resp.status().as_u16() as i32),
+            error: None,
+        },
+        Ok(resp) => Attempt {
+            status_code: Some(resp.status().as_u16() as i32),
+            error: Some(format!("unexpected status {}", resp.status())),
+        },
+        Err(err) => Attempt {
+            status_code: None,
+            error: Some(err.to_string()),
+        },
+    }
+}
+
+/// 推送并记录结果
+pub async fn process<R: Repository>(
+    repo: &R,
+    webhook: WebhookModel,
+    delivery: WebhookDeliveryModel,
+    timeout: Duration,
+) {
+    let attempt = deliver(&webhook, &delivery, timeout).await;
+    if let Some(error) = &attempt.error {
+        tracing::warn!(webhook_id = %webhook.id, delivery_id = %delivery.id, %error, "webhook delivery failed");
+    }
+    if let Err(err) = repo.finish_webhook_delivery(&delivery, &attempt).await {
+        tracing::error!(?err, "failed to record webhook delivery");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entity::webhook_deliveries::{EventType, WebhookDeliveryStatus};
+    use serde_json::json;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// 接收一个请求并返回指定状态码, 返回收到的请求头和请求体
+    async fn stub_server(status: u16) -> (String, tokio::task::JoinHandle<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![];
+            let mut chunk = [0u8; 1024];
+            loop {
+                let n = stream.read(&mut chunk).await.unwrap();
+                buf.extend_from_slice(&chunk[..n]);
+                let text = String::from_utf8_lossy(&buf).to_string();
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|l| {
+                            l.to_lowercase()
+                                .strip_prefix("content-length:")
+                                .map(|v| v.trim().parse::<usize>().unwrap())
+                        })
+                        .unwrap_or(0);
+                    if body.len() >= length {
+                        let resp = format!("HTTP/1.1 {} Stub\r\ncontent-length: 0\r\n\r\n", status);
+                        stream.write_all(resp.as_bytes()).await.unwrap();
+                        return (head.to_lowercase(), body.to_string());
+                    }
+                }
+            }
+        });
+        (url, handle)
+    }
+
+    fn header<'a>(head: &'a str, name: &str) -> &'a str {
+        let prefix = format!("{}: ", name.to_lowercase());
+        head.lines()
+            .find_map(|l| l.strip_prefix(prefix.as_str()))
+            .unwrap()
+    }
+
+    fn models(url: &str) -> (WebhookModel, WebhookDeliveryModel) {
+        let now = Local::now().into();
+        let webhook = WebhookModel {
+            id: "wh".to_string(),
+            account_id: "acc".to_string(),
+            url: url.to_string(),
+            description: None,
+            secret: "secret".to_string(),
+            event_types: json!([]),
+            is_active: true,
+            created_at: now,
+            updated_at: None,
+        };
+        let delivery = WebhookDeliveryModel {
+            id: "dl".to_string(),
+            account_id: "acc".to_string(),
+            webhook_id: "wh".to_string(),
+            event_type: EventType::DeviceCreated,
+            payload: json!({"type": "device_created", "data": {"id": "dev"}}),
+            status: WebhookDeliveryStatus::Pending,
+            attempts: 0,
+            next_attempt_at: now,
+            last_status_code: None,
+            last_error: None,
+            delivered_at: None,
+            created_at: now,
+            updated_at: None,
+        };
+        (webhook, delivery)
+    }
+
+    #[tokio::test]
+    async fn test_deliver() {
+        let (url, server) = stub_server(200).await;
+        let (webhook, delivery) = models(&url);
+        let attempt = deliver(&webhook, &delivery, Duration::from_secs(5)).await;
+        assert_eq!(
+            attempt,
+            Attempt {
+                status_code: Some(200),
+                error: None
+            }
+        );
+        let (head, body) = server.await.unwrap();
+        assert_eq!(header(&head, EVENT_HEADER), "device_created");
+        assert_eq!(header(&head, DELIVERY_HEADER), "dl");
+        let timestamp: i64 = header(&head, TIMESTAMP_HEADER).parse().unwrap();
+        // 订阅方用相同的密钥重新计算签名
+        assert_eq!(
+            header(&head, SIGNATURE_HEADER),
+            sign("secret", timestamp, &body)
+        );
+        assert_ne!(
+            sign("other", timestamp, &body),
+            sign("secret", timestamp, &body)
+        );
+
+        let (url, server) = stub_server(500).await;
+        let (webhook, delivery) = models(&url);
+        let attempt = deliver(&webhook, &delivery, Duration::from_secs(5)).await;
+        assert_eq!(attempt.status_code, Some(500));
+        assert!(!attempt.is_success());
+        server.await.unwrap();
+    }
+
+    #[test]
+    fn test_backoff() {
+        let config = WebhookConfig::default();
+        assert_eq!(backoff(&config, 1), chrono::Duration::seconds(10));
+        assert_eq!(backoff(&config, 2), chrono::Duration::seconds(20));
+        assert_eq!(backoff(&config, 4), chrono::Duration::seconds(80));
+        assert_eq!(backoff(&config, 100), chrono::Duration::seconds(3600));
+    }
+}